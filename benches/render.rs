@@ -0,0 +1,68 @@
+use std::f64::consts::PI;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use nalgebra::Vector3;
+
+use raytracer::geometry::{Geometry, Sphere};
+use raytracer::mesh::Mesh;
+use raytracer::{Control, Ray, RenderSettings, Scene};
+
+// A UV sphere with 2 * n * n triangles, for exercising the mesh BVH.
+fn uv_sphere(n: usize) -> Mesh {
+    let vertices = (0..=n).flat_map(|i| (0..=2 * n).map(move |j| {
+        let (theta, phi) = (PI * i as f64 / n as f64, PI * j as f64 / n as f64);
+        Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+    })).collect();
+    let index = |i: usize, j: usize| i * (2 * n + 1) + j;
+    let faces = (0..n).flat_map(|i| (0..2 * n).flat_map(move |j| {
+        [[index(i, j), index(i + 1, j), index(i + 1, j + 1)], [index(i, j), index(i + 1, j + 1), index(i, j + 1)]]
+    })).collect::<Vec<_>>();
+    let materials = vec![0; faces.len()];
+    Mesh::new(vertices, faces, materials)
+}
+
+// Rays from a ring around the origin aimed at points scattered over the unit sphere.
+fn rays(count: usize) -> Vec<Ray<f64>> {
+    (0..count).map(|k| {
+        let a = k as f64 * 2.399963;
+        let origin = Vector3::new(3.0 * a.cos(), 0.5, 3.0 * a.sin());
+        let y = 1.0 - 2.0 * (k as f64 + 0.5) / count as f64;
+        let r = (1.0 - y * y).sqrt();
+        let target = Vector3::new(r * (a * 7.0).cos(), y, r * (a * 7.0).sin());
+        Ray::new(origin, target - origin)
+    }).collect()
+}
+
+fn sphere_intersection(c: &mut Criterion) {
+    let sphere = Sphere::new(Vector3::zeros(), 1.0);
+    let rays = rays(1024);
+    c.bench_function("sphere intersection", |b| b.iter(|| {
+        rays.iter().filter(|ray| sphere.intersect(black_box(ray), 0.0..f64::INFINITY).is_some()).count()
+    }));
+}
+
+fn bvh_traversal(c: &mut Criterion) {
+    let mesh = uv_sphere(128);
+    let rays = rays(1024);
+    c.bench_function("bvh traversal", |b| b.iter(|| {
+        rays.iter().filter(|ray| mesh.intersect(black_box(ray), 0.0..f64::INFINITY).is_some()).count()
+    }));
+}
+
+fn full_frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("full frame");
+    group.sample_size(10);
+    for name in ["random_spheres", "colonnade"] {
+        let path = format!("{}/scenes/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        let scene = Scene::load(path.as_ref(), &[]).unwrap();
+        let settings = RenderSettings { width: 160, height: 90, samples: 1, threads: 1, ..Default::default() };
+        group.bench_function(name, |b| b.iter(|| {
+            raytracer::render_scene(&scene, &settings, &Control::new(&settings))
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, sphere_intersection, bvh_traversal, full_frame);
+criterion_main!(benches);
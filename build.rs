@@ -0,0 +1,17 @@
+fn main() {
+    // the C header for the `capi` feature
+    #[cfg(feature = "capi")]
+    {
+        println!("cargo:rerun-if-changed=src/capi.rs");
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        cbindgen::generate(&dir).unwrap().write_to_file(format!("{}/include/raytracer.h", dir));
+    }
+
+    // the C shim over OpenColorIO's C++ API for the `ocio` feature
+    #[cfg(feature = "ocio")]
+    {
+        println!("cargo:rerun-if-changed=src/ocio.cpp");
+        cc::Build::new().cpp(true).std("c++17").file("src/ocio.cpp").compile("rtocio");
+        println!("cargo:rustc-link-lib=OpenColorIO");
+    }
+}
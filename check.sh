@@ -0,0 +1,9 @@
+#!/bin/sh
+# What to run before committing: the default build and tests, and clippy over the optional features that
+# are compiled out by default, which otherwise go stale unnoticed.
+set -e
+cargo build --workspace
+cargo clippy --workspace --all-targets -- -D warnings
+cargo clippy --all-targets --features sdl2 -- -D warnings
+cargo clippy --all-targets --features embree -- -D warnings
+cargo test --workspace
@@ -0,0 +1,130 @@
+#ifndef RAYTRACER_H
+#define RAYTRACER_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum RtMaterialKind {
+  RT_MATERIAL_KIND_LAMBERTIAN,
+  RT_MATERIAL_KIND_METAL,
+  RT_MATERIAL_KIND_DIELECTRIC,
+  RT_MATERIAL_KIND_GGX,
+} RtMaterialKind;
+
+/*
+ A scene being assembled or loaded, owned by the caller until passed to `rt_scene_free`.
+ */
+typedef struct RtScene RtScene;
+
+/*
+ `parameter` is the fuzz of a metal, the index of refraction of a dielectric, or the roughness of a
+ GGX material, and is ignored for Lambertian ones. Dielectrics ignore `color`.
+ */
+typedef struct RtMaterial {
+  enum RtMaterialKind kind;
+  double color[3];
+  double parameter;
+} RtMaterial;
+
+typedef struct Processor {
+  uint8_t _private[0];
+} Processor;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ A new empty scene, with the camera at (0, 0, 1) looking at the origin.
+ */
+struct RtScene *rt_scene_new(void);
+
+/*
+ Loads a scene file, returning null if it can't be read.
+
+ # Safety
+
+ `path` must be a null-terminated string.
+ */
+struct RtScene *rt_scene_load(const char *path);
+
+/*
+ # Safety
+
+ `scene` must come from `rt_scene_new` or `rt_scene_load` and not have been freed, or be null.
+ */
+void rt_scene_free(struct RtScene *scene);
+
+/*
+ Adds a sphere made of `material`. Does nothing if `scene` or `material` is null.
+
+ # Safety
+
+ `scene` must be null or a live scene from `rt_scene_new` or `rt_scene_load`, `material` null or a
+ valid material, and `center` must point to three doubles.
+ */
+void rt_scene_add_sphere(struct RtScene *scene,
+                         const double *center,
+                         double radius,
+                         const struct RtMaterial *material);
+
+/*
+ Points the camera from `from` at `at` with a vertical field of view of `fov` degrees. Does nothing if
+ `scene` is null.
+
+ # Safety
+
+ `scene` must be null or a live scene from `rt_scene_new` or `rt_scene_load`, and `from` and `at` must
+ each point to three doubles.
+ */
+void rt_scene_set_camera(struct RtScene *scene,
+                         const double *from,
+                         const double *at,
+                         double fov);
+
+/*
+ Sets the depth of field; an aperture of zero makes a pinhole camera. Does nothing if `scene` is null.
+
+ # Safety
+
+ `scene` must be null or a live scene from `rt_scene_new` or `rt_scene_load`.
+ */
+void rt_scene_set_lens(struct RtScene *scene,
+                       double aperture,
+                       double focus_distance);
+
+/*
+ Renders with `samples` passes on each of `threads` threads into `pixels`, which must hold
+ `width * height * 3` floats: RGB display values in rows from the top. Returns 0 on success, and -1 if
+ `scene` or `pixels` is null, the image is empty or too large, or the render fails.
+
+ # Safety
+
+ `scene` must be null or a live scene from `rt_scene_new` or `rt_scene_load`, and `pixels` null or
+ pointing to at least `width * height * 3` writable floats.
+ */
+int rt_render(const struct RtScene *scene,
+              uint32_t width,
+              uint32_t height,
+              uint32_t samples,
+              uint32_t threads,
+              float *pixels);
+
+extern struct Processor *rt_ocio_new(const char *config,
+                                     const char *display,
+                                     const char *view,
+                                     char *error,
+                                     size_t error_len);
+
+extern void rt_ocio_apply(const struct Processor *processor, float *rgb, size_t pixels);
+
+extern void rt_ocio_free(struct Processor *processor);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RAYTRACER_H */
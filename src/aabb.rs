@@ -0,0 +1,71 @@
+use std::ops::Range;
+
+use nalgebra::{Affine3, Point3, Vector3};
+
+use crate::ray::Ray;
+
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3<f64>,
+    pub max: Vector3<f64>,
+}
+
+impl Aabb {
+    pub const fn new(min: Vector3<f64>, max: Vector3<f64>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points<'a>(points: impl IntoIterator<Item=&'a Vector3<f64>>) -> Self {
+        let empty = Self::new(Vector3::repeat(f64::INFINITY), Vector3::repeat(f64::NEG_INFINITY));
+        points.into_iter().fold(empty, |b, p| Self::new(b.min.inf(p), b.max.sup(p)))
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(self.min.inf(&other.min), self.max.sup(&other.max))
+    }
+
+    pub fn transform(&self, transform: &Affine3<f64>) -> Self {
+        let corners = (0..8)
+            .map(|i| {
+                let corner = Vector3::new(
+                    if i & 1 == 0 { self.min.x } else { self.max.x },
+                    if i & 2 == 0 { self.min.y } else { self.max.y },
+                    if i & 4 == 0 { self.min.z } else { self.max.z },
+                );
+                transform.transform_point(&Point3::from(corner)).coords
+            })
+            .collect::<Vec<_>>();
+        Self::from_points(&corners)
+    }
+
+    pub fn center(&self) -> Vector3<f64> {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn diagonal(&self) -> Vector3<f64> {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.diagonal().map(|x| x.max(0.0));
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    // The part of the range where the ray is inside the box, if any.
+    pub fn clip(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Range<f64>> {
+        let inv_direction = ray.direction().map(|x| 1.0 / x);
+        let t0 = (self.min - ray.origin).component_mul(&inv_direction);
+        let t1 = (self.max - ray.origin).component_mul(&inv_direction);
+        let near = t0.inf(&t1).max().max(range.start);
+        let far = t0.sup(&t1).min().min(range.end);
+        Some(near..far).filter(|_| near < far)
+    }
+
+    pub fn hit(&self, ray: &Ray<f64>, inv_direction: &Vector3<f64>, range: &Range<f64>) -> bool {
+        let t0 = (self.min - ray.origin).component_mul(inv_direction);
+        let t1 = (self.max - ray.origin).component_mul(inv_direction);
+        let near = t0.inf(&t1).max().max(range.start);
+        let far = t0.sup(&t1).min().min(range.end);
+        near <= far
+    }
+}
@@ -0,0 +1,91 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::texture::{ColorSpace, ImageTexture};
+
+type SharedMaterial = Arc<dyn Material + Send + Sync>;
+type Cache<T, K = String> = RefCell<HashMap<K, T>>;
+
+// How many distinct files were loaded and how many references reused one already loaded.
+#[derive(Clone, Copy, Default)]
+pub struct AssetStats {
+    pub images: usize,
+    pub image_hits: usize,
+    pub meshes: usize,
+    pub mesh_hits: usize,
+}
+
+// The images and meshes loaded while building scenes, kept by path so every reference to a file shares
+// one copy of it. Scenes are loaded on a single thread, so lookups take `&self` and can be made from
+// anywhere in the parser. Files that fail to load aren't kept, so the next reference tries again.
+#[derive(Default)]
+pub struct Assets {
+    images: Cache<ImageTexture, (String, Option<ColorSpace>)>,
+    meshes: Cache<(Arc<Mesh>, Vec<String>)>,
+    objs: Cache<(Arc<Mesh>, Vec<SharedMaterial>)>,
+    stats: Cell<AssetStats>,
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The texture shares its pixels with every other load of `path` in the same color space; only the filter
+    // is its own.
+    pub fn image(&self, path: &str, space: Option<ColorSpace>) -> Result<ImageTexture> {
+        let (image, loaded) = cached(&self.images, (path.to_owned(), space), || ImageTexture::load_in(path, space))?;
+        self.count(|s| if loaded { s.images += 1 } else { s.image_hits += 1 });
+        Ok(image)
+    }
+
+    // A mesh and the names of its material groups. `key` identifies the file along with whatever `load`
+    // does to it besides reading it, such as subdividing.
+    pub fn mesh(
+        &self, key: &str, load: impl FnOnce() -> Result<(Mesh, Vec<String>)>,
+    ) -> Result<(Arc<Mesh>, Vec<String>)> {
+        let (mesh, loaded) = cached(&self.meshes, key.to_owned(), || {
+            let (mesh, names) = load()?;
+            Ok((Arc::new(mesh), names))
+        })?;
+        self.count(|s| if loaded { s.meshes += 1 } else { s.mesh_hits += 1 });
+        Ok(mesh)
+    }
+
+    // A mesh along with the materials it brought, such as an OBJ file's MTL library, shared as well.
+    pub fn mesh_with_materials(
+        &self, key: &str, load: impl FnOnce() -> Result<(Mesh, Vec<Box<dyn Material + Send + Sync>>)>,
+    ) -> Result<(Arc<Mesh>, Vec<SharedMaterial>)> {
+        let (object, loaded) = cached(&self.objs, key.to_owned(), || {
+            let (mesh, materials) = load()?;
+            Ok((Arc::new(mesh), materials.into_iter().map(Arc::from).collect()))
+        })?;
+        self.count(|s| if loaded { s.meshes += 1 } else { s.mesh_hits += 1 });
+        Ok(object)
+    }
+
+    fn count(&self, count: impl FnOnce(&mut AssetStats)) {
+        let mut stats = self.stats.get();
+        count(&mut stats);
+        self.stats.set(stats);
+    }
+
+    pub fn stats(&self) -> AssetStats {
+        self.stats.get()
+    }
+}
+
+// The entry for `key`, loaded first if it isn't there yet, and whether it was.
+fn cached<K: Eq + Hash, T: Clone>(cache: &Cache<T, K>, key: K, load: impl FnOnce() -> Result<T>) -> Result<(T, bool)> {
+    if let Some(value) = cache.borrow().get(&key) {
+        return Ok((value.clone(), false));
+    }
+    let value = load()?;
+    cache.borrow_mut().insert(key, value.clone());
+    Ok((value, true))
+}
@@ -0,0 +1,95 @@
+use std::f64::consts::PI;
+
+use itertools::iproduct;
+use nalgebra::{Rotation3, Vector2, Vector3};
+
+use crate::material::orthonormal_basis;
+use crate::sampler::radical_inverse;
+use crate::texture::ImageTexture;
+
+// The normals the irradiance is tabulated for, in the layout of the map, and the directions averaged for each.
+const IRRADIANCE_SIZE: (usize, usize) = (32, 16);
+const IRRADIANCE_SAMPLES: u32 = 256;
+
+// What rays leaving the scene see: an environment map in latitude-longitude layout, or the built-in
+// gradient sky without one, turned `rotation` degrees about the vertical axis and scaled by `intensity`.
+// Turning the map is how a scene is relit in look development.
+#[derive(Clone)]
+pub struct Background {
+    pub map: Option<ImageTexture>,
+    pub rotation: f64,
+    pub intensity: f64,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self { map: None, rotation: 0.0, intensity: 1.0 }
+    }
+}
+
+impl Background {
+    // `direction` needn't be of unit length.
+    pub fn radiance(&self, direction: &Vector3<f64>) -> Vector3<f64> {
+        let color = match &self.map {
+            Some(map) => {
+                let turn = Rotation3::from_axis_angle(&Vector3::y_axis(), -self.rotation.to_radians());
+                map.lookup(&latitude_longitude(&(turn * direction)), 0.0)
+            }
+            // only depends on height, so there's nothing to turn
+            None => {
+                let t = 0.5 * (direction.y + 1.0);
+                Vector3::new(1.0 - t, 1.0 - t, 1.0 - t) + t * Vector3::new(0.5, 0.7, 1.0)
+            }
+        };
+        color * self.intensity
+    }
+}
+
+// The brightness of the light the background casts on surfaces facing each way with nothing in the way,
+// for telling how much of it objects shadow. It is tabulated once, as each entry averages many directions.
+pub(crate) struct Irradiance {
+    table: Vec<f64>,
+}
+
+impl Irradiance {
+    pub fn new(background: &Background) -> Self {
+        let (width, height) = IRRADIANCE_SIZE;
+        let table = iproduct!(0..width, 0..height).map(|(i, j)| {
+            let normal = direction((i as f64 + 0.5) / width as f64, (j as f64 + 0.5) / height as f64);
+            let (t, b) = orthonormal_basis(&normal);
+            // Hammersley points mapped to the hemisphere with the density of a diffuse surface's bounces
+            (0..IRRADIANCE_SAMPLES).map(|k| {
+                let (u, v) = ((k as f64 + 0.5) / IRRADIANCE_SAMPLES as f64, radical_inverse(k, 2));
+                let (r, phi) = (u.sqrt(), 2.0 * PI * v);
+                let d = t * r * phi.cos() + b * r * phi.sin() + normal * (1.0 - u).sqrt();
+                luminance(&background.radiance(&d))
+            }).sum::<f64>() / IRRADIANCE_SAMPLES as f64
+        }).collect();
+        Self { table }
+    }
+
+    // The mean luminance of the background over the hemisphere around `normal`, weighted by the cosine.
+    pub fn at(&self, normal: &Vector3<f64>) -> f64 {
+        let (width, height) = IRRADIANCE_SIZE;
+        let uv = latitude_longitude(normal);
+        let i = ((uv.x * width as f64) as usize).min(width - 1);
+        let j = ((uv.y * height as f64) as usize).min(height - 1);
+        self.table[i * height + j]
+    }
+}
+
+pub(crate) fn luminance(c: &Vector3<f64>) -> f64 {
+    c.dot(&Vector3::new(0.2126, 0.7152, 0.0722))
+}
+
+// Where a direction falls on a map in latitude-longitude layout, the middle of it looking along -z with +x
+// to its right.
+fn latitude_longitude(direction: &Vector3<f64>) -> Vector2<f64> {
+    let d = direction.normalize();
+    Vector2::new(0.5 + d.x.atan2(-d.z) / (2.0 * PI), 0.5 + d.y.asin() / PI)
+}
+
+fn direction(u: f64, v: f64) -> Vector3<f64> {
+    let (phi, theta) = ((u - 0.5) * 2.0 * PI, (v - 0.5) * PI);
+    Vector3::new(theta.cos() * phi.sin(), theta.sin(), -theta.cos() * phi.cos())
+}
@@ -0,0 +1,67 @@
+use std::ops::Range;
+
+use nalgebra::{Vector2, Vector3};
+
+use crate::aabb::Aabb;
+use crate::geometry::Geometry;
+use crate::ray::Ray;
+use crate::texture::{ALPHA_CUTOFF, ImageTexture};
+
+// An upright textured quad turned about the vertical axis to face a viewpoint. Texels whose alpha falls
+// below the cutoff are not hit at all, so the closest hit already resolves overlapping billboards in depth.
+pub struct Billboard<'a> {
+    center: Vector3<f64>,
+    right: Vector3<f64>,
+    up: Vector3<f64>,
+    normal: Vector3<f64>,
+    texture: &'a ImageTexture,
+}
+
+impl<'a> Billboard<'a> {
+    pub fn new(
+        center: Vector3<f64>, size: Vector2<f64>, viewpoint: &Vector3<f64>, texture: &'a ImageTexture,
+    ) -> Self {
+        let up = Vector3::y();
+        let to_view = viewpoint - center;
+        let normal = Vector3::new(to_view.x, 0.0, to_view.z).try_normalize(1e-12).unwrap_or_else(Vector3::z);
+        let right = up.cross(&normal) * size.x;
+        Self { center, right, up: up * size.y, normal, texture }
+    }
+
+    fn local(&self, point: &Vector3<f64>) -> Vector2<f64> {
+        let d = point - self.center;
+        let u = d.dot(&self.right) / self.right.norm_squared();
+        let v = d.dot(&self.up) / self.up.norm_squared();
+        Vector2::new(u + 0.5, v + 0.5)
+    }
+}
+
+impl Geometry for Billboard<'_> {
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<f64> {
+        let denom = ray.direction().dot(&self.normal);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (self.center - ray.origin).dot(&self.normal) / denom;
+        if !range.contains(&t) {
+            return None;
+        }
+        let uv = self.local(&ray.at(t));
+        let inside = (0.0..=1.0).contains(&uv.x) && (0.0..=1.0).contains(&uv.y);
+        Some(t).filter(|_| inside && self.texture.alpha(&uv) >= ALPHA_CUTOFF)
+    }
+
+    fn normal(&self, _point: &Vector3<f64>) -> Vector3<f64> {
+        self.normal
+    }
+
+    fn uv(&self, point: &Vector3<f64>) -> Vector2<f64> {
+        self.local(point)
+    }
+
+    fn bounds(&self) -> Aabb {
+        let (r, u) = (self.right / 2.0, self.up / 2.0);
+        let c = self.center;
+        Aabb::from_points(&[c - r - u, c + r - u, c - r + u, c + r + u])
+    }
+}
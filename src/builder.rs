@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use nalgebra::Vector3;
+
+use crate::background::Background;
+use crate::geometry::Sphere;
+use crate::library::MaterialLibrary;
+use crate::material::{Convention, Dielectric, Ggx, Lambertian, Material, Metal};
+use crate::mesh::Mesh;
+use crate::object::Object;
+use crate::portal::Portal;
+use crate::scene::{PreviewSettings, Scene, View};
+use crate::text::text_mesh;
+use crate::volume::Fog;
+
+type SharedMaterial = Arc<dyn Material + Send + Sync>;
+type Attach = Box<dyn FnOnce(SharedMaterial) -> Box<dyn Object + Sync>>;
+
+// Assembles a scene in code. Adding a shape returns an `ObjectBuilder` that takes its material and hands
+// the scene builder back, so a scene reads as a chain of `.sphere(center, radius).lambertian(color)`.
+#[derive(Default)]
+pub struct SceneBuilder {
+    view: View,
+    cameras: Vec<(String, View)>,
+    objects: Vec<Box<dyn Object + Sync>>,
+    fog: Option<Fog>,
+    portals: Vec<Portal>,
+    background: Background,
+}
+
+impl SceneBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn look_at(self, from: Vector3<f64>, at: Vector3<f64>) -> Self {
+        Self { view: View { from, at, ..self.view }, ..self }
+    }
+
+    // Vertical field of view, in degrees.
+    pub fn fov(self, fov: f64) -> Self {
+        Self { view: View { fov, ..self.view }, ..self }
+    }
+
+    pub fn depth_of_field(self, aperture: f64, focus_distance: f64) -> Self {
+        Self { view: View { aperture, focus_distance, ..self.view }, ..self }
+    }
+
+    // Keeps the view set up so far as a named camera, so `.look_at(..).camera("front").look_at(..)` sets up
+    // several; the last view set up is the scene's own.
+    pub fn camera(mut self, name: &str) -> Self {
+        self.cameras.push((name.to_owned(), self.view.clone()));
+        self
+    }
+
+    pub fn fog(self, fog: Fog) -> Self {
+        Self { fog: Some(fog), ..self }
+    }
+
+    pub fn background(self, background: Background) -> Self {
+        Self { background, ..self }
+    }
+
+    pub fn portal(mut self, portal: Portal) -> Self {
+        self.portals.push(portal);
+        self
+    }
+
+    pub fn sphere(self, center: Vector3<f64>, radius: f64) -> ObjectBuilder {
+        let sphere = Sphere::new(center, radius);
+        ObjectBuilder { scene: self, attach: Box::new(move |material| Box::new((sphere, material))) }
+    }
+
+    // The material applies to every group of the OBJ file.
+    pub fn mesh(self, path: &str) -> ObjectBuilder {
+        let (mesh, names) = Mesh::load_obj(path).unwrap();
+        let groups = names.len().max(1);
+        ObjectBuilder { scene: self, attach: Box::new(move |material| Box::new((mesh, vec![material; groups]))) }
+    }
+
+    // Upright text facing +z with the bottom left of its first character at `position`, each character
+    // `size` wide and high and one font pixel deep.
+    pub fn text(self, text: &str, position: Vector3<f64>, size: f64) -> ObjectBuilder {
+        let mesh = text_mesh(text, position, Vector3::x() * size, Vector3::y() * size, size / 8.0);
+        ObjectBuilder { scene: self, attach: Box::new(move |material| Box::new((mesh, vec![material]))) }
+    }
+
+    // Adds an object that already has its materials, such as an OBJ file loaded with its MTL library.
+    pub fn object(mut self, object: impl Object + Sync + 'static) -> Self {
+        self.objects.push(Box::new(object));
+        self
+    }
+
+    pub fn build(self) -> Scene {
+        Scene {
+            view: self.view, cameras: self.cameras, objects: self.objects, materials: MaterialLibrary::new(),
+            fog: self.fog, portals: self.portals, neutral: None, names: Vec::new(), sampling: Vec::new(),
+            emissive: Vec::new(), background: self.background, preview: PreviewSettings::default(), summary: None,
+            source: None,
+        }
+    }
+}
+
+pub struct ObjectBuilder {
+    scene: SceneBuilder,
+    attach: Attach,
+}
+
+impl ObjectBuilder {
+    pub fn material(self, material: impl Material + Send + Sync + 'static) -> SceneBuilder {
+        let mut scene = self.scene;
+        scene.objects.push((self.attach)(Arc::new(material)));
+        scene
+    }
+
+    pub fn lambertian(self, color: Vector3<f64>) -> SceneBuilder {
+        self.material(Lambertian::new(color))
+    }
+
+    pub fn metal(self, color: Vector3<f64>, fuzz: f64) -> SceneBuilder {
+        self.material(Metal::new(color, fuzz))
+    }
+
+    pub fn dielectric(self, index_refraction: f64) -> SceneBuilder {
+        self.material(Dielectric::new(index_refraction))
+    }
+
+    // `roughness` is perceptual, squared to get the GGX alpha as in scene files.
+    pub fn ggx(self, color: Vector3<f64>, roughness: f64) -> SceneBuilder {
+        self.material(Ggx::new(color, roughness * roughness))
+    }
+
+    // Stretched along the surface tangent by `anisotropy` as the other renderer's `convention` has it.
+    pub fn ggx_anisotropic(
+        self, color: Vector3<f64>, roughness: f64, anisotropy: f64, convention: Convention,
+    ) -> SceneBuilder {
+        self.material(Ggx::perceptual(color, roughness, anisotropy, convention))
+    }
+}
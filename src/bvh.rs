@@ -0,0 +1,204 @@
+use std::mem::size_of_val;
+use std::ops::Range;
+
+use nalgebra::Vector3;
+
+use crate::aabb::Aabb;
+use crate::counters;
+use crate::ray::Ray;
+
+const NUM_BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// Cost of visiting a node relative to intersecting one primitive, for the surface area heuristic.
+const TRAVERSAL_COST: f64 = 0.5;
+// Refitted trees are rebuilt once their expected cost grows by this factor over the freshly built tree.
+const REBUILD_RATIO: f64 = 1.5;
+
+struct Node {
+    bounds: Aabb,
+    start: usize,
+    count: usize,
+    right: usize,
+}
+
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+    built_cost: f64,
+}
+
+impl Bvh {
+    pub fn memory(&self) -> usize {
+        size_of_val(self.nodes.as_slice()) + size_of_val(self.indices.as_slice())
+    }
+
+    pub fn build(bounds: &[Aabb]) -> Self {
+        Self::build_parallel(bounds, 1)
+    }
+
+    // The right subtrees near the root are built on separate threads into their own node lists and
+    // spliced in afterwards, so the result is identical to a serial build.
+    pub fn build_parallel(bounds: &[Aabb], threads: u32) -> Self {
+        let mut indices = (0..bounds.len()).collect::<Vec<_>>();
+        let mut nodes = Vec::new();
+        if !bounds.is_empty() {
+            let centers = bounds.iter().map(Aabb::center).collect::<Vec<_>>();
+            let depth = 31 - threads.max(1).leading_zeros();
+            build_node(bounds, &centers, &mut indices, 0, depth, &mut nodes);
+        }
+        let mut bvh = Self { nodes, indices, built_cost: 0.0 };
+        bvh.built_cost = bvh.cost();
+        bvh
+    }
+
+    // The expected cost of a ray that hits the root by the surface area heuristic, weighting every node
+    // by the chance that such a ray also hits it.
+    pub fn cost(&self) -> f64 {
+        let root = match self.nodes.first() {
+            Some(root) => root.bounds.surface_area(),
+            None => return 0.0,
+        };
+        self.nodes.iter().map(|node| {
+            let work = if node.count > 0 { node.count as f64 } else { TRAVERSAL_COST };
+            node.bounds.surface_area() / root * work
+        }).sum()
+    }
+
+    // Refits for moved primitives, or rebuilds if refitting has let the tree degrade too far, as when
+    // parts of a deforming mesh that started out together move apart. Returns whether it rebuilt.
+    pub fn update(&mut self, bounds: &[Aabb], threads: u32) -> bool {
+        self.refit(bounds);
+        let rebuild = self.cost() > REBUILD_RATIO * self.built_cost;
+        if rebuild {
+            *self = Self::build_parallel(bounds, threads);
+        }
+        rebuild
+    }
+
+    // Updates the node bounds in place for moved primitives, keeping the tree topology.
+    pub fn refit(&mut self, bounds: &[Aabb]) {
+        // nodes are stored in preorder, so children always come after their parent
+        for n in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[n];
+            self.nodes[n].bounds = if node.count > 0 {
+                self.indices[node.start..node.start + node.count].iter()
+                    .map(|&i| bounds[i])
+                    .reduce(|a, b| a.union(&b))
+                    .unwrap()
+            } else {
+                self.nodes[n + 1].bounds.union(&self.nodes[node.right].bounds)
+            };
+        }
+    }
+
+    // The bounds of the nodes at most `depth` levels below the root, with their levels.
+    pub fn node_bounds(&self, depth: usize) -> Vec<(usize, Aabb)> {
+        let mut bounds = Vec::new();
+        let mut stack = if self.nodes.is_empty() { Vec::new() } else { vec![(0, 0)] };
+        while let Some((n, level)) = stack.pop() {
+            let node = &self.nodes[n];
+            bounds.push((level, node.bounds));
+            if node.count == 0 && level < depth {
+                stack.push((node.right, level + 1));
+                stack.push((n + 1, level + 1));
+            }
+        }
+        bounds
+    }
+
+    pub fn intersect<T>(
+        &self, ray: &Ray<f64>, range: Range<f64>,
+        mut hit: impl FnMut(usize, Range<f64>) -> Option<(f64, T)>,
+    ) -> Option<(f64, T)> {
+        let inv_direction = ray.direction().map(|x| 1.0 / x);
+        let mut range = range;
+        let mut closest = None;
+        let mut stack = vec![0];
+        while let Some(n) = stack.pop() {
+            let node = match self.nodes.get(n) {
+                Some(node) if node.bounds.hit(ray, &inv_direction, &range) => node,
+                _ => continue,
+            };
+            counters::visit_node();
+            if node.count > 0 {
+                for &i in &self.indices[node.start..node.start + node.count] {
+                    if let Some((t, x)) = hit(i, range.clone()) {
+                        range.end = t;
+                        closest = Some((t, x));
+                    }
+                }
+            } else {
+                stack.push(node.right);
+                stack.push(n + 1);
+            }
+        }
+        closest
+    }
+}
+
+fn build_node(
+    bounds: &[Aabb], centers: &[Vector3<f64>], indices: &mut [usize], start: usize, depth: u32, nodes: &mut Vec<Node>,
+) -> usize {
+    let node = nodes.len();
+    let count = indices.len();
+    let node_bounds = indices.iter().map(|&i| bounds[i]).reduce(|a, b| a.union(&b)).unwrap();
+    nodes.push(Node { bounds: node_bounds, start, count, right: 0 });
+    if count <= MAX_LEAF_SIZE {
+        return node;
+    }
+
+    let centroid_bounds = Aabb::from_points(indices.iter().map(|&i| &centers[i]));
+    let axis = centroid_bounds.diagonal().imax();
+    let (lo, extent) = (centroid_bounds.min[axis], centroid_bounds.diagonal()[axis]);
+    if extent <= 0.0 {
+        return node;
+    }
+    let bin = |i: usize| (((centers[i][axis] - lo) / extent * NUM_BINS as f64) as usize).min(NUM_BINS - 1);
+
+    let mut bins = [(None::<Aabb>, 0usize); NUM_BINS];
+    for &i in indices.iter() {
+        let b = &mut bins[bin(i)];
+        b.0 = Some(b.0.map_or(bounds[i], |a| a.union(&bounds[i])));
+        b.1 += 1;
+    }
+    let cost = |range: &[(Option<Aabb>, usize)]| {
+        let count = range.iter().map(|b| b.1).sum::<usize>();
+        range.iter().filter_map(|b| b.0).reduce(|a, b| a.union(&b))
+            .map_or(0.0, |a| a.surface_area() * count as f64)
+    };
+    let (split, split_cost) = (1..NUM_BINS)
+        .map(|k| (k, cost(&bins[..k]) + cost(&bins[k..])))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .unwrap();
+    if split_cost >= node_bounds.surface_area() * count as f64 {
+        return node;
+    }
+
+    let mid = itertools::partition(indices.iter_mut(), |&i| bin(i) < split);
+    if mid == 0 || mid == count {
+        return node;
+    }
+    nodes[node].count = 0;
+    let (left, right) = indices.split_at_mut(mid);
+    nodes[node].right = if depth > 0 {
+        crossbeam::scope(|s| {
+            let subtree = s.spawn(|_| {
+                let mut subtree = Vec::new();
+                build_node(bounds, centers, right, start + mid, depth - 1, &mut subtree);
+                subtree
+            });
+            build_node(bounds, centers, left, start, depth - 1, nodes);
+            let subtree = subtree.join().unwrap();
+            let offset = nodes.len();
+            nodes.extend(subtree.into_iter().map(|n| match n.count {
+                0 => Node { right: n.right + offset, ..n },
+                _ => n,
+            }));
+            offset
+        }).unwrap()
+    } else {
+        build_node(bounds, centers, left, start, 0, nodes);
+        build_node(bounds, centers, right, start + mid, 0, nodes)
+    };
+    node
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::PathBuf;
+
+use nalgebra::Vector3;
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+use crate::{read_from_file, write_to_file};
+
+type Image = (u32, u32, Vec<Vector3<f64>>);
+
+// A directory of finished renders, keyed by the fingerprint of the scene and the settings, so that editing
+// the settings, the scene file or the files it names renders again.
+pub struct RenderCache {
+    dir: PathBuf,
+    key: String,
+}
+
+impl RenderCache {
+    // None for scenes that have no fingerprint, which can't be told from another with the same objects.
+    pub fn new(dir: impl Into<PathBuf>, scene: &Scene, settings: &RenderSettings) -> Option<Self> {
+        Some(Self { dir: dir.into(), key: scene.fingerprint(settings)? })
+    }
+
+    fn path(&self, extension: &str) -> PathBuf {
+        self.dir.join(&self.key).with_extension(extension)
+    }
+
+    // The cached image and the stats of the render that made it, if there is one.
+    pub fn load(&self) -> Result<Option<(Image, Value)>> {
+        let (image, stats) = (self.path("txt"), self.path("json"));
+        if !image.exists() || !stats.exists() {
+            return Ok(None);
+        }
+        Ok(Some((read_from_file(image.to_str().unwrap())?, serde_json::from_slice(&fs::read(stats)?)?)))
+    }
+
+    pub fn store(&self, image: &Image, stats: &Value) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_to_file(self.path("txt").to_str().unwrap(), image.clone())?;
+        Ok(fs::write(self.path("json"), stats.to_string())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::temp_path;
+
+    #[test]
+    fn stores_and_loads_by_fingerprint() {
+        let dir = temp_path("cache");
+        let description = json!({
+            "camera": { "from": [0, 0, 5], "at": [0, 0, 0] },
+            "materials": { "white": { "type": "lambertian", "albedo": [0.8, 0.8, 0.8] } },
+            "objects": [{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "white" }],
+        });
+        let scene = Scene::from_json(&description, Path::new("")).unwrap();
+        let settings = RenderSettings::default();
+        let cache = RenderCache::new(&dir, &scene, &settings).unwrap();
+        assert!(cache.load().unwrap().is_none());
+        let image = (2, 1, vec![Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0)]);
+        cache.store(&image, &json!({ "passes": 4 })).unwrap();
+        assert_eq!(cache.load().unwrap(), Some((image, json!({ "passes": 4 }))));
+
+        let other = RenderCache::new(&dir, &scene, &RenderSettings { samples: 3, ..settings }).unwrap();
+        assert!(other.load().unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,9 +1,44 @@
+use std::f64::consts::PI;
+use std::iter::Sum;
+use std::ops::{Div, Range};
+
 use nalgebra::Vector3;
+use rand::Rng;
 use rand_distr::{Distribution, UnitDisc};
 
-use crate::ray::Ray;
+use crate::ray::{Differentials, Ray};
 use crate::RNG;
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    // The left eye on the left half of the image.
+    SideBySide,
+    // The left eye on the top half of the image.
+    OverUnder,
+}
+
+// Renders a pair of eyes into one image. Perspective eyes are offset sideways and look at the same focus
+// plane; omnidirectional stereo (ODS) eyes each see the whole sphere in equirectangular projection, from
+// opposite sides of a circle the interocular distance across, as 360° VR video expects.
+#[derive(Clone, Copy)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    // In scene units.
+    pub interocular: f64,
+    pub ods: bool,
+}
+
+// The imperfections of a real lens, none by default. `distortion` holds the radial coefficients k1 and k2:
+// the pixel a distance r from the center, in units of the half diagonal, looks where a perfect lens would
+// show r (1 + k1 r² + k2 r⁴), so positive coefficients give barrel distortion and negative ones pincushion.
+// `dispersion` is how much longer the focal length is for red than for green, and for green than for
+// blue, as a fraction, which both fringes edges towards the corners and focuses the colors apart.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Lens {
+    pub distortion: [f64; 2],
+    pub dispersion: f64,
+}
+
 pub struct Camera {
     horizontal: Vector3<f64>,
     vertical: Vector3<f64>,
@@ -11,7 +46,13 @@ pub struct Camera {
     direction: Vector3<f64>,
     right: Vector3<f64>,
     up: Vector3<f64>,
+    // The scene's up, which tilting the camera doesn't change, for keeping ODS panoramas level.
+    zenith: Vector3<f64>,
     lens_radius: f64,
+    // The part of the image that image coordinates span, from and to in u, then in v.
+    window: [f64; 4],
+    stereo: Option<Stereo>,
+    lens: Lens,
 }
 
 impl Camera {
@@ -30,6 +71,7 @@ impl Camera {
 
         let front = (at - origin).normalize();
         let right = front.cross(up).normalize();
+        let zenith = up.normalize();
         let up = right.cross(&front);
 
         let horizontal = right * focus_plane_width;
@@ -42,14 +84,232 @@ impl Camera {
             direction,
             right,
             up,
+            zenith,
             lens_radius: aperture / 2.0,
+            window: [0.0, 1.0, 0.0, 1.0],
+            stereo: None,
+            lens: Lens::default(),
+        }
+    }
+
+    pub fn lens(self, lens: Lens) -> Self {
+        Self { lens, ..self }
+    }
+
+    // The aspect ratio is then that of one eye.
+    pub fn stereo(self, stereo: Stereo) -> Self {
+        Self { stereo: Some(stereo), ..self }
+    }
+
+    // Narrows the view to the part of the image between `u` and `v`, so the image coordinates span just
+    // that part and its pixels keep the size they had in the whole image.
+    pub fn window(self, u: Range<f64>, v: Range<f64>) -> Self {
+        Self { window: [u.start, u.end, v.start, v.end], ..self }
+    }
+
+    // A point in camera space, right-handed with x to the right, y up and the camera looking down -z.
+    pub fn camera_space(&self, point: &Vector3<f64>) -> Vector3<f64> {
+        let p = point - self.origin;
+        Vector3::new(p.dot(&self.right), p.dot(&self.up), p.dot(&self.right.cross(&self.up)))
+    }
+
+    // Where `point` shows in image coordinates through the center of the lens, if it is in front of the
+    // camera; stereo cameras aren't projected.
+    pub(crate) fn project(&self, point: &Vector3<f64>) -> Option<(f64, f64)> {
+        let d = point - self.origin;
+        let depth = d.dot(&self.direction) / self.direction.norm_squared();
+        if depth <= 0.0 || self.stereo.is_some() {
+            return None;
+        }
+        let u = 0.5 + d.dot(&self.horizontal) / self.horizontal.norm_squared() / depth;
+        let v = 0.5 + d.dot(&self.vertical) / self.vertical.norm_squared() / depth;
+        let [u0, u1, v0, v1] = self.window;
+        Some(((u - u0) / (u1 - u0), (v - v0) / (v1 - v0)))
+    }
+
+    // The camera's position and the corners of its view `distance` in front of it, counterclockwise from
+    // the bottom left.
+    pub(crate) fn frustum(&self, distance: f64) -> (Vector3<f64>, [Vector3<f64>; 4]) {
+        let scale = distance / self.direction.norm();
+        let corner = |u: f64, v: f64| self.origin + (self.direction + self.horizontal * u + self.vertical * v) * scale;
+        (self.origin, [corner(-0.5, -0.5), corner(0.5, -0.5), corner(0.5, 0.5), corner(-0.5, 0.5)])
+    }
+
+    pub fn defocused(&self) -> bool {
+        self.lens_radius > 0.0 && !self.stereo.is_some_and(|s| s.ods)
+    }
+
+    // `du` and `dv` are the size of a pixel in image coordinates, for the ray differentials.
+    pub fn ray_at(&self, u: f64, v: f64, du: f64, dv: f64) -> Ray<f64> {
+        let lens = RNG.with(|r| UnitDisc.sample(&mut *r.borrow_mut()));
+        self.ray_through(u, v, du, dv, lens)
+    }
+
+    // Like `ray_at`, through the point `lens` of the unit disc scaled to the aperture.
+    pub fn ray_through(&self, u: f64, v: f64, du: f64, dv: f64, lens: [f64; 2]) -> Ray<f64> {
+        self.ray_in_focus(u, v, du, dv, lens, 1.0)
+    }
+
+    // A ray for a sample of the image, through a random point of the lens unless given `lens`, with the
+    // throughput it starts with. A dispersing lens bends each color its own way, so every ray is traced
+    // in one channel, picked at random, and counts three times over in it.
+    pub(crate) fn sample_ray(
+        &self, u: f64, v: f64, du: f64, dv: f64, lens: Option<[f64; 2]>,
+    ) -> (Ray<f64>, Vector3<f64>) {
+        let Some(lens) = lens else {
+            let lens = RNG.with(|r| UnitDisc.sample(&mut *r.borrow_mut()));
+            return self.sample_ray(u, v, du, dv, Some(lens));
+        };
+        if self.lens.dispersion == 0.0 {
+            return (self.ray_through(u, v, du, dv, lens), Vector3::repeat(1.0));
+        }
+        let channel = RNG.with(|r| r.borrow_mut().gen_range(0..3));
+        let focal = 1.0 + self.lens.dispersion * (1 - channel as i32) as f64;
+        let mut throughput = Vector3::zeros();
+        throughput[channel] = 3.0;
+        (self.ray_in_focus(u, v, du, dv, lens, focal), throughput)
+    }
+
+    // With the focal length scaled by `focal`.
+    fn ray_in_focus(&self, u: f64, v: f64, du: f64, dv: f64, lens: [f64; 2], focal: f64) -> Ray<f64> {
+        let Some(stereo) = self.stereo else {
+            return self.perspective(u, v, du, dv, lens, Vector3::zeros(), focal, self.window);
+        };
+        let [u0, u1, v0, v1] = self.window;
+        let (u, v, du, dv) = (u0 + u * (u1 - u0), v0 + v * (v1 - v0), du * (u1 - u0), dv * (v1 - v0));
+        // which eye, and where in its half of the image
+        let (left, u, v, du, dv) = match stereo.layout {
+            StereoLayout::SideBySide => (u < 0.5, (2.0 * u).fract(), v, 2.0 * du, dv),
+            StereoLayout::OverUnder => (v >= 0.5, u, (2.0 * v).fract(), du, 2.0 * dv),
+        };
+        let side = if left { -0.5 } else { 0.5 } * stereo.interocular;
+        match stereo.ods {
+            false => self.perspective(u, v, du, dv, lens, self.right * side, focal, [0.0, 1.0, 0.0, 1.0]),
+            true => self.ods(u, v, du, dv, side),
+        }
+    }
+
+    // Through the point (u, v) of the part of the focus plane `window` spans, from `eye` off the center of the
+    // lens. The window's part of the plane is worked out first, as crops always have been, so that they trace
+    // the same rays as ever; distortion is about the center of the whole image.
+    #[allow(clippy::too_many_arguments)]
+    fn perspective(
+        &self, u: f64, v: f64, du: f64, dv: f64, lens: [f64; 2], eye: Vector3<f64>, focal: f64, window: [f64; 4],
+    ) -> Ray<f64> {
+        let [u0, u1, v0, v1] = window;
+        let (width, height) = (u1 - u0, v1 - v0);
+        let (u, v, du, dv) = match self.lens.distortion {
+            [0.0, 0.0] => (u, v, du, dv),
+            [k1, k2] => {
+                let (u, v) = (u0 + u * width, v0 + v * height);
+                let (w, h) = (self.horizontal.norm_squared(), self.vertical.norm_squared());
+                let r2 = ((u - 0.5).powi(2) * w + (v - 0.5).powi(2) * h) / ((w + h) / 4.0);
+                let scale = 1.0 + k1 * r2 + k2 * r2 * r2;
+                let (u, v) = (0.5 + (u - 0.5) * scale, 0.5 + (v - 0.5) * scale);
+                ((u - u0) / width, (v - v0) / height, du * scale, dv * scale)
+            }
+        };
+        let (center, middle) = ((u0 + u1) / 2.0 - 0.5, (v0 + v1) / 2.0 - 0.5);
+        let front = self.direction * focal + self.horizontal * center + self.vertical * middle;
+        let (horizontal, vertical) = (self.horizontal * width, self.vertical * height);
+        let [x, y] = lens;
+        let offset = eye + self.lens_radius * (self.right * x + self.up * y);
+        let direction = front + horizontal * (u - 0.5) + vertical * (v - 0.5) - offset;
+        let norm = direction.norm();
+        let derivative = |d: Vector3<f64>| (d - direction * direction.dot(&d) / (norm * norm)) / norm;
+        Ray::new(self.origin + offset, direction / norm).with_differentials(Differentials {
+            origin_x: Vector3::zeros(),
+            direction_x: derivative(horizontal * du),
+            origin_y: Vector3::zeros(),
+            direction_y: derivative(vertical * dv),
+        })
+    }
+
+    // Longitude runs with u all the way around, starting behind the camera, and latitude with v from
+    // straight down to straight up, level whichever way the camera is tilted. Each ray starts on the
+    // circle where it is tangent, on the eye's side.
+    fn ods(&self, u: f64, v: f64, du: f64, dv: f64, side: f64) -> Ray<f64> {
+        let front = self.zenith.cross(&self.right);
+        let direction = |u: f64, v: f64| {
+            let (longitude, latitude) = ((u - 0.5) * 2.0 * PI, (v - 0.5) * PI);
+            let along = front * longitude.cos() + self.right * longitude.sin();
+            let tangent = self.right * longitude.cos() - front * longitude.sin();
+            (along * latitude.cos() + self.zenith * latitude.sin(), tangent)
+        };
+        let (d, tangent) = direction(u, v.clamp(0.0, 1.0));
+        let differential = |u, v: f64| direction(u, v.clamp(0.0, 1.0)).0 - d;
+        Ray::new(self.origin + tangent * side, d).with_differentials(Differentials {
+            origin_x: Vector3::zeros(),
+            direction_x: differential(u + du, v),
+            origin_y: Vector3::zeros(),
+            direction_y: differential(u, v + dv),
+        })
+    }
+}
+
+// Splits pixel samples into several lens samples each, which pays off where defocus dominates the noise.
+// Every pass traces `average` lens samples per pixel in total, shared out in proportion to how much the
+// lens samples of each pixel have disagreed so far, so sharp regions give up samples to the bokeh.
+pub(crate) struct LensSplitting {
+    average: u32,
+    deviation: Vec<f64>,
+    measured: Vec<u32>,
+}
+
+impl LensSplitting {
+    pub fn new(pixels: usize, average: u32) -> Self {
+        Self { average: average.max(1), deviation: vec![0.0; pixels], measured: vec![0; pixels] }
+    }
+
+    // The number of lens samples for each pixel this pass, all the same until there are measurements.
+    pub fn splits(&self) -> Vec<u32> {
+        let total = self.deviation.iter().sum::<f64>();
+        if self.average == 1 || total == 0.0 {
+            return vec![self.average; self.deviation.len()];
         }
+        let spare = (self.average - 1) as f64 * self.deviation.len() as f64;
+        self.deviation.iter().map(|d| 1 + (spare * d / total).round() as u32).collect()
+    }
+
+    // Averages the lens samples, laid out pixel by pixel as in `splits`, into one value per pixel, and
+    // folds their spread in luminance into the estimates.
+    pub fn gather(&mut self, splits: &[u32], samples: &[Vector3<f64>]) -> Vec<Vector3<f64>> {
+        let mut start = 0;
+        splits.iter().enumerate().map(|(pixel, &k)| {
+            let lens = &samples[start..start + k as usize];
+            start += k as usize;
+            let mean = lens.iter().sum::<Vector3<f64>>() / k as f64;
+            if k > 1 {
+                let luminance = |c: &Vector3<f64>| c.dot(&Vector3::new(0.2126, 0.7152, 0.0722));
+                let variance =
+                    lens.iter().map(|c| (luminance(c) - luminance(&mean)).powi(2)).sum::<f64>() / (k - 1) as f64;
+                let n = self.measured[pixel] as f64;
+                self.deviation[pixel] = (self.deviation[pixel] * n + variance.sqrt()) / (n + 1.0);
+                self.measured[pixel] += 1;
+            }
+            mean
+        }).collect()
     }
 
-    pub fn ray_at(&self, u: f64, v: f64) -> Ray<f64> {
-        let [x, y]: [f64; 2] = RNG.with(|r| UnitDisc.sample(&mut *r.borrow_mut()));
-        let offset = self.lens_radius * (self.right * x + self.up * y);
-        let direction = self.direction + self.horizontal * (u - 0.5) + self.vertical * (v - 0.5);
-        Ray::new(self.origin + offset, (direction - offset).normalize())
+    // Averages values laid out as in `splits`, one per lens sample, into one per pixel, leaving the spread
+    // to `gather`.
+    pub fn mean<T: Copy + Sum + Div<f64, Output = T>>(splits: &[u32], values: &[T]) -> Vec<T> {
+        let mut start = 0;
+        splits.iter().map(|&k| {
+            let lens = &values[start..start + k as usize];
+            start += k as usize;
+            lens.iter().copied().sum::<T>() / k as f64
+        }).collect()
     }
 }
+
+// A point in sector `i` of `k` equal sectors of the unit disc, keeping split lens samples spread out.
+pub(crate) fn lens_stratum(i: u32, k: u32) -> [f64; 2] {
+    let (a, b): (f64, f64) = RNG.with(|r| {
+        let mut r = r.borrow_mut();
+        (r.gen(), r.gen())
+    });
+    let angle = 2.0 * PI * (i as f64 + a) / k as f64;
+    let radius = b.sqrt();
+    [radius * angle.cos(), radius * angle.sin()]
+}
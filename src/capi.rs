@@ -0,0 +1,153 @@
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+use std::slice;
+
+use nalgebra::Vector3;
+
+use crate::builder::SceneBuilder;
+use crate::control::Control;
+use crate::geometry::Sphere;
+use crate::material::{Dielectric, Ggx, Lambertian, Metal};
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+// The C API, with the header generated into include/raytracer.h by cbindgen when building with the
+// `capi` feature. Doc comments here end up in the header.
+
+/// A scene being assembled or loaded, owned by the caller until passed to `rt_scene_free`.
+pub struct RtScene(Scene);
+
+#[repr(C)]
+pub enum RtMaterialKind {
+    Lambertian,
+    Metal,
+    Dielectric,
+    Ggx,
+}
+
+/// `parameter` is the fuzz of a metal, the index of refraction of a dielectric, or the roughness of a
+/// GGX material, and is ignored for Lambertian ones. Dielectrics ignore `color`.
+#[repr(C)]
+pub struct RtMaterial {
+    pub kind: RtMaterialKind,
+    pub color: [f64; 3],
+    pub parameter: f64,
+}
+
+unsafe fn vector(p: *const f64) -> Vector3<f64> {
+    Vector3::from_column_slice(slice::from_raw_parts(p, 3))
+}
+
+/// A new empty scene, with the camera at (0, 0, 1) looking at the origin.
+#[no_mangle]
+pub extern "C" fn rt_scene_new() -> *mut RtScene {
+    Box::into_raw(Box::new(RtScene(SceneBuilder::new().build())))
+}
+
+/// Loads a scene file, returning null if it can't be read.
+///
+/// # Safety
+///
+/// `path` must be a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_load(path: *const c_char) -> *mut RtScene {
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    match Scene::load(Path::new(path), &[]) {
+        Ok(scene) => Box::into_raw(Box::new(RtScene(scene))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `scene` must come from `rt_scene_new` or `rt_scene_load` and not have been freed, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_free(scene: *mut RtScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
+
+/// Adds a sphere made of `material`. Does nothing if `scene` or `material` is null.
+///
+/// # Safety
+///
+/// `scene` must be null or a live scene from `rt_scene_new` or `rt_scene_load`, `material` null or a
+/// valid material, and `center` must point to three doubles.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_sphere(
+    scene: *mut RtScene, center: *const f64, radius: f64, material: *const RtMaterial,
+) {
+    let (Some(scene), Some(material)) = (scene.as_mut(), material.as_ref()) else { return };
+    let sphere = Sphere::new(vector(center), radius);
+    let color = Vector3::from(material.color);
+    scene.0.objects.push(match material.kind {
+        RtMaterialKind::Lambertian => Box::new((sphere, Lambertian::new(color))),
+        RtMaterialKind::Metal => Box::new((sphere, Metal::new(color, material.parameter))),
+        RtMaterialKind::Dielectric => Box::new((sphere, Dielectric::new(material.parameter))),
+        RtMaterialKind::Ggx => Box::new((sphere, Ggx::new(color, material.parameter * material.parameter))),
+    });
+    scene.0.source = None;
+}
+
+/// Points the camera from `from` at `at` with a vertical field of view of `fov` degrees. Does nothing if
+/// `scene` is null.
+///
+/// # Safety
+///
+/// `scene` must be null or a live scene from `rt_scene_new` or `rt_scene_load`, and `from` and `at` must
+/// each point to three doubles.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_camera(scene: *mut RtScene, from: *const f64, at: *const f64, fov: f64) {
+    let Some(scene) = scene.as_mut() else { return };
+    let view = &mut scene.0.view;
+    view.from = vector(from);
+    view.at = vector(at);
+    view.fov = fov;
+    scene.0.source = None;
+}
+
+/// Sets the depth of field; an aperture of zero makes a pinhole camera. Does nothing if `scene` is null.
+///
+/// # Safety
+///
+/// `scene` must be null or a live scene from `rt_scene_new` or `rt_scene_load`.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_lens(scene: *mut RtScene, aperture: f64, focus_distance: f64) {
+    let Some(scene) = scene.as_mut() else { return };
+    scene.0.view.aperture = aperture;
+    scene.0.view.focus_distance = focus_distance;
+    scene.0.source = None;
+}
+
+/// Renders with `samples` passes on each of `threads` threads into `pixels`, which must hold
+/// `width * height * 3` floats: RGB display values in rows from the top. Returns 0 on success, and -1 if
+/// `scene` or `pixels` is null, the image is empty or too large, or the render fails.
+///
+/// # Safety
+///
+/// `scene` must be null or a live scene from `rt_scene_new` or `rt_scene_load`, and `pixels` null or
+/// pointing to at least `width * height * 3` writable floats.
+#[no_mangle]
+pub unsafe extern "C" fn rt_render(
+    scene: *const RtScene, width: u32, height: u32, samples: u32, threads: u32, pixels: *mut f32,
+) -> c_int {
+    let Some(scene) = scene.as_ref() else { return -1 };
+    let len = (width as usize).checked_mul(height as usize).and_then(|n| n.checked_mul(3));
+    let Some(len) = len.filter(|&n| n > 0 && !pixels.is_null()) else { return -1 };
+    let settings = RenderSettings { width, height, samples, threads: threads.max(1), ..Default::default() };
+    let Ok((_, _, buffer)) = crate::render_scene(&scene.0, &settings, &Control::new(&settings)) else {
+        return -1;
+    };
+    let pixels = slice::from_raw_parts_mut(pixels, len);
+    for (k, rgb) in pixels.chunks_mut(3).enumerate() {
+        let (i, j) = (k % width as usize, k / width as usize);
+        let c = buffer[i * height as usize + j];
+        rgb.copy_from_slice(&[c.x as f32, c.y as f32, c.z as f32]);
+    }
+    0
+}
@@ -0,0 +1,383 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nalgebra::Vector3;
+use serde_json::{json, Value};
+
+use crate::firefly::Fireflies;
+use crate::light_paths::{LightPaths, PathLengths, BANDS};
+use crate::settings::RenderSettings;
+use crate::{save_image, Image};
+
+struct Schedule {
+    paused: bool,
+    claimed: u32,
+    target: u32,
+    started: Option<Instant>,
+}
+
+struct Accumulator {
+    sum: Vec<Vector3<f64>>,
+    // How much of each pixel isn't background, weighted like `sum`.
+    alpha: Vec<f64>,
+    // The filter weights summed, one for every pass with the box filter.
+    weights: Vec<f64>,
+    // Camera samples per pixel, which differ between pixels when lens samples are split adaptively.
+    samples: Vec<u32>,
+    passes: u32,
+    rays: u64,
+    node_visits: u64,
+    fireflies: Fireflies,
+    // Summed like `sum` and `samples`, with `settings.light_paths`.
+    light_paths: Option<LightPaths>,
+}
+
+pub struct Stats {
+    pub passes: u32,
+    pub target: u32,
+    pub paused: bool,
+    pub rays: u64,
+    // Only counted with the `stats` feature.
+    pub node_visits: u64,
+    pub pixels: u32,
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    pub fn samples_per_second(&self) -> f64 {
+        self.passes as f64 * self.pixels as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn rays_per_pixel(&self) -> f64 {
+        self.rays as f64 / self.pixels as f64
+    }
+
+    pub fn node_visits_per_ray(&self) -> f64 {
+        self.node_visits as f64 / self.rays as f64
+    }
+
+    // Extrapolated from the average time per completed pass.
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.target.saturating_sub(self.passes);
+        (self.passes > 0).then(|| self.elapsed.mul_f64(remaining as f64 / self.passes as f64))
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut json = json!({
+            "passes": self.passes,
+            "target": self.target,
+            "paused": self.paused,
+            "rays": self.rays,
+            "elapsed": self.elapsed.as_secs_f64(),
+        });
+        if cfg!(feature = "stats") {
+            json["node_visits"] = self.node_visits.into();
+        }
+        json
+    }
+}
+
+type Callback = Box<dyn Fn(&Stats) + Send + Sync>;
+
+// Shared state of a running render. Workers claim one full-image sample pass at a time and add it to the
+// accumulator, so the render can be paused, retargeted, or snapshotted between passes.
+pub struct Control {
+    width: u32,
+    height: u32,
+    // Passes per sample, one for each thread, as `settings.samples` counts them.
+    threads: u32,
+    schedule: Mutex<Schedule>,
+    resumed: Condvar,
+    accumulator: Mutex<Accumulator>,
+    callback: Option<Callback>,
+}
+
+impl Control {
+    pub fn new(settings: &RenderSettings) -> Self {
+        let target = settings.samples * settings.threads;
+        let (width, height) = settings.image_size();
+        Self {
+            width,
+            height,
+            threads: settings.threads,
+            schedule: Mutex::new(Schedule { paused: false, claimed: 0, target, started: None }),
+            resumed: Condvar::new(),
+            accumulator: Mutex::new(Accumulator {
+                sum: vec![Vector3::zeros(); (width * height) as usize],
+                alpha: vec![0.0; (width * height) as usize],
+                weights: vec![0.0; (width * height) as usize],
+                samples: vec![0; (width * height) as usize],
+                passes: 0,
+                rays: 0,
+                node_visits: 0,
+                fireflies: Fireflies::default(),
+                light_paths: settings.light_paths.then(|| LightPaths::new((width * height) as usize)),
+            }),
+            callback: None,
+        }
+    }
+
+    // Called from the worker threads each time a pass completes.
+    pub fn with_callback(self, callback: impl Fn(&Stats) + Send + Sync + 'static) -> Self {
+        Self { callback: Some(Box::new(callback)), ..self }
+    }
+
+    // Returns the index of the claimed pass, or None once the target is reached.
+    pub(crate) fn claim(&self) -> Option<u32> {
+        let mut schedule = self.resumed.wait_while(self.schedule.lock().unwrap(), |s| s.paused).unwrap();
+        if schedule.started.is_none() {
+            schedule.started = now();
+        }
+        let pass = schedule.claimed;
+        if pass >= schedule.target {
+            return None;
+        }
+        schedule.claimed += 1;
+        Some(pass)
+    }
+
+    // `weights` are those of a pass splatted through a pixel filter, and None for one sample to a pixel.
+    pub(crate) fn accumulate(
+        &self, pass: &[Vector3<f64>], alpha: &[f64], weights: Option<&[f64]>, splits: &[u32], rays: u64,
+        node_visits: u64,
+    ) {
+        {
+            let mut accumulator = self.accumulator.lock().unwrap();
+            accumulator.sum.iter_mut().zip(pass).for_each(|(a, b)| *a += b);
+            accumulator.alpha.iter_mut().zip(alpha).for_each(|(a, b)| *a += b);
+            match weights {
+                Some(weights) => accumulator.weights.iter_mut().zip(weights).for_each(|(a, b)| *a += b),
+                None => accumulator.weights.iter_mut().for_each(|a| *a += 1.0),
+            }
+            accumulator.samples.iter_mut().zip(splits).for_each(|(a, b)| *a += b);
+            accumulator.passes += 1;
+            accumulator.rays += rays;
+            accumulator.node_visits += node_visits;
+        }
+        if let Some(callback) = &self.callback {
+            callback(&self.stats());
+        }
+    }
+
+    // Called from the worker threads before accumulating a pass recorded with `settings.fireflies`.
+    pub(crate) fn add_fireflies(&self, fireflies: &Fireflies) {
+        self.accumulator.lock().unwrap().fireflies.merge(fireflies);
+    }
+
+    pub fn fireflies(&self) -> Fireflies {
+        self.accumulator.lock().unwrap().fireflies.clone()
+    }
+
+    // Called from the worker threads before accumulating a pass recorded with `settings.light_paths`, with its
+    // bands already through the pixel filter.
+    pub(crate) fn add_light_paths(&self, pass: &LightPaths) {
+        if let Some(light_paths) = &mut self.accumulator.lock().unwrap().light_paths {
+            for (sum, band) in light_paths.bands.iter_mut().zip(&pass.bands) {
+                sum.iter_mut().zip(band).for_each(|(a, b)| *a += b);
+            }
+            light_paths.lengths.add(&pass.lengths);
+        }
+    }
+
+    // The mean radiance of each pixel so far in each band of bounces, by name, if they were recorded.
+    pub fn bands(&self) -> Vec<(&'static str, Image)> {
+        let accumulator = self.accumulator.lock().unwrap();
+        let bands = accumulator.light_paths.as_ref().map_or(&[][..], |l| &l.bands[..]);
+        BANDS.iter().zip(bands).map(|(&name, band)| {
+            let buffer = band.iter().zip(&accumulator.weights)
+                .map(|(x, &w)| if w > 0.0 { x / w } else { *x })
+                .collect();
+            (name, (self.width, self.height, buffer))
+        }).collect()
+    }
+
+    // How long the paths through each pixel were so far, if that was recorded.
+    pub fn path_lengths(&self) -> Option<PathLengths> {
+        let accumulator = self.accumulator.lock().unwrap();
+        let lengths = &accumulator.light_paths.as_ref()?.lengths;
+        Some(PathLengths::new(self.width, self.height, lengths, &accumulator.samples))
+    }
+
+    pub fn pause(&self) {
+        self.schedule.lock().unwrap().paused = true;
+    }
+
+    pub fn resume(&self) {
+        self.schedule.lock().unwrap().paused = false;
+        self.resumed.notify_all();
+    }
+
+    // Retargets the render to `samples` as `settings.samples` counts them, passes on each thread.
+    pub fn set_samples(&self, samples: u32) {
+        self.schedule.lock().unwrap().target = samples.saturating_mul(self.threads);
+    }
+
+    pub fn stats(&self) -> Stats {
+        let (passes, rays, node_visits) = {
+            let accumulator = self.accumulator.lock().unwrap();
+            (accumulator.passes, accumulator.rays, accumulator.node_visits)
+        };
+        let schedule = self.schedule.lock().unwrap();
+        Stats {
+            passes,
+            target: schedule.target,
+            paused: schedule.paused,
+            rays,
+            node_visits,
+            pixels: self.width * self.height,
+            elapsed: schedule.started.map(|s| s.elapsed()).unwrap_or_default(),
+        }
+    }
+
+    pub fn image(&self) -> (u32, u32, Vec<Vector3<f64>>) {
+        to_display(self.linear_image())
+    }
+
+    // The mean radiance of each pixel so far, weighted by the pixel filter.
+    pub fn linear_image(&self) -> (u32, u32, Vec<Vector3<f64>>) {
+        let accumulator = self.accumulator.lock().unwrap();
+        let buffer = accumulator.sum.iter().zip(&accumulator.weights)
+            .map(|(x, &w)| if w > 0.0 { x / w } else { *x })
+            .collect();
+        (self.width, self.height, buffer)
+    }
+
+    // How much of each pixel objects cover so far, 0 where the camera sees only background. Shadow catchers
+    // cover as much of it as they are in shadow.
+    pub fn alpha(&self) -> (u32, u32, Vec<f64>) {
+        let accumulator = self.accumulator.lock().unwrap();
+        let alpha = accumulator.alpha.iter().zip(&accumulator.weights)
+            .map(|(&a, &w)| if w > 0.0 { (a / w).clamp(0.0, 1.0) } else { a })
+            .collect();
+        (self.width, self.height, alpha)
+    }
+
+    // The number of camera samples each pixel has received so far.
+    pub fn sample_counts(&self) -> (u32, u32, Vec<u32>) {
+        (self.width, self.height, self.accumulator.lock().unwrap().samples.clone())
+    }
+
+    // The alpha channel as a grey image, white where objects cover the pixel.
+    pub fn alpha_matte(&self) -> (u32, u32, Vec<Vector3<f64>>) {
+        let (width, height, alpha) = self.alpha();
+        (width, height, alpha.into_iter().map(Vector3::repeat).collect())
+    }
+
+    // The sample counts as colors from black through red and yellow to white, relative to the most
+    // sampled pixel.
+    pub fn sample_heatmap(&self) -> (u32, u32, Vec<Vector3<f64>>) {
+        let (width, height, counts) = self.sample_counts();
+        let most = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+        (width, height, counts.iter().map(|&n| heat(n as f64 / most)).collect())
+    }
+}
+
+pub(crate) fn heat(x: f64) -> Vector3<f64> {
+    let x = 3.0 * x.clamp(0.0, 1.0);
+    Vector3::new(x.min(1.0), (x - 1.0).clamp(0.0, 1.0), (x - 2.0).clamp(0.0, 1.0))
+}
+
+// The built-in display transform, a gamma of 2.
+pub(crate) fn to_display(image: (u32, u32, Vec<Vector3<f64>>)) -> (u32, u32, Vec<Vector3<f64>>) {
+    let (width, height, buffer) = image;
+    (width, height, buffer.into_iter().map(|x| x.map(f64::sqrt)).collect())
+}
+
+// std has no clock on wasm32 without JavaScript bindings, so renders there report no elapsed time.
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now() -> Option<Instant> {
+    None
+}
+
+// Writes the image so far to `path` every `interval` from a background thread, for keeping an eye on
+// headless renders, with `<path>.json` saying how many passes it has and the `fingerprint` of the scene and
+// settings it belongs to, null for scenes that have none. Each snapshot is written next to `path` first and
+// renamed over it, so readers never see a partial file.
+pub fn snapshot_every(control: Arc<Control>, path: String, interval: Duration, fingerprint: Option<String>) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        // counted first, so the image has at least as many passes as it says
+        let passes = control.stats().passes;
+        let record = json!({ "fingerprint": fingerprint, "passes": passes }).to_string();
+        let path = Path::new(&path);
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let partial = path.with_file_name(format!(".partial-{}", name));
+        let saved = save_image(partial.to_str().unwrap(), control.image())
+            .and_then(|_| Ok(fs::rename(&partial, path)?))
+            .and_then(|_| Ok(fs::write(path.with_extension("json"), &record)?));
+        if let Err(e) = saved {
+            eprintln!("failed to write snapshot {}: {}", path.display(), e);
+        }
+    });
+}
+
+// Serves line-delimited JSON-RPC 2.0 requests on `address` from a background thread. Methods are
+// `pause`, `resume`, `set_samples` (`{"samples": n}`), `snapshot` (`{"path": p}`), and `progress`. Fails
+// if the address can't be listened on, as when the port is taken.
+pub fn serve(address: impl ToSocketAddrs, control: Arc<Control>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let control = control.clone();
+            thread::spawn(move || handle(stream, &control));
+        }
+    });
+    Ok(())
+}
+
+fn handle(stream: TcpStream, control: &Control) {
+    let mut writer = stream.try_clone().unwrap();
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => {
+                let id = request["id"].clone();
+                match call(control, request["method"].as_str().unwrap_or_default(), &request["params"]) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+                    Err((code, message)) => {
+                        json!({ "jsonrpc": "2.0", "error": { "code": code, "message": message }, "id": id })
+                    }
+                }
+            }
+            Err(_) => json!({ "jsonrpc": "2.0", "error": { "code": -32700, "message": "Parse error" }, "id": null }),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            break;
+        }
+    }
+}
+
+fn call(control: &Control, method: &str, params: &Value) -> Result<Value, (i32, &'static str)> {
+    let invalid = (-32602, "Invalid params");
+    match method {
+        "pause" => control.pause(),
+        "resume" => control.resume(),
+        "set_samples" => {
+            let samples = params["samples"].as_u64().and_then(|n| u32::try_from(n).ok()).ok_or(invalid)?;
+            control.set_samples(samples)
+        }
+        "snapshot" => save_image(params["path"].as_str().ok_or(invalid)?, control.image())
+            .map_err(|_| (-32000, "Snapshot failed"))?,
+        "progress" => return Ok(control.stats().to_json()),
+        _ => return Err((-32601, "Method not found")),
+    }
+    Ok(Value::Null)
+}
@@ -0,0 +1,39 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+use crate::mesh::Winding;
+
+// Counters for the statistics gathered with the `stats` feature. They are kept per thread and collected
+// by the workers after each pass, so counting needs no synchronization; without the feature nothing is counted.
+thread_local! {
+    static NODE_VISITS: Cell<u64> = const { Cell::new(0) };
+    // Counted regardless of the feature, for the scene summary; scenes are loaded on a single thread.
+    static TEXTURE_BYTES: Cell<u64> = const { Cell::new(0) };
+    static MISWOUND: RefCell<BTreeMap<String, Winding>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+#[inline]
+pub(crate) fn visit_node() {
+    #[cfg(feature = "stats")]
+    NODE_VISITS.with(|n| n.set(n.get() + 1));
+}
+
+pub(crate) fn take_node_visits() -> u64 {
+    NODE_VISITS.with(|n| n.replace(0))
+}
+
+pub(crate) fn load_texture(bytes: u64) {
+    TEXTURE_BYTES.with(|n| n.set(n.get() + bytes));
+}
+
+pub(crate) fn take_texture_bytes() -> u64 {
+    TEXTURE_BYTES.with(|n| n.replace(0))
+}
+
+pub(crate) fn miswound(path: &str, winding: Winding) {
+    MISWOUND.with(|m| m.borrow_mut().insert(path.to_owned(), winding));
+}
+
+pub(crate) fn take_miswound() -> BTreeMap<String, Winding> {
+    MISWOUND.with(|m| m.take())
+}
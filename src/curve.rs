@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+use std::mem::size_of_val;
+use std::ops::Range;
+
+use nalgebra::{Vector2, Vector3};
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::material::Material;
+use crate::object::{unmasked, Intersection, Object};
+use crate::ray::Ray;
+
+const MAX_SPLIT_DEPTH: u32 = 8;
+
+struct Segment {
+    a: Vector3<f64>,
+    b: Vector3<f64>,
+    radius: f64,
+    params: (f64, f64),
+}
+
+impl Segment {
+    fn bounds(&self) -> Aabb {
+        let r = Vector3::repeat(self.radius);
+        Aabb::new(self.a.inf(&self.b) - r, self.a.sup(&self.b) + r)
+    }
+
+    // Only rays entering the capsule from outside hit it, and entries within a strand diameter of the
+    // origin are rays leaving this or a neighboring segment, so transmitted rays pass through.
+    fn intersect(&self, ray: &Ray<f64>, range: &Range<f64>) -> Option<f64> {
+        let length = ray.direction().norm();
+        let rd = ray.direction() / length;
+        let ba = self.b - self.a;
+        let oa = ray.origin - self.a;
+        let (baba, bard, baoa) = (ba.dot(&ba), ba.dot(&rd), ba.dot(&oa));
+        let (rdoa, oaoa) = (rd.dot(&oa), oa.dot(&oa));
+        let r2 = self.radius * self.radius;
+        let a = baba - bard * bard;
+        let b = baba * rdoa - baoa * bard;
+        let c = baba * oaoa - baoa * baoa - r2 * baba;
+        let h = b * b - a * c;
+        if h < 0.0 {
+            return None;
+        }
+        let t = (-b - h.sqrt()) / a;
+        let y = baoa + t * bard;
+        let t = if y > 0.0 && y < baba {
+            t
+        } else {
+            let oc = if y <= 0.0 { oa } else { ray.origin - self.b };
+            let b = rd.dot(&oc);
+            let h = b * b - (oc.dot(&oc) - r2);
+            if h <= 0.0 {
+                return None;
+            }
+            -b - h.sqrt()
+        };
+        Some(t).filter(|t| *t > self.radius * 2.0).map(|t| t / length).filter(|t| range.contains(t))
+    }
+
+    fn closest(&self, point: &Vector3<f64>) -> f64 {
+        let ba = self.b - self.a;
+        ((point - self.a).dot(&ba) / ba.norm_squared()).clamp(0.0, 1.0)
+    }
+}
+
+pub struct Curves {
+    segments: Vec<Segment>,
+    bvh: Bvh,
+}
+
+impl Curves {
+    // Each curve is a cubic Bézier with a radius at its root and its tip. Curves are split adaptively
+    // into capsule segments until flat relative to their radius, so every segment has tight bounds.
+    pub fn bezier(curves: &[([Vector3<f64>; 4], f64, f64)]) -> Self {
+        let mut segments = Vec::new();
+        for (points, root, tip) in curves {
+            split(points, (0.0, 1.0), (*root, *tip), 0, &mut segments);
+        }
+        let bounds = segments.iter().map(Segment::bounds).collect::<Vec<_>>();
+        let bvh = Bvh::build(&bounds);
+        Self { segments, bvh }
+    }
+
+    pub fn catmull_rom(strands: &[Vec<Vector3<f64>>], root: f64, tip: f64) -> Self {
+        let curves = strands.iter().flat_map(|p| {
+            let n = p.len();
+            (0..n.saturating_sub(1)).map(move |i| {
+                let prev = p[i.saturating_sub(1)];
+                let next = p[(i + 2).min(n - 1)];
+                let control = [p[i], p[i] + (p[i + 1] - prev) / 6.0, p[i + 1] - (next - p[i]) / 6.0, p[i + 1]];
+                let lerp = |k: usize| root + (tip - root) * k as f64 / (n - 1) as f64;
+                (control, lerp(i), lerp(i + 1))
+            })
+        }).collect::<Vec<_>>();
+        Self::bezier(&curves)
+    }
+
+    pub fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<(f64, usize)> {
+        self.bvh.intersect(ray, range, |s, range| self.segments[s].intersect(ray, &range).map(|t| (t, s)))
+    }
+
+    pub fn normal(&self, point: &Vector3<f64>, segment: usize) -> Vector3<f64> {
+        let s = &self.segments[segment];
+        let h = s.closest(point);
+        (point - (s.a + (s.b - s.a) * h)).normalize()
+    }
+
+    pub fn tangent(&self, segment: usize) -> Vector3<f64> {
+        let s = &self.segments[segment];
+        (s.b - s.a).normalize()
+    }
+
+    pub fn uv(&self, point: &Vector3<f64>, segment: usize) -> Vector2<f64> {
+        let s = &self.segments[segment];
+        let h = s.closest(point);
+        Vector2::new(s.params.0 + (s.params.1 - s.params.0) * h, 0.0)
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.segments.iter().map(Segment::bounds).reduce(|a, b| a.union(&b))
+    }
+}
+
+fn split(points: &[Vector3<f64>; 4], params: (f64, f64), radii: (f64, f64), depth: u32, segments: &mut Vec<Segment>) {
+    let [p0, p1, p2, p3] = *points;
+    let chord = p3 - p0;
+    let distance = |p: Vector3<f64>| {
+        let h = match chord.norm_squared() > 0.0 {
+            true => ((p - p0).dot(&chord) / chord.norm_squared()).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+        (p - p0 - chord * h).norm()
+    };
+    let radius = (radii.0 + radii.1) / 2.0;
+    if depth >= MAX_SPLIT_DEPTH || distance(p1).max(distance(p2)) < radius * 0.5 {
+        segments.push(Segment { a: p0, b: p3, radius, params });
+        return;
+    }
+    let (p01, p12, p23) = ((p0 + p1) / 2.0, (p1 + p2) / 2.0, (p2 + p3) / 2.0);
+    let (p012, p123) = ((p01 + p12) / 2.0, (p12 + p23) / 2.0);
+    let mid = (p012 + p123) / 2.0;
+    let t = (params.0 + params.1) / 2.0;
+    let r = (radii.0 + radii.1) / 2.0;
+    split(&[p0, p01, p012, mid], (params.0, t), (radii.0, r), depth + 1, segments);
+    split(&[mid, p123, p23, p3], (t, params.1), (r, radii.1), depth + 1, segments);
+}
+
+impl<M: Material> Object for (Curves, M) {
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection<'_>> {
+        let hit = |range| self.0.intersect(ray, range).map(|(t, s)| Intersection::new(t, ray, self, s));
+        unmasked(range, hit, |int| self.1.masked(int))
+    }
+
+    fn normal(&self, point: &Vector3<f64>, index: usize) -> Vector3<f64> {
+        self.0.normal(point, index)
+    }
+
+    fn uv(&self, point: &Vector3<f64>, index: usize) -> Vector2<f64> {
+        self.0.uv(point, index)
+    }
+
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        self.1.scatter(int)
+    }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        self.1.scatter_lobe(int)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        self.1.eval(int, wi)
+    }
+
+    fn specular(&self, _index: usize) -> bool {
+        self.1.specular()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.0.bounds()
+    }
+
+    fn specular_bounds(&self) -> Option<Aabb> {
+        self.0.bounds().filter(|_| self.1.specular())
+    }
+
+    fn bvh_bounds(&self, depth: usize) -> Vec<(usize, Aabb)> {
+        self.0.bvh.node_bounds(depth)
+    }
+
+    fn primitives(&self) -> usize {
+        self.0.segments.len()
+    }
+
+    fn memory(&self, _shared: &mut HashSet<usize>) -> usize {
+        size_of_val(self.0.segments.as_slice()) + self.0.bvh.memory()
+    }
+
+    fn tangent(&self, _point: &Vector3<f64>, index: usize) -> Option<Vector3<f64>> {
+        Some(self.0.tangent(index))
+    }
+
+    fn emitted(&self, int: &Intersection) -> Vector3<f64> {
+        if int.front() { self.1.emission() } else { Vector3::zeros() }
+    }
+}
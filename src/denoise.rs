@@ -0,0 +1,67 @@
+use nalgebra::Vector3;
+
+// Pixels on each side of the center that the filter reaches.
+const RADIUS: i64 = 2;
+const SPATIAL_SIGMA: f64 = 1.5;
+// How different two colors may be, after tone mapping, before they stop smoothing each other.
+const RANGE_SIGMA: f64 = 0.1;
+
+// Smooths the noise left by few samples while keeping edges: a bilateral filter over the 5×5 pixels around
+// each one, weighing neighbours down by distance and by how different their color is. Colors are compared
+// tone mapped, so bright and dark regions are smoothed alike, but the image keeps its radiance. Fine texture
+// goes with the noise, so it suits drafts more than finals.
+pub(crate) fn denoise((width, height, buffer): (u32, u32, Vec<Vector3<f64>>)) -> (u32, u32, Vec<Vector3<f64>>) {
+    let index = |i: i64, j: i64| (i * height as i64 + j) as usize;
+    let mapped = buffer.iter().map(|c| c.map(|x| x.max(0.0) / (1.0 + x.max(0.0)))).collect::<Vec<_>>();
+    let smoothed = (0..width as i64).flat_map(|i| (0..height as i64).map(move |j| (i, j))).map(|(i, j)| {
+        let center = mapped[index(i, j)];
+        let (mut sum, mut total) = (Vector3::zeros(), 0.0);
+        for di in -RADIUS..=RADIUS {
+            for dj in -RADIUS..=RADIUS {
+                let (x, y) = (i + di, j + dj);
+                if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                    continue;
+                }
+                let spatial = (di * di + dj * dj) as f64 / (2.0 * SPATIAL_SIGMA * SPATIAL_SIGMA);
+                let range = (mapped[index(x, y)] - center).norm_squared() / (2.0 * RANGE_SIGMA * RANGE_SIGMA);
+                let weight = (-spatial - range).exp();
+                sum += buffer[index(x, y)] * weight;
+                total += weight;
+            }
+        }
+        sum / total
+    }).collect();
+    (width, height, smoothed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variance(values: &[Vector3<f64>]) -> f64 {
+        let mean = values.iter().sum::<Vector3<f64>>() / values.len() as f64;
+        values.iter().map(|c| (c - mean).norm_squared()).sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn smooths_noise() {
+        // a checkerboard of grey levels a little apart, as noise about a flat grey
+        let buffer = (0..64).map(|k| Vector3::repeat(if (k / 8 + k % 8) % 2 == 0 { 0.45 } else { 0.55 })).collect();
+        let (_, _, smoothed) = denoise((8, 8, buffer));
+        assert!(variance(&smoothed) < 0.1 * 0.0025, "variance {}", variance(&smoothed));
+    }
+
+    #[test]
+    fn keeps_edges() {
+        // black on the left half, white on the right
+        let buffer = (0..64).map(|k| Vector3::repeat(if k / 8 < 4 { 0.0 } else { 1.0 })).collect();
+        let (_, _, smoothed) = denoise((8, 8, buffer));
+        assert!(smoothed[3 * 8].x < 0.01 && smoothed[4 * 8].x > 0.99, "{:?}", (smoothed[3 * 8], smoothed[4 * 8]));
+    }
+
+    #[test]
+    fn keeps_flat_radiance() {
+        let (_, _, smoothed) = denoise((4, 4, vec![Vector3::new(3.0, 2.0, 1.0); 16]));
+        assert!(smoothed.iter().all(|c| (c - Vector3::new(3.0, 2.0, 1.0)).amax() < 1e-12));
+    }
+}
@@ -0,0 +1,62 @@
+use itertools::iproduct;
+use nalgebra::Vector3;
+
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+use crate::{closest_hit, frame_camera};
+
+// How far a hit is from the camera. `Distance` is measured along the ray from the camera's center;
+// `Planar` is the depth along the view axis, the z a rasterizer's depth buffer holds before projection.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum DepthMode {
+    Distance,
+    #[default]
+    Planar,
+}
+
+// Where the ray through the center of each pixel first hits, in camera space, for compositing with
+// rasterized images. Camera space is right-handed with x to the right, y up and the camera looking
+// down -z, as in OpenGL.
+pub struct DepthPass {
+    width: u32,
+    height: u32,
+    positions: Vec<Option<Vector3<f64>>>,
+}
+
+impl DepthPass {
+    pub fn new(scene: &Scene, settings: &RenderSettings) -> Self {
+        let (width, height) = settings.image_size();
+        let camera = frame_camera(&scene.view, settings);
+        let positions = iproduct!(0..width, 0..height).map(|(i, j)| {
+            let u = (i as f64 + 0.5) / width as f64;
+            let v = 1.0 - (j as f64 + 0.5) / height as f64;
+            let ray = camera.ray_through(u, v, 1.0 / width as f64, 1.0 / height as f64, [0.0, 0.0]);
+            closest_hit(&scene.objects, &ray).map(|i| camera.camera_space(i.point()))
+        }).collect();
+        Self { width, height, positions }
+    }
+
+    // The depth in every channel. With a `(near, far)` range it is mapped linearly to 0 at near and 1 at
+    // far and clamped, and pixels that see the sky are 1; without one it is in scene units, and the sky
+    // is infinitely far.
+    pub fn depth(&self, mode: DepthMode, range: Option<(f64, f64)>) -> (u32, u32, Vec<Vector3<f64>>) {
+        let buffer = self.positions.iter().map(|p| {
+            let depth = p.map_or(f64::INFINITY, |p| match mode {
+                DepthMode::Distance => p.norm(),
+                DepthMode::Planar => -p.z,
+            });
+            let depth = match range {
+                Some((near, far)) => ((depth - near) / (far - near)).clamp(0.0, 1.0),
+                None => depth,
+            };
+            Vector3::repeat(depth)
+        }).collect();
+        (self.width, self.height, buffer)
+    }
+
+    // The camera-space position in the channels, and zero where the pixel sees the sky.
+    pub fn position(&self) -> (u32, u32, Vec<Vector3<f64>>) {
+        let buffer = self.positions.iter().map(|p| p.unwrap_or_else(Vector3::zeros)).collect();
+        (self.width, self.height, buffer)
+    }
+}
@@ -0,0 +1,132 @@
+use std::ffi::c_void;
+use std::ops::Range;
+use std::ptr;
+
+use nalgebra::Vector3;
+
+use crate::ray::Ray;
+
+// The few entry points of the Embree 3 C API needed to build a triangle BVH and trace single rays.
+type Device = *mut c_void;
+type Scene = *mut c_void;
+type Geometry = *mut c_void;
+
+const GEOMETRY_TYPE_TRIANGLE: u32 = 0;
+const BUFFER_TYPE_INDEX: u32 = 0;
+const BUFFER_TYPE_VERTEX: u32 = 1;
+const FORMAT_UINT3: u32 = 0x5003;
+const FORMAT_FLOAT3: u32 = 0x9003;
+const INVALID_GEOMETRY_ID: u32 = u32::MAX;
+
+#[repr(C)]
+struct IntersectContext {
+    flags: u32,
+    filter: Option<unsafe extern "C" fn(*const c_void)>,
+    inst_id: [u32; 1],
+}
+
+#[repr(C, align(16))]
+struct RayHit {
+    org: [f32; 3],
+    tnear: f32,
+    dir: [f32; 3],
+    time: f32,
+    tfar: f32,
+    mask: u32,
+    id: u32,
+    flags: u32,
+    ng: [f32; 3],
+    u: f32,
+    v: f32,
+    prim_id: u32,
+    geom_id: u32,
+    inst_id: [u32; 1],
+}
+
+#[link(name = "embree3")]
+extern "C" {
+    fn rtcNewDevice(config: *const i8) -> Device;
+    fn rtcReleaseDevice(device: Device);
+    fn rtcNewScene(device: Device) -> Scene;
+    fn rtcCommitScene(scene: Scene);
+    fn rtcReleaseScene(scene: Scene);
+    fn rtcNewGeometry(device: Device, kind: u32) -> Geometry;
+    fn rtcSetNewGeometryBuffer(
+        geometry: Geometry, kind: u32, slot: u32, format: u32, stride: usize, count: usize,
+    ) -> *mut c_void;
+    fn rtcCommitGeometry(geometry: Geometry);
+    fn rtcAttachGeometry(scene: Scene, geometry: Geometry) -> u32;
+    fn rtcReleaseGeometry(geometry: Geometry);
+    fn rtcIntersect1(scene: Scene, context: *mut IntersectContext, rayhit: *mut RayHit);
+}
+
+// A triangle mesh handed to Embree, which builds its own BVH over it. Embree works in single precision,
+// so hits are only good enough to pick the face; the caller should recompute t in double precision.
+pub(crate) struct TriangleScene {
+    device: Device,
+    scene: Scene,
+}
+
+// Committed Embree scenes can be traced from any number of threads at once.
+unsafe impl Send for TriangleScene {}
+unsafe impl Sync for TriangleScene {}
+
+impl TriangleScene {
+    pub fn new(vertices: &[Vector3<f64>], faces: &[[usize; 3]]) -> Self {
+        unsafe {
+            let device = rtcNewDevice(ptr::null());
+            assert!(!device.is_null(), "failed to create an Embree device");
+            let scene = rtcNewScene(device);
+            let geometry = rtcNewGeometry(device, GEOMETRY_TYPE_TRIANGLE);
+            // Embree reads 16 bytes at the last vertex, so the vertex buffer gets one float of padding
+            let buffer =
+                rtcSetNewGeometryBuffer(geometry, BUFFER_TYPE_VERTEX, 0, FORMAT_FLOAT3, 12, vertices.len() + 1);
+            let buffer = std::slice::from_raw_parts_mut(buffer as *mut [f32; 3], vertices.len());
+            buffer.iter_mut().zip(vertices).for_each(|(b, v)| *b = [v.x as f32, v.y as f32, v.z as f32]);
+            let buffer = rtcSetNewGeometryBuffer(geometry, BUFFER_TYPE_INDEX, 0, FORMAT_UINT3, 12, faces.len());
+            let buffer = std::slice::from_raw_parts_mut(buffer as *mut [u32; 3], faces.len());
+            buffer.iter_mut().zip(faces).for_each(|(b, f)| *b = f.map(|i| i as u32));
+            rtcCommitGeometry(geometry);
+            rtcAttachGeometry(scene, geometry);
+            rtcReleaseGeometry(geometry);
+            rtcCommitScene(scene);
+            Self { device, scene }
+        }
+    }
+
+    // The closest face hit within `range`, with its approximate t.
+    pub fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<(f64, usize)> {
+        let (o, d) = (ray.origin, ray.direction());
+        let mut context = IntersectContext { flags: 0, filter: None, inst_id: [INVALID_GEOMETRY_ID] };
+        let mut rayhit = RayHit {
+            org: [o.x as f32, o.y as f32, o.z as f32],
+            tnear: range.start as f32,
+            dir: [d.x as f32, d.y as f32, d.z as f32],
+            time: 0.0,
+            tfar: range.end as f32,
+            mask: u32::MAX,
+            id: 0,
+            flags: 0,
+            ng: [0.0; 3],
+            u: 0.0,
+            v: 0.0,
+            prim_id: INVALID_GEOMETRY_ID,
+            geom_id: INVALID_GEOMETRY_ID,
+            inst_id: [INVALID_GEOMETRY_ID],
+        };
+        unsafe { rtcIntersect1(self.scene, &mut context, &mut rayhit) };
+        match rayhit.geom_id {
+            INVALID_GEOMETRY_ID => None,
+            _ => Some((rayhit.tfar as f64, rayhit.prim_id as usize)),
+        }
+    }
+}
+
+impl Drop for TriangleScene {
+    fn drop(&mut self) {
+        unsafe {
+            rtcReleaseScene(self.scene);
+            rtcReleaseDevice(self.device);
+        }
+    }
+}
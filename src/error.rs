@@ -0,0 +1,35 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Image(#[from] image::ImageError),
+    #[error("invalid scene: {0}")]
+    Scene(#[from] serde_json::Error),
+    #[error("invalid scene: {0}")]
+    Description(String),
+    #[error("invalid image file: {0}")]
+    ImageFile(String),
+    #[error("invalid mesh: {0}")]
+    Mesh(String),
+    #[error("invalid material: {0}")]
+    Material(String),
+    #[error("invalid volume: {0}")]
+    Volume(String),
+    #[error("invalid LUT: {0}")]
+    Lut(String),
+    #[error("SDL: {0}")]
+    Sdl(String),
+    #[error("OpenColorIO: {0}")]
+    Ocio(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// SDL reports most failures as strings, and the rest as assorted error types that display as such.
+#[cfg(feature = "sdl2")]
+pub(crate) fn sdl<T, E: ToString>(result: std::result::Result<T, E>) -> Result<T> {
+    result.map_err(|e| Error::Sdl(e.to_string()))
+}
@@ -0,0 +1,71 @@
+use std::mem::size_of;
+use std::thread;
+use std::time::Duration;
+
+use nalgebra::Vector3;
+
+use crate::control::Control;
+use crate::render_scene;
+use crate::scene::Scene;
+use crate::settings::{Crop, RenderSettings};
+
+// The most pixels the probe pass traces; smaller renders are probed at full size.
+const PROBE_PIXELS: f64 = 4096.0;
+
+// What a render is expected to take, extrapolated from one pass over a downscaled image.
+pub struct Estimate {
+    pub probe: Duration,
+    pub duration: Duration,
+    // Resident memory with the scene loaded, where the OS reports it.
+    pub resident: Option<u64>,
+    // The accumulation and per-thread pass buffers the render will add.
+    pub buffers: u64,
+}
+
+impl Estimate {
+    // The time assumes the threads scale linearly up to the number of cores. Fixed costs such as emitting
+    // photons are scaled up along with the pass, so it errs on the long side for photon mapping.
+    pub fn new(scene: &Scene, settings: &RenderSettings) -> Self {
+        let (width, height) = settings.image_size();
+        let pixels = width as f64 * height as f64;
+        let scale = (PROBE_PIXELS / pixels).sqrt().min(1.0);
+        // the crop window scales with the frame, so the probe sees the same part of it
+        let (fw, fh) = (settings.width as f64, settings.height as f64);
+        let crop = settings.crop_window().map(|(x, y, width, height)| Crop::Normalized {
+            x: x as f64 / fw, y: y as f64 / fh, width: width as f64 / fw, height: height as f64 / fh,
+        });
+        let probe = RenderSettings {
+            width: ((fw * scale).round() as u32).max(1),
+            height: ((fh * scale).round() as u32).max(1),
+            samples: 1,
+            threads: 1,
+            crop,
+            backplate: None,
+            ..settings.clone()
+        };
+        let control = Control::new(&probe);
+        // without a backplate there's nothing to read, so the probe can't fail
+        let _ = render_scene(scene, &probe, &control);
+        let elapsed = control.stats().elapsed;
+        let (probe_width, probe_height) = probe.image_size();
+        let per_pass = elapsed.mul_f64(pixels / (probe_width * probe_height) as f64);
+        let pixels = pixels as u64;
+        let buffers = pixels * (size_of::<Vector3<f64>>() as u64 * (1 + settings.threads as u64) + 4);
+        let cores = thread::available_parallelism().map_or(1, |n| n.get() as u32);
+        let duration = per_pass * settings.samples * settings.threads / settings.threads.clamp(1, cores);
+        Self { probe: elapsed, duration, resident: resident(), buffers }
+    }
+}
+
+// statm counts pages, which are 4 KiB on the platforms we run on.
+#[cfg(target_os = "linux")]
+fn resident() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(pages * 4096)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident() -> Option<u64> {
+    None
+}
@@ -0,0 +1,74 @@
+use std::f64::consts::PI;
+use std::ops::{AddAssign, Mul};
+
+// How the samples of a pass are weighted into the pixels around them. `Box` counts each sample in its own
+// pixel only, as uniform jitter over the pixel amounts to; the others spread it over every pixel within
+// their radius, weighted by the distance to its center, which trades a little sharpness for less aliasing
+// along high-contrast edges. Of those the tent is the narrowest, the Gaussian the softest and
+// Blackman-Harris the sharpest for its width.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PixelFilter {
+    Box,
+    Tent,
+    Gaussian,
+    BlackmanHarris,
+}
+
+impl PixelFilter {
+    // How far from a pixel's center samples count toward it, in pixels.
+    pub fn radius(self) -> f64 {
+        match self {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent => 1.0,
+            PixelFilter::Gaussian => 1.5,
+            PixelFilter::BlackmanHarris => 2.0,
+        }
+    }
+
+    // The weight of a sample `x` pixels off a pixel's center along one axis; the filters are separable.
+    pub(crate) fn weight(self, x: f64) -> f64 {
+        let (x, radius) = (x.abs(), self.radius());
+        if x >= radius {
+            return 0.0;
+        }
+        // the Gaussian has a standard deviation of half a pixel, shifted down to reach 0 at the radius
+        let gaussian = |x: f64| (-2.0 * x * x).exp();
+        match self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent => 1.0 - x / radius,
+            PixelFilter::Gaussian => gaussian(x) - gaussian(radius),
+            PixelFilter::BlackmanHarris => {
+                let t = 2.0 * PI * (0.5 + x / (2.0 * radius));
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+
+    // Spreads a pass, one sample to a pixel at the offset within it `offsets` gives, over the pixels
+    // around, into weighted sums and the weights summed, pixel by pixel. The samples are colors, or alpha.
+    pub(crate) fn splat<T: Copy + Default + AddAssign + Mul<f64, Output = T>>(
+        self, width: u32, height: u32, samples: &[T], offsets: &[(f64, f64)],
+    ) -> (Vec<T>, Vec<f64>) {
+        let mut sums = vec![T::default(); samples.len()];
+        let mut weights = vec![0.0; samples.len()];
+        let reach = self.radius().ceil() as i64;
+        for (pixel, (sample, &(x, y))) in samples.iter().zip(offsets).enumerate() {
+            let (i, j) = ((pixel as u32 / height) as i64, (pixel as u32 % height) as i64);
+            // pixel centers are at whole coordinates, and samples within half a pixel of them
+            let (px, py) = (i as f64 + x - 0.5, j as f64 + y - 0.5);
+            for ni in (i - reach).max(0)..=(i + reach).min(width as i64 - 1) {
+                let wx = self.weight(px - ni as f64);
+                if wx == 0.0 {
+                    continue;
+                }
+                for nj in (j - reach).max(0)..=(j + reach).min(height as i64 - 1) {
+                    let weight = wx * self.weight(py - nj as f64);
+                    let neighbor = (ni * height as i64 + nj) as usize;
+                    sums[neighbor] += *sample * weight;
+                    weights[neighbor] += weight;
+                }
+            }
+        }
+        (sums, weights)
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::sidecar::fnv1a;
+
+// A hash of a scene description and the files it names (meshes, textures, volumes), so that editing any of
+// them changes it, along with the files those name in turn: an OBJ's MTL libraries and their textures.
+pub(crate) fn description_hash(description: &Value, dir: &Path) -> u64 {
+    let mut text = String::new();
+    canonical(description, &mut text);
+    let mut bytes = text.into_bytes();
+    let mut seen = HashSet::new();
+    for name in referenced_files(description, dir) {
+        bytes.extend(name.bytes());
+        hash_file(&dir.join(&name), &mut seen, &mut bytes);
+    }
+    fnv1a(&bytes)
+}
+
+// Adds the file's contents and, for OBJ and MTL files, those of the files it names, each file once.
+fn hash_file(path: &Path, seen: &mut HashSet<PathBuf>, bytes: &mut Vec<u8>) {
+    if !seen.insert(path.to_owned()) {
+        return;
+    }
+    let contents = fs::read(path).unwrap_or_default();
+    let nested = match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("obj") | Some("mtl") => nested_files(&String::from_utf8_lossy(&contents)),
+        _ => Vec::new(),
+    };
+    bytes.extend(contents);
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    for name in nested {
+        bytes.extend(name.bytes());
+        hash_file(&dir.join(&name), seen, bytes);
+    }
+}
+
+// The libraries an OBJ file names and the texture maps of an MTL file, as the loaders read them.
+fn nested_files(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("mtllib") => names.extend(tokens.map(str::to_owned)),
+            Some(key) if key.starts_with("map_") => names.extend(tokens.last().map(str::to_owned)),
+            _ => {}
+        }
+    }
+    names
+}
+
+// JSON with the keys of every object sorted and every number written as the shortest f64 that reads back
+// the same, which doesn't depend on the platform, so that descriptions meaning the same hash the same
+// whichever order their keys came in and however their numbers were spelled.
+pub(crate) fn canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Number(n) => write!(out, "{:?}", n.as_f64().unwrap()).unwrap(),
+        Value::Array(values) => {
+            out.push('[');
+            for (k, v) in values.iter().enumerate() {
+                if k > 0 {
+                    out.push(',');
+                }
+                canonical(v, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|&(key, _)| key);
+            out.push('{');
+            for (k, (key, v)) in entries.into_iter().enumerate() {
+                if k > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push(':');
+                canonical(v, out);
+            }
+            out.push('}');
+        }
+        // strings, booleans and null have only one spelling
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+// Every string in the scene that names a file next to it, in document order.
+fn referenced_files(value: &Value, dir: &Path) -> Vec<String> {
+    match value {
+        Value::String(s) if dir.join(s).is_file() => vec![s.clone()],
+        Value::Array(values) => values.iter().flat_map(|v| referenced_files(v, dir)).collect(),
+        Value::Object(map) => map.values().flat_map(|v| referenced_files(v, dir)).collect(),
+        _ => Vec::new(),
+    }
+}
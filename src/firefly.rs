@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// What a clamped contribution was light from.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Light {
+    // The object, by index, emitting it.
+    Emitter(usize),
+    Background,
+    // The sky sampled through the portals.
+    Portals,
+    // The photon map's estimate, which doesn't tell the lights apart.
+    Photons,
+}
+
+// Where a clamped contribution came from: the light, the object, by index, the path bounced off last before
+// reaching it, None for lights seen straight from the camera, and the number of bounces on the way.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Origin {
+    pub light: Light,
+    pub object: Option<usize>,
+    pub depth: usize,
+}
+
+// How many contributions were clamped and how much the clamp took off them, in the brightest channel.
+#[derive(Clone, Copy, Default)]
+pub struct Tally {
+    pub count: u64,
+    pub excess: f64,
+}
+
+impl Tally {
+    fn add(&mut self, other: Tally) {
+        self.count += other.count;
+        self.excess += other.excess;
+    }
+}
+
+// The contributions the clamp cut in a render, by where they came from, with `--fireflies`. Clamping hides
+// fireflies at the cost of energy; this tells which light and which surface make them, so the scene can be
+// fixed instead, say by making a small bright light bigger or a glossy floor rougher.
+#[derive(Clone, Default)]
+pub struct Fireflies {
+    origins: HashMap<Origin, Tally>,
+}
+
+impl Fireflies {
+    pub(crate) fn record(&mut self, origin: Origin, excess: f64) {
+        self.origins.entry(origin).or_default().add(Tally { count: 1, excess });
+    }
+
+    pub(crate) fn merge(&mut self, other: &Fireflies) {
+        for (origin, tally) in &other.origins {
+            self.origins.entry(*origin).or_default().add(*tally);
+        }
+    }
+
+    pub fn total(&self) -> Tally {
+        let mut total = Tally::default();
+        self.origins.values().for_each(|&tally| total.add(tally));
+        total
+    }
+
+    // The tallies grouped by `key`, such as the light or the depth, the most excess first.
+    pub fn by<K: Eq + Hash>(&self, key: impl Fn(&Origin) -> K) -> Vec<(K, Tally)> {
+        let mut groups = HashMap::<K, Tally>::new();
+        for (origin, &tally) in &self.origins {
+            groups.entry(key(origin)).or_default().add(tally);
+        }
+        let mut groups = groups.into_iter().collect::<Vec<_>>();
+        groups.sort_by(|(_, a), (_, b)| b.excess.total_cmp(&a.excess));
+        groups
+    }
+}
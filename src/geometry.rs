@@ -1,12 +1,26 @@
+use std::f64::consts::PI;
 use std::ops::Range;
 
-use nalgebra::Vector3;
+use nalgebra::{Vector2, Vector3};
 
+use crate::aabb::Aabb;
+use crate::light_tree::Shape;
 use crate::ray::Ray;
 
 pub trait Geometry {
     fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<f64>;
     fn normal(&self, point: &Vector3<f64>) -> Vector3<f64>;
+    fn uv(&self, point: &Vector3<f64>) -> Vector2<f64>;
+    fn bounds(&self) -> Aabb;
+
+    fn tangent(&self, _point: &Vector3<f64>) -> Option<Vector3<f64>> {
+        None
+    }
+
+    // The shape as a light, for geometry that the light tree can sample.
+    fn shape(&self) -> Option<Shape> {
+        None
+    }
 }
 
 pub struct Sphere {
@@ -21,22 +35,86 @@ impl Sphere {
 }
 
 impl Geometry for Sphere {
+    // The discriminant comes from the distance of the center to the line rather than from b² - ac, which
+    // cancels badly for large spheres or distant rays, and the nearer root from c / q so that it isn't a
+    // difference of nearly equal numbers either (Haines et al., Ray Tracing Gems, chapter 7).
     fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<f64> {
+        let d = ray.direction();
         let v = ray.origin - self.center;
-        let a = ray.direction().norm_squared();
-        let b = ray.direction().dot(&v);
-        let c = v.norm_squared() - self.radius * self.radius;
-        let disc = b * b - a * c;
-        if disc > 0.0 {
-            let d = disc.sqrt();
-            [(-b - d) / a, (-b + d) / a].iter().copied()
-                .find(|t| range.contains(t))
-        } else {
-            None
+        let a = d.norm_squared();
+        let b = -d.dot(&v);
+        let off_line = v + d * (b / a);
+        let disc = a * (self.radius * self.radius - off_line.norm_squared());
+        if disc <= 0.0 {
+            return None;
         }
+        let q = b + disc.sqrt().copysign(b);
+        let c = v.norm_squared() - self.radius * self.radius;
+        let (t0, t1) = (c / q, q / a);
+        [t0.min(t1), t0.max(t1)].iter().copied().find(|&t| range.contains(&t) && clear_of_origin(ray, t))
     }
 
     fn normal(&self, point: &Vector3<f64>) -> Vector3<f64> {
         (point - self.center).normalize()
     }
+
+    fn uv(&self, point: &Vector3<f64>) -> Vector2<f64> {
+        spherical_uv(&((point - self.center) / self.radius))
+    }
+
+    fn bounds(&self) -> Aabb {
+        let r = Vector3::repeat(self.radius.abs());
+        Aabb::new(self.center - r, self.center + r)
+    }
+
+    // Along the lines of latitude, undefined at the poles.
+    fn tangent(&self, point: &Vector3<f64>) -> Option<Vector3<f64>> {
+        Vector3::y().cross(&(point - self.center)).try_normalize(1e-8)
+    }
+
+    fn shape(&self) -> Option<Shape> {
+        Some(Shape::Sphere { center: self.center, radius: self.radius.abs() })
+    }
+}
+
+pub(crate) fn spherical_uv(p: &Vector3<f64>) -> Vector2<f64> {
+    Vector2::new((-p.z).atan2(p.x) / (2.0 * PI) + 0.5, (-p.y).clamp(-1.0, 1.0).acos() / PI)
+}
+
+// Watertight, after Woop, Benthin and Wald: the triangle is sheared into a space where the ray runs along z
+// from the origin, and the edge tests are done there in 2D, so that rays through an edge shared by two
+// triangles hit one of them rather than slipping through between.
+pub(crate) fn intersect_triangle(
+    ray: &Ray<f64>, range: &Range<f64>, a: &Vector3<f64>, b: &Vector3<f64>, c: &Vector3<f64>,
+) -> Option<f64> {
+    let d = ray.direction();
+    let kz = d.iamax();
+    let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+    // keeps the winding, so the signs of the edge functions agree for either side of the triangle
+    if d[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+    let (sx, sy, sz) = (d[kx] / d[kz], d[ky] / d[kz], 1.0 / d[kz]);
+    let shear = |p: &Vector3<f64>| {
+        let p = p - ray.origin;
+        (p[kx] - sx * p[kz], p[ky] - sy * p[kz], sz * p[kz])
+    };
+    let ((ax, ay, az), (bx, by, bz), (cx, cy, cz)) = (shear(a), shear(b), shear(c));
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+    Some((u * az + v * bz + w * cz) / det).filter(|&t| range.contains(&t) && clear_of_origin(ray, t))
+}
+
+// Bounces leave from the point they hit, which is only on the surface to within rounding, so a hit closer
+// to the origin than that is the surface the ray leaves and would have it shadow itself.
+fn clear_of_origin(ray: &Ray<f64>, t: f64) -> bool {
+    t * ray.direction().norm() > 1e-9 * ray.origin.amax().max(1.0)
 }
@@ -0,0 +1,159 @@
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex, RwLock};
+
+use nalgebra::Vector3;
+use rand::Rng;
+
+use crate::aabb::Aabb;
+use crate::camera::Camera;
+use crate::material::random_unit_vector;
+use crate::object::Object;
+use crate::{closest_hit, RNG};
+
+// The guide divides the part of the scene the camera sees into a grid of cells, and the directions at
+// each into bins of equal solid angle, even in the cosine of the polar angle and in the azimuth.
+const CELLS: usize = 8;
+const Z_BINS: usize = 8;
+const PHI_BINS: usize = 16;
+const BINS: usize = Z_BINS * PHI_BINS;
+// The share of guided directions drawn from the cosine around the normal, so directions no light has
+// been seen from yet are still explored.
+const COSINE: f64 = 0.5;
+// How often a non-specular bounce follows the guide rather than its material.
+pub(crate) const GUIDED: f64 = 0.5;
+
+// A guided bounce of a path: the cell and bin of the direction it took, the density it was drawn with,
+// and the luminance of the path's throughput after it, to tell the radiance that came back that way.
+#[derive(Clone, Copy)]
+pub(crate) struct GuidedBounce {
+    slot: usize,
+    pdf: f64,
+    throughput: f64,
+}
+
+impl GuidedBounce {
+    pub(crate) fn new(slot: usize, pdf: f64, throughput: &Vector3<f64>) -> Self {
+        Self { slot, pdf, throughput: luminance(throughput) }
+    }
+
+    // The training sample for light reaching the pixel through this bounce with the path's `contribution`.
+    pub(crate) fn sample(&self, contribution: &Vector3<f64>) -> (usize, f64) {
+        let radiance = if self.throughput > 0.0 { luminance(contribution) / self.throughput } else { 0.0 };
+        (self.slot, radiance / self.pdf)
+    }
+}
+
+fn luminance(c: &Vector3<f64>) -> f64 {
+    c.dot(&Vector3::new(0.2126, 0.7152, 0.0722))
+}
+
+// Learns where light comes from at each cell while rendering. Passes report the radiance their guided
+// bounces found, and the distribution sampled from is rebuilt from everything seen so far each time the
+// number of passes doubles, so it sharpens as the estimates settle. Which passes have trained the guide
+// depends on the order they finish in, so unlike plain path tracing the image isn't reproducible exactly.
+pub(crate) struct Guide {
+    training: Mutex<(Vec<f64>, u32)>,
+    distribution: RwLock<Arc<GuideDistribution>>,
+}
+
+impl Guide {
+    pub(crate) fn new(camera: &Camera, objects: &[Box<dyn Object + Sync>]) -> Self {
+        // the cells cover what primary rays hit, since the ground and other large objects would
+        // otherwise spread them far too thin
+        let n = 32;
+        let points = (0..n * n).filter_map(|k| {
+            let (u, v) = ((k % n) as f64 / (n - 1) as f64, (k / n) as f64 / (n - 1) as f64);
+            closest_hit(objects, &camera.ray_through(u, v, 0.0, 0.0, [0.0, 0.0])).map(|i| *i.point())
+        }).collect::<Vec<_>>();
+        let bounds = match points.is_empty() {
+            true => Aabb::new(Vector3::repeat(-1.0), Vector3::repeat(1.0)),
+            false => Aabb::from_points(&points),
+        };
+        let distribution = GuideDistribution { bounds, probabilities: vec![Vec::new(); CELLS * CELLS * CELLS] };
+        Self {
+            training: Mutex::new((vec![0.0; CELLS * CELLS * CELLS * BINS], 0)),
+            distribution: RwLock::new(Arc::new(distribution)),
+        }
+    }
+
+    pub(crate) fn distribution(&self) -> Arc<GuideDistribution> {
+        self.distribution.read().unwrap().clone()
+    }
+
+    pub(crate) fn train(&self, samples: &[(usize, f64)]) {
+        let mut training = self.training.lock().unwrap();
+        let (sums, passes) = &mut *training;
+        for &(slot, radiance) in samples {
+            sums[slot] += radiance;
+        }
+        *passes += 1;
+        if !passes.is_power_of_two() {
+            return;
+        }
+        let probabilities = sums.chunks(BINS).map(|bins| {
+            let total = bins.iter().sum::<f64>();
+            if total > 0.0 { bins.iter().map(|b| b / total).collect() } else { Vec::new() }
+        }).collect();
+        let bounds = self.distribution().bounds;
+        *self.distribution.write().unwrap() = Arc::new(GuideDistribution { bounds, probabilities });
+    }
+}
+
+pub(crate) struct GuideDistribution {
+    bounds: Aabb,
+    // per cell, the share of the light seen in each bin, or nothing if no light has been seen there
+    probabilities: Vec<Vec<f64>>,
+}
+
+impl GuideDistribution {
+    fn cell(&self, p: &Vector3<f64>) -> usize {
+        let size = self.bounds.diagonal().map(|d| d.max(1e-9));
+        let cell = (p - self.bounds.min).component_div(&size).map(|x| ((x * CELLS as f64) as usize).min(CELLS - 1));
+        (cell.z * CELLS + cell.y) * CELLS + cell.x
+    }
+
+    fn bin(direction: &Vector3<f64>) -> usize {
+        let z = ((direction.z + 1.0) / 2.0 * Z_BINS as f64) as usize;
+        let phi = ((direction.y.atan2(direction.x) + PI) / (2.0 * PI) * PHI_BINS as f64) as usize;
+        z.min(Z_BINS - 1) * PHI_BINS + phi.min(PHI_BINS - 1)
+    }
+
+    // The density over the whole sphere the cell's light was seen with.
+    fn learned_pdf(&self, cell: usize, direction: &Vector3<f64>) -> f64 {
+        self.probabilities[cell].get(Self::bin(direction)).map_or(1.0, |p| p * BINS as f64) / (4.0 * PI)
+    }
+
+    // A direction at `point` on a surface facing `normal`, with its solid angle density and its slot for
+    // training. Learned directions below the surface are mirrored above it, and a share are drawn from the
+    // cosine instead, so no direction the material scatters to is left out.
+    pub(crate) fn sample(&self, point: &Vector3<f64>, normal: &Vector3<f64>) -> (Vector3<f64>, f64, usize) {
+        let cell = self.cell(point);
+        let probabilities = &self.probabilities[cell];
+        let (choice, u, v) = RNG.with(|r| {
+            let mut r = r.borrow_mut();
+            (r.gen::<f64>(), r.gen::<f64>(), r.gen::<f64>())
+        });
+        let direction = if choice < COSINE {
+            (normal + random_unit_vector()).try_normalize(1e-8).unwrap_or(*normal)
+        } else {
+            let target = (choice - COSINE) / (1.0 - COSINE);
+            let mut sum = 0.0;
+            let bin = match probabilities.is_empty() {
+                true => ((target * BINS as f64) as usize).min(BINS - 1),
+                false => probabilities.iter().position(|p| {
+                    sum += p;
+                    sum > target
+                }).unwrap_or(BINS - 1),
+            };
+            let z = -1.0 + 2.0 * ((bin / PHI_BINS) as f64 + u) / Z_BINS as f64;
+            let phi = 2.0 * PI * ((bin % PHI_BINS) as f64 + v) / PHI_BINS as f64 - PI;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let d = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+            if d.dot(normal) < 0.0 { d - 2.0 * d.dot(normal) * normal } else { d }
+        };
+        let mirrored = direction - 2.0 * direction.dot(normal) * normal;
+        let pdf = COSINE * direction.dot(normal).max(0.0) / PI
+            + (1.0 - COSINE) * (self.learned_pdf(cell, &direction) + self.learned_pdf(cell, &mirrored));
+        (direction, pdf, cell * BINS + Self::bin(&direction))
+    }
+}
@@ -0,0 +1,146 @@
+use std::ops::Range;
+
+use nalgebra::{Vector2, Vector3};
+
+use crate::aabb::Aabb;
+use crate::error::{Error, Result};
+use crate::geometry::{intersect_triangle, Geometry};
+use crate::ray::Ray;
+
+pub struct Heightfield {
+    origin: Vector3<f64>,
+    size: Vector3<f64>,
+    width: usize,
+    depth: usize,
+    heights: Vec<f64>,
+    bounds: Aabb,
+}
+
+impl Heightfield {
+    pub fn new(origin: Vector3<f64>, size: Vector3<f64>, width: usize, depth: usize, heights: Vec<f64>) -> Self {
+        assert!(width >= 2 && depth >= 2, "a heightfield needs at least 2x2 samples");
+        assert_eq!(heights.len(), width * depth, "one height per grid sample is required");
+        let (lo, hi) = heights.iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &h| (lo.min(h), hi.max(h)));
+        let bounds = Aabb::new(
+            Vector3::new(origin.x, origin.y + lo * size.y, origin.z),
+            Vector3::new(origin.x + size.x, origin.y + hi * size.y, origin.z + size.z),
+        );
+        Self { origin, size, width, depth, heights, bounds }
+    }
+
+    pub fn load(path: &str, origin: Vector3<f64>, size: Vector3<f64>) -> Result<Self> {
+        let invalid = |what: String| Error::ImageFile(format!("{}: {}", path, what));
+        let image = image::open(path).map_err(|e| invalid(e.to_string()))?.to_luma32f();
+        let (width, depth) = (image.width() as usize, image.height() as usize);
+        if width < 2 || depth < 2 {
+            return Err(invalid(format!("a heightfield needs at least 2x2 samples, not {}x{}", width, depth)));
+        }
+        let heights = image.pixels().map(|p| p[0] as f64).collect();
+        Ok(Self::new(origin, size, width, depth, heights))
+    }
+
+    fn cell_size(&self) -> Vector2<f64> {
+        Vector2::new(self.size.x / (self.width - 1) as f64, self.size.z / (self.depth - 1) as f64)
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Vector3<f64> {
+        let cell = self.cell_size();
+        let h = self.heights[j * self.width + i];
+        self.origin + Vector3::new(i as f64 * cell.x, h * self.size.y, j as f64 * cell.y)
+    }
+
+    // Cells are split along the (i, j)-(i + 1, j + 1) diagonal.
+    fn triangles(&self, i: usize, j: usize) -> [[Vector3<f64>; 3]; 2] {
+        let [a, b, c, d] = [self.vertex(i, j), self.vertex(i + 1, j), self.vertex(i + 1, j + 1), self.vertex(i, j + 1)];
+        [[a, b, c], [a, c, d]]
+    }
+
+    fn cell_of(&self, point: &Vector3<f64>) -> (usize, usize, Vector2<f64>) {
+        let cell = self.cell_size();
+        let g = Vector2::new((point.x - self.origin.x) / cell.x, (point.z - self.origin.z) / cell.y);
+        let i = (g.x.floor().max(0.0) as usize).min(self.width - 2);
+        let j = (g.y.floor().max(0.0) as usize).min(self.depth - 2);
+        (i, j, g - Vector2::new(i as f64, j as f64))
+    }
+
+    fn height_range(&self, i: usize, j: usize) -> (f64, f64) {
+        [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)].iter()
+            .map(|&(i, j)| self.origin.y + self.heights[j * self.width + i] * self.size.y)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), h| (lo.min(h), hi.max(h)))
+    }
+}
+
+impl Geometry for Heightfield {
+    // 2D DDA over the grid cells crossed by the ray's projection onto the xz plane.
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<f64> {
+        let d = ray.direction();
+        let inv = d.map(|x| 1.0 / x);
+        let bounds = &self.bounds;
+        let t0 = (bounds.min - ray.origin).component_mul(&inv);
+        let t1 = (bounds.max - ray.origin).component_mul(&inv);
+        let mut t = t0.inf(&t1).max().max(range.start);
+        let t_end = t0.sup(&t1).min().min(range.end);
+        if t > t_end {
+            return None;
+        }
+
+        let cell = self.cell_size();
+        let (mut i, mut j, _) = self.cell_of(&ray.at(t));
+        let step_i = if d.x >= 0.0 { 1 } else { -1 };
+        let step_j = if d.z >= 0.0 { 1 } else { -1 };
+        let boundary = |k: usize, step: isize, origin: f64, size: f64| {
+            origin + (k as f64 + if step > 0 { 1.0 } else { 0.0 }) * size
+        };
+        let mut next_x = (boundary(i, step_i, self.origin.x, cell.x) - ray.origin.x) * inv.x;
+        let mut next_z = (boundary(j, step_j, self.origin.z, cell.y) - ray.origin.z) * inv.z;
+        let (delta_x, delta_z) = ((cell.x * inv.x).abs(), (cell.y * inv.z).abs());
+
+        loop {
+            let exit = next_x.min(next_z).min(t_end);
+            let (y0, y1) = (ray.at(t).y, ray.at(exit).y);
+            let (lo, hi) = self.height_range(i, j);
+            if y0.min(y1) <= hi && y0.max(y1) >= lo {
+                let segment = range.start..range.end.min(exit + 1e-9);
+                let hit = self.triangles(i, j).iter()
+                    .filter_map(|[a, b, c]| intersect_triangle(ray, &segment, a, b, c))
+                    .min_by(|x, y| x.partial_cmp(y).unwrap());
+                if hit.is_some() {
+                    return hit;
+                }
+            }
+            if exit >= t_end {
+                return None;
+            }
+            t = exit;
+            if next_x < next_z {
+                i = (i as isize + step_i) as usize;
+                next_x += delta_x;
+                if i >= self.width - 1 {
+                    return None;
+                }
+            } else {
+                j = (j as isize + step_j) as usize;
+                next_z += delta_z;
+                if j >= self.depth - 1 {
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn normal(&self, point: &Vector3<f64>) -> Vector3<f64> {
+        let (i, j, f) = self.cell_of(point);
+        let [a, b, c] = self.triangles(i, j)[if f.x > f.y { 0 } else { 1 }];
+        let n = (c - a).cross(&(b - a)).normalize();
+        if n.y < 0.0 { -n } else { n }
+    }
+
+    fn uv(&self, point: &Vector3<f64>) -> Vector2<f64> {
+        Vector2::new((point.x - self.origin.x) / self.size.x, (point.z - self.origin.z) / self.size.z)
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+}
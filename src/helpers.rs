@@ -0,0 +1,157 @@
+use itertools::iproduct;
+use nalgebra::Vector3;
+
+use crate::aabb::Aabb;
+use crate::camera::Camera;
+use crate::control::heat;
+use crate::overlay::Canvas;
+use crate::scene::Scene;
+
+type Image = (u32, u32, Vec<Vector3<f64>>);
+
+// Cells of the ground grid on each side of the origin.
+const GRID: i32 = 20;
+// Lines closer to the camera plane than this are cut off rather than projected.
+const NEAR: f64 = 1e-3;
+
+// What to draw over previews. `helpers` are the scene-setup guides; `bounds` outlines every object's
+// bounding box and `bvh` the nodes of the objects' BVHs down to that many levels below the root, colored
+// from red at the root through yellow to white, to check bounds and how the trees were built.
+#[derive(Clone, Copy, Default)]
+pub struct Guides {
+    pub helpers: bool,
+    pub bounds: bool,
+    pub bvh: Option<usize>,
+}
+
+impl Guides {
+    pub fn any(&self) -> bool {
+        self.helpers || self.bounds || self.bvh.is_some()
+    }
+
+    pub(crate) fn segments(&self, scene: &Scene, aspect_ratio: f64) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        if self.helpers {
+            segments.extend(helpers(scene, aspect_ratio));
+        }
+        if let Some(depth) = self.bvh {
+            for (level, bounds) in scene.objects.iter().flat_map(|o| o.bvh_bounds(depth)) {
+                outline(&bounds, heat((level + 1) as f64 / (depth + 1) as f64), 0.6, &mut segments);
+            }
+        }
+        if self.bounds {
+            for bounds in scene.objects.iter().filter_map(|o| o.bounds()) {
+                outline(&bounds, Vector3::new(0.2, 1.0, 0.4), 1.0, &mut segments);
+            }
+        }
+        segments
+    }
+}
+
+// A line in world space.
+pub(crate) struct Segment {
+    from: Vector3<f64>,
+    to: Vector3<f64>,
+    color: Vector3<f64>,
+    alpha: f64,
+}
+
+// Guides for setting up a scene, drawn as lines over previews and never rendered: the world axes in red,
+// green and blue, a grid on the ground plane, the outline of the scene camera's view, which shows once the
+// viewer flies away from it, and outlines of the lights. They are sized to the distance the camera looks
+// across, with grid cells a power of ten.
+fn helpers(scene: &Scene, aspect_ratio: f64) -> Vec<Segment> {
+    let view = &scene.view;
+    let size = (view.at - view.from).norm();
+    let spacing = 10f64.powf((size / 10.0).log10().round());
+    let mut segments = Vec::new();
+    let mut line = |from, to, color, alpha| segments.push(Segment { from, to, color, alpha });
+
+    let extent = GRID as f64 * spacing;
+    for k in -GRID..=GRID {
+        let c = k as f64 * spacing;
+        line(Vector3::new(c, 0.0, -extent), Vector3::new(c, 0.0, extent), Vector3::repeat(0.6), 0.4);
+        line(Vector3::new(-extent, 0.0, c), Vector3::new(extent, 0.0, c), Vector3::repeat(0.6), 0.4);
+    }
+    for axis in [Vector3::x(), Vector3::y(), Vector3::z()] {
+        line(Vector3::zeros(), axis * 5.0 * spacing, axis, 1.0);
+    }
+
+    let camera = Vector3::new(0.3, 0.8, 1.0);
+    let (origin, corners) = view.camera(aspect_ratio).frustum(size / 4.0);
+    for (k, corner) in corners.iter().enumerate() {
+        line(origin, *corner, camera, 1.0);
+        line(*corner, corners[(k + 1) % 4], camera, 1.0);
+    }
+
+    let light = Vector3::new(1.0, 0.8, 0.2);
+    for portal in &scene.portals {
+        let (corner, u, v) = (portal.corner, portal.u, portal.v);
+        let corners = [corner, corner + u, corner + u + v, corner + v];
+        for (k, corner) in corners.iter().enumerate() {
+            line(*corner, corners[(k + 1) % 4], light, 1.0);
+        }
+        line(corners[0], corners[2], light, 1.0);
+        line(corners[1], corners[3], light, 1.0);
+    }
+    for bounds in scene.emissive.iter().filter_map(|&k| scene.objects[k].bounds()) {
+        outline(&bounds, light, 1.0, &mut segments);
+    }
+    segments
+}
+
+fn outline(bounds: &Aabb, color: Vector3<f64>, alpha: f64, segments: &mut Vec<Segment>) {
+    let corner = |i: usize| Vector3::from_fn(|axis, _| match i >> axis & 1 {
+        1 => bounds.max[axis],
+        _ => bounds.min[axis],
+    });
+    // the box's edges join corners differing along one axis
+    for (i, axis) in iproduct!(0..8, 0..3).filter(|(i, axis)| i >> axis & 1 == 0) {
+        segments.push(Segment { from: corner(i), to: corner(i | 1 << axis), color, alpha });
+    }
+}
+
+// How far the ray through the center of each pixel goes before it hits anything.
+#[cfg(feature = "sdl2")]
+pub(crate) fn distances(scene: &Scene, camera: &Camera, width: u32, height: u32) -> Vec<f64> {
+    iproduct!(0..width, 0..height).map(|(i, j)| {
+        let u = (i as f64 + 0.5) / width as f64;
+        let v = 1.0 - (j as f64 + 0.5) / height as f64;
+        let ray = camera.ray_through(u, v, 1.0 / width as f64, 1.0 / height as f64, [0.0, 0.0]);
+        crate::closest_hit(&scene.objects, &ray).map_or(f64::INFINITY, |i| i.t())
+    }).collect()
+}
+
+// Draws the segments seen through `camera`, hidden where they pass behind the surfaces `distances` away.
+pub(crate) fn draw(image: &mut Image, distances: &[f64], camera: &Camera, segments: &[Segment]) {
+    let (width, height) = (image.0, image.1);
+    let mut canvas = Canvas::new(image);
+    let depth = |p: &Vector3<f64>| -camera.camera_space(p).z;
+    let pixel = |p: &Vector3<f64>| camera.project(p).map(|(u, v)| (u * width as f64, (1.0 - v) * height as f64));
+    for segment in segments {
+        let (a, b) = (depth(&segment.from), depth(&segment.to));
+        let cut = || segment.from.lerp(&segment.to, (NEAR - a) / (b - a));
+        let (from, to) = match (a < NEAR, b < NEAR) {
+            (true, true) => continue,
+            (true, false) => (cut(), segment.to),
+            (false, true) => (segment.from, cut()),
+            (false, false) => (segment.from, segment.to),
+        };
+        let (Some(p), Some(q)) = (pixel(&from), pixel(&to)) else { continue };
+        // half a pixel apart, up to a limit for lines reaching far off the image
+        let steps = ((q.0 - p.0).abs().max((q.1 - p.1).abs()) * 2.0).min(4.0 * (width + height) as f64);
+        let steps = steps.ceil().max(1.0);
+        for k in 0..=steps as u32 {
+            let point = from.lerp(&to, k as f64 / steps);
+            let Some((x, y)) = pixel(&point) else { continue };
+            let (x, y) = (x.floor() as i64, y.floor() as i64);
+            if !(0..width as i64).contains(&x) || !(0..height as i64).contains(&y) {
+                continue;
+            }
+            // with some slack so lines lying on surfaces, such as the grid on a floor, show
+            if camera.camera_space(&point).norm() <= distances[(x as u32 * height + y as u32) as usize] * 1.001 {
+                canvas.blend(x, y, segment.color, segment.alpha);
+            }
+        }
+    }
+}
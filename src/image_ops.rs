@@ -0,0 +1,91 @@
+use itertools::iproduct;
+use nalgebra::Vector3;
+
+use crate::background::luminance;
+use crate::filter::PixelFilter;
+
+type Image = (u32, u32, Vec<Vector3<f64>>);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Flip {
+    // Left to right, as in a mirror.
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Luminance,
+}
+
+// The part of `image` `width` by `height` from (`x`, `y`), counted from the top left, as far as it lies in
+// the image.
+pub fn crop_image(image: &Image, x: u32, y: u32, width: u32, height: u32) -> Image {
+    let (w, h, buffer) = image;
+    let (x, y) = (x.min(*w), y.min(*h));
+    let (width, height) = (width.min(w - x), height.min(h - y));
+    let buffer = iproduct!(x..x + width, y..y + height).map(|(i, j)| buffer[(i * h + j) as usize]).collect();
+    (width, height, buffer)
+}
+
+// `image` resampled to `width` by `height` through `filter`, as render passes are splatted through it. The
+// filter is widened when shrinking, so every pixel of the original counts toward the result.
+pub fn resize_image(image: &Image, width: u32, height: u32, filter: PixelFilter) -> Image {
+    let (w, h, buffer) = image;
+    let (across, down) = (taps(*w, width, filter), taps(*h, height, filter));
+    // the rows first, at the original height
+    let wide = iproduct!(&across, 0..*h as usize)
+        .map(|(taps, j)| taps.iter().map(|&(i, t)| buffer[i * *h as usize + j] * t).sum())
+        .collect::<Vec<Vector3<f64>>>();
+    let buffer = iproduct!(0..width as usize, &down)
+        .map(|(i, taps)| taps.iter().map(|&(j, t)| wide[i * *h as usize + j] * t).sum())
+        .collect();
+    (width, height, buffer)
+}
+
+// The source pixels along one axis, and their normalized weights, of each of `to` pixels resampled from
+// `from`.
+fn taps(from: u32, to: u32, filter: PixelFilter) -> Vec<Vec<(usize, f64)>> {
+    let scale = from as f64 / to as f64;
+    let stretch = scale.max(1.0);
+    let reach = filter.radius() * stretch;
+    (0..to).map(|k| {
+        let center = (k as f64 + 0.5) * scale;
+        let first = (center - reach).floor().max(0.0) as usize;
+        let last = ((center + reach).ceil() as usize).min(from as usize);
+        let taps = (first..last)
+            .map(|s| (s, filter.weight((s as f64 + 0.5 - center) / stretch)))
+            .filter(|&(_, t)| t > 0.0)
+            .collect::<Vec<_>>();
+        let total = taps.iter().map(|&(_, t)| t).sum::<f64>();
+        // the box filter can fall between pixels, leaving the nearest one
+        match total > 0.0 {
+            true => taps.into_iter().map(|(s, t)| (s, t / total)).collect(),
+            false => vec![((center as usize).min(from as usize - 1), 1.0)],
+        }
+    }).collect()
+}
+
+pub fn flip_image(image: &Image, flip: Flip) -> Image {
+    let (w, h, buffer) = image;
+    let buffer = iproduct!(0..*w, 0..*h).map(|(i, j)| match flip {
+        Flip::Horizontal => buffer[((w - 1 - i) * h + j) as usize],
+        Flip::Vertical => buffer[(i * h + h - 1 - j) as usize],
+    }).collect();
+    (*w, *h, buffer)
+}
+
+// One channel of `image` as a single value per pixel, laid out as the image.
+pub fn image_channel(image: &Image, channel: Channel) -> (u32, u32, Vec<f64>) {
+    let (w, h, buffer) = image;
+    let values = buffer.iter().map(|c| match channel {
+        Channel::Red => c.x,
+        Channel::Green => c.y,
+        Channel::Blue => c.z,
+        Channel::Luminance => luminance(c),
+    }).collect();
+    (*w, *h, values)
+}
@@ -0,0 +1,204 @@
+use std::collections::HashSet;
+use std::mem::size_of_val;
+use std::ops::Range;
+use std::sync::Arc;
+
+use nalgebra::{Affine3, Point3, Vector2, Vector3};
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::light_tree::Emitter;
+use crate::material::Material;
+use crate::object::{Intersection, Object};
+use crate::ray::Ray;
+
+pub struct Instance {
+    object: Arc<dyn Object + Send + Sync>,
+    transform: Affine3<f64>,
+    inverse: Affine3<f64>,
+    material: Option<Arc<dyn Material + Send + Sync>>,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Object + Send + Sync>, transform: Affine3<f64>) -> Self {
+        Self { object, transform, inverse: transform.inverse(), material: None }
+    }
+
+    // Shades everything hit through this instance with `material`, overriding any set by the object
+    // or by instances nested inside it. Masking still follows the object's own materials.
+    pub fn with_material(self, material: Arc<dyn Material + Send + Sync>) -> Self {
+        Self { material: Some(material), ..self }
+    }
+
+    pub fn set_transform(&mut self, transform: Affine3<f64>) {
+        self.transform = transform;
+        self.inverse = transform.inverse();
+    }
+}
+
+// Instances and the TLAS only route rays: the intersections they return refer to the instanced object,
+// carrying the accumulated transform and any material override, so the shading methods below are never
+// reached.
+impl Object for Instance {
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection<'_>> {
+        let origin = self.inverse.transform_point(&Point3::from(ray.origin)).coords;
+        let local = Ray::new(origin, self.inverse.transform_vector(ray.direction()));
+        let int = self.object.intersect(&local, range)?.transformed(ray, &self.transform, &self.inverse);
+        Some(match &self.material {
+            Some(material) => int.with_material(&**material),
+            None => int,
+        })
+    }
+
+    fn normal(&self, _point: &Vector3<f64>, _index: usize) -> Vector3<f64> {
+        unreachable!()
+    }
+
+    fn uv(&self, _point: &Vector3<f64>, _index: usize) -> Vector2<f64> {
+        unreachable!()
+    }
+
+    fn scatter(&self, _int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        unreachable!()
+    }
+
+    fn eval(&self, _int: &Intersection, _wi: &Vector3<f64>) -> Vector3<f64> {
+        unreachable!()
+    }
+
+    fn specular(&self, _index: usize) -> bool {
+        unreachable!()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.object.bounds().map(|b| b.transform(&self.transform))
+    }
+
+    fn specular_bounds(&self) -> Option<Aabb> {
+        let bounds = match &self.material {
+            Some(material) => self.object.bounds().filter(|_| material.specular()),
+            None => self.object.specular_bounds(),
+        };
+        bounds.map(|b| b.transform(&self.transform))
+    }
+
+    fn bvh_bounds(&self, depth: usize) -> Vec<(usize, Aabb)> {
+        self.object.bvh_bounds(depth).into_iter().map(|(level, b)| (level, b.transform(&self.transform))).collect()
+    }
+
+    fn primitives(&self) -> usize {
+        self.object.primitives()
+    }
+
+    // a material set further out overrides this instance's
+    fn emitters(&self, material: Option<&dyn Material>) -> Option<Vec<Emitter>> {
+        let material = material.or_else(|| self.material.as_deref().map(|m| m as &dyn Material));
+        self.object.emitters(material)?.iter().map(|e| e.transformed(&self.transform)).collect()
+    }
+
+    fn memory(&self, shared: &mut HashSet<usize>) -> usize {
+        match shared.insert(Arc::as_ptr(&self.object) as *const () as usize) {
+            true => self.object.memory(shared),
+            false => 0,
+        }
+    }
+}
+
+// The top level of a two-level hierarchy: each instanced object keeps its own BVH (a mesh's is built
+// once on load), and only this BVH over instance bounds is rebuilt when transforms change. A TLAS is
+// itself an object, so it can be instanced again.
+pub struct Tlas {
+    instances: Vec<Instance>,
+    bvh: Bvh,
+}
+
+impl Tlas {
+    pub fn new(instances: Vec<Instance>) -> Self {
+        let bvh = Bvh::build(&bounds(&instances));
+        Self { instances, bvh }
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    // Moving instances refits the existing tree unless that has degraded it too far; call `rebuild`
+    // after adding and removing instances.
+    pub fn set_transforms(&mut self, transforms: impl IntoIterator<Item=(usize, Affine3<f64>)>) {
+        for (i, transform) in transforms {
+            self.instances[i].set_transform(transform);
+        }
+        self.bvh.update(&bounds(&self.instances), 1);
+    }
+
+    pub fn instances_mut(&mut self) -> &mut Vec<Instance> {
+        &mut self.instances
+    }
+
+    pub fn rebuild(&mut self, threads: u32) {
+        self.bvh = Bvh::build_parallel(&bounds(&self.instances), threads);
+    }
+}
+
+fn bounds(instances: &[Instance]) -> Vec<Aabb> {
+    instances.iter()
+        .map(|i| i.bounds().unwrap_or_else(|| {
+            let origin = i.transform.transform_point(&Point3::origin()).coords;
+            Aabb::new(origin, origin)
+        }))
+        .collect()
+}
+
+impl Object for Tlas {
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection<'_>> {
+        self.bvh.intersect(ray, range, |i, range| self.instances[i].intersect(ray, range).map(|int| (int.t(), int)))
+            .map(|(_, int)| int)
+    }
+
+    fn normal(&self, _point: &Vector3<f64>, _index: usize) -> Vector3<f64> {
+        unreachable!()
+    }
+
+    fn uv(&self, _point: &Vector3<f64>, _index: usize) -> Vector2<f64> {
+        unreachable!()
+    }
+
+    fn scatter(&self, _int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        unreachable!()
+    }
+
+    fn eval(&self, _int: &Intersection, _wi: &Vector3<f64>) -> Vector3<f64> {
+        unreachable!()
+    }
+
+    fn specular(&self, _index: usize) -> bool {
+        unreachable!()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.instances.iter().filter_map(Object::bounds).reduce(|a, b| a.union(&b))
+    }
+
+    fn specular_bounds(&self) -> Option<Aabb> {
+        self.instances.iter().filter_map(Object::specular_bounds).reduce(|a, b| a.union(&b))
+    }
+
+    // Just the tree over the instances.
+    fn bvh_bounds(&self, depth: usize) -> Vec<(usize, Aabb)> {
+        self.bvh.node_bounds(depth)
+    }
+
+    fn primitives(&self) -> usize {
+        self.instances.iter().map(Object::primitives).sum()
+    }
+
+    fn emitters(&self, material: Option<&dyn Material>) -> Option<Vec<Emitter>> {
+        let emitters = self.instances.iter().map(|i| i.emitters(material)).collect::<Option<Vec<_>>>()?;
+        Some(emitters.concat())
+    }
+
+    fn memory(&self, shared: &mut HashSet<usize>) -> usize {
+        let instances = self.instances.iter().map(|i| i.memory(shared)).sum::<usize>();
+        size_of_val(self.instances.as_slice()) + self.bvh.memory() + instances
+    }
+}
@@ -1,221 +1,1166 @@
-#![feature(box_syntax)]
-
 use std::borrow::Borrow;
 use std::cell::RefCell;
-use std::f64::consts::PI;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::iter;
 use std::ops::Range;
+use std::path::Path;
+use std::sync::OnceLock;
 
 use itertools::iproduct;
-use nalgebra::Vector3;
+use nalgebra::{Vector2, Vector3};
 use rand::{Rng, SeedableRng};
 use rand::distributions::{Distribution, Uniform};
 use rand::rngs::SmallRng;
 
-use crate::camera::Camera;
+use crate::background::{luminance, Irradiance};
+use crate::camera::{lens_stratum, LensSplitting};
+use crate::denoise::denoise;
 use crate::geometry::Sphere;
-use crate::material::{Dielectric, Lambertian, Metal};
-use crate::object::Object;
-use crate::ray::Ray;
-
-mod camera;
-mod geometry;
-mod material;
-mod object;
-mod ray;
-
-const NUM_SAMPLES: u32 = 128;
-const NUM_THREADS: u32 = 8;
+use crate::material::{Dielectric, Lambertian, Medium, Metal};
+use crate::guiding::{Guide, GuideDistribution, GuidedBounce, GUIDED};
+use crate::light_paths::{band, LightPaths};
+use crate::light_tree::LightTree;
+use crate::photon::PhotonMap;
+use crate::texture::ImageTexture;
+use crate::volume::Fog;
+use crate::white_balance::white_balance;
+pub use crate::assets::{Assets, AssetStats};
+pub use crate::background::Background;
+pub use crate::builder::{ObjectBuilder, SceneBuilder};
+pub use crate::cache::RenderCache;
+pub use crate::camera::{Camera, Lens, Stereo, StereoLayout};
+pub use crate::control::{Control, serve, snapshot_every, Stats};
+pub use crate::depth::{DepthMode, DepthPass};
+pub use crate::error::{Error, Result};
+pub use crate::estimate::Estimate;
+pub use crate::filter::PixelFilter;
+pub use crate::firefly::{Fireflies, Light, Origin, Tally};
+pub use crate::helpers::Guides;
+pub use crate::image_ops::{crop_image, flip_image, image_channel, resize_image, Channel, Flip};
+pub use crate::library::{MaterialHandle, MaterialLibrary};
+pub use crate::light_paths::PathLengths;
+pub use crate::lut::Lut;
+pub use crate::matte::Matte;
+pub use crate::notify::{notify_command, notify_webhook};
+pub use crate::object::{Intersection, Object};
+#[cfg(feature = "ocio")]
+pub use crate::ocio::DisplayTransform;
+pub use crate::overlay::burn_in;
+pub use crate::portal::Portal;
+pub use crate::ray::Ray;
+pub use crate::sampler::{LensSampler, Sampler};
+pub use crate::scene::{Focus, LightHandle, ObjectHandle, PreviewSettings, Scene, Summary, View};
+pub use crate::shader_ball::material_preview;
+pub use crate::settings::{Crop, Integrator, Quality, RenderSettings};
+pub use crate::sidecar::write_sidecar;
+pub use crate::svg::svg_overlay;
+pub use crate::video::{GifEncoder, VideoEncoder};
+pub use crate::white_balance::WhiteBalance;
+
+pub mod aabb;
+mod assets;
+mod background;
+pub mod billboard;
+pub mod builder;
+mod bvh;
+mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod camera;
+mod control;
+mod counters;
+mod depth;
+mod denoise;
+pub mod curve;
+#[cfg(feature = "embree")]
+mod embree;
+mod error;
+mod estimate;
+mod filter;
+mod fingerprint;
+mod firefly;
+pub mod geometry;
+mod guiding;
+mod helpers;
+pub mod heightfield;
+mod image_ops;
+pub mod instance;
+mod library;
+mod light_paths;
+pub mod light_tree;
+mod lut;
+mod matte;
+pub mod material;
+pub mod mesh;
+mod mtl;
+mod notify;
+pub mod object;
+pub mod ocean;
+#[cfg(feature = "ocio")]
+mod ocio;
+mod overlay;
+pub mod paged;
+mod photon;
+mod portal;
+#[cfg(feature = "python")]
+mod python;
+pub mod planet;
+mod ply;
+pub mod ray;
+mod sampler;
+pub mod scene;
+pub mod sdf;
+pub mod section;
+mod settings;
+mod shader_ball;
+mod sidecar;
+mod subdivision;
+mod svg;
+#[cfg(test)]
+mod testing;
+pub mod text;
+pub mod texture;
+mod video;
+pub mod volume;
+mod white_balance;
+
 const RANDOM_RANGE: Range<i32> = -11..11;
-const IMAGE_WIDTH: u32 = 300;
-const IMAGE_HEIGHT: u32 = 200;
 
 thread_local! {
     pub(crate) static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_rng(rand::thread_rng()).unwrap());
 }
 
-fn ray_color<R: Borrow<dyn Object + Sync>>(objects: &[R], ray: &Ray<f64>, depth: usize) -> Vector3<f64> {
-    if depth > 0 {
-        objects.iter()
-            .filter_map(|o| o.borrow().intersect(ray, 0.0..f64::INFINITY))
-            .min_by(|x, y| x.t().partial_cmp(&y.t()).expect("some compare thing failed"))
-            .map(|i| {
-                let (r, m) = i.scatter();
-                ray_color(objects, &r, depth - 1).component_mul(&m)
-            })
-            .unwrap_or_else(|| {
-                let v = ray.direction();
-                let t = 0.5 * (v.y + 1.0);
-                Vector3::new(1.0 - t, 1.0 - t, 1.0 - t) + t * Vector3::new(0.5, 0.7, 1.0)
+#[derive(Clone)]
+struct PathState {
+    pixel: usize,
+    ray: Ray<f64>,
+    throughput: Vector3<f64>,
+    diffuse: bool,
+    caustic: bool,
+    // Whether the lights, the sky through the portals and the emitters in the light tree, were sampled
+    // directly at the last bounce for the lobe the path went on through, so the path mustn't count them
+    // again if it reaches one.
+    sampled_lights: bool,
+    guided: Vec<GuidedBounce>,
+    media: Vec<Medium>,
+    // Whether the path has been split at an object with a sampling multiplier; it only splits once.
+    split: bool,
+    // The object the path bounced off last, by index, for telling where fireflies come from.
+    last: Option<usize>,
+    // The share of its pixel's camera sample the path carries, for the alpha channel.
+    weight: f64,
+    // The shadow catcher the camera saw, until the bounce off it tells how much of it is in shadow.
+    catcher: Option<Catcher>,
+    // The path's own stream of random numbers, for `seed_path`.
+    seed: u64,
+}
+
+// What a shadow catcher seen from the camera covers, the irradiance the background alone casts on it and the
+// attenuation of the bounce off it.
+#[derive(Clone, Copy)]
+struct Catcher {
+    behind: Vector3<f64>,
+    irradiance: f64,
+    attenuation: Vector3<f64>,
+}
+
+// What paths are traced through besides the objects, and how.
+struct Tracer<'a, R> {
+    objects: &'a [R],
+    background: &'a Background,
+    photons: Option<&'a PhotonMap>,
+    fog: Option<&'a Fog>,
+    portals: &'a [Portal],
+    lights: &'a LightTree,
+    guide: Option<&'a GuideDistribution>,
+    // Sampling multipliers of the objects, by index; missing entries are 1.
+    sampling: &'a [u32],
+    max_depth: usize,
+    clamp: Option<f64>,
+    // Tabulated the first time a shadow catcher is seen.
+    irradiance: &'a OnceLock<Irradiance>,
+    // Whether the camera sees through to nothing where it would see the background.
+    transparent: bool,
+    view: &'a View,
+}
+
+pub(crate) fn closest_hit<'a, R: Borrow<dyn Object + Sync>>(
+    objects: &'a [R], ray: &Ray<f64>,
+) -> Option<Intersection<'a>> {
+    closest_object_hit(objects, ray, 0.0..f64::INFINITY).map(|(_, i)| i)
+}
+
+// The closest hit along with the index of the object it is on.
+fn closest_object_hit<'a, R: Borrow<dyn Object + Sync>>(
+    objects: &'a [R], ray: &Ray<f64>, range: Range<f64>,
+) -> Option<(usize, Intersection<'a>)> {
+    objects.iter().enumerate()
+        .filter_map(|(k, o)| o.borrow().intersect(ray, range.clone()).map(|i| (k, i)))
+        .filter(|(_, i)| !i.t().is_nan())
+        .min_by(|(_, x), (_, y)| x.t().total_cmp(&y.t()))
+}
+
+// Paths reaching an object with a sampling multiplier for the first time go on as that many copies
+// sharing their throughput, which then scatter independently, each on a stream of its own.
+fn split_paths<'a>(
+    paths: Vec<PathState>, hits: Vec<Option<(usize, Intersection<'a>)>>, sampling: &[u32],
+) -> (Vec<PathState>, Vec<Option<(usize, Intersection<'a>)>>) {
+    paths.into_iter().zip(hits).flat_map(|(p, hit)| {
+        let copies = match &hit {
+            Some((k, _)) if !p.split => sampling.get(*k).copied().unwrap_or(1).max(1),
+            _ => 1,
+        };
+        let p = match copies {
+            1 => p,
+            n => PathState { throughput: p.throughput / n as f64, weight: p.weight / n as f64, split: true, ..p },
+        };
+        iter::repeat_n((p, hit), copies as usize).enumerate().map(move |(c, (p, hit))| match copies {
+            1 => (p, hit),
+            _ => (PathState { seed: splitmix64(p.seed ^ splitmix64(c as u64)), ..p }, hit),
+        })
+    }).unzip()
+}
+
+// Seeds this thread's generator for one stream of a pass. Every pixel of every pass gets its own
+// stream, as does every camera sample for tracing (`seed_path`), so no two parts of the image share a
+// sequence of random numbers whichever threads end up rendering them, and the image depends only on the
+// passes rendered.
+fn seed_stream(pass: u32, stream: u64) {
+    RNG.with(|r| *r.borrow_mut() = counter_rng(splitmix64(pass as u64), stream));
+}
+
+// Seeds this thread's generator for one step of a path: finding what it hits at `depth`, or what it does
+// there. Paths are traced a wave at a time, so each step picks up the path's own stream rather than going
+// on from whichever path the thread traced before.
+fn seed_path(p: &PathState, depth: usize, shading: bool) {
+    RNG.with(|r| *r.borrow_mut() = counter_rng(p.seed, 2 * depth as u64 + shading as u64));
+}
+
+// The camera paths of a pass, along with where in its pixel each pixel's sample is, for the pixel filter.
+fn camera_wave(
+    camera: &Camera, width: u32, height: u32, settings: &RenderSettings, pass: u32, splits: &[u32],
+) -> (Vec<PathState>, Vec<(f64, f64)>) {
+    let passes = settings.samples * settings.threads;
+    let mut offsets = Vec::with_capacity((width * height) as usize);
+    let paths = iproduct!(0..width, 0..height).enumerate().flat_map(|(pixel, (i, j))| {
+        seed_stream(pass, pixel as u64);
+        let (x, y) = settings.sampler.pixel_offset(pixel, pass, || RNG.with(|r| r.borrow_mut().gen()));
+        offsets.push((x, y));
+        let u = (i as f64 + x - 0.5) / (width as f64);
+        let v = 1.0 - (j as f64 + y - 0.5) / (height as f64);
+        let k = splits[pixel];
+        (0..k).map(move |s| {
+            let lens = match k {
+                1 => settings.lens_sampler.lens_point(pixel, pass, passes),
+                k => Some(lens_stratum(s, k)),
+            };
+            let (ray, throughput) = camera.sample_ray(u, v, 1.0 / width as f64, 1.0 / height as f64, lens);
+            PathState {
+                pixel, ray, throughput, diffuse: false, caustic: false, sampled_lights: false, guided: Vec::new(),
+                media: Vec::new(), split: false, last: None, weight: 1.0, catcher: None, seed: 0,
+            }
+        })
+    }).enumerate().map(|(sample, p)| {
+        PathState { pixel: sample, seed: splitmix64(splitmix64(pass as u64) ^ splitmix64(sample as u64)), ..p }
+    }).collect::<Vec<_>>();
+    (paths, offsets)
+}
+
+// Traces the paths, adding what they see to their samples in `buffer` and how much of them isn't background
+// to `alpha`, and returns the number of rays traced. With a guide, the radiance found through guided bounces
+// goes to `training`, with `fireflies`, where the contributions over the clamp came from, and with
+// `light_paths`, the light by the number of bounces it took and how long the paths were.
+fn trace_wave<R: Borrow<dyn Object + Sync>>(
+    tracer: &Tracer<R>, mut paths: Vec<PathState>, buffer: &mut [Vector3<f64>], alpha: &mut [f64],
+    training: &mut Vec<(usize, f64)>, mut fireflies: Option<&mut Fireflies>, light_paths: Option<&mut LightPaths>,
+) -> u64 {
+    let Tracer {
+        objects, background, photons, fog, portals, lights, guide, sampling, max_depth, clamp, irradiance, transparent,
+        view,
+    } = *tracer;
+    let (mut bands, mut lengths) = match light_paths {
+        Some(LightPaths { bands, lengths }) => (Some(bands), Some(lengths)),
+        None => (None, None),
+    };
+    // `bounces` is the number of surfaces the light came off on the way
+    let mut contribute = |p: &PathState, radiance: Vector3<f64>, light: Light, depth: usize, bounces: usize| {
+        let mut contribution = p.throughput.component_mul(&radiance);
+        let brightest = contribution.max();
+        if let Some(clamp) = clamp.filter(|&c| brightest > c) {
+            contribution *= clamp / brightest;
+            if let Some(fireflies) = fireflies.as_deref_mut() {
+                fireflies.record(Origin { light, object: p.last, depth }, brightest - clamp);
+            }
+        }
+        buffer[p.pixel] += contribution;
+        if let Some(bands) = bands.as_deref_mut() {
+            bands[band(bounces)][p.pixel] += contribution;
+        }
+        training.extend(p.guided.iter().map(|g| g.sample(&contribution)));
+    };
+    // paths end having scattered `bounces` times, cut off if still going at the maximum depth
+    let mut end = |p: &PathState, bounces: usize, cut: bool| {
+        if let Some(lengths) = lengths.as_deref_mut() {
+            lengths.end(p.pixel, bounces, p.weight, cut);
+        }
+    };
+    let mut rays = 0;
+    for depth in 0..max_depth {
+        if paths.is_empty() {
+            break;
+        }
+        rays += paths.len() as u64;
+        // the camera only sees between the clipping planes
+        let hits = paths.iter()
+            .map(|p| {
+                seed_path(p, depth, false);
+                let range = if depth == 0 { view.clipped(&p.ray) } else { 0.0..f64::INFINITY };
+                closest_object_hit(objects, &p.ray, range)
             })
-    } else { Default::default() }
+            .collect::<Vec<_>>();
+        let (split, hits) = split_paths(paths, hits, sampling);
+        paths = split;
+        paths = paths.into_iter().zip(hits).filter_map(|(mut p, hit)| {
+            seed_path(&p, depth, true);
+            // fog scatters the paths that get through it before reaching a surface
+            let scattered = fog.and_then(|fog| {
+                let t = fog.distance(&p.ray);
+                (t.is_finite() && hit.as_ref().is_none_or(|(_, i)| t < i.t())).then(|| fog.scatter(&p.ray, t))
+            });
+            let escaped = scattered.is_none() && hit.is_none();
+            let caught = depth == 0 && scattered.is_none() && hit.as_ref().is_some_and(|(_, i)| i.shadow_catcher());
+            if let Some(catcher) = p.catcher.take() {
+                // the background shows through the catcher as far as the light it casts gets past the objects
+                let shown = match escaped {
+                    true if catcher.irradiance > 0.0 => {
+                        luminance(&background.radiance(&p.ray.direction().normalize())) / catcher.irradiance
+                    }
+                    true => 1.0,
+                    false => 0.0,
+                };
+                alpha[p.pixel] += p.weight * (1.0 - shown);
+                contribute(&p, catcher.behind * shown, Light::Background, depth, 0);
+                if escaped {
+                    end(&p, depth, false);
+                    return None;
+                }
+                p.throughput = p.throughput.component_mul(&catcher.attenuation);
+            } else if depth == 0 && !escaped && !caught {
+                alpha[p.pixel] += p.weight;
+            }
+            if let Some((ray, attenuation)) = scattered {
+                let throughput = p.throughput.component_mul(&attenuation);
+                return Some(PathState { ray, throughput, diffuse: true, caustic: false, sampled_lights: false, ..p });
+            }
+            match hit {
+                Some((k, i)) if caught => {
+                    // composited over the background, which the bounce off it tells how much of shows
+                    let (ray, attenuation) = i.scatter();
+                    let catcher = Catcher {
+                        behind: if transparent { Vector3::zeros() } else { background.radiance(p.ray.direction()) },
+                        irradiance: irradiance.get_or_init(|| Irradiance::new(background)).at(i.normal()),
+                        attenuation,
+                    };
+                    Some(PathState { ray, diffuse: true, last: Some(k), catcher: Some(catcher), ..p })
+                }
+                Some((k, i)) if i.medium().is_some() => {
+                    Some(PathState { last: Some(k), ..cross_interface(p, &i, i.medium().unwrap()) })
+                }
+                Some((k, i)) => {
+                    if !(p.sampled_lights && lights.covers(k)) {
+                        contribute(&p, i.emitted(), Light::Emitter(k), depth, depth);
+                    }
+                    let specular = i.specular();
+                    if let (Some(map), false) = (photons, specular) {
+                        // caustics, having come off a specular surface before this one
+                        contribute(&p, map.radiance(&i), Light::Photons, depth, depth + 2);
+                    }
+                    if !specular && !portals.is_empty() {
+                        rays += 1;
+                        let sky = sky_through_portals(objects, fog, background, portals, &i);
+                        contribute(&p, sky, Light::Portals, depth, depth + 1);
+                    }
+                    if !specular && !lights.is_empty() {
+                        rays += 1;
+                        if let Some((object, radiance)) = light_from_emitters(objects, fog, lights, &i) {
+                            contribute(&p, radiance, Light::Emitter(object), depth, depth + 1);
+                        }
+                    }
+                    let (ray, attenuation, evaluated) = match guide {
+                        Some(guide) if !specular && RNG.with(|r| r.borrow_mut().gen::<f64>()) < GUIDED => {
+                            // this relies on `eval` covering everything the material scatters
+                            let (direction, pdf, slot) = guide.sample(i.point(), i.normal());
+                            let weight = i.eval(&direction) * direction.dot(i.normal()).abs() / pdf;
+                            p.guided.push(GuidedBounce::new(slot, pdf, &p.throughput.component_mul(&weight)));
+                            (Ray::new(*i.point(), direction), weight, true)
+                        }
+                        _ => i.scatter_lobe(),
+                    };
+                    // the lights were sampled through `eval`, which only covers some lobes of some materials
+                    let sampled_lights = evaluated && !specular && (!portals.is_empty() || !lights.is_empty());
+                    let throughput = p.throughput.component_mul(&attenuation);
+                    // absorbed, as by lights, which reflect nothing
+                    if throughput == Vector3::zeros() {
+                        end(&p, depth + 1, false);
+                        return None;
+                    }
+                    let (diffuse, caustic) = (p.diffuse || !specular, specular && p.diffuse);
+                    Some(PathState { ray, throughput, diffuse, caustic, sampled_lights, last: Some(k), ..p })
+                }
+                None => {
+                    // diffuse-specular-light paths are already accounted for by the caustic photon map
+                    let counted = p.sampled_lights && portal::crosses_any(portals, &p.ray);
+                    // and a transparent background is left out where the camera sees it
+                    let hidden = counted || transparent && depth == 0;
+                    if (photons.is_none() || !p.caustic) && !hidden {
+                        contribute(&p, background.radiance(p.ray.direction()), Light::Background, depth, depth);
+                    }
+                    end(&p, depth, false);
+                    None
+                }
+            }
+        }).collect();
+    }
+    paths.iter().for_each(|p| end(p, max_depth, true));
+    rays
 }
 
-fn worker<R: Borrow<dyn Object + Sync>>(
-    camera: &Camera, objects: &[R],
-    width: u32, height: u32, i: u32, j: u32,
+// How much of the light along a shadow ray over `range` gets to its origin: none if a surface is in the way,
+// and otherwise what the fog and the media it crosses let through.
+fn transmittance<R: Borrow<dyn Object + Sync>>(
+    objects: &[R], fog: Option<&Fog>, ray: &Ray<f64>, range: Range<f64>,
+) -> f64 {
+    let mut transmittance = fog.map_or(1.0, |fog| fog.transmittance(ray, range.clone()));
+    for object in objects {
+        let object = object.borrow();
+        match object.transmittance(ray, range.clone()) {
+            Some(t) => transmittance *= t,
+            None if object.intersect(ray, range.clone()).is_some_and(|i| !i.t().is_nan()) => return 0.0,
+            None => {}
+        }
+    }
+    transmittance
+}
+
+// The sky seen through a random point of a random portal, as far as it gets there, through the lobes the
+// material's `eval` covers; paths bouncing off the others find the sky themselves.
+fn sky_through_portals<R: Borrow<dyn Object + Sync>>(
+    objects: &[R], fog: Option<&Fog>, background: &Background, portals: &[Portal], int: &Intersection,
 ) -> Vector3<f64> {
-    (0..NUM_SAMPLES).map(|_| {
-        let u = (i as f64 + RNG.with(|r| r.borrow_mut().gen_range(-0.5..0.5))) / (width as f64);
-        let v = 1.0 - (j as f64 + RNG.with(|r| r.borrow_mut().gen_range(-0.5..0.5))) / (height as f64);
-        let ray = camera.ray_at(u, v);
-        ray_color(objects, &ray, 20)
-    }).sum::<Vector3<f64>>() / (NUM_SAMPLES as f64)
+    let (direction, pdf) = portal::sample(portals, int.point());
+    if pdf <= 0.0 {
+        return Vector3::zeros();
+    }
+    let shown = transmittance(objects, fog, &Ray::new(*int.point(), direction), 0.0..f64::INFINITY);
+    let cos = direction.dot(int.normal()).abs();
+    int.eval(&direction).component_mul(&background.radiance(&direction)) * (shown * cos / pdf)
+}
+
+// The light reaching `int` from an emitter picked from the light tree, as far as it gets there, along with
+// the object it is on. Like the portals, this only covers the lobes `eval` does.
+fn light_from_emitters<R: Borrow<dyn Object + Sync>>(
+    objects: &[R], fog: Option<&Fog>, lights: &LightTree, int: &Intersection,
+) -> Option<(usize, Vector3<f64>)> {
+    let (object, emitter, chance) = lights.sample(int.point(), int.normal())?;
+    let (direction, distance, pdf) = emitter.sample(int.point())?;
+    // short of the emitter, so as not to hit it
+    let shown = transmittance(objects, fog, &Ray::new(*int.point(), direction), 0.0..distance * (1.0 - 1e-6));
+    if shown == 0.0 {
+        return None;
+    }
+    let cos = direction.dot(int.normal()).abs();
+    Some((object, int.eval(&direction).component_mul(&emitter.radiance) * (shown * cos / (chance * pdf))))
 }
 
-fn create_camera() -> Camera {
-    Camera::look_at(
-        Vector3::new(13.0, 2.0, 3.0),
-        &Vector3::new(0.0, 0.0, 0.0),
-        &Vector3::new(0.0, 1.0, 0.0),
-        PI / 9.0,
-        3.0 / 2.0,
-        0.1,
-        10.0,
-    )
+// Keeps track of the media a path is inside so that nested dielectrics refract by the ratio of the
+// indices on either side. Where media overlap the highest-priority one (the latest entered among
+// equals) wins, and surfaces of the others inside it are false hits that the path passes straight through.
+fn cross_interface(mut p: PathState, int: &Intersection, medium: Medium) -> PathState {
+    let top = |media: &[Medium]| media.iter().rev().max_by_key(|m| m.priority).copied();
+    // rays leave from just off the surface on the side they head to, or they would hit it again at once
+    let offset = int.normal() * 1e-9 * int.point().amax().max(1.0);
+    let leave = |ray: Ray<f64>| {
+        let side = if ray.direction().dot(int.normal()) < 0.0 { -offset } else { offset };
+        Ray::new(ray.origin + side, *ray.direction())
+    };
+    // shadow rays stop at any surface, so lights only count straight from the surface that sampled them
+    let pass = |p: PathState| PathState {
+        ray: leave(Ray::new(*int.point(), *int.ray().direction())), sampled_lights: false, ..p
+    };
+    let (from, to) = if int.front() {
+        let current = top(&p.media);
+        if current.is_some_and(|c| c.priority > medium.priority) {
+            p.media.push(medium);
+            return pass(p);
+        }
+        (current.map_or(1.0, |c| c.index_refraction), medium.index_refraction)
+    } else {
+        let inside = p.media.iter().rposition(|m| *m == medium);
+        let innermost = inside.map(|i| p.media.remove(i));
+        let outside = top(&p.media);
+        if outside.is_some_and(|c| c.priority > medium.priority) {
+            return pass(p);
+        }
+        p.media.extend(innermost);
+        (medium.index_refraction, outside.map_or(1.0, |c| c.index_refraction))
+    };
+    let (ray, transmitted) = Medium::scatter(int, from, to);
+    if transmitted {
+        if int.front() {
+            p.media.push(medium);
+        } else if let Some(i) = p.media.iter().rposition(|m| *m == medium) {
+            p.media.remove(i);
+        }
+    }
+    PathState { ray: leave(ray), caustic: p.diffuse, sampled_lights: false, ..p }
 }
 
-fn random_range(range: Range<f64>) -> f64 {
-    RNG.with(|r| r.borrow_mut().gen_range(range))
+fn worker<R: Borrow<dyn Object + Sync>>(
+    camera: &Camera, tracer: &Tracer<R>, guide: Option<&Guide>, settings: &RenderSettings, control: &Control,
+) {
+    let (width, height) = settings.image_size();
+    let lens_splits = if camera.defocused() { settings.lens_splits } else { 1 };
+    let mut splitting = LensSplitting::new((width * height) as usize, lens_splits);
+    while let Some(pass) = control.claim() {
+        let splits = splitting.splits();
+        let (paths, offsets) = camera_wave(camera, width, height, settings, pass, &splits);
+        let (mut samples, mut alpha) = (vec![Vector3::zeros(); paths.len()], vec![0.0; paths.len()]);
+        let distribution = guide.map(Guide::distribution);
+        let mut training = Vec::new();
+        let tracer = Tracer { guide: distribution.as_deref(), ..*tracer };
+        let mut fireflies = settings.fireflies.then(Fireflies::default);
+        let mut light_paths = settings.light_paths.then(|| LightPaths::new(paths.len()));
+        let rays = trace_wave(
+            &tracer, paths, &mut samples, &mut alpha, &mut training, fireflies.as_mut(), light_paths.as_mut(),
+        );
+        if let Some(guide) = guide {
+            guide.train(&training);
+        }
+        if let Some(fireflies) = &fireflies {
+            control.add_fireflies(fireflies);
+        }
+        let (pass, alpha) = (splitting.gather(&splits, &samples), LensSplitting::mean(&splits, &alpha));
+        let node_visits = counters::take_node_visits();
+        if let Some(light_paths) = light_paths {
+            let mut light_paths = light_paths.gather(&splits);
+            if settings.filter != PixelFilter::Box {
+                let filter = settings.filter;
+                light_paths.bands = light_paths.bands.map(|band| filter.splat(width, height, &band, &offsets).0);
+            }
+            control.add_light_paths(&light_paths);
+        }
+        match settings.filter {
+            PixelFilter::Box => control.accumulate(&pass, &alpha, None, &splits, rays, node_visits),
+            filter => {
+                let (sums, weights) = filter.splat(width, height, &pass, &offsets);
+                let (alpha, _) = filter.splat(width, height, &alpha, &offsets);
+                control.accumulate(&sums, &alpha, Some(&weights), &splits, rays, node_visits);
+            }
+        }
+    }
+}
+
+// Runs each job on a thread of its own and waits for them all. wasm32 has no threads, so there the jobs
+// run one after another on the calling thread; a render's first worker then claims every pass.
+fn run_parallel<F: FnOnce() + Send>(jobs: impl IntoIterator<Item=F>) {
+    if cfg!(target_arch = "wasm32") {
+        jobs.into_iter().for_each(|job| job());
+        return;
+    }
+    crossbeam::scope(|s| {
+        for job in jobs {
+            s.spawn(move |_| job());
+        }
+    }).unwrap();
 }
 
-fn random_vector(range: Range<f64>) -> Vector3<f64> {
+fn create_view() -> View {
+    View {
+        from: Vector3::new(13.0, 2.0, 3.0),
+        at: Vector3::new(0.0, 0.0, 0.0),
+        up: Vector3::new(0.0, 1.0, 0.0),
+        fov: 20.0,
+        aperture: 0.1,
+        focus_distance: 10.0,
+        focus: None,
+        lens: Lens::default(),
+        clipping: (0.0, f64::INFINITY),
+    }
+}
+
+const SCENE_SEED: u64 = 0;
+
+// The SplitMix64 output function, a good enough hash to seed independent streams from counters.
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let z = x.wrapping_add(0x9e3779b97f4a7c15);
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// A generator that depends only on the seed and the counter, so that each cell or copy of a
+// generated scene comes out the same regardless of the order it is built in.
+pub(crate) fn counter_rng(seed: u64, counter: u64) -> SmallRng {
+    SmallRng::seed_from_u64(splitmix64(seed ^ splitmix64(counter)))
+}
+
+fn random_vector(rng: &mut SmallRng, range: Range<f64>) -> Vector3<f64> {
     let uniform = Uniform::from(range);
-    let x = RNG.with(|r| uniform.sample(&mut *r.borrow_mut()));
-    let y = RNG.with(|r| uniform.sample(&mut *r.borrow_mut()));
-    let z = RNG.with(|r| uniform.sample(&mut *r.borrow_mut()));
-    Vector3::new(x, y, z)
+    Vector3::new(uniform.sample(rng), uniform.sample(rng), uniform.sample(rng))
 }
 
-fn create_scene() -> Vec<Box<dyn Object + Sync>> {
-    let mut scene = iproduct!(RANDOM_RANGE, RANDOM_RANGE)
-        .filter_map(|(a, b)| -> Option<Box<dyn Object + Sync>> {
-            let x = a as f64 + random_range(0.0..0.9);
+type Objects = Vec<Box<dyn Object + Sync>>;
+type Image = (u32, u32, Vec<Vector3<f64>>);
+
+fn create_scene() -> Objects {
+    let mut scene = iproduct!(RANDOM_RANGE, RANDOM_RANGE).enumerate()
+        .filter_map(|(cell, (a, b))| -> Option<Box<dyn Object + Sync>> {
+            let rng = &mut counter_rng(SCENE_SEED, cell as u64);
+            let x = a as f64 + rng.gen_range(0.0..0.9);
             let y = 0.2;
-            let z = b as f64 + random_range(0.0..0.9);
+            let z = b as f64 + rng.gen_range(0.0..0.9);
             let center = Vector3::new(x, y, z);
             if (center - Vector3::new(4.0, 0.2, 0.0)).norm_squared() > 0.81 {
                 let sphere = Sphere::new(center, 0.2);
-                let choose_material = RNG.with(|r| r.borrow_mut().gen::<f64>());
+                let choose_material = rng.gen::<f64>();
                 Some(if choose_material < 0.8 {
-                    let color = random_vector(0.0..1.0).component_mul(&random_vector(0.0..1.0));
-                    box (sphere, Lambertian::new(color))
+                    let color = random_vector(rng, 0.0..1.0).component_mul(&random_vector(rng, 0.0..1.0));
+                    Box::new((sphere, Lambertian::new(color)))
                 } else if choose_material < 0.95 {
-                    let color = random_vector(0.5..1.0);
-                    let fuzz = random_range(0.0..0.5);
-                    box (sphere, Metal::new(color, fuzz))
+                    let color = random_vector(rng, 0.5..1.0);
+                    let fuzz = rng.gen_range(0.0..0.5);
+                    Box::new((sphere, Metal::new(color, fuzz)))
                 } else {
-                    box (sphere, Dielectric::new(1.5))
+                    Box::new((sphere, Dielectric::new(1.5)))
                 })
             } else { None }
         }).collect::<Vec<_>>();
-    scene.push(box (
+    scene.push(Box::new((
         Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0),
         Lambertian::new(Vector3::new(0.5, 0.5, 0.5))
-    ));
-    scene.push(box (
+    )));
+    scene.push(Box::new((
         Sphere::new(Vector3::new(0.0, 1.0, 0.0), 1.0),
         Dielectric::new(1.5)
-    ));
-    scene.push(box (
+    )));
+    scene.push(Box::new((
         Sphere::new(Vector3::new(-4.0, 1.0, 0.0), 1.0),
         Lambertian::new(Vector3::new(0.4, 0.2, 0.1))
-    ));
-    scene.push(box (
+    )));
+    scene.push(Box::new((
         Sphere::new(Vector3::new(4.0, 1.0, 0.0), 1.0),
         Metal::new(Vector3::new(0.7, 0.6, 0.5), 0.0)
-    ));
+    )));
     scene
 }
 
-pub fn render() -> (u32, u32, Vec<Vector3<f64>>) {
-    let camera = create_camera();
-    let scene = create_scene();
-    let objects = &scene[..];
+pub fn render(settings: &RenderSettings) -> Result<(u32, u32, Vec<Vector3<f64>>)> {
+    render_with(settings, &Control::new(settings))
+}
 
-    let mut results = crossbeam::scope(|s| {
-        let threads = (0..NUM_THREADS).map(|_| {
-            s.spawn(|_| {
-                iproduct!(0..IMAGE_WIDTH, 0..IMAGE_HEIGHT)
-                    .map(|(i, j)| worker(&camera, objects, IMAGE_WIDTH, IMAGE_HEIGHT, i, j))
-                    .collect::<Vec<_>>()
-            })
-        }).collect::<Vec<_>>();
-        threads.into_iter().map(|t| t.join().unwrap()).collect::<Vec<_>>()
-    }).unwrap().into_iter();
+// The scene file named by the settings, or the built-in scene without one.
+pub fn load_scene(settings: &RenderSettings) -> Result<Scene> {
+    Ok(match &settings.scene {
+        Some(path) => {
+            let mut scene = Scene::load(Path::new(path), &settings.overrides)?;
+            scene.autofocus(aspect_ratio(settings))?;
+            scene
+        }
+        None => Scene {
+            view: create_view(), cameras: Vec::new(), objects: create_scene(), materials: MaterialLibrary::new(),
+            fog: None, portals: Vec::new(), neutral: None, names: Vec::new(), sampling: Vec::new(),
+            emissive: Vec::new(), background: Background::default(), preview: PreviewSettings::default(),
+            // the built-in scene is decided by the version, which the fingerprint has anyway
+            summary: None, source: Some(0),
+        },
+    })
+}
 
-    let mut buffer = results.next().unwrap();
-    results.for_each(|r| {
-        buffer.iter_mut().zip(&r).for_each(|(a, b)| *a += b);
-    });
-    buffer.iter_mut().for_each(|x| {
-        *x = (*x / NUM_THREADS as f64).map(f64::sqrt);
+fn aspect_ratio(settings: &RenderSettings) -> f64 {
+    settings.width as f64 / settings.height as f64
+}
+
+// The view's camera for the frame, or the pair of eyes sharing it, narrowed to the crop window if there
+// is one.
+pub(crate) fn frame_camera(view: &View, settings: &RenderSettings) -> Camera {
+    let camera = match settings.stereo {
+        Some(stereo) => {
+            let eye = match stereo.layout {
+                StereoLayout::SideBySide => 0.5,
+                StereoLayout::OverUnder => 2.0,
+            };
+            view.camera(aspect_ratio(settings) * eye).stereo(stereo)
+        }
+        None => view.camera(aspect_ratio(settings)),
+    };
+    match settings.crop_window() {
+        Some((x, y, width, height)) => {
+            let (w, h) = (settings.width as f64, settings.height as f64);
+            let u = x as f64 / w..(x + width) as f64 / w;
+            let v = 1.0 - (y + height) as f64 / h..1.0 - y as f64 / h;
+            camera.window(u, v)
+        }
+        None => camera,
+    }
+}
+
+pub fn render_with(settings: &RenderSettings, control: &Control) -> Result<(u32, u32, Vec<Vector3<f64>>)> {
+    render_scene(&load_scene(settings)?, settings, control)
+}
+
+// Renders a scene built in code rather than loaded from a file; `settings.scene` is ignored.
+pub fn render_scene(scene: &Scene, settings: &RenderSettings, control: &Control) -> Result<Image> {
+    // the backplate is read first so a missing one doesn't cost a render
+    let plate = settings.backplate.as_deref().map(ImageTexture::load).transpose()?;
+    let camera = frame_camera(&scene.view, settings);
+    let objects = &scene.objects[..];
+    let photons = match settings.integrator {
+        Integrator::PathTracing | Integrator::PathGuiding => None,
+        Integrator::PhotonMapping { photons, radius } =>
+            Some(PhotonMap::emit(objects, &scene.background, photons, radius, settings.max_depth)),
+    };
+    let (photons, fog) = (photons.as_ref(), scene.fog.as_ref());
+    let portals = &scene.portals;
+    let guide = match settings.integrator {
+        Integrator::PathGuiding => Some(Guide::new(&camera, objects)),
+        _ => None,
+    };
+    let sampling = &scene.sampling[..];
+    let lights = LightTree::new(objects);
+    let tracer = Tracer {
+        objects, background: &scene.background, photons, fog, portals, lights: &lights, guide: None, sampling,
+        max_depth: settings.max_depth, clamp: settings.clamp, irradiance: &OnceLock::new(),
+        transparent: settings.transparent || settings.backplate.is_some(), view: &scene.view,
+    };
+
+    run_parallel((0..settings.threads).map(|_| || worker(&camera, &tracer, guide.as_ref(), settings, control)));
+    let image = match settings.white_balance {
+        Some(mode) => white_balance(control.linear_image(), mode, &camera, objects, scene.neutral),
+        None => control.linear_image(),
+    };
+    let image = if settings.denoise { denoise(image) } else { image };
+    let image = match &plate {
+        Some(plate) => composite(image, &control.alpha().2, plate, settings),
+        None => image,
+    };
+    let image = display(image, settings);
+    Ok(match &settings.lut {
+        Some(lut) => lut.apply(image),
+        None => image,
+    })
+}
+
+// Lays a render with its alpha over `plate`, stretched over the full frame. Renders are premultiplied, having
+// left out what they let through.
+fn composite(image: Image, alpha: &[f64], plate: &ImageTexture, settings: &RenderSettings) -> Image {
+    let (width, height, buffer) = image;
+    let (x, y, _, _) = settings.crop_window().unwrap_or((0, 0, width, height));
+    let (w, h) = (settings.width as f64, settings.height as f64);
+    let buffer = iproduct!(0..width, 0..height).zip(buffer.iter().zip(alpha)).map(|((i, j), (color, a))| {
+        let uv = Vector2::new((x + i) as f64 + 0.5, h - (y + j) as f64 - 0.5).component_div(&Vector2::new(w, h));
+        color + plate.lookup(&uv, 0.0) * (1.0 - a)
+    }).collect();
+    (width, height, buffer)
+}
+
+// Renders the scene through each of its named cameras in turn, focused as each asks, with a fresh control
+// from `control` for every one. The scene's own view is put back afterwards.
+pub fn render_all_cameras(
+    scene: &mut Scene, settings: &RenderSettings, control: impl Fn() -> Control,
+) -> Result<Vec<(String, Image)>> {
+    let view = scene.view.clone();
+    let images = scene.cameras.clone().into_iter().map(|(name, camera)| {
+        scene.view = camera;
+        scene.autofocus(aspect_ratio(settings))?;
+        Ok((name, render_scene(scene, settings, &control())?))
+    }).collect();
+    scene.view = view;
+    images
+}
+
+// Renders `frames` frames of the camera orbiting the point it looks at, a full turn in all, and hands them
+// to `frame` in order as they finish, for showing off a model. Each frame is focused as the view asks, with
+// a fresh control from `control`, and the scene's own view is put back afterwards.
+pub fn turntable(
+    scene: &mut Scene, frames: u32, settings: &RenderSettings, control: impl Fn() -> Control,
+    mut frame: impl FnMut(Image) -> Result<()>,
+) -> Result<()> {
+    let view = scene.view.clone();
+    let result = (0..frames).try_for_each(|k| {
+        scene.view = view.orbited(360.0 * k as f64 / frames as f64);
+        scene.autofocus(aspect_ratio(settings))?;
+        frame(render_scene(scene, settings, &control())?)
     });
-    (IMAGE_WIDTH, IMAGE_HEIGHT, buffer)
+    scene.view = view;
+    result
 }
 
-pub fn write_to_file(path: &str, image: (u32, u32, Vec<Vector3<f64>>)) {
-    let mut file = File::create(path).unwrap();
+#[cfg(feature = "ocio")]
+fn display(image: (u32, u32, Vec<Vector3<f64>>), settings: &RenderSettings) -> (u32, u32, Vec<Vector3<f64>>) {
+    match &settings.display_transform {
+        Some(transform) => transform.apply(image),
+        None => control::to_display(image),
+    }
+}
+
+#[cfg(not(feature = "ocio"))]
+fn display(image: (u32, u32, Vec<Vector3<f64>>), _settings: &RenderSettings) -> (u32, u32, Vec<Vector3<f64>>) {
+    control::to_display(image)
+}
+
+// A quick look for setting up scenes: one ray through the center of each pixel, no bounces, and surfaces
+// shaded flat by how squarely they face the camera, ignoring materials and fog, with `guides` drawn over.
+pub fn preview(settings: &RenderSettings, guides: &Guides) -> Result<(u32, u32, Vec<Vector3<f64>>)> {
+    let scene = load_scene(settings)?;
+    let camera = frame_camera(&scene.view, settings);
+    let (width, height) = settings.image_size();
+    let pixels = iproduct!(0..width, 0..height).collect::<Vec<_>>();
+    let chunk = pixels.len().div_ceil(settings.threads.max(1) as usize).max(1);
+    let mut buffer = vec![(Vector3::zeros(), f64::INFINITY); pixels.len()];
+    let (camera, objects, background) = (&camera, &scene.objects[..], &scene.background);
+    run_parallel(pixels.chunks(chunk).zip(buffer.chunks_mut(chunk)).map(|(pixels, buffer)| move || {
+        for (&(i, j), pixel) in pixels.iter().zip(buffer) {
+            let u = (i as f64 + 0.5) / width as f64;
+            let v = 1.0 - (j as f64 + 0.5) / height as f64;
+            let ray = camera.ray_through(u, v, 1.0 / width as f64, 1.0 / height as f64, [0.0, 0.0]);
+            *pixel = match closest_hit(objects, &ray) {
+                Some(i) => (Vector3::repeat(0.2 + 0.8 * i.normal().dot(ray.direction()).abs()), i.t()),
+                None => (background.radiance(ray.direction()), f64::INFINITY),
+            };
+        }
+    }));
+    let (buffer, distances): (Vec<_>, Vec<_>) = buffer.into_iter().unzip();
+    let mut image = (width, height, buffer);
+    if guides.any() {
+        helpers::draw(&mut image, &distances, camera, &guides.segments(&scene, aspect_ratio(settings)));
+    }
+    Ok(image)
+}
+
+pub fn write_to_file(path: &str, image: (u32, u32, Vec<Vector3<f64>>)) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
     let (width, height, buffer) = image;
-    writeln!(file, "{} {}", width, height).unwrap();
-    buffer.iter().for_each(|c| {
+    writeln!(file, "{} {}", width, height)?;
+    for c in &buffer {
         let color = c.map(|x| (x * 255.0) as u8);
-        writeln!(file, "{} {} {}", color.x, color.y, color.z).unwrap();
-    });
+        writeln!(file, "{} {} {}", color.x, color.y, color.z)?;
+    }
+    Ok(file.flush()?)
+}
+
+// Saves in the format given by the extension, PNG or PPM among others, falling back to the plain text
+// format of `write_to_file`.
+pub fn save_image(path: &str, image: (u32, u32, Vec<Vector3<f64>>)) -> Result<()> {
+    let (width, height, buffer) = &image;
+    match image::ImageFormat::from_path(path) {
+        Ok(_) => Ok(image::RgbImage::from_fn(*width, *height, |i, j| {
+            let color = buffer[(i * height + j) as usize].map(|x| (x.clamp(0.0, 1.0) * 255.0) as u8);
+            image::Rgb([color.x, color.y, color.z])
+        }).save(path)?),
+        Err(_) => write_to_file(path, image),
+    }
+}
+
+// Saves a render left transparent with its alpha, premultiplied as it was rendered: 8-bit RGBA in most
+// formats and floats in OpenEXR. The text format has no alpha channel, so it gets the color alone.
+pub fn save_rgba(path: &str, image: Image, alpha: &[f64]) -> Result<()> {
+    let (width, height, buffer) = &image;
+    let pixel = |i: u32, j: u32| {
+        let k = (i * height + j) as usize;
+        (buffer[k], alpha[k])
+    };
+    match image::ImageFormat::from_path(path) {
+        Ok(image::ImageFormat::OpenExr) => Ok(image::Rgba32FImage::from_fn(*width, *height, |i, j| {
+            let (color, a) = pixel(i, j);
+            image::Rgba([color.x as f32, color.y as f32, color.z as f32, a as f32])
+        }).save(path)?),
+        Ok(_) => Ok(image::RgbaImage::from_fn(*width, *height, |i, j| {
+            let (color, a) = pixel(i, j);
+            let [r, g, b, a] = [color.x, color.y, color.z, a].map(|x| (x.clamp(0.0, 1.0) * 255.0) as u8);
+            image::Rgba([r, g, b, a])
+        }).save(path)?),
+        Err(_) => write_to_file(path, image),
+    }
+}
+
+// Saves the values as they are, unclamped, in OpenEXR if the extension is `.exr`, and otherwise like
+// `save_image`.
+pub fn save_float_image(path: &str, image: (u32, u32, Vec<Vector3<f64>>)) -> Result<()> {
+    let (width, height, buffer) = &image;
+    match image::ImageFormat::from_path(path) {
+        Ok(image::ImageFormat::OpenExr) => Ok(image::Rgb32FImage::from_fn(*width, *height, |i, j| {
+            let color = buffer[(i * height + j) as usize].map(|x| x as f32);
+            image::Rgb([color.x, color.y, color.z])
+        }).save(path)?),
+        _ => save_image(path, image),
+    }
+}
+
+// Saves a pass of the render's light as it is in OpenEXR, where the passes add up to the render, and through
+// the display transform otherwise, to be looked at beside it.
+pub fn save_light_pass(path: &str, image: Image, settings: &RenderSettings) -> Result<()> {
+    match image::ImageFormat::from_path(path) {
+        Ok(image::ImageFormat::OpenExr) => save_float_image(path, image),
+        _ => save_image(path, display(image, settings)),
+    }
+}
+
+// Reads an image written by `save_image`, in any format the image crate knows or the text format.
+pub fn load_image(path: &str) -> Result<(u32, u32, Vec<Vector3<f64>>)> {
+    match image::ImageFormat::from_path(path) {
+        Ok(_) => {
+            let image = image::open(path)?.to_rgb8();
+            let (width, height) = image.dimensions();
+            let buffer = (0..width).flat_map(|i| (0..height).map(move |j| (i, j)))
+                .map(|(i, j)| Vector3::from(image.get_pixel(i, j).0).map(|x| x as f64 / 255.0))
+                .collect();
+            Ok((width, height, buffer))
+        }
+        Err(_) => read_from_file(path),
+    }
 }
 
-pub fn read_from_file(path: &str) -> (u32, u32, Vec<Vector3<f64>>) {
-    let file = File::open(path).unwrap();
+pub fn read_from_file(path: &str) -> Result<(u32, u32, Vec<Vector3<f64>>)> {
+    let file = File::open(path)?;
     let reader = BufReader::new(file);
     let mut lines = reader.lines();
-    let header = lines.next().unwrap().unwrap();
-    let (width, height) = {
-        let mut i = header
-            .split_ascii_whitespace()
-            .map(|s| s.parse::<u32>().unwrap());
-        (i.next().unwrap(), i.next().unwrap())
+    let invalid = |what: &str| Error::ImageFile(format!("{}: {}", path, what));
+    let numbers = |line: &str| line
+        .split_ascii_whitespace()
+        .map(|s| s.parse::<u32>().map_err(|_| invalid("expected a number")))
+        .collect::<Result<Vec<_>>>();
+    let (width, height) = match numbers(&lines.next().ok_or_else(|| invalid("missing header"))??)?[..] {
+        [width, height] => (width, height),
+        _ => return Err(invalid("the header should be the width and height")),
     };
-    let buffer = lines.map(|s| Vector3::from_iterator(
-        s.unwrap()
-            .split_ascii_whitespace()
-            .map(|s| s.parse::<u32>().unwrap() as f64 / 255.0)
-    )).collect();
-    (width, height, buffer)
+    let buffer = lines.map(|line| match numbers(&line?)?[..] {
+        [r, g, b] => Ok(Vector3::new(r, g, b).map(|x| x as f64 / 255.0)),
+        _ => Err(invalid("expected three components per pixel")),
+    }).collect::<Result<Vec<_>>>()?;
+    if buffer.len() != (width * height) as usize {
+        return Err(invalid("the pixel count doesn't match the size"));
+    }
+    Ok((width, height, buffer))
 }
 
 #[cfg(feature = "sdl2")]
-pub fn show_image(image: (u32, u32, Vec<Vector3<f64>>)) {
+pub fn show_image(image: (u32, u32, Vec<Vector3<f64>>)) -> Result<()> {
     use sdl2::event::Event;
     use sdl2::keyboard::Keycode;
     use sdl2::pixels::Color;
     use sdl2::rect::Point;
 
+    use crate::error::sdl;
+
     let (width, height, buffer) = image;
-    let sdl = sdl2::init().unwrap();
-    let window_subsystem = sdl.video().unwrap();
-    let window = window_subsystem
+    let context = sdl(sdl2::init())?;
+    let window_subsystem = sdl(context.video())?;
+    let window = sdl(window_subsystem
         .window("Raytracer", width, height)
-        .build()
-        .unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
-    iproduct!(0..width, 0..height)
-        .zip(buffer.into_iter())
-        .for_each(|((i, j), c)| {
-            let color = c.map(|x| (x * 255.0) as u8);
-            canvas.set_draw_color(Color::RGB(color.x, color.y, color.z));
-            canvas.draw_point(Point::new(i as i32, j as i32)).unwrap();
-        });
+        .build())?;
+    let mut canvas = sdl(window.into_canvas().build())?;
+    for ((i, j), c) in iproduct!(0..width, 0..height).zip(buffer) {
+        let color = c.map(|x| (x * 255.0) as u8);
+        canvas.set_draw_color(Color::RGB(color.x, color.y, color.z));
+        sdl(canvas.draw_point(Point::new(i as i32, j as i32)))?;
+    }
     canvas.present();
-    let mut event_pump = sdl.event_pump().unwrap();
+    let mut event_pump = sdl(context.event_pump())?;
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// Flies the camera around the scene with WASD, Q and E for down and up, dragging with the left mouse
+// button to look around and the wheel to change speed. The image refines progressively, one sample per
+// thread per frame up to the samples per thread of a render, and starts over whenever the camera moves.
+// H shows and hides `guides`, which start out shown, or the scene-setup helpers if there are none. The
+// scene's preview settings apply, so heavy scenes stay responsive at the cost of bounces, fireflies and fog.
+#[cfg(feature = "sdl2")]
+pub fn fly(settings: &RenderSettings, guides: &Guides) -> Result<()> {
+    use std::time::{Duration, Instant};
+
+    use sdl2::event::Event;
+    use sdl2::keyboard::{Keycode, Scancode};
+    use sdl2::pixels::PixelFormatEnum;
+
+    use crate::error::sdl;
+
+    const MOUSE_SENSITIVITY: f64 = 0.005;
+
+    let scene = load_scene(settings)?;
+    let mut view = scene.view.clone();
+    let mut background = scene.background.clone();
+    let preview = scene.preview;
+    let fog = scene.fog.as_ref().filter(|_| preview.fog);
+    let (objects, portals) = (&scene.objects[..], &scene.portals[..]);
+    let lights = &LightTree::new(objects);
+    let sampling = &scene.sampling[..];
+    let max_depth = settings.max_depth.min(preview.max_depth);
+    let clamp = match (settings.clamp, preview.clamp) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    view.aperture = 0.0;
+    let (width, height) = (settings.width, settings.height);
+    let pixels = (width * height) as usize;
+    let context = sdl(sdl2::init())?;
+    let window = sdl(sdl(context.video())?
+        .window("Raytracer", width, height)
+        .build())?;
+    let mut canvas = sdl(window.into_canvas().build())?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = sdl(texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, width, height))?;
+    let mut event_pump = sdl(context.event_pump())?;
+
+    let front = (view.at - view.from).normalize();
+    let (mut yaw, mut pitch) = (front.z.atan2(front.x), front.y.asin());
+    let mut speed = (view.at - view.from).norm() / 2.0;
+    let mut accumulated = vec![Vector3::zeros(); pixels];
+    let mut passes = 0;
+    // `samples` counts per thread, as in renders
+    let target = settings.samples * settings.threads;
+    let mut shown = guides.any();
+    let guides = if shown { *guides } else { Guides { helpers: true, ..Guides::default() } };
+    let segments = guides.segments(&scene, aspect_ratio(settings));
+    let mut distances = None;
+    let mut last = Instant::now();
     loop {
+        let mut moved = false;
+        let mut relit = false;
+        let mut toggled = false;
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    return;
+                    return Ok(());
+                }
+                Event::KeyDown { keycode: Some(Keycode::H), repeat: false, .. } => {
+                    shown = !shown;
+                    toggled = true;
+                }
+                // the brackets turn the background and minus and equals step its intensity, to relight the
+                // scene
+                Event::KeyDown { keycode: Some(key), .. } => {
+                    match key {
+                        Keycode::LeftBracket => background.rotation -= 15.0,
+                        Keycode::RightBracket => background.rotation += 15.0,
+                        Keycode::Minus => background.intensity /= 1.25,
+                        Keycode::Equals => background.intensity *= 1.25,
+                        _ => continue,
+                    }
+                    relit = true;
+                }
+                Event::MouseMotion { mousestate, xrel, yrel, .. } if mousestate.left() => {
+                    yaw += xrel as f64 * MOUSE_SENSITIVITY;
+                    pitch = (pitch - yrel as f64 * MOUSE_SENSITIVITY).clamp(-1.5, 1.5);
+                    moved = true;
                 }
+                Event::MouseWheel { y, .. } => speed *= 1.25f64.powi(y),
                 _ => {}
             }
         }
+        let dt = last.elapsed().as_secs_f64();
+        last = Instant::now();
+        let front = Vector3::new(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos());
+        let right = front.cross(&view.up).normalize();
+        let keys = event_pump.keyboard_state();
+        let step = [
+            (Scancode::W, front), (Scancode::S, -front), (Scancode::D, right), (Scancode::A, -right),
+            (Scancode::E, view.up), (Scancode::Q, -view.up),
+        ].iter().filter(|(key, _)| keys.is_scancode_pressed(*key)).map(|(_, d)| d).sum::<Vector3<f64>>();
+        if step != Vector3::zeros() {
+            view.from += step * speed * dt;
+            moved = true;
+        }
+        if moved {
+            view.at = view.from + front;
+            distances = None;
+        }
+        if moved || relit {
+            accumulated.iter_mut().for_each(|c| *c = Vector3::zeros());
+            passes = 0;
+        }
+        if passes >= target && !toggled {
+            std::thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+
+        let camera = view.camera(aspect_ratio(settings));
+        let (splits, irradiance) = (vec![1; pixels], OnceLock::new());
+        // once all the samples are in, only toggling the guides gets here
+        let buffers = if passes >= target { Vec::new() } else { crossbeam::scope(|s| {
+            let workers = (0..settings.threads.min(target - passes)).map(|t| {
+                let (camera, splits, background) = (&camera, &splits, &background);
+                let (irradiance, view) = (&irradiance, &view);
+                s.spawn(move |_| {
+                    let mut buffer = vec![Vector3::zeros(); pixels];
+                    let (paths, _) = camera_wave(camera, width, height, settings, passes + t, splits);
+                    let tracer = Tracer {
+                        objects, background, photons: None, fog, portals, lights, guide: None,
+                        sampling, max_depth, clamp, irradiance, transparent: false, view,
+                    };
+                    trace_wave(&tracer, paths, &mut buffer, &mut vec![0.0; pixels], &mut Vec::new(), None, None);
+                    buffer
+                })
+            }).collect::<Vec<_>>();
+            workers.into_iter().map(|w| w.join().unwrap()).collect::<Vec<_>>()
+        }).unwrap() };
+        passes += buffers.len() as u32;
+        for buffer in buffers {
+            accumulated.iter_mut().zip(buffer).for_each(|(a, b)| *a += b);
+        }
+
+        let mut image = (width, height, accumulated.iter().map(|c| c / passes as f64).collect::<Vec<_>>());
+        if shown {
+            let distances = distances.get_or_insert_with(|| helpers::distances(&scene, &camera, width, height));
+            helpers::draw(&mut image, distances, &camera, &segments);
+        }
+        sdl(texture.with_lock(None, |bytes, pitch| {
+            for (k, (i, j)) in iproduct!(0..width as usize, 0..height as usize).enumerate() {
+                let color = image.2[k].map(|x| (x.clamp(0.0, 1.0) * 255.0) as u8);
+                bytes[j * pitch + i * 3..j * pitch + i * 3 + 3].copy_from_slice(&[color.x, color.y, color.z]);
+            }
+        }))?;
+        sdl(canvas.copy(&texture, None, None))?;
+        canvas.present();
+        sdl(canvas.window_mut().set_title(&format!("Raytracer - {} samples", passes)))?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_errors, temp_path};
+
+    #[test]
+    fn text_images_round_trip() {
+        let path = temp_path("round.txt");
+        let path = path.to_str().unwrap();
+        let image = (1, 2, vec![Vector3::new(1.0, 0.0, 0.2), Vector3::new(0.0, 0.6, 1.0)]);
+        write_to_file(path, image.clone()).unwrap();
+        let (width, height, buffer) = read_from_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!((width, height), (1, 2));
+        assert!(buffer.iter().zip(&image.2).all(|(a, b)| (a - b).amax() < 1.0 / 255.0));
+    }
+
+    #[test]
+    fn reports_bad_text_images() {
+        assert_errors([
+            ("empty.txt", "", "missing header"),
+            ("header.txt", "2\n", "the header should be the width and height"),
+            ("number.txt", "1 1\n255 x 0\n", "expected a number"),
+            ("pixel.txt", "1 1\n255 0\n", "expected three components per pixel"),
+            ("count.txt", "2 1\n255 0 0\n", "the pixel count doesn't match the size"),
+        ], read_from_file);
+        assert!(matches!(read_from_file("/nonexistent/image.txt"), Err(Error::Io(_))));
     }
 }
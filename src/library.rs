@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use arc_swap::ArcSwap;
+
+use nalgebra::Vector3;
+
+use crate::material::{Material, Medium};
+use crate::object::Intersection;
+use crate::ray::Ray;
+
+pub type SharedMaterial = Box<dyn Material + Send + Sync>;
+
+// A reference to a library entry. Objects hold handles rather than materials, so replacing the entry
+// changes every object that uses it. The entry is swapped atomically rather than locked, as masking is
+// asked for every candidate hit. A render running while the entry is replaced shades some hits with the
+// old material and some with the new, but never waits.
+#[derive(Clone)]
+pub struct MaterialHandle(Arc<ArcSwap<SharedMaterial>>);
+
+impl Material for MaterialHandle {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        self.0.load().scatter(int)
+    }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        self.0.load().scatter_lobe(int)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        self.0.load().eval(int, wi)
+    }
+
+    fn specular(&self) -> bool {
+        self.0.load().specular()
+    }
+
+    fn masked(&self, int: &Intersection) -> bool {
+        self.0.load().masked(int)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.0.load().medium()
+    }
+
+    fn shadow_catcher(&self) -> bool {
+        self.0.load().shadow_catcher()
+    }
+
+    fn emission(&self) -> Vector3<f64> {
+        self.0.load().emission()
+    }
+}
+
+#[derive(Default)]
+pub struct MaterialLibrary {
+    materials: HashMap<String, MaterialHandle>,
+    // Whether `set` has replaced a material, which the scene's fingerprint can't see.
+    overridden: AtomicBool,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a material, or replaces it in place if the name is taken so existing handles stay valid.
+    pub fn insert(&mut self, name: &str, material: SharedMaterial) -> MaterialHandle {
+        match self.materials.get(name) {
+            Some(handle) => {
+                handle.0.store(Arc::new(material));
+                handle.clone()
+            }
+            None => {
+                let handle = MaterialHandle(Arc::new(ArcSwap::from_pointee(material)));
+                self.materials.insert(name.to_owned(), handle.clone());
+                handle
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<MaterialHandle> {
+        self.materials.get(name).cloned()
+    }
+
+    // Overrides a named material scene-wide. Returns false if there is no such material.
+    pub fn set(&self, name: &str, material: SharedMaterial) -> bool {
+        let handle = self.materials.get(name);
+        if let Some(handle) = handle {
+            handle.0.store(Arc::new(material));
+            self.overridden.store(true, Ordering::Relaxed);
+        }
+        handle.is_some()
+    }
+
+    pub(crate) fn overridden(&self) -> bool {
+        self.overridden.load(Ordering::Relaxed)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.materials.keys().map(String::as_str)
+    }
+}
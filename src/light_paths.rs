@@ -0,0 +1,124 @@
+use nalgebra::Vector3;
+
+use crate::camera::LensSplitting;
+use crate::control::heat;
+
+// Light split by how many surfaces it bounced off between the lights and the camera: direct light, off at
+// most one (lights the camera sees are in it too), the first bounce of indirect light, off two, and the
+// rest. The three add up to the render.
+pub const BANDS: [&str; 3] = ["direct", "indirect", "rest"];
+
+// The band of light that bounced off `bounces` surfaces on the way.
+pub(crate) fn band(bounces: usize) -> usize {
+    bounces.saturating_sub(1).min(BANDS.len() - 1)
+}
+
+// How long paths were, in surfaces and fog they scattered off, for each camera sample or pixel: summed
+// weighted by the share of the sample each path carries, the longest, and the share `max_depth` cut off.
+// Paths otherwise end only by escaping or being absorbed, as there's no Russian roulette to end them early,
+// so the share cut off tells whether the depth is too low for the scene.
+#[derive(Clone)]
+pub(crate) struct Lengths {
+    pub sum: Vec<f64>,
+    pub longest: Vec<u32>,
+    pub cut: Vec<f64>,
+}
+
+impl Lengths {
+    fn new(size: usize) -> Self {
+        Self { sum: vec![0.0; size], longest: vec![0; size], cut: vec![0.0; size] }
+    }
+
+    pub fn end(&mut self, sample: usize, bounces: usize, weight: f64, cut: bool) {
+        self.sum[sample] += weight * bounces as f64;
+        self.longest[sample] = self.longest[sample].max(bounces as u32);
+        if cut {
+            self.cut[sample] += weight;
+        }
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        self.sum.iter_mut().zip(&other.sum).for_each(|(a, b)| *a += b);
+        self.longest.iter_mut().zip(&other.longest).for_each(|(a, b)| *a = (*a).max(*b));
+        self.cut.iter_mut().zip(&other.cut).for_each(|(a, b)| *a += b);
+    }
+}
+
+// What a pass records with `settings.light_paths`, for each camera sample as it is traced and then for each
+// pixel.
+#[derive(Clone)]
+pub(crate) struct LightPaths {
+    pub bands: [Vec<Vector3<f64>>; 3],
+    pub lengths: Lengths,
+}
+
+impl LightPaths {
+    pub fn new(size: usize) -> Self {
+        let bands = [vec![Vector3::zeros(); size], vec![Vector3::zeros(); size], vec![Vector3::zeros(); size]];
+        Self { bands, lengths: Lengths::new(size) }
+    }
+
+    // From the samples to the pixels they are split from: the light averaged like the render, and the lengths
+    // summed, to be divided by the number of samples in the end.
+    pub fn gather(&self, splits: &[u32]) -> Self {
+        let bands = self.bands.clone().map(|band| LensSplitting::mean(splits, &band));
+        let mut lengths = Lengths::new(splits.len());
+        let mut start = 0;
+        for (pixel, &k) in splits.iter().enumerate() {
+            for sample in start..start + k as usize {
+                lengths.sum[pixel] += self.lengths.sum[sample];
+                lengths.longest[pixel] = lengths.longest[pixel].max(self.lengths.longest[sample]);
+                lengths.cut[pixel] += self.lengths.cut[sample];
+            }
+            start += k as usize;
+        }
+        Self { bands, lengths }
+    }
+}
+
+// The path lengths of a render, per pixel.
+pub struct PathLengths {
+    pub width: u32,
+    pub height: u32,
+    pub mean: Vec<f64>,
+    pub longest: Vec<u32>,
+    // The share of the paths cut off at the maximum depth.
+    pub cut: Vec<f64>,
+}
+
+impl PathLengths {
+    pub(crate) fn new(width: u32, height: u32, lengths: &Lengths, samples: &[u32]) -> Self {
+        let per_sample = |sums: &[f64]| {
+            sums.iter().zip(samples).map(|(&x, &n)| if n > 0 { x / n as f64 } else { 0.0 }).collect()
+        };
+        Self {
+            width,
+            height,
+            mean: per_sample(&lengths.sum),
+            longest: lengths.longest.clone(),
+            cut: per_sample(&lengths.cut),
+        }
+    }
+
+    // The mean over the image, the longest anywhere, and the share cut off over the image.
+    pub fn overall(&self) -> (f64, u32, f64) {
+        let pixels = self.mean.len().max(1) as f64;
+        let longest = self.longest.iter().copied().max().unwrap_or(0);
+        (self.mean.iter().sum::<f64>() / pixels, longest, self.cut.iter().sum::<f64>() / pixels)
+    }
+
+    // The mean, the longest and the share cut off in the channels, for reading back the numbers.
+    pub fn values(&self) -> (u32, u32, Vec<Vector3<f64>>) {
+        let buffer = self.mean.iter().zip(&self.longest).zip(&self.cut)
+            .map(|((&mean, &longest), &cut)| Vector3::new(mean, longest as f64, cut))
+            .collect();
+        (self.width, self.height, buffer)
+    }
+
+    // The mean as colors from black through red and yellow to white, relative to the longest mean.
+    pub fn heatmap(&self) -> (u32, u32, Vec<Vector3<f64>>) {
+        let most = self.mean.iter().copied().fold(0.0, f64::max);
+        let most = if most > 0.0 { most } else { 1.0 };
+        (self.width, self.height, self.mean.iter().map(|&m| heat(m / most)).collect())
+    }
+}
@@ -0,0 +1,271 @@
+use std::borrow::Borrow;
+use std::f64::consts::PI;
+
+use nalgebra::{Affine3, Matrix3, Point3, Rotation3, Unit, Vector3};
+use rand::Rng;
+
+use crate::aabb::Aabb;
+use crate::background::luminance;
+use crate::material::orthonormal_basis;
+use crate::object::Object;
+use crate::RNG;
+
+// What a light is shaped like, in world space.
+#[derive(Clone, Copy)]
+pub enum Shape {
+    Sphere { center: Vector3<f64>, radius: f64 },
+    // Lit on the side its vertices wind counterclockwise around.
+    Triangle([Vector3<f64>; 3]),
+}
+
+// A surface giving off `radiance` on its front, which next-event estimation aims shadow rays at.
+#[derive(Clone, Copy)]
+pub struct Emitter {
+    pub shape: Shape,
+    pub radiance: Vector3<f64>,
+}
+
+impl Emitter {
+    // The emitter moved by `transform`, if that keeps its shape: spheres only stay spheres when scaled evenly.
+    pub(crate) fn transformed(&self, transform: &Affine3<f64>) -> Option<Self> {
+        let point = |p: &Vector3<f64>| transform.transform_point(&Point3::from(*p)).coords;
+        let shape = match self.shape {
+            Shape::Triangle(vertices) => Shape::Triangle(vertices.map(|v| point(&v))),
+            Shape::Sphere { center, radius } => {
+                let linear = transform.matrix().fixed_view::<3, 3>(0, 0).into_owned();
+                let squared = linear.transpose() * linear;
+                let scale = squared.trace() / 3.0;
+                if (squared - Matrix3::identity() * scale).norm() > 1e-9 * scale {
+                    return None;
+                }
+                Shape::Sphere { center: point(&center), radius: radius * scale.sqrt() }
+            }
+        };
+        Some(Self { shape, ..*self })
+    }
+
+    fn area(&self) -> f64 {
+        match self.shape {
+            Shape::Sphere { radius, .. } => 4.0 * PI * radius * radius,
+            Shape::Triangle([a, b, c]) => (b - a).cross(&(c - a)).norm() / 2.0,
+        }
+    }
+
+    // The light it gives off in all, by luminance.
+    fn power(&self) -> f64 {
+        luminance(&self.radiance) * self.area() * PI
+    }
+
+    fn bounds(&self) -> Aabb {
+        match self.shape {
+            Shape::Sphere { center, radius } => {
+                Aabb::new(center - Vector3::repeat(radius), center + Vector3::repeat(radius))
+            }
+            Shape::Triangle(vertices) => Aabb::from_points(&vertices),
+        }
+    }
+
+    fn cone(&self) -> Cone {
+        match self.shape {
+            Shape::Sphere { .. } => Cone { axis: Vector3::y(), spread: PI, falloff: PI / 2.0 },
+            Shape::Triangle([a, b, c]) => {
+                Cone { axis: (b - a).cross(&(c - a)).normalize(), spread: 0.0, falloff: PI / 2.0 }
+            }
+        }
+    }
+
+    // The direction from `point` to a random point of the emitter facing it, the distance there, and the
+    // density of the direction per solid angle; None if no part of the emitter faces `point`. Spheres are
+    // sampled within the cone they fill as seen from `point`, triangles by area.
+    pub(crate) fn sample(&self, point: &Vector3<f64>) -> Option<(Vector3<f64>, f64, f64)> {
+        let (u, v) = RNG.with(|r| r.borrow_mut().gen::<(f64, f64)>());
+        match self.shape {
+            Shape::Sphere { center, radius } => {
+                let axis = center - point;
+                let squared = axis.norm_squared();
+                if squared <= radius * radius {
+                    return None;
+                }
+                // 1 - cos of the cone's half-angle, without the cancellation for small, distant spheres
+                let sin_squared = radius * radius / squared;
+                let solid = sin_squared / (1.0 + (1.0 - sin_squared).sqrt());
+                let cos = 1.0 - u * solid;
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let axis = axis / squared.sqrt();
+                let (t, b) = orthonormal_basis(&axis);
+                let phi = 2.0 * PI * v;
+                let direction = axis * cos + (t * phi.cos() + b * phi.sin()) * sin;
+                let distance = squared.sqrt() * cos - (radius * radius - squared * sin * sin).max(0.0).sqrt();
+                Some((direction, distance, 1.0 / (2.0 * PI * solid)))
+            }
+            Shape::Triangle([a, b, c]) => {
+                let (u, v) = if u + v > 1.0 { (1.0 - u, 1.0 - v) } else { (u, v) };
+                let to = a + (b - a) * u + (c - a) * v - point;
+                let distance = to.norm();
+                let direction = to / distance;
+                let normal = (b - a).cross(&(c - a));
+                let cos = -direction.dot(&normal) / normal.norm();
+                (cos > 0.0).then(|| (direction, distance, distance * distance / (cos * self.area())))
+            }
+        }
+    }
+}
+
+// The directions a group of emitters face: all within `spread` of `axis`, each lighting up to `falloff`
+// past its own facing.
+#[derive(Clone, Copy)]
+struct Cone {
+    axis: Vector3<f64>,
+    spread: f64,
+    falloff: f64,
+}
+
+impl Cone {
+    fn union(&self, other: &Self) -> Self {
+        let falloff = self.falloff.max(other.falloff);
+        let (wide, narrow) = if self.spread >= other.spread { (self, other) } else { (other, self) };
+        let between = angle(&wide.axis, &narrow.axis);
+        if (between + narrow.spread).min(PI) <= wide.spread {
+            return Cone { falloff, ..*wide };
+        }
+        let spread = (wide.spread + between + narrow.spread) / 2.0;
+        let turn = wide.axis.cross(&narrow.axis);
+        if spread >= PI || turn.norm_squared() == 0.0 {
+            return Cone { axis: wide.axis, spread: PI, falloff };
+        }
+        let axis = Rotation3::from_axis_angle(&Unit::new_normalize(turn), spread - wide.spread) * wide.axis;
+        Cone { axis, spread, falloff }
+    }
+}
+
+fn angle(a: &Vector3<f64>, b: &Vector3<f64>) -> f64 {
+    a.dot(b).clamp(-1.0, 1.0).acos()
+}
+
+struct Node {
+    bounds: Aabb,
+    cone: Cone,
+    power: f64,
+    contents: Contents,
+}
+
+enum Contents {
+    Emitter(usize),
+    Children(usize, usize),
+}
+
+impl Node {
+    // An upper bound on how much the node's emitters could light `point` on a surface facing `normal`
+    // (either way), after Conty Estevez and Kulla: their power, falling off with the square of the
+    // distance, and with the cosines at both ends taken at the best angle the bounds allow.
+    fn importance(&self, point: &Vector3<f64>, normal: &Vector3<f64>) -> f64 {
+        let center = self.bounds.center();
+        let radius = self.bounds.diagonal().norm() / 2.0;
+        let to_point = point - center;
+        let squared = to_point.norm_squared();
+        // from inside the bounds, the emitters could be in any direction
+        let subtended = if squared > radius * radius { (radius / squared.sqrt()).asin() } else { PI };
+        let outward = to_point.try_normalize(0.0).unwrap_or(self.cone.axis);
+        let off_axis = (angle(&self.cone.axis, &outward) - self.cone.spread - subtended).max(0.0);
+        if off_axis >= self.cone.falloff {
+            return 0.0;
+        }
+        let incidence = (normal.dot(&outward).abs().min(1.0).acos() - subtended).max(0.0);
+        self.power * off_axis.cos() * incidence.cos() / squared.max(radius * radius)
+    }
+}
+
+// A BVH over the scene's emitters for picking one to aim a shadow ray at in proportion to how much it
+// stands to light the point being shaded, so that scenes with thousands of lights don't spend most shadow
+// rays on ones far away or facing elsewhere.
+pub(crate) struct LightTree {
+    emitters: Vec<(usize, Emitter)>,
+    nodes: Vec<Node>,
+    // Whether all of each object's light is in the tree, so that paths reaching it after sampling the tree
+    // mustn't count it again.
+    covered: Vec<bool>,
+}
+
+impl LightTree {
+    pub fn new<R: Borrow<dyn Object + Sync>>(objects: &[R]) -> Self {
+        let mut emitters = Vec::new();
+        let covered = objects.iter().enumerate().map(|(k, o)| match o.borrow().emitters(None) {
+            Some(list) => {
+                emitters.extend(list.into_iter().filter(|e| e.power() > 0.0).map(|e| (k, e)));
+                true
+            }
+            None => false,
+        }).collect();
+        let mut tree = Self { emitters, nodes: Vec::new(), covered };
+        if !tree.emitters.is_empty() {
+            let mut indices = (0..tree.emitters.len()).collect::<Vec<_>>();
+            tree.build(&mut indices);
+        }
+        tree
+    }
+
+    // Splits the emitters at the median of their centers along the longest side of their bounds. The root
+    // is the last node.
+    fn build(&mut self, indices: &mut [usize]) -> usize {
+        let node = match indices {
+            [e] => {
+                let (_, emitter) = &self.emitters[*e];
+                let contents = Contents::Emitter(*e);
+                Node { bounds: emitter.bounds(), cone: emitter.cone(), power: emitter.power(), contents }
+            }
+            _ => {
+                let centers = indices.iter().map(|&e| self.emitters[e].1.bounds().center()).collect::<Vec<_>>();
+                let axis = Aabb::from_points(&centers).diagonal().imax();
+                let middle = indices.len() / 2;
+                indices.select_nth_unstable_by(middle, |&a, &b| {
+                    let (a, b) = (self.emitters[a].1.bounds().center(), self.emitters[b].1.bounds().center());
+                    a[axis].total_cmp(&b[axis])
+                });
+                let (left, right) = indices.split_at_mut(middle);
+                let (left, right) = (self.build(left), self.build(right));
+                let (a, b) = (&self.nodes[left], &self.nodes[right]);
+                Node {
+                    bounds: a.bounds.union(&b.bounds),
+                    cone: a.cone.union(&b.cone),
+                    power: a.power + b.power,
+                    contents: Contents::Children(left, right),
+                }
+            }
+        };
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.emitters.is_empty()
+    }
+
+    pub fn covers(&self, object: usize) -> bool {
+        self.covered.get(object).copied().unwrap_or(false)
+    }
+
+    // An emitter and the object it is on, picked by walking down the tree toward the children more
+    // important to `point` on a surface facing `normal`, along with the chance of picking it; None if no
+    // emitter can light it.
+    pub fn sample(&self, point: &Vector3<f64>, normal: &Vector3<f64>) -> Option<(usize, &Emitter, f64)> {
+        let mut node = self.nodes.len().checked_sub(1)?;
+        let mut chance = 1.0;
+        loop {
+            match self.nodes[node].contents {
+                Contents::Emitter(e) => {
+                    let (object, emitter) = &self.emitters[e];
+                    return Some((*object, emitter, chance));
+                }
+                Contents::Children(left, right) => {
+                    let a = self.nodes[left].importance(point, normal);
+                    let b = self.nodes[right].importance(point, normal);
+                    if a + b <= 0.0 {
+                        return None;
+                    }
+                    let u = RNG.with(|r| r.borrow_mut().gen::<f64>()) * (a + b);
+                    (node, chance) = if u < a { (left, chance * a / (a + b)) } else { (right, chance * b / (a + b)) };
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::fs;
+
+use nalgebra::Vector3;
+
+use crate::error::{Error, Result};
+
+// A 3D lookup table in the .cube format, applied to display values as a final look or grade.
+pub struct Lut {
+    pub path: String,
+    size: usize,
+    domain_min: Vector3<f64>,
+    domain_max: Vector3<f64>,
+    // red varies fastest, then green, then blue
+    table: Vec<Vector3<f64>>,
+}
+
+impl Lut {
+    pub fn load(path: &str) -> Result<Self> {
+        let invalid = |what: String| Error::Lut(format!("{}: {}", path, what));
+        let triple = |words: &[&str]| match words.iter().map(|w| w.parse()).collect::<Vec<_>>()[..] {
+            [Ok(r), Ok(g), Ok(b)] => Ok(Vector3::new(r, g, b)),
+            _ => Err(invalid(format!("expected three numbers, got {}", words.join(" ")))),
+        };
+        let (mut size, mut domain_min, mut domain_max) = (None, Vector3::zeros(), Vector3::repeat(1.0));
+        let mut table = Vec::new();
+        for line in fs::read_to_string(path)?.lines() {
+            let words = line.split_ascii_whitespace().collect::<Vec<_>>();
+            match words[..] {
+                [] => (),
+                [word, ..] if word.starts_with('#') => (),
+                ["TITLE", ..] => (),
+                ["LUT_3D_SIZE", n] => size = Some(n.parse().map_err(|_| invalid(format!("bad size {}", n)))?),
+                ["LUT_1D_SIZE", _] => return Err(invalid("1D LUTs aren't supported".to_owned())),
+                ["DOMAIN_MIN", ..] => domain_min = triple(&words[1..])?,
+                ["DOMAIN_MAX", ..] => domain_max = triple(&words[1..])?,
+                _ => table.push(triple(&words)?),
+            }
+        }
+        let size = size.ok_or_else(|| invalid("missing LUT_3D_SIZE".to_owned()))?;
+        if size < 2 || table.len() != size * size * size {
+            return Err(invalid(format!("expected {} entries, got {}", size * size * size, table.len())));
+        }
+        Ok(Self { path: path.to_owned(), size, domain_min, domain_max, table })
+    }
+
+    // Looks up a color by trilinear interpolation, clamping it to the domain first.
+    pub fn lookup(&self, color: &Vector3<f64>) -> Vector3<f64> {
+        let n = self.size;
+        let scaled = (color - self.domain_min).component_div(&(self.domain_max - self.domain_min))
+            .map(|x| x.clamp(0.0, 1.0) * (n - 1) as f64);
+        let base = scaled.map(|x| (x as usize).min(n - 2));
+        let f = scaled - base.cast();
+        let at = |dr, dg, db| self.table[(base.z + db) * n * n + (base.y + dg) * n + base.x + dr];
+        let lerp = |a: Vector3<f64>, b: Vector3<f64>, t: f64| a + (b - a) * t;
+        let [c00, c10, c01, c11] = [(0, 0), (1, 0), (0, 1), (1, 1)]
+            .map(|(dg, db)| lerp(at(0, dg, db), at(1, dg, db), f.x));
+        lerp(lerp(c00, c10, f.y), lerp(c01, c11, f.y), f.z)
+    }
+
+    pub fn apply(&self, image: (u32, u32, Vec<Vector3<f64>>)) -> (u32, u32, Vec<Vector3<f64>>) {
+        let (width, height, buffer) = image;
+        (width, height, buffer.iter().map(|c| self.lookup(c)).collect())
+    }
+}
@@ -1,4 +1,655 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::io::{stderr, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use raytracer::{
+    Control, Crop, DepthMode, DepthPass, Estimate, Fireflies, GifEncoder, Guides, Integrator, LensSampler, Light, Lut,
+    Matte, PathLengths, PixelFilter, Quality, RenderCache, RenderSettings, Result, Sampler, Scene, Stats,
+    Stereo, StereoLayout, Summary, Tally, VideoEncoder, WhiteBalance,
+};
+
+const BAR_WIDTH: usize = 30;
+// Side by side with eyes 6.4 cm apart, for scenes in meters, until --stereo, --interocular or --ods say
+// otherwise.
+const STEREO: Stereo = Stereo { layout: StereoLayout::SideBySide, interocular: 0.064, ods: false };
+
+// The config, display and view of an OpenColorIO display transform.
+type OcioView = (Option<String>, Option<String>, Option<String>);
+
 fn main() {
-    let image = raytracer::render();
-    raytracer::show_image(image);
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let mut settings = RenderSettings::default();
+    let mut output = None;
+    let mut address = None;
+    let mut quiet = false;
+    let mut webhook = None;
+    let mut command = None;
+    let mut sidecar = false;
+    let mut preview = false;
+    let mut interactive = false;
+    let mut all_cameras = false;
+    let mut turntable = None;
+    let mut guides = Guides::default();
+    let mut snapshot = None;
+    let mut cache = None;
+    let mut burn_in = false;
+    let mut frame = None;
+    let mut ocio = (None, None, None);
+    let mut heatmap = None;
+    let mut bounces = None;
+    let mut lengths = None;
+    let mut alpha = None;
+    let mut passes = Passes::default();
+    let mut dry_run = false;
+    let mut video = None;
+    let mut fps = 24.0;
+    let mut inputs = Vec::new();
+    let mut interval = Duration::from_secs(60);
+    // the preset comes first so the individual flags override it wherever they appear
+    if let Some(quality) = env::args().skip_while(|a| a != "--quality").nth(1) {
+        settings = settings.with_quality(match quality.as_str() {
+            "draft" => Quality::Draft,
+            "medium" => Quality::Medium,
+            "final" => Quality::Final,
+            _ => panic!("--quality must be draft, medium or final"),
+        });
+    }
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--set" => {
+                let assignment = args.next().expect("--set requires key=value");
+                let (key, value) = assignment.split_once('=').expect("--set requires key=value");
+                settings.overrides.push((key.to_owned(), value.to_owned()));
+            }
+            "-o" | "--output" => output = args.next(),
+            "--control" => address = Some(args.next().expect("--control requires an address")),
+            "-q" | "--quiet" => quiet = true,
+            "--quality" => {
+                args.next();
+            }
+            "--samples" => settings.samples = args.next().and_then(|n| n.parse().ok())
+                .expect("--samples requires a number"),
+            "--max-depth" => settings.max_depth = args.next().and_then(|n| n.parse().ok())
+                .expect("--max-depth requires a number"),
+            "--sampler" => settings.sampler = match args.next().as_deref() {
+                Some("random") => Sampler::Random,
+                Some("halton") => Sampler::Halton,
+                _ => panic!("--sampler must be random or halton"),
+            },
+            "--lens-sampler" => settings.lens_sampler = match args.next().as_deref() {
+                Some("random") => LensSampler::Random,
+                Some("spiral") => LensSampler::Spiral,
+                _ => panic!("--lens-sampler must be random or spiral"),
+            },
+            "--filter" => settings.filter = match args.next().as_deref() {
+                Some("box") => PixelFilter::Box,
+                Some("tent") => PixelFilter::Tent,
+                Some("gaussian") => PixelFilter::Gaussian,
+                Some("blackman-harris") => PixelFilter::BlackmanHarris,
+                _ => panic!("--filter must be box, tent, gaussian or blackman-harris"),
+            },
+            "--integrator" => settings.integrator = match args.next().as_deref() {
+                Some("path") => Integrator::PathTracing,
+                Some("guided") => Integrator::PathGuiding,
+                _ => panic!("--integrator must be path or guided"),
+            },
+            "--clamp" => settings.clamp = Some(args.next().and_then(|n| n.parse().ok())
+                .expect("--clamp requires a number")),
+            "--no-clamp" => settings.clamp = None,
+            "--denoise" => settings.denoise = true,
+            "--no-denoise" => settings.denoise = false,
+            "--fireflies" => settings.fireflies = true,
+            "--transparent" => settings.transparent = true,
+            "--backplate" => settings.backplate = Some(args.next().expect("--backplate requires a path")),
+            "--white-balance" => settings.white_balance = match args.next().as_deref() {
+                Some("grey") => Some(WhiteBalance::GreyWorld),
+                Some("neutral") => Some(WhiteBalance::Neutral),
+                _ => panic!("--white-balance must be grey or neutral"),
+            },
+            "--ocio-config" => ocio.0 = Some(args.next().expect("--ocio-config requires a path")),
+            "--ocio-display" => ocio.1 = Some(args.next().expect("--ocio-display requires a display")),
+            "--ocio-view" => ocio.2 = Some(args.next().expect("--ocio-view requires a view")),
+            "--lut" => settings.lut = Some(Arc::new(Lut::load(&args.next().expect("--lut requires a path"))?)),
+            "--lens-splits" => settings.lens_splits = args.next().and_then(|n| n.parse().ok())
+                .expect("--lens-splits requires a number"),
+            "--crop" => settings.crop = Some(parse_crop(&args.next().expect("--crop requires x,y,width,height"))),
+            "--stereo" => settings.stereo = Some(Stereo {
+                layout: match args.next().as_deref() {
+                    Some("sbs") => StereoLayout::SideBySide,
+                    Some("ou") => StereoLayout::OverUnder,
+                    _ => panic!("--stereo must be sbs or ou"),
+                },
+                ..settings.stereo.unwrap_or(STEREO)
+            }),
+            "--interocular" => settings.stereo = Some(Stereo {
+                interocular: args.next().and_then(|n| n.parse().ok()).expect("--interocular requires a distance"),
+                ..settings.stereo.unwrap_or(STEREO)
+            }),
+            "--ods" => settings.stereo = Some(Stereo { ods: true, ..settings.stereo.unwrap_or(STEREO) }),
+            "--sidecar" => sidecar = true,
+            "--id-matte" => passes.matte = Some(args.next().expect("--id-matte requires a path")),
+            "--depth" => passes.depth = Some(args.next().expect("--depth requires a path")),
+            "--depth-mode" => passes.depth_mode = match args.next().as_deref() {
+                Some("distance") => DepthMode::Distance,
+                Some("planar") => DepthMode::Planar,
+                _ => panic!("--depth-mode must be distance or planar"),
+            },
+            "--depth-range" => passes.depth_range = args.next().and_then(|range| {
+                let (near, far) = range.split_once(',')?;
+                Some((near.parse().ok()?, far.parse().ok()?))
+            }).map(Some).expect("--depth-range requires near,far"),
+            "--position" => passes.position = Some(args.next().expect("--position requires a path")),
+            "--svg" => passes.svg = Some(args.next().expect("--svg requires a path")),
+            "--sample-heatmap" => heatmap = Some(args.next().expect("--sample-heatmap requires a path")),
+            "--bounces" => bounces = Some(args.next().expect("--bounces requires a path")),
+            "--path-lengths" => lengths = Some(args.next().expect("--path-lengths requires a path")),
+            "--alpha" => alpha = Some(args.next().expect("--alpha requires a path")),
+            "--snapshot" => snapshot = Some(args.next().expect("--snapshot requires a path")),
+            "--snapshot-interval" => interval = args.next().and_then(|s| s.parse().ok()).map(Duration::from_secs_f64)
+                .expect("--snapshot-interval requires a number of seconds"),
+            "--cache" => cache = Some(args.next().expect("--cache requires a directory")),
+            "--burn-in" => burn_in = true,
+            "--frame" => frame = Some(args.next().and_then(|n| n.parse().ok()).expect("--frame requires a number")),
+            "--encode" => video = Some(args.next().expect("--encode requires a path")),
+            "--fps" => fps = args.next().and_then(|n| n.parse().ok()).expect("--fps requires a number"),
+            "--dry-run" => dry_run = true,
+            "--preview" => preview = true,
+            "--fly" => interactive = true,
+            "--all-cameras" => all_cameras = true,
+            "--turntable" => turntable = Some(args.next().and_then(|n| n.parse().ok())
+                .expect("--turntable requires a number of frames")),
+            "--helpers" => guides.helpers = true,
+            "--show-bounds" => guides.bounds = true,
+            "--show-bvh" => guides.bvh = Some(args.next().and_then(|n| n.parse().ok())
+                .expect("--show-bvh requires a number of levels")),
+            "--notify-url" => webhook = Some(args.next().expect("--notify-url requires a URL")),
+            "--notify-command" => command = Some(args.next().expect("--notify-command requires a command")),
+            _ => inputs.push(arg),
+        }
+    }
+    // with --encode, the arguments are the frames, in order
+    if let Some(path) = video {
+        return encode(&path, &inputs, fps);
+    }
+    // `material-preview <file> [material]` renders a material on shader balls rather than a scene
+    if inputs.first().is_some_and(|a| a == "material-preview") {
+        let file = inputs.get(1).expect("material-preview requires a material or scene file");
+        let control = || match quiet {
+            true => Control::new(&settings),
+            false => Control::new(&settings).with_callback(progress_bar),
+        };
+        let name = inputs.get(2).map(String::as_str);
+        let image = raytracer::material_preview(Path::new(file), name, &settings, control)?;
+        if !quiet {
+            eprintln!();
+        }
+        return match output {
+            Some(path) => raytracer::save_image(&path, image),
+            None => show(image),
+        };
+    }
+    if settings.fireflies && settings.clamp.is_none() {
+        eprintln!("--fireflies requires --clamp, ignoring");
+        settings.fireflies = false;
+    }
+    settings.light_paths = bounces.is_some() || lengths.is_some();
+    settings.scene = inputs.pop();
+    if settings.scene.is_none() && !settings.overrides.is_empty() {
+        eprintln!("--set only applies to scene files, ignoring");
+    }
+
+    if ocio != (None, None, None) {
+        display_transform(&mut settings, ocio)?;
+    }
+
+    if interactive {
+        return fly(&settings, &guides);
+    }
+    if dry_run {
+        return estimate(&settings);
+    }
+    if preview {
+        let mut image = raytracer::preview(&settings, &guides)?;
+        if burn_in {
+            image = raytracer::burn_in(image, &settings, frame);
+        }
+        return match output {
+            Some(path) => raytracer::save_image(&path, image),
+            None => show(image),
+        };
+    }
+
+    if let Some(frames) = turntable {
+        let output = output.expect("--turntable requires --output");
+        return render_turntable(&settings, frames, &output, fps, quiet);
+    }
+    if all_cameras {
+        return render_cameras(&settings, &output.expect("--all-cameras requires --output"), quiet);
+    }
+
+    // loaded even for a cached render, whose key is the scene's fingerprint
+    let scene = raytracer::load_scene(&settings)?;
+    let cache = cache.and_then(|dir| {
+        let cache = RenderCache::new(dir, &scene, &settings);
+        if cache.is_none() {
+            eprintln!("the scene has no fingerprint to cache it by, rendering it anew");
+        }
+        cache
+    });
+    let cached = match &cache {
+        Some(cache) => cache.load()?,
+        None => None,
+    };
+    let (image, mut stats, coverage) = match cached {
+        Some((image, stats)) => {
+            if !quiet {
+                eprintln!("unchanged since the cached render, skipping");
+            }
+            if heatmap.is_some() {
+                eprintln!("the cached render has no sample counts, ignoring --sample-heatmap");
+            }
+            if settings.light_paths {
+                eprintln!("the cached render has no light paths, ignoring --bounces and --path-lengths");
+            }
+            if alpha.is_some() || settings.transparent {
+                eprintln!("the cached render has no alpha channel, leaving it out");
+            }
+            // the geometry passes need no rendering, just the scene
+            passes.save(&scene, &settings)?;
+            (image, stats, None)
+        }
+        None => {
+            let control = Control::new(&settings);
+            let control = Arc::new(if quiet { control } else { control.with_callback(progress_bar) });
+            if let Some(address) = address {
+                raytracer::serve(address, control.clone())?;
+            }
+            if let Some(path) = snapshot {
+                raytracer::snapshot_every(control.clone(), path, interval, scene.fingerprint(&settings));
+            }
+            if let (Some(summary), false) = (&scene.summary, quiet) {
+                print_summary(summary);
+            }
+            let image = raytracer::render_scene(&scene, &settings, &control)?;
+            if !quiet {
+                eprintln!();
+            }
+            if cfg!(feature = "stats") {
+                print_stats(&control.stats());
+            }
+            if settings.fireflies {
+                print_fireflies(&control.fireflies(), &scene);
+            }
+            if let Some(path) = &heatmap {
+                let (_, _, counts) = control.sample_counts();
+                if !quiet {
+                    let (fewest, most) = (counts.iter().min().unwrap(), counts.iter().max().unwrap());
+                    eprintln!("samples per pixel: {} to {}", fewest, most);
+                }
+                raytracer::save_image(path, control.sample_heatmap())?;
+            }
+            if let Some(path) = &alpha {
+                raytracer::save_image(path, control.alpha_matte())?;
+            }
+            // `out.exr` gives `out-direct.exr`, `out-indirect.exr` and `out-rest.exr`
+            if let Some(path) = &bounces {
+                for (name, band) in control.bands() {
+                    raytracer::save_light_pass(&suffixed(path, name), band, &settings)?;
+                }
+            }
+            if let (Some(path), Some(lengths)) = (&lengths, control.path_lengths()) {
+                if !quiet {
+                    print_path_lengths(&lengths, settings.max_depth);
+                }
+                save_path_lengths(path, &lengths)?;
+            }
+            passes.save(&scene, &settings)?;
+            let stats = control.stats().to_json();
+            // renders retargeted over --control don't match their settings, so they aren't cached
+            if let (Some(cache), true) = (&cache, control.stats().passes == settings.samples * settings.threads) {
+                cache.store(&image, &stats)?;
+            }
+            // a backplate fills in what the render leaves transparent
+            let coverage = (settings.transparent && settings.backplate.is_none()).then(|| control.alpha().2);
+            (image, stats, coverage)
+        }
+    };
+    stats["output"] = output.clone().into();
+    if let Some(Err(e)) = webhook.map(|url| raytracer::notify_webhook(&url, &stats)) {
+        eprintln!("{}", e);
+    }
+    if let Some(Err(e)) = command.map(|command| raytracer::notify_command(&command, &stats)) {
+        eprintln!("{}", e);
+    }
+    let image = if burn_in { raytracer::burn_in(image, &settings, frame) } else { image };
+    match output {
+        Some(path) => {
+            if sidecar {
+                raytracer::write_sidecar(&path, &image, &scene, &settings, &stats)?;
+            }
+            match coverage {
+                Some(alpha) => raytracer::save_rgba(&path, image, &alpha),
+                None => raytracer::save_image(&path, image),
+            }
+        }
+        None => {
+            if sidecar {
+                eprintln!("--sidecar requires --output, ignoring");
+            }
+            show(image)
+        }
+    }
+}
+
+// Renders each of the scene's named cameras to the output with the camera's name added, `out-front.png`
+// for the camera "front" with `-o out.png`.
+fn render_cameras(settings: &RenderSettings, output: &str, quiet: bool) -> Result<()> {
+    let mut scene = raytracer::load_scene(settings)?;
+    if let (Some(summary), false) = (&scene.summary, quiet) {
+        print_summary(summary);
+    }
+    if scene.cameras.is_empty() {
+        eprintln!("the scene has no named cameras, nothing to render");
+    }
+    for (name, image) in raytracer::render_all_cameras(&mut scene, settings, controls(settings, quiet))? {
+        raytracer::save_image(&suffixed(output, &name), image)?;
+    }
+    if !quiet {
+        eprintln!();
+    }
+    Ok(())
+}
+
+// Renders a turntable of the scene into a looping GIF for `.gif`, a video for the extensions of `--encode`
+// and otherwise numbered frames, `out-000.png` and on with `-o out.png`.
+fn render_turntable(settings: &RenderSettings, frames: u32, output: &str, fps: f64, quiet: bool) -> Result<()> {
+    let mut scene = raytracer::load_scene(settings)?;
+    if let (Some(summary), false) = (&scene.summary, quiet) {
+        print_summary(summary);
+    }
+    let control = controls(settings, quiet);
+    match Path::new(output).extension().and_then(|e| e.to_str()) {
+        Some("gif") => {
+            let mut gif = GifEncoder::new(output, fps)?;
+            raytracer::turntable(&mut scene, frames, settings, control, |image| gif.push(&image))?;
+        }
+        Some("mp4" | "mkv" | "webm" | "mov") => {
+            let (width, height) = settings.image_size();
+            let mut video = VideoEncoder::new(output, width, height, fps)?;
+            raytracer::turntable(&mut scene, frames, settings, control, |image| Ok(video.push(&image)?))?;
+            video.finish()?;
+        }
+        _ => {
+            let mut k = 0;
+            raytracer::turntable(&mut scene, frames, settings, control, |image| {
+                k += 1;
+                raytracer::save_image(&suffixed(output, &format!("{:03}", k - 1)), image)
+            })?;
+        }
+    }
+    if !quiet {
+        eprintln!();
+    }
+    Ok(())
+}
+
+// Fresh controls for a series of renders, each showing its progress on a line of its own.
+fn controls(settings: &RenderSettings, quiet: bool) -> impl Fn() -> Control + '_ {
+    let bars = Cell::new(0);
+    move || match quiet {
+        true => Control::new(settings),
+        false => {
+            if bars.replace(bars.get() + 1) > 0 {
+                eprintln!();
+            }
+            Control::new(settings).with_callback(progress_bar)
+        }
+    }
+}
+
+// The path with `-suffix` added to the file name, before the extension.
+fn suffixed(path: &str, suffix: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().map_or_else(Default::default, |s| s.to_string_lossy());
+    let file = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+    path.with_file_name(file).to_string_lossy().into_owned()
+}
+
+// `x,y,width,height` in pixels, or in fractions of the frame if any of them has a decimal point.
+fn parse_crop(window: &str) -> Crop {
+    let numbers = window.split(',').map(|n| n.parse::<f64>().ok()).collect::<Option<Vec<_>>>();
+    match numbers.as_deref() {
+        Some(&[x, y, width, height]) if window.contains('.') => Crop::Normalized { x, y, width, height },
+        Some(&[x, y, width, height]) => {
+            Crop::Pixels { x: x as u32, y: y as u32, width: width as u32, height: height as u32 }
+        }
+        _ => panic!("--crop requires x,y,width,height"),
+    }
+}
+
+// The passes traced through pixel centers from the scene alone, besides the render.
+#[derive(Default)]
+struct Passes {
+    matte: Option<String>,
+    depth: Option<String>,
+    depth_mode: DepthMode,
+    depth_range: Option<(f64, f64)>,
+    position: Option<String>,
+    svg: Option<String>,
+}
+
+impl Passes {
+    fn save(&self, scene: &Scene, settings: &RenderSettings) -> Result<()> {
+        if let Some(path) = &self.matte {
+            Matte::new(scene, settings).save(path)?;
+        }
+        if let Some(path) = &self.svg {
+            fs::write(path, raytracer::svg_overlay(scene, settings))?;
+        }
+        if self.depth.is_none() && self.position.is_none() {
+            return Ok(());
+        }
+        let pass = DepthPass::new(scene, settings);
+        if let Some(path) = &self.depth {
+            raytracer::save_float_image(path, pass.depth(self.depth_mode, self.depth_range))?;
+        }
+        if let Some(path) = &self.position {
+            raytracer::save_float_image(path, pass.position())?;
+        }
+        Ok(())
+    }
+}
+
+fn print_path_lengths(lengths: &PathLengths, max_depth: usize) {
+    let (mean, longest, cut) = lengths.overall();
+    eprintln!("path length: {:.2} bounces on average, {} at most", mean, longest);
+    if cut > 0.0 {
+        eprintln!("             {:.2}% of paths cut off at the maximum depth of {}", cut * 100.0, max_depth);
+    }
+}
+
+// The numbers themselves in OpenEXR, the mean, the longest and the share cut off in the channels, and the
+// mean as a heatmap otherwise.
+fn save_path_lengths(path: &str, lengths: &PathLengths) -> Result<()> {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("exr") => raytracer::save_float_image(path, lengths.values()),
+        _ => raytracer::save_image(path, lengths.heatmap()),
+    }
+}
+
+fn print_stats(stats: &Stats) {
+    eprintln!("rays:            {} ({}/s)", stats.rays, si(stats.rays_per_second()));
+    eprintln!("rays per pixel:  {:.1}", stats.rays_per_pixel());
+    eprintln!("BVH node visits: {} ({:.1} per ray)", stats.node_visits, stats.node_visits_per_ray());
+}
+
+// Loads the scene and times a probe pass to check the settings before committing to a long render.
+fn estimate(settings: &RenderSettings) -> Result<()> {
+    let scene = raytracer::load_scene(settings)?;
+    if let Some(summary) = &scene.summary {
+        print_summary(summary);
+    }
+    let estimate = Estimate::new(&scene, settings);
+    eprintln!("probe pass: {:.2}s", estimate.probe.as_secs_f64());
+    eprintln!("estimated:  {} for {} passes", hms(estimate.duration), settings.samples * settings.threads);
+    match estimate.resident {
+        Some(resident) => eprintln!(
+            "memory:     {}B loaded + {}B of buffers", si(resident as f64), si(estimate.buffers as f64),
+        ),
+        None => eprintln!("memory:     {}B of buffers besides the scene", si(estimate.buffers as f64)),
+    }
+    Ok(())
+}
+
+// The contributions the clamp cut, by light, by the object the paths bounced off last and by bounce, each
+// with its share of the energy cut, so the worst offenders come first.
+fn print_fireflies(fireflies: &Fireflies, scene: &Scene) {
+    let total = fireflies.total();
+    eprintln!("fireflies: {} contributions clamped, {} cut", total.count, si(total.excess));
+    if total.count == 0 {
+        return;
+    }
+    let name = |k: usize| scene.names().get(k).cloned().unwrap_or_else(|| format!("object {}", k));
+    let shares = |groups: Vec<(String, Tally)>| groups.iter().take(4)
+        .map(|(key, tally)| format!("{} {:.0}% ({})", key, 100.0 * tally.excess / total.excess, tally.count))
+        .collect::<Vec<_>>().join(", ");
+    let lights = fireflies.by(|o| o.light).into_iter().map(|(light, tally)| (match light {
+        Light::Emitter(k) => name(k),
+        Light::Background => "background".to_owned(),
+        Light::Portals => "portals".to_owned(),
+        Light::Photons => "photons".to_owned(),
+    }, tally)).collect();
+    let objects = fireflies.by(|o| o.object).into_iter()
+        .map(|(object, tally)| (object.map_or_else(|| "camera".to_owned(), name), tally)).collect();
+    let depths = fireflies.by(|o| o.depth).into_iter()
+        .map(|(depth, tally)| (format!("depth {}", depth), tally)).collect();
+    eprintln!("  lights:  {}", shares(lights));
+    eprintln!("  objects: {}", shares(objects));
+    eprintln!("  bounces: {}", shares(depths));
+}
+
+fn print_summary(summary: &Summary) {
+    let tally = |counts: &BTreeMap<String, usize>| {
+        let total = counts.values().sum::<usize>();
+        let kinds = counts.iter().map(|(kind, n)| format!("{} {}", n, kind)).collect::<Vec<_>>();
+        if kinds.is_empty() { "0".to_owned() } else { format!("{} ({})", total, kinds.join(", ")) }
+    };
+    eprintln!("objects:   {}", tally(&summary.objects));
+    eprintln!("materials: {}", tally(&summary.materials));
+    eprintln!("lights:    {}", tally(&summary.lights));
+    eprintln!("geometry:  {} primitives, {}B", summary.primitives, si(summary.geometry_bytes as f64));
+    eprintln!("textures:  {}B", si(summary.texture_bytes as f64));
+    let assets = &summary.assets;
+    eprintln!(
+        "assets:    {} images ({} reused), {} meshes ({} reused)",
+        assets.images, assets.image_hits, assets.meshes, assets.mesh_hits,
+    );
+    match summary.bounds {
+        Some(b) => eprintln!(
+            "bounds:    ({:.2}, {:.2}, {:.2}) to ({:.2}, {:.2}, {:.2})",
+            b.min.x, b.min.y, b.min.z, b.max.x, b.max.y, b.max.z,
+        ),
+        None => eprintln!("bounds:    none"),
+    }
+    for (path, winding) in &summary.miswound {
+        eprintln!(
+            "warning: {} has {} inconsistently wound edges and {} faces facing the wrong way; \"fix_winding\": true \
+             flips them", path, winding.inconsistent, winding.flipped,
+        );
+    }
+}
+
+fn si(x: f64) -> String {
+    match x {
+        x if x >= 1e9 => format!("{:.1}G", x / 1e9),
+        x if x >= 1e6 => format!("{:.1}M", x / 1e6),
+        x if x >= 1e3 => format!("{:.1}k", x / 1e3),
+        x => format!("{:.0}", x),
+    }
+}
+
+fn hms(duration: Duration) -> String {
+    let s = duration.as_secs();
+    format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+fn progress_bar(stats: &Stats) {
+    let fraction = (stats.passes as f64 / stats.target.max(1) as f64).min(1.0);
+    let filled = (fraction * BAR_WIDTH as f64) as usize;
+    let eta = stats.eta().map_or_else(|| "-".to_owned(), hms);
+    eprint!(
+        "\r[{}{}] {:3.0}% {}/{} passes  {} samples/s  {} rays/s  elapsed {}  ETA {} ",
+        "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), fraction * 100.0, stats.passes, stats.target,
+        si(stats.samples_per_second()), si(stats.rays_per_second()), hms(stats.elapsed), eta,
+    );
+    stderr().flush().unwrap();
+}
+
+// Encodes frames rendered one per invocation, for instance with --frame and a scene override per frame,
+// into a video with ffmpeg.
+fn encode(path: &str, frames: &[String], fps: f64) -> Result<()> {
+    let mut encoder = None;
+    for frame in frames {
+        let image = raytracer::load_image(frame)?;
+        if encoder.is_none() {
+            encoder = Some(VideoEncoder::new(path, image.0, image.1, fps)?);
+        }
+        encoder.as_mut().unwrap().push(&image)?;
+    }
+    match encoder {
+        Some(encoder) => Ok(encoder.finish()?),
+        None => {
+            eprintln!("--encode requires the frames to encode");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "sdl2")]
+fn show(image: (u32, u32, Vec<nalgebra::Vector3<f64>>)) -> Result<()> {
+    raytracer::show_image(image)
+}
+
+#[cfg(not(feature = "sdl2"))]
+fn show(image: (u32, u32, Vec<nalgebra::Vector3<f64>>)) -> Result<()> {
+    raytracer::write_to_file("image.txt", image)
+}
+
+#[cfg(feature = "ocio")]
+fn display_transform(settings: &mut RenderSettings, (config, display, view): OcioView) -> Result<()> {
+    settings.display_transform = Some(Arc::new(raytracer::DisplayTransform::new(config, display, view)?));
+    Ok(())
+}
+
+#[cfg(not(feature = "ocio"))]
+fn display_transform(_settings: &mut RenderSettings, _ocio: OcioView) -> Result<()> {
+    eprintln!("--ocio-* require the ocio feature, ignoring");
+    Ok(())
+}
+
+#[cfg(feature = "sdl2")]
+fn fly(settings: &RenderSettings, guides: &Guides) -> Result<()> {
+    raytracer::fly(settings, guides)
+}
+
+#[cfg(not(feature = "sdl2"))]
+fn fly(_settings: &RenderSettings, _guides: &Guides) -> Result<()> {
+    eprintln!("--fly requires the sdl2 feature");
+    Ok(())
 }
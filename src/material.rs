@@ -1,14 +1,146 @@
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+
 use nalgebra::{ArrayStorage, Vector3};
 use rand::Rng;
-use rand_distr::{Distribution, UnitSphere};
+use rand_distr::{Distribution, StandardNormal, UnitSphere};
 use rand_distr::num_traits::Pow;
 
+use crate::error::{Error, Result};
 use crate::object::Intersection;
 use crate::ray::Ray;
+use crate::texture::{ALPHA_CUTOFF, Texture};
 use crate::RNG;
 
 pub trait Material {
     fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>);
+
+    // Scatters as `scatter` does, also saying whether `eval` covers the lobe the direction was drawn from.
+    // Lights sampled directly at the surface only reach the path through `eval`, so a path going on through
+    // a lobe it leaves out, such as the mirror of a clear coat, counts the lights it hits itself.
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        let (ray, attenuation) = self.scatter(int);
+        (ray, attenuation, !self.specular())
+    }
+
+    fn eval(&self, _int: &Intersection, _wi: &Vector3<f64>) -> Vector3<f64> {
+        Vector3::zeros()
+    }
+
+    fn specular(&self) -> bool {
+        false
+    }
+
+    // Masked-out hits are skipped by intersection, so they neither shade nor cast shadows.
+    fn masked(&self, _int: &Intersection) -> bool {
+        false
+    }
+
+    // Materials that bound a medium are refracted by the path tracer itself, which knows the medium on
+    // the other side of the surface.
+    fn medium(&self) -> Option<Medium> {
+        None
+    }
+
+    // Shadow catchers seen from the camera are shaded by the path tracer itself, which composites them
+    // over what is behind them.
+    fn shadow_catcher(&self) -> bool {
+        false
+    }
+
+    // The radiance the surface gives off on its front, the side its normal points to.
+    fn emission(&self) -> Vector3<f64> {
+        Vector3::zeros()
+    }
+}
+
+impl<M: Material + ?Sized> Material for Box<M> {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        (**self).scatter(int)
+    }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        (**self).scatter_lobe(int)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        (**self).eval(int, wi)
+    }
+
+    fn specular(&self) -> bool {
+        (**self).specular()
+    }
+
+    fn masked(&self, int: &Intersection) -> bool {
+        (**self).masked(int)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        (**self).medium()
+    }
+
+    fn shadow_catcher(&self) -> bool {
+        (**self).shadow_catcher()
+    }
+
+    fn emission(&self) -> Vector3<f64> {
+        (**self).emission()
+    }
+}
+
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        (**self).scatter(int)
+    }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        (**self).scatter_lobe(int)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        (**self).eval(int, wi)
+    }
+
+    fn specular(&self) -> bool {
+        (**self).specular()
+    }
+
+    fn masked(&self, int: &Intersection) -> bool {
+        (**self).masked(int)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        (**self).medium()
+    }
+
+    fn shadow_catcher(&self) -> bool {
+        (**self).shadow_catcher()
+    }
+
+    fn emission(&self) -> Vector3<f64> {
+        (**self).emission()
+    }
+}
+
+// The interior of a dielectric. Where media overlap, the one with the highest priority is the one the
+// path is in, and surfaces of the others are passed through.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Medium {
+    pub index_refraction: f64,
+    pub priority: u32,
+}
+
+impl Medium {
+    // Refracts or reflects off the surface going from a medium of index `from` into one of index `to`,
+    // returning whether the ray was transmitted.
+    pub(crate) fn scatter(int: &Intersection, from: f64, to: f64) -> (Ray<f64>, bool) {
+        let v = int.ray().direction();
+        let n = int.normal();
+        let direction = refract_schlick(v, n, from / to);
+        (Ray::new(*int.point(), direction), direction.dot(n) < 0.0)
+    }
 }
 
 pub struct Metal {
@@ -29,31 +161,209 @@ impl Material for Metal {
         let r = reflect(v, n) + self.fuzz * random_unit_vector();
         (Ray::new(*int.point(), r), self.color)
     }
+
+    fn specular(&self) -> bool {
+        true
+    }
 }
 
-pub struct Lambertian {
+// How other renderers turn a perceptual roughness and an anisotropy into GGX alphas, so that materials
+// carried over from them look the same. Both square the roughness, and differ in how anisotropy stretches
+// it along the tangent.
+#[derive(Clone, Copy)]
+pub enum Convention {
+    // Blender's Principled BSDF: alphas divided and multiplied by sqrt(1 - 0.9 anisotropy).
+    Blender,
+    // glTF's KHR_materials_anisotropy: the tangent alpha is blended towards 1 by the strength squared.
+    Gltf,
+}
+
+// A rough conductor with a GGX microfacet distribution, stretched by `alpha_x` along the surface tangent
+// and `alpha_y` along the bitangent. `color` is the reflectance at normal incidence.
+pub struct Ggx {
     color: Vector3<f64>,
+    alpha_x: f64,
+    alpha_y: f64,
 }
 
-impl Lambertian {
-    pub fn new(color: Vector3<f64>) -> Self {
-        Self { color }
+impl Ggx {
+    pub fn new(color: Vector3<f64>, alpha: f64) -> Self {
+        Self::anisotropic(color, alpha, alpha)
+    }
+
+    pub fn anisotropic(color: Vector3<f64>, alpha_x: f64, alpha_y: f64) -> Self {
+        Self { color, alpha_x: alpha_x.max(1e-4), alpha_y: alpha_y.max(1e-4) }
+    }
+
+    // From a perceptual roughness and an anisotropy from 0 to 1, as `convention` maps them.
+    pub fn perceptual(color: Vector3<f64>, roughness: f64, anisotropy: f64, convention: Convention) -> Self {
+        let alpha = roughness * roughness;
+        let (x, y) = match convention {
+            Convention::Blender => {
+                let aspect = (1.0 - 0.9 * anisotropy).sqrt();
+                (alpha / aspect, alpha * aspect)
+            }
+            Convention::Gltf => (alpha + (1.0 - alpha) * anisotropy * anisotropy, alpha),
+        };
+        Self::anisotropic(color, x, y)
+    }
+
+    fn fresnel(&self, cos: f64) -> Vector3<f64> {
+        self.color + (Vector3::new(1.0, 1.0, 1.0) - self.color) * (1.0 - cos.clamp(0.0, 1.0)).pow(5)
+    }
+
+    fn distribution(&self, h: &Vector3<f64>) -> f64 {
+        let (x, y) = (h.x / self.alpha_x, h.y / self.alpha_y);
+        let d = x * x + y * y + h.z * h.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * d * d)
+    }
+
+    fn smith_g1(&self, w: &Vector3<f64>) -> f64 {
+        let (x, y) = (w.x * self.alpha_x, w.y * self.alpha_y);
+        let lambda = (-1.0 + (1.0 + (x * x + y * y) / (w.z * w.z)).sqrt()) / 2.0;
+        1.0 / (1.0 + lambda)
+    }
+
+    // Samples a microfacet normal from the distribution of normals visible from `wo` (Heitz 2018).
+    fn sample_visible_normal(&self, wo: &Vector3<f64>) -> Vector3<f64> {
+        let vh = Vector3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0.0 { Vector3::new(-vh.y, vh.x, 0.0) / len2.sqrt() } else { Vector3::x() };
+        let t2 = vh.cross(&t1);
+        let (u1, u2): (f64, f64) = RNG.with(|r| {
+            let mut r = r.borrow_mut();
+            (r.gen(), r.gen())
+        });
+        let (r, phi) = (u1.sqrt(), 2.0 * PI * u2);
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        Vector3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).normalize()
+    }
+}
+
+impl Material for Ggx {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        let n = int.normal();
+        let (t, b) = int.tangent_frame();
+        let to_local = |v: &Vector3<f64>| Vector3::new(v.dot(&t), v.dot(&b), v.dot(n));
+        let wo = to_local(&-int.ray().direction().normalize());
+        let h = self.sample_visible_normal(&wo);
+        let wi = reflect(&-wo, &h);
+        let direction = t * wi.x + b * wi.y + n * wi.z;
+        // with visible normal sampling the weight reduces to F * G1(wi)
+        let attenuation = if wi.z > 0.0 { self.fresnel(wo.dot(&h)) * self.smith_g1(&wi) } else { Vector3::zeros() };
+        (Ray::new(*int.point(), direction), attenuation)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        let n = int.normal();
+        let (t, b) = int.tangent_frame();
+        let to_local = |v: &Vector3<f64>| Vector3::new(v.dot(&t), v.dot(&b), v.dot(n));
+        let (wi, wo) = (to_local(&wi.normalize()), to_local(&-int.ray().direction().normalize()));
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return Vector3::zeros();
+        }
+        let h = (wi + wo).normalize();
+        let g = self.smith_g1(&wi) * self.smith_g1(&wo);
+        self.fresnel(wi.dot(&h)) * (self.distribution(&h) * g / (4.0 * wi.z * wo.z))
+    }
+}
+
+pub struct Lambertian<T = Vector3<f64>> {
+    albedo: T,
+}
+
+impl<T> Lambertian<T> {
+    pub fn new(albedo: T) -> Self {
+        Self { albedo }
+    }
+}
+
+impl<T: Texture> Material for Lambertian<T> {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        (Ray::new(*int.point(), int.normal() + random_unit_vector()), self.albedo.value(int))
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        if wi.dot(int.normal()) > 0.0 { self.albedo.value(int) / PI } else { Vector3::zeros() }
+    }
+
+    fn masked(&self, int: &Intersection) -> bool {
+        self.albedo.opacity(int) < ALPHA_CUTOFF
+    }
+}
+
+// A stand-in for a real surface of a photo, such as the ground CG objects are to stand on, that shows only
+// what the objects add to it. Seen from the camera it lets what is behind it show through, darkened by the
+// shadows the objects cast on it, which make up its alpha, and lit by the light they bounce onto it;
+// anywhere else, such as in reflections, it is a diffuse surface of `albedo`.
+pub struct ShadowCatcher {
+    albedo: Vector3<f64>,
+}
+
+impl ShadowCatcher {
+    pub fn new(albedo: Vector3<f64>) -> Self {
+        Self { albedo }
     }
 }
 
-impl Material for Lambertian {
+impl Material for ShadowCatcher {
+    // cosine-weighted like a Lambertian surface, which the shadow estimate relies on, and from just off the
+    // surface, as a bounce hitting the catcher itself again would count as a shadow
     fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
-        (Ray::new(*int.point(), int.normal() + random_unit_vector()), self.color)
+        let origin = int.point() + int.normal() * 1e-9 * int.point().amax().max(1.0);
+        (Ray::new(origin, int.normal() + random_unit_vector()), self.albedo)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        if wi.dot(int.normal()) > 0.0 { self.albedo / PI } else { Vector3::zeros() }
+    }
+
+    fn shadow_catcher(&self) -> bool {
+        true
+    }
+}
+
+// A light: a surface giving off `radiance` evenly on its front and reflecting nothing. Many of them are
+// sampled through the scene's light tree.
+pub struct Emissive {
+    radiance: Vector3<f64>,
+}
+
+impl Emissive {
+    pub fn new(radiance: Vector3<f64>) -> Self {
+        Self { radiance }
+    }
+}
+
+impl Material for Emissive {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        (Ray::new(*int.point(), *int.normal()), Vector3::zeros())
+    }
+
+    fn emission(&self) -> Vector3<f64> {
+        self.radiance
     }
 }
 
 pub struct Dielectric {
     index_refraction: f64,
+    priority: u32,
 }
 
 impl Dielectric {
     pub fn new(index_refraction: f64) -> Self {
-        Self { index_refraction }
+        Self { index_refraction, priority: 0 }
+    }
+
+    pub fn with_priority(self, priority: u32) -> Self {
+        Self { priority, ..self }
+    }
+
+    pub fn water() -> Self {
+        Self::new(1.333)
     }
 }
 
@@ -64,9 +374,389 @@ impl Material for Dielectric {
         let n = int.normal();
         (Ray::new(*int.point(), refract_schlick(v, n, ratio)), Vector3::new(1.0, 1.0, 1.0))
     }
+
+    fn specular(&self) -> bool {
+        true
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        Some(Medium { index_refraction: self.index_refraction, priority: self.priority })
+    }
+}
+
+pub struct Hair {
+    color: Vector3<f64>,
+    roughness: f64,
+    reflection: f64,
+}
+
+impl Hair {
+    pub fn new(color: Vector3<f64>, roughness: f64) -> Self {
+        Self { color, roughness, reflection: 0.25 }
+    }
+}
+
+impl Material for Hair {
+    // A lobe-sampled fiber model: an uncolored reflection lobe (R) off the cuticle and a colored
+    // forward transmission lobe (TT), both spread longitudinally and azimuthally by `roughness`.
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        let n = int.normal();
+        let t = int.tangent().unwrap_or_else(|| orthonormal_basis(n).0);
+        let v = int.ray().direction().normalize();
+        let (specular, gaussian): (bool, f64) = RNG.with(|r| {
+            let mut r = r.borrow_mut();
+            (r.gen::<f64>() < self.reflection, StandardNormal.sample(&mut *r))
+        });
+        let sin_o = (v.dot(&t) + self.roughness * gaussian).clamp(-1.0, 1.0);
+        let perpendicular = v - v.dot(&t) * t;
+        let azimuth = if specular { reflect(&perpendicular, n) } else { perpendicular };
+        let azimuth = azimuth.try_normalize(1e-8).unwrap_or(*n);
+        let spread = RNG.with(|r| r.borrow_mut().gen_range(-1.0..1.0)) * self.roughness * PI;
+        let azimuth = azimuth * spread.cos() + t.cross(&azimuth) * spread.sin();
+        let w = t * sin_o + azimuth.normalize() * (1.0 - sin_o * sin_o).sqrt();
+        let attenuation = if specular { Vector3::new(1.0, 1.0, 1.0) } else { self.color };
+        (Ray::new(*int.point(), w), attenuation)
+    }
+
+    // the fiber has no `eval`, so only its own bounces find lights
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        let (ray, attenuation) = self.scatter(int);
+        (ray, attenuation, false)
+    }
+}
+
+// Picks `a` or `b` at random with the blend factor as the probability of `b`, so the weight cancels out
+// of the attenuation. A texture factor is reduced to its mean over the channels.
+pub struct Mix<A, B, T = Vector3<f64>> {
+    a: A,
+    b: B,
+    factor: T,
+}
+
+impl<A, B, T> Mix<A, B, T> {
+    pub fn new(a: A, b: B, factor: T) -> Self {
+        Self { a, b, factor }
+    }
+}
+
+impl<A: Material, B: Material, T: Texture> Mix<A, B, T> {
+    fn weight(&self, int: &Intersection) -> f64 {
+        self.factor.value(int).mean().clamp(0.0, 1.0)
+    }
+}
+
+impl<A: Material, B: Material, T: Texture> Material for Mix<A, B, T> {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        let (ray, attenuation, _) = self.scatter_lobe(int);
+        (ray, attenuation)
+    }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        match RNG.with(|r| r.borrow_mut().gen::<f64>()) < self.weight(int) {
+            true => self.b.scatter_lobe(int),
+            false => self.a.scatter_lobe(int),
+        }
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        let w = self.weight(int);
+        self.a.eval(int, wi) * (1.0 - w) + self.b.eval(int, wi) * w
+    }
+
+    fn specular(&self) -> bool {
+        self.a.specular() && self.b.specular()
+    }
+
+    fn masked(&self, int: &Intersection) -> bool {
+        self.a.masked(int) && self.b.masked(int)
+    }
+}
+
+// A clear dielectric coat over a base material. The coat reflects with Fresnel probability; the rest of
+// the light reaches the base and is tinted by `color` on its way through the coat.
+pub struct Layered<M> {
+    base: M,
+    index_refraction: f64,
+    color: Vector3<f64>,
+}
+
+impl<M> Layered<M> {
+    pub fn new(base: M, index_refraction: f64) -> Self {
+        Self { base, index_refraction, color: Vector3::new(1.0, 1.0, 1.0) }
+    }
+
+    pub fn with_color(self, color: Vector3<f64>) -> Self {
+        Self { color, ..self }
+    }
+
+    fn coat(&self, cos: f64) -> f64 {
+        reflectance(cos.abs().min(1.0), 1.0 / self.index_refraction)
+    }
+}
+
+impl<M: Material> Material for Layered<M> {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        let (ray, attenuation, _) = self.scatter_lobe(int);
+        (ray, attenuation)
+    }
+
+    // the coat is a mirror, which `eval` leaves out
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        let v = int.ray().direction().normalize();
+        let n = int.normal();
+        if RNG.with(|r| r.borrow_mut().gen::<f64>()) < self.coat(v.dot(n)) {
+            return (Ray::new(*int.point(), reflect(&v, n)), Vector3::new(1.0, 1.0, 1.0), false);
+        }
+        let (ray, attenuation, evaluated) = self.base.scatter_lobe(int);
+        (ray, attenuation.component_mul(&self.color), evaluated)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        let v = int.ray().direction().normalize();
+        let transmitted = (1.0 - self.coat(v.dot(int.normal()))) * (1.0 - self.coat(wi.normalize().dot(int.normal())));
+        self.base.eval(int, wi).component_mul(&self.color) * transmitted
+    }
+
+    fn specular(&self) -> bool {
+        self.base.specular()
+    }
+
+    fn masked(&self, int: &Intersection) -> bool {
+        self.base.masked(int)
+    }
+}
+
+// Representative wavelengths in nanometers for the red, green and blue channels.
+const RGB_WAVELENGTHS: [f64; 3] = [650.0, 510.0, 475.0];
+
+// An interference coat of the given thickness (in nanometers) and index of refraction over a base
+// material, evaluated at one wavelength per channel. The substrate behind the film defaults to air,
+// as in a soap bubble; light not reflected by the film reaches the base.
+pub struct ThinFilm<M> {
+    base: M,
+    thickness: f64,
+    index_refraction: f64,
+    substrate: f64,
+}
+
+impl<M> ThinFilm<M> {
+    pub fn new(base: M, thickness: f64, index_refraction: f64) -> Self {
+        Self { base, thickness, index_refraction, substrate: 1.0 }
+    }
+
+    pub fn with_substrate(self, substrate: f64) -> Self {
+        Self { substrate, ..self }
+    }
+
+    // Airy reflectance of the film, averaged over s and p polarization.
+    fn reflectance(&self, cos: f64) -> Vector3<f64> {
+        let (n1, n2, n3) = (1.0, self.index_refraction, self.substrate);
+        let sin2 = 1.0 - cos * cos;
+        let cos2 = 1.0 - sin2 * (n1 / n2) * (n1 / n2);
+        let cos3 = 1.0 - sin2 * (n1 / n3) * (n1 / n3);
+        if cos2 <= 0.0 || cos3 <= 0.0 {
+            return Vector3::new(1.0, 1.0, 1.0);
+        }
+        let (c1, c2, c3) = (cos, cos2.sqrt(), cos3.sqrt());
+        let s = ((n1 * c1 - n2 * c2) / (n1 * c1 + n2 * c2), (n2 * c2 - n3 * c3) / (n2 * c2 + n3 * c3));
+        let p = ((n2 * c1 - n1 * c2) / (n2 * c1 + n1 * c2), (n3 * c2 - n2 * c3) / (n3 * c2 + n2 * c3));
+        Vector3::from_iterator(RGB_WAVELENGTHS.iter().map(|lambda| {
+            let phase = (4.0 * PI * n2 * self.thickness * c2 / lambda).cos();
+            let airy = |(r12, r23): (f64, f64)| {
+                (r12 * r12 + r23 * r23 + 2.0 * r12 * r23 * phase)
+                    / (1.0 + r12 * r12 * r23 * r23 + 2.0 * r12 * r23 * phase)
+            };
+            (airy(s) + airy(p)) / 2.0
+        }))
+    }
+}
+
+impl<M: Material> Material for ThinFilm<M> {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        let (ray, attenuation, _) = self.scatter_lobe(int);
+        (ray, attenuation)
+    }
+
+    // the film reflects like a mirror, which `eval` leaves out
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        let v = int.ray().direction().normalize();
+        let n = int.normal();
+        let r = self.reflectance(v.dot(n).abs().min(1.0));
+        let p = r.mean();
+        if RNG.with(|rng| rng.borrow_mut().gen::<f64>()) < p {
+            return (Ray::new(*int.point(), reflect(&v, n)), r / p, false);
+        }
+        let (ray, attenuation, evaluated) = self.base.scatter_lobe(int);
+        (ray, attenuation.component_mul(&(Vector3::new(1.0, 1.0, 1.0) - r)) / (1.0 - p), evaluated)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        let cos = int.ray().direction().normalize().dot(int.normal()).abs().min(1.0);
+        self.base.eval(int, wi).component_mul(&(Vector3::new(1.0, 1.0, 1.0) - self.reflectance(cos)))
+    }
+
+    fn specular(&self) -> bool {
+        self.base.specular()
+    }
+
+    fn masked(&self, int: &Intersection) -> bool {
+        self.base.masked(int)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Backface {
+    Shade,
+    Cull,
+    Black,
+}
+
+// Gives a material different behavior on the side facing away from its normal: culled backfaces are
+// skipped by intersection like masked-out hits, black ones absorb all light.
+pub struct Sided<M> {
+    material: M,
+    backface: Backface,
+}
+
+impl<M> Sided<M> {
+    pub fn new(material: M, backface: Backface) -> Self {
+        Self { material, backface }
+    }
+}
+
+impl<M: Material> Material for Sided<M> {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        let (ray, attenuation, _) = self.scatter_lobe(int);
+        (ray, attenuation)
+    }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        let (ray, attenuation, evaluated) = self.material.scatter_lobe(int);
+        match self.backface {
+            Backface::Black if !int.front() => (ray, Vector3::zeros(), evaluated),
+            _ => (ray, attenuation, evaluated),
+        }
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        match self.backface {
+            Backface::Black if !int.front() => Vector3::zeros(),
+            _ => self.material.eval(int, wi),
+        }
+    }
+
+    fn specular(&self) -> bool {
+        self.material.specular()
+    }
+
+    fn masked(&self, int: &Intersection) -> bool {
+        (matches!(self.backface, Backface::Cull) && !int.front()) || self.material.masked(int)
+    }
+
+    fn shadow_catcher(&self) -> bool {
+        self.material.shadow_catcher()
+    }
+
+    fn emission(&self) -> Vector3<f64> {
+        self.material.emission()
+    }
+}
+
+const MERL_THETA_H: usize = 90;
+const MERL_THETA_D: usize = 90;
+const MERL_PHI_D: usize = 180;
+const MERL_SIZE: usize = MERL_THETA_H * MERL_THETA_D * MERL_PHI_D;
+const MERL_SCALE: [f64; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+pub struct Merl {
+    data: Vec<f64>,
+}
+
+impl Merl {
+    pub fn load(path: &str) -> Result<Self> {
+        let invalid = |what: &str| Error::Material(format!("{}: {}", path, what));
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        if bytes.len() < 12 {
+            return Err(invalid("truncated MERL file"));
+        }
+        let dims = bytes[..12].chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .fold(1usize, usize::saturating_mul);
+        if dims != MERL_SIZE {
+            return Err(invalid("unexpected MERL dimensions"));
+        }
+        let data = bytes[12..].chunks_exact(8)
+            .take(3 * MERL_SIZE)
+            .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]).max(0.0))
+            .collect::<Vec<_>>();
+        if data.len() != 3 * MERL_SIZE {
+            return Err(invalid("truncated MERL file"));
+        }
+        Ok(Self { data })
+    }
+
+    pub fn brdf(&self, n: &Vector3<f64>, wi: &Vector3<f64>, wo: &Vector3<f64>) -> Vector3<f64> {
+        let (t, b) = orthonormal_basis(n);
+        let to_local = |v: &Vector3<f64>| Vector3::new(v.dot(&t), v.dot(&b), v.dot(n));
+        let (wi, wo) = (to_local(wi), to_local(wo));
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return Vector3::zeros();
+        }
+
+        let half = (wi + wo).normalize();
+        let theta_h = half.z.min(1.0).acos();
+        let phi_h = half.y.atan2(half.x);
+        let diff = rotate_y(&rotate_z(&wi, -phi_h), -theta_h);
+        let theta_d = diff.z.clamp(-1.0, 1.0).acos();
+        let phi_d = diff.y.atan2(diff.x);
+
+        let i_theta_h = ((theta_h / FRAC_PI_2).sqrt() * MERL_THETA_H as f64) as usize;
+        let i_theta_d = (theta_d / FRAC_PI_2 * MERL_THETA_D as f64) as usize;
+        let phi_d = if phi_d < 0.0 { phi_d + PI } else { phi_d };
+        let i_phi_d = (phi_d / PI * MERL_PHI_D as f64) as usize;
+        let index = i_phi_d.min(MERL_PHI_D - 1)
+            + i_theta_d.min(MERL_THETA_D - 1) * MERL_PHI_D
+            + i_theta_h.min(MERL_THETA_H - 1) * MERL_PHI_D * MERL_THETA_D;
+        Vector3::new(
+            self.data[index] * MERL_SCALE[0],
+            self.data[index + MERL_SIZE] * MERL_SCALE[1],
+            self.data[index + 2 * MERL_SIZE] * MERL_SCALE[2],
+        )
+    }
+}
+
+impl Material for Merl {
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        let n = int.normal();
+        let wo = -int.ray().direction().normalize();
+        let wi = (n + random_unit_vector()).try_normalize(1e-8).unwrap_or(*n);
+        // cosine-weighted sampling: brdf * cos / pdf = brdf * pi
+        (Ray::new(*int.point(), wi), self.brdf(n, &wi, &wo) * PI)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        self.brdf(int.normal(), &wi.normalize(), &-int.ray().direction().normalize())
+    }
+}
+
+pub(crate) fn orthonormal_basis(n: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let a = if n.x.abs() > 0.9 { Vector3::y() } else { Vector3::x() };
+    let t = n.cross(&a).normalize();
+    (t, n.cross(&t))
+}
+
+fn rotate_z(v: &Vector3<f64>, angle: f64) -> Vector3<f64> {
+    let (s, c) = angle.sin_cos();
+    Vector3::new(c * v.x - s * v.y, s * v.x + c * v.y, v.z)
+}
+
+fn rotate_y(v: &Vector3<f64>, angle: f64) -> Vector3<f64> {
+    let (s, c) = angle.sin_cos();
+    Vector3::new(c * v.x + s * v.z, v.y, -s * v.x + c * v.z)
 }
 
-fn random_unit_vector() -> Vector3<f64> {
+pub(crate) fn random_unit_vector() -> Vector3<f64> {
     Vector3::from_data(ArrayStorage([RNG.with(|r| UnitSphere.sample(&mut *r.borrow_mut()))]))
 }
 
@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::Path;
+
+use itertools::iproduct;
+use nalgebra::Vector3;
+use serde_json::{Map, Value};
+
+use crate::frame_camera;
+use crate::error::Result;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+use crate::sidecar::fnv1a;
+use crate::white_balance::first_object;
+
+// Rays per pixel along each axis when finding which object covers how much of it.
+const SUBPIXELS: u32 = 4;
+
+// A simplified Cryptomatte: the object covering most of each pixel and how much of it it covers,
+// with every object named so it can be picked out in compositing.
+pub struct Matte {
+    width: u32,
+    height: u32,
+    ids: Vec<Option<usize>>,
+    coverage: Vec<f64>,
+    names: Vec<String>,
+}
+
+impl Matte {
+    pub fn new(scene: &Scene, settings: &RenderSettings) -> Self {
+        let (width, height) = settings.image_size();
+        let camera = frame_camera(&scene.view, settings);
+        let objects = &scene.objects[..];
+        let (ids, coverage) = iproduct!(0..width, 0..height).map(|(i, j)| {
+            let mut hits = Vec::<(usize, u32)>::new();
+            for (s, t) in iproduct!(0..SUBPIXELS, 0..SUBPIXELS) {
+                let u = (i as f64 + (s as f64 + 0.5) / SUBPIXELS as f64) / width as f64;
+                let v = 1.0 - (j as f64 + (t as f64 + 0.5) / SUBPIXELS as f64) / height as f64;
+                let ray = camera.ray_through(u, v, 1.0 / width as f64, 1.0 / height as f64, [0.0, 0.0]);
+                if let Some(k) = first_object(objects, &ray, scene.view.clipped(&ray)) {
+                    match hits.iter_mut().find(|(id, _)| *id == k) {
+                        Some((_, n)) => *n += 1,
+                        None => hits.push((k, 1)),
+                    }
+                }
+            }
+            let most = hits.into_iter().max_by_key(|&(_, n)| n);
+            (most.map(|(k, _)| k), most.map_or(0.0, |(_, n)| n as f64 / (SUBPIXELS * SUBPIXELS) as f64))
+        }).unzip();
+        let names = (0..objects.len())
+            .map(|k| scene.names.get(k).cloned().unwrap_or_else(|| format!("object.{}", k)))
+            .collect();
+        Self { width, height, ids, coverage, names }
+    }
+
+    // Each object's color, scaled by its coverage, on black.
+    pub fn image(&self) -> (u32, u32, Vec<Vector3<f64>>) {
+        let buffer = self.ids.iter().zip(&self.coverage)
+            .map(|(id, c)| id.map_or_else(Vector3::zeros, |k| color(&self.names[k]) * *c))
+            .collect();
+        (self.width, self.height, buffer)
+    }
+
+    // The object names with the colors that stand for them in the image, as `#rrggbb`.
+    pub fn manifest(&self) -> Value {
+        let entries = self.names.iter().map(|name| (name.clone(), Value::from(format!("#{:06x}", hash(name)))));
+        Value::Object(entries.collect::<Map<_, _>>())
+    }
+
+    // The object covering most of pixel (`i`, `j`), None off the image.
+    pub(crate) fn id(&self, i: i64, j: i64) -> Option<usize> {
+        if !(0..self.width as i64).contains(&i) || !(0..self.height as i64).contains(&j) {
+            return None;
+        }
+        self.ids[(i * self.height as i64 + j) as usize]
+    }
+
+    pub(crate) fn name(&self, k: usize) -> &str {
+        &self.names[k]
+    }
+
+    // Saves the image, and the manifest next to it as `<path>.json`.
+    pub fn save(&self, path: &str) -> Result<()> {
+        crate::save_image(path, self.image())?;
+        Ok(fs::write(Path::new(path).with_extension("json"), serde_json::to_string_pretty(&self.manifest())?)?)
+    }
+}
+
+// Colors come from a hash of the name, as in Cryptomatte, so an object keeps its color as the scene changes.
+pub(crate) fn hash(name: &str) -> u32 {
+    (fnv1a(name.as_bytes()) & 0xffffff) as u32
+}
+
+fn color(name: &str) -> Vector3<f64> {
+    let h = hash(name);
+    Vector3::new(h >> 16, h >> 8 & 0xff, h & 0xff).map(|x| x as f64 / 255.0)
+}
@@ -0,0 +1,622 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::mem::size_of_val;
+use std::ops::Range;
+use std::path::Path;
+
+use nalgebra::{Vector2, Vector3};
+
+use crate::aabb::Aabb;
+#[cfg(not(feature = "embree"))]
+use crate::bvh::Bvh;
+#[cfg(feature = "embree")]
+use crate::embree::TriangleScene;
+use crate::error::{Error, Result};
+use crate::geometry::intersect_triangle;
+use crate::light_tree::{Emitter, Shape};
+use crate::material::{Lambertian, Material, Medium, orthonormal_basis};
+use crate::mtl::load_mtl;
+use crate::object::{Intersection, Object};
+use crate::ray::Ray;
+use crate::subdivision::{Cage, catmull_clark, Creases, Polygon};
+
+// How consistently a mesh's faces are wound, as `Mesh::winding` finds it. Neighbouring faces should run
+// along their shared edge in opposite directions, and closed pieces should face out; faces that don't
+// render black or wrong without complaint, as their normals point the wrong way.
+#[derive(Clone, Copy, Default)]
+pub struct Winding {
+    // Edges that both their faces run along the same way, so that one of the two is flipped.
+    pub inconsistent: usize,
+    // The faces `fix_winding` flips: those at odds with the rest of their piece of the mesh, and every
+    // face of a closed piece that is inside out.
+    pub flipped: usize,
+}
+
+pub struct Mesh {
+    vertices: Vec<Vector3<f64>>,
+    faces: Vec<[usize; 3]>,
+    materials: Vec<usize>,
+    uvs: Vec<Vector2<f64>>,
+    face_uvs: Vec<Option<[usize; 3]>>,
+    colors: Vec<Vector3<f64>>,
+    tangents: Vec<[(Vector3<f64>, f64); 3]>,
+    normals: Vec<Vector3<f64>>,
+    #[cfg(not(feature = "embree"))]
+    bvh: Bvh,
+    #[cfg(feature = "embree")]
+    embree: TriangleScene,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vector3<f64>>, faces: Vec<[usize; 3]>, materials: Vec<usize>) -> Self {
+        assert_eq!(faces.len(), materials.len(), "one material index per face is required");
+        let face_uvs = vec![None; faces.len()];
+        Self {
+            #[cfg(not(feature = "embree"))]
+            bvh: Bvh::build(&face_bounds(&vertices, &faces)),
+            #[cfg(feature = "embree")]
+            embree: TriangleScene::new(&vertices, &faces),
+            vertices, faces, materials, uvs: Vec::new(), face_uvs, colors: Vec::new(), tangents: Vec::new(),
+            normals: Vec::new(),
+        }
+    }
+
+    // Moves the vertices of an animated mesh, keeping its faces. The BVH is refitted rather than rebuilt
+    // where it can be, so deforming a mesh each frame stays cheap. Returns whether the BVH was rebuilt.
+    pub fn set_vertices(&mut self, vertices: Vec<Vector3<f64>>, threads: u32) -> bool {
+        assert_eq!(self.vertices.len(), vertices.len(), "deforming a mesh can't change its vertex count");
+        self.vertices = vertices;
+        if !self.normals.is_empty() {
+            self.normals = self.vertex_normals();
+        }
+        if !self.tangents.is_empty() {
+            self.tangents = self.generate_tangents();
+        }
+        #[cfg(not(feature = "embree"))]
+        return self.bvh.update(&face_bounds(&self.vertices, &self.faces), threads);
+        #[cfg(feature = "embree")]
+        {
+            let _ = threads;
+            self.embree = TriangleScene::new(&self.vertices, &self.faces);
+            true
+        }
+    }
+
+    pub fn winding(&self) -> Winding {
+        let (flip, inconsistent) = self.orientation();
+        Winding { inconsistent, flipped: flip.iter().filter(|&&f| f).count() }
+    }
+
+    // Flips the faces that `winding` counts, keeping their uvs and tangents with their corners.
+    pub fn fix_winding(mut self) -> Self {
+        let (flip, _) = self.orientation();
+        for f in (0..self.faces.len()).filter(|&f| flip[f]) {
+            self.faces[f].swap(1, 2);
+            if let Some(uvs) = &mut self.face_uvs[f] {
+                uvs.swap(1, 2);
+            }
+        }
+        if !self.normals.is_empty() {
+            self.normals = self.vertex_normals();
+        }
+        if !self.tangents.is_empty() {
+            self.tangents = self.generate_tangents();
+        }
+        self
+    }
+
+    // Which faces to flip for every piece of the mesh, the faces joined by shared edges, to be wound
+    // consistently: facing out if the piece is closed, and otherwise the way most of its faces already
+    // are. Also the number of edges whose faces disagree as they are.
+    fn orientation(&self) -> (Vec<bool>, usize) {
+        let edge = |a: usize, b: usize| (a.min(b), a.max(b));
+        let mut edges = HashMap::<(usize, usize), Vec<(usize, bool)>>::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (face[k], face[(k + 1) % 3]);
+                edges.entry(edge(a, b)).or_default().push((f, a < b));
+            }
+        }
+        let inconsistent = edges.values().filter(|e| e.len() == 2 && e[0].1 == e[1].1).count();
+        let mut flip = vec![None; self.faces.len()];
+        for seed in 0..self.faces.len() {
+            if flip[seed].is_some() {
+                continue;
+            }
+            flip[seed] = Some(false);
+            let (mut piece, mut stack, mut closed) = (vec![seed], vec![seed], true);
+            while let Some(f) = stack.pop() {
+                let face = self.faces[f];
+                for k in 0..3 {
+                    let (a, b) = (face[k], face[(k + 1) % 3]);
+                    let shared = &edges[&edge(a, b)];
+                    closed &= shared.len() == 2;
+                    // neighbours run along the edge the other way from this face as it will be
+                    let forward = (a < b) != flip[f].unwrap();
+                    for &(g, along) in shared {
+                        if flip[g].is_none() {
+                            flip[g] = Some(along == forward);
+                            piece.push(g);
+                            stack.push(g);
+                        }
+                    }
+                }
+            }
+            let turn = match closed {
+                // the enclosed volume comes out negative for pieces facing in
+                true => piece.iter().map(|&f| {
+                    let [a, b, c] = self.faces[f].map(|i| self.vertices[i]);
+                    let volume = a.dot(&b.cross(&c));
+                    if flip[f].unwrap() { -volume } else { volume }
+                }).sum::<f64>() < 0.0,
+                false => piece.iter().filter(|&&f| flip[f].unwrap()).count() * 2 > piece.len(),
+            };
+            if turn {
+                piece.iter().for_each(|&f| flip[f] = flip[f].map(|x| !x));
+            }
+        }
+        (flip.into_iter().map(Option::unwrap).collect(), inconsistent)
+    }
+
+    pub fn with_uvs(self, uvs: Vec<Vector2<f64>>, face_uvs: Vec<Option<[usize; 3]>>) -> Self {
+        assert_eq!(self.faces.len(), face_uvs.len(), "one uv triple per face is required");
+        let mesh = Self { uvs, face_uvs, ..self };
+        let tangents = if mesh.uvs.is_empty() { Vec::new() } else { mesh.generate_tangents() };
+        Self { tangents, ..mesh }
+    }
+
+    // Interpolates angle-weighted vertex normals across faces instead of shading them flat.
+    pub fn with_smooth_normals(self) -> Self {
+        let normals = self.vertex_normals();
+        Self { normals, ..self }
+    }
+
+    fn vertex_normals(&self) -> Vec<Vector3<f64>> {
+        let mut normals = vec![Vector3::zeros(); self.vertices.len()];
+        for (f, face) in self.faces.iter().enumerate() {
+            let normal = self.normal(f);
+            face.iter().zip(self.angles(f)).for_each(|(&v, angle)| normals[v] += normal * angle);
+        }
+        normals.into_iter().map(|n| n.try_normalize(1e-12).unwrap_or_else(Vector3::z)).collect()
+    }
+
+    fn angles(&self, face: usize) -> [f64; 3] {
+        let p = self.faces[face].map(|i| self.vertices[i]);
+        [0, 1, 2].map(|k| (p[(k + 1) % 3] - p[k]).angle(&(p[(k + 2) % 3] - p[k])))
+    }
+
+    // Per-corner tangents in the manner of MikkTSpace: every face's uv-aligned tangent and bitangent are
+    // accumulated, weighted by corner angle, over the corners sharing a position and a uv, then
+    // orthogonalized against the angle-weighted vertex normal. The sign is the bitangent's handedness.
+    fn generate_tangents(&self) -> Vec<[(Vector3<f64>, f64); 3]> {
+        let normals = self.vertex_normals();
+        let mut frames = HashMap::<(usize, usize), (Vector3<f64>, Vector3<f64>)>::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let p = face.map(|i| self.vertices[i]);
+            let angles = self.angles(f);
+            let uvs = match self.face_uvs[f] {
+                Some(uvs) => uvs,
+                None => continue,
+            };
+            let t = uvs.map(|i| self.uvs[i]);
+            let (e1, e2, d1, d2) = (p[1] - p[0], p[2] - p[0], t[1] - t[0], t[2] - t[0]);
+            let det = d1.x * d2.y - d2.x * d1.y;
+            if det.abs() < 1e-12 {
+                continue;
+            }
+            let tangent = ((e1 * d2.y - e2 * d1.y) / det).normalize();
+            let bitangent = ((e2 * d1.x - e1 * d2.x) / det).normalize();
+            for k in 0..3 {
+                let frame = frames.entry((face[k], uvs[k])).or_insert((Vector3::zeros(), Vector3::zeros()));
+                frame.0 += tangent * angles[k];
+                frame.1 += bitangent * angles[k];
+            }
+        }
+        self.faces.iter().zip(&self.face_uvs).map(|(face, uvs)| [0, 1, 2].map(|k| {
+            let n = normals[face[k]];
+            let (t, b) = uvs.and_then(|uvs| frames.get(&(face[k], uvs[k])).copied())
+                .unwrap_or((Vector3::zeros(), Vector3::zeros()));
+            let t = (t - n * n.dot(&t)).try_normalize(1e-12).unwrap_or_else(|| orthonormal_basis(&n).0);
+            (t, if n.cross(&t).dot(&b) < 0.0 { -1.0 } else { 1.0 })
+        })).collect()
+    }
+
+    pub fn with_colors(self, colors: Vec<Vector3<f64>>) -> Self {
+        assert_eq!(self.vertices.len(), colors.len(), "one color per vertex is required");
+        Self { colors, ..self }
+    }
+
+    pub fn load_obj(path: &str) -> Result<(Self, Vec<String>)> {
+        Self::load_obj_subdivided(path, 0, &Creases::new())
+    }
+
+    pub fn load_obj_with_materials(path: &str) -> Result<(Self, Vec<Box<dyn Material + Send + Sync>>)> {
+        Self::load_obj_subdivided_with_materials(path, 0, &Creases::new())
+    }
+
+    // Applies `levels` of Catmull-Clark subdivision to the polygons before triangulating, and shades the
+    // result with smooth vertex normals.
+    pub fn load_obj_subdivided(path: &str, levels: usize, creases: &Creases) -> Result<(Self, Vec<String>)> {
+        let (mesh, names, _) = Self::parse_obj(path, levels, creases)?;
+        Ok((mesh, names))
+    }
+
+    pub fn load_obj_subdivided_with_materials(
+        path: &str, levels: usize, creases: &Creases,
+    ) -> Result<(Self, Vec<Box<dyn Material + Send + Sync>>)> {
+        let (mesh, names, libraries) = Self::parse_obj(path, levels, creases)?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let mut library = HashMap::new();
+        for l in &libraries {
+            library.extend(load_mtl(&dir.join(l))?);
+        }
+        let materials = names.iter()
+            .map(|n| library.remove(n).unwrap_or_else(|| Box::new(Lambertian::new(Vector3::new(0.8, 0.8, 0.8)))))
+            .collect();
+        Ok((mesh, materials))
+    }
+
+    fn parse_obj(path: &str, levels: usize, creases: &Creases) -> Result<(Self, Vec<String>, Vec<String>)> {
+        let invalid = |what: String| Error::Mesh(format!("{}: {}", path, what));
+        let number = |s: &str| s.parse::<f64>().map_err(|_| invalid(format!("bad number {}", s)));
+        // one-based, or negative counting back from the last one so far
+        let resolve = |s: &str, len: usize| {
+            let i = s.parse::<isize>().map_err(|_| invalid(format!("bad index {}", s)))?;
+            let k = if i < 0 { len as isize + i } else { i - 1 };
+            if (0..len as isize).contains(&k) { Ok(k as usize) } else { Err(invalid(format!("no element {}", s))) }
+        };
+        let file = File::open(path)?;
+        let mut vertices = Vec::new();
+        let mut colors = Vec::new();
+        let mut uvs = Vec::new();
+        let mut polygons = Vec::new();
+        let mut names: Vec<String> = Vec::new();
+        let mut libraries = Vec::new();
+        let mut current = None;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let mut tokens = line.split_ascii_whitespace();
+            match tokens.next() {
+                Some("v") => {
+                    let c = tokens.map(number).collect::<Result<Vec<_>>>()?;
+                    if c.len() < 3 {
+                        return Err(invalid(format!("vertex with {} coordinates", c.len())));
+                    }
+                    vertices.push(Vector3::from_row_slice(&c[..3]));
+                    if c.len() >= 6 {
+                        colors.push(Vector3::from_row_slice(&c[3..6]));
+                    }
+                }
+                Some("vt") => {
+                    let c = tokens.map(number).collect::<Result<Vec<_>>>()?;
+                    let u = *c.first().ok_or_else(|| invalid("texture coordinate without a u".to_owned()))?;
+                    uvs.push(Vector2::new(u, c.get(1).copied().unwrap_or(0.0)));
+                }
+                Some("mtllib") => libraries.extend(tokens.map(str::to_owned)),
+                Some("usemtl") => {
+                    let name = tokens.next().unwrap_or_default();
+                    current = Some(intern(&mut names, name));
+                }
+                Some("f") => {
+                    let material = *current.get_or_insert_with(|| intern(&mut names, "default"));
+                    let indices = tokens.map(|s| {
+                        let mut parts = s.split('/');
+                        let v = resolve(parts.next().unwrap_or_default(), vertices.len())?;
+                        let vt = parts.next().filter(|s| !s.is_empty()).map(|s| resolve(s, uvs.len())).transpose()?;
+                        Ok((v, vt))
+                    }).collect::<Result<Vec<_>>>()?;
+                    if indices.len() < 3 {
+                        return Err(invalid(format!("face with {} vertices", indices.len())));
+                    }
+                    polygons.push(Polygon {
+                        vertices: indices.iter().map(|i| i.0).collect(),
+                        uvs: indices.iter().map(|i| i.1).collect(),
+                        material,
+                    });
+                }
+                _ => {}
+            }
+        }
+        if !colors.is_empty() && colors.len() != vertices.len() {
+            return Err(invalid(format!("colors on {} of {} vertices", colors.len(), vertices.len())));
+        }
+        let creases = creases.iter().map(|(&(a, b), &s)| ((a.min(b), a.max(b)), s)).collect();
+        let cage = Cage { vertices, uvs, polygons, creases };
+        let Cage { vertices, uvs, polygons, .. } = (0..levels).fold(cage, |cage, _| catmull_clark(&cage));
+        let mut faces = Vec::new();
+        let mut face_uvs = Vec::new();
+        let mut materials = Vec::new();
+        for polygon in &polygons {
+            let (v, vt) = (&polygon.vertices, polygon.uvs.as_ref());
+            for k in 1..v.len() - 1 {
+                faces.push([v[0], v[k], v[k + 1]]);
+                face_uvs.push(vt.map(|vt| [vt[0], vt[k], vt[k + 1]]));
+                materials.push(polygon.material);
+            }
+        }
+        let mesh = Self::new(vertices, faces, materials).with_uvs(uvs, face_uvs);
+        // subdivision moves the vertices, so per-vertex colors no longer line up
+        let mesh = if !colors.is_empty() && levels == 0 { mesh.with_colors(colors) } else { mesh };
+        let mesh = if levels > 0 { mesh.with_smooth_normals() } else { mesh };
+        Ok((mesh, names, libraries))
+    }
+
+    fn intersect_face(&self, ray: &Ray<f64>, range: &Range<f64>, face: usize) -> Option<f64> {
+        let [a, b, c] = self.faces[face].map(|i| self.vertices[i]);
+        intersect_triangle(ray, range, &a, &b, &c)
+    }
+
+    pub fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<(f64, usize)> {
+        self.intersect_with(ray, range, |_, _| true)
+    }
+
+    // Like `intersect`, but only hits that `accept` returns true for are considered.
+    #[cfg(not(feature = "embree"))]
+    pub fn intersect_with(
+        &self, ray: &Ray<f64>, range: Range<f64>, mut accept: impl FnMut(f64, usize) -> bool,
+    ) -> Option<(f64, usize)> {
+        self.bvh.intersect(ray, range, |f, range| {
+            self.intersect_face(ray, &range, f).filter(|&t| accept(t, f)).map(|t| (t, f))
+        })
+    }
+
+    // Embree finds the face, and t is recomputed in double precision. Rejected hits are stepped past
+    // and the ray traced again.
+    #[cfg(feature = "embree")]
+    pub fn intersect_with(
+        &self, ray: &Ray<f64>, mut range: Range<f64>, mut accept: impl FnMut(f64, usize) -> bool,
+    ) -> Option<(f64, usize)> {
+        loop {
+            let (t, f) = self.embree.intersect(ray, range.clone())?;
+            let t = self.intersect_face(ray, &range, f).unwrap_or(t);
+            if accept(t, f) {
+                return Some((t, f));
+            }
+            range.start = t + t.abs().max(1.0) * 1e-9;
+        }
+    }
+
+    pub fn normal(&self, face: usize) -> Vector3<f64> {
+        let [a, b, c] = self.faces[face].map(|i| self.vertices[i]);
+        (b - a).cross(&(c - a)).normalize()
+    }
+
+    // The interpolated vertex normal for smooth-shaded meshes, or the face normal.
+    pub fn shading_normal(&self, point: &Vector3<f64>, face: usize) -> Vector3<f64> {
+        if self.normals.is_empty() {
+            return self.normal(face);
+        }
+        let bary = self.barycentric(point, face);
+        let n = self.faces[face].iter().zip(bary.iter()).map(|(&i, &w)| self.normals[i] * w).sum::<Vector3<f64>>();
+        n.try_normalize(1e-12).unwrap_or_else(|| self.normal(face))
+    }
+
+    fn barycentric(&self, point: &Vector3<f64>, face: usize) -> Vector3<f64> {
+        let [a, b, c] = self.faces[face].map(|i| self.vertices[i]);
+        let (e1, e2, p) = (b - a, c - a, point - a);
+        let (d11, d12, d22) = (e1.dot(&e1), e1.dot(&e2), e2.dot(&e2));
+        let (dp1, dp2) = (p.dot(&e1), p.dot(&e2));
+        let denom = d11 * d22 - d12 * d12;
+        let v = (d22 * dp1 - d12 * dp2) / denom;
+        let w = (d11 * dp2 - d12 * dp1) / denom;
+        Vector3::new(1.0 - v - w, v, w)
+    }
+
+    pub fn uv(&self, point: &Vector3<f64>, face: usize) -> Vector2<f64> {
+        self.face_uvs[face].map_or_else(Vector2::zeros, |indices| {
+            let bary = self.barycentric(point, face);
+            indices.iter().zip(bary.iter()).map(|(&i, &w)| self.uvs[i] * w).sum()
+        })
+    }
+
+    pub fn color(&self, point: &Vector3<f64>, face: usize) -> Option<Vector3<f64>> {
+        if self.colors.is_empty() {
+            return None;
+        }
+        let bary = self.barycentric(point, face);
+        Some(self.faces[face].iter().zip(bary.iter()).map(|(&i, &w)| self.colors[i] * w).sum())
+    }
+
+    // The interpolated tangent at a point and the handedness of its bitangent, for meshes with uvs.
+    pub fn tangent(&self, point: &Vector3<f64>, face: usize) -> Option<(Vector3<f64>, f64)> {
+        let corners = self.tangents.get(face)?;
+        let bary = self.barycentric(point, face);
+        let t = corners.iter().zip(bary.iter()).map(|((t, _), &w)| t * w).sum::<Vector3<f64>>();
+        Some((t.try_normalize(1e-12)?, corners[0].1))
+    }
+
+    pub fn vertices(&self) -> &[Vector3<f64>] {
+        &self.vertices
+    }
+
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    pub fn material(&self, face: usize) -> usize {
+        self.materials[face]
+    }
+
+    pub fn bounds(&self, faces: impl Iterator<Item=usize>) -> Option<Aabb> {
+        faces.map(|f| Aabb::from_points(self.faces[f].iter().map(|&i| &self.vertices[i])))
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+fn intern(names: &mut Vec<String>, name: &str) -> usize {
+    names.iter().position(|n| n == name).unwrap_or_else(|| {
+        names.push(name.to_owned());
+        names.len() - 1
+    })
+}
+
+#[cfg(not(feature = "embree"))]
+fn face_bounds(vertices: &[Vector3<f64>], faces: &[[usize; 3]]) -> Vec<Aabb> {
+    faces.iter().map(|f| Aabb::from_points(f.iter().map(|&i| &vertices[i]))).collect()
+}
+
+// For a mesh owned by the object or shared with others, as files referenced more than once are.
+impl<G: Borrow<Mesh>, M: Material> Object for (G, Vec<M>) {
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection<'_>> {
+        let accept = |t, face| !self.1[self.0.borrow().material(face)].masked(&Intersection::new(t, ray, self, face));
+        self.0.borrow().intersect_with(ray, range, accept).map(|(t, face)| Intersection::new(t, ray, self, face))
+    }
+
+    fn normal(&self, point: &Vector3<f64>, index: usize) -> Vector3<f64> {
+        self.0.borrow().shading_normal(point, index)
+    }
+
+    fn uv(&self, point: &Vector3<f64>, index: usize) -> Vector2<f64> {
+        self.0.borrow().uv(point, index)
+    }
+
+    fn color(&self, point: &Vector3<f64>, index: usize) -> Option<Vector3<f64>> {
+        self.0.borrow().color(point, index)
+    }
+
+    fn tangent(&self, point: &Vector3<f64>, index: usize) -> Option<Vector3<f64>> {
+        self.0.borrow().tangent(point, index).map(|(t, _)| t)
+    }
+
+    fn medium(&self, index: usize) -> Option<Medium> {
+        self.1[self.0.borrow().material(index)].medium()
+    }
+
+    fn shadow_catcher(&self, index: usize) -> bool {
+        self.1[self.0.borrow().material(index)].shadow_catcher()
+    }
+
+    fn emitted(&self, int: &Intersection) -> Vector3<f64> {
+        if int.front() { self.1[self.0.borrow().material(int.index())].emission() } else { Vector3::zeros() }
+    }
+
+    // every emissive face is a light
+    fn emitters(&self, material: Option<&dyn Material>) -> Option<Vec<Emitter>> {
+        let mesh = self.0.borrow();
+        let emitters = mesh.faces().iter().enumerate().filter_map(|(face, vertices)| {
+            let radiance = material.map_or_else(|| self.1[mesh.material(face)].emission(), |m| m.emission());
+            let shape = Shape::Triangle(vertices.map(|i| mesh.vertices()[i]));
+            (radiance != Vector3::zeros()).then_some(Emitter { shape, radiance })
+        });
+        Some(emitters.collect())
+    }
+
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        self.1[self.0.borrow().material(int.index())].scatter(int)
+    }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        self.1[self.0.borrow().material(int.index())].scatter_lobe(int)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        self.1[self.0.borrow().material(int.index())].eval(int, wi)
+    }
+
+    fn specular(&self, index: usize) -> bool {
+        self.1[self.0.borrow().material(index)].specular()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.0.borrow().bounds(0..self.0.borrow().faces.len())
+    }
+
+    fn specular_bounds(&self) -> Option<Aabb> {
+        self.0.borrow().bounds((0..self.0.borrow().faces.len()).filter(|&f| self.specular(f)))
+    }
+
+    #[cfg(not(feature = "embree"))]
+    fn bvh_bounds(&self, depth: usize) -> Vec<(usize, Aabb)> {
+        self.0.borrow().bvh.node_bounds(depth)
+    }
+
+    fn primitives(&self) -> usize {
+        self.0.borrow().faces.len()
+    }
+
+    // Embree's copy of the triangles isn't counted.
+    fn memory(&self, shared: &mut HashSet<usize>) -> usize {
+        let mesh = self.0.borrow();
+        if !shared.insert(mesh as *const Mesh as usize) {
+            return 0;
+        }
+        let bytes = size_of_val(mesh.vertices.as_slice()) + size_of_val(mesh.faces.as_slice())
+            + size_of_val(mesh.materials.as_slice()) + size_of_val(mesh.uvs.as_slice())
+            + size_of_val(mesh.face_uvs.as_slice()) + size_of_val(mesh.colors.as_slice())
+            + size_of_val(mesh.tangents.as_slice()) + size_of_val(mesh.normals.as_slice());
+        #[cfg(not(feature = "embree"))]
+        let bytes = bytes + mesh.bvh.memory();
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_errors, with_file};
+
+    fn tetrahedron() -> Mesh {
+        let vertices = vec![Vector3::zeros(), Vector3::x(), Vector3::y(), Vector3::z()];
+        let faces = vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+        Mesh::new(vertices, faces, vec![0; 4])
+    }
+
+    #[test]
+    fn parses_obj() {
+        let obj = "\
+v 0 0 0 1 1 1
+v 1 0 0 1 0 0
+v 1 1 0 0 1 0
+v 0 1 0 0 0 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+usemtl red
+f 1/1 2/2 3/3 4/4
+usemtl blue
+f -4 -2 -1
+";
+        let (mesh, names) = with_file("quad.obj", obj, Mesh::load_obj).unwrap();
+        assert_eq!(names, ["red", "blue"]);
+        assert_eq!(mesh.faces(), [[0, 1, 2], [0, 2, 3], [0, 2, 3]]);
+        assert_eq!((mesh.material(0), mesh.material(1), mesh.material(2)), (0, 0, 1));
+        assert_eq!(mesh.vertices()[2], Vector3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.uv(&Vector3::new(1.0, 0.0, 0.0), 0), Vector2::new(1.0, 0.0));
+        assert_eq!(mesh.color(&Vector3::new(1.0, 0.0, 0.0), 0), Some(Vector3::new(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn reports_bad_obj() {
+        assert_errors([
+            ("index.obj", "v 0 0 0\nv 1 0 0\nf 1 2 3\n", "no element 3"),
+            ("number.obj", "v 0 zero 0\n", "bad number zero"),
+            ("vertex.obj", "v 0 0\n", "vertex with 2 coordinates"),
+            ("face.obj", "v 0 0 0\nv 1 0 0\nf 1 2\n", "face with 2 vertices"),
+            ("colors.obj", "v 0 0 0 1 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n", "colors on 1 of 3 vertices"),
+        ], Mesh::load_obj);
+    }
+
+    #[test]
+    fn closed_meshes_face_out() {
+        let mesh = tetrahedron();
+        assert_eq!((mesh.winding().inconsistent, mesh.winding().flipped), (0, 0));
+
+        let mut inside_out = tetrahedron();
+        inside_out.faces.iter_mut().for_each(|f| f.swap(1, 2));
+        assert_eq!((inside_out.winding().inconsistent, inside_out.winding().flipped), (0, 4));
+        assert_eq!(inside_out.fix_winding().faces(), mesh.faces());
+    }
+
+    #[test]
+    fn fixes_a_flipped_face() {
+        let mut mesh = tetrahedron();
+        mesh.faces[3].swap(1, 2);
+        assert_eq!((mesh.winding().inconsistent, mesh.winding().flipped), (3, 1));
+        let fixed = mesh.fix_winding();
+        assert_eq!(fixed.faces(), tetrahedron().faces());
+        assert_eq!(fixed.winding().inconsistent, 0);
+    }
+}
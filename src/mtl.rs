@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use nalgebra::Vector3;
+
+use crate::error::{Error, Result};
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::texture::{ImageTexture, Texture};
+
+struct MtlEntry {
+    diffuse: Vector3<f64>,
+    specular: Vector3<f64>,
+    shininess: f64,
+    dissolve: f64,
+    index_refraction: f64,
+    diffuse_map: Option<String>,
+}
+
+impl Default for MtlEntry {
+    fn default() -> Self {
+        Self {
+            diffuse: Vector3::new(0.8, 0.8, 0.8),
+            specular: Vector3::zeros(),
+            shininess: 0.0,
+            dissolve: 1.0,
+            index_refraction: 1.5,
+            diffuse_map: None,
+        }
+    }
+}
+
+impl MtlEntry {
+    fn into_material(self, dir: &Path) -> Result<Box<dyn Material + Send + Sync>> {
+        Ok(if self.dissolve < 1.0 {
+            Box::new(Dielectric::new(self.index_refraction))
+        } else if self.specular.max() > self.diffuse.max() {
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt().min(1.0);
+            Box::new(Metal::new(self.specular, fuzz))
+        } else if let Some(map) = self.diffuse_map {
+            let image = ImageTexture::load(dir.join(map).to_str().unwrap())?;
+            let texture: Box<dyn Texture + Send + Sync> = Box::new(image);
+            Box::new(Lambertian::new(texture))
+        } else {
+            Box::new(Lambertian::new(self.diffuse))
+        })
+    }
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, Box<dyn Material + Send + Sync>>> {
+    let file = File::open(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut entries = Vec::new();
+    let invalid = |what: String| Error::Material(format!("{}: {}", path.display(), what));
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let mut tokens = line.split_ascii_whitespace();
+        let keyword = tokens.next();
+        let rest = tokens.collect::<Vec<_>>();
+        if keyword == Some("newmtl") {
+            entries.push((rest.first().copied().unwrap_or_default().to_owned(), MtlEntry::default()));
+            continue;
+        }
+        let entry = match entries.last_mut() {
+            Some((_, entry)) => entry,
+            None => continue,
+        };
+        // the first `count` values after the keyword
+        let numbers = |count: usize| {
+            let values = rest.iter().take(count)
+                .map(|s| s.parse::<f64>().map_err(|_| invalid(format!("bad number {}", s))))
+                .collect::<Result<Vec<_>>>()?;
+            match values.len() == count {
+                true => Ok(values),
+                false => Err(invalid(format!("{} with {} values", keyword.unwrap_or_default(), values.len()))),
+            }
+        };
+        match keyword {
+            Some("Kd") => entry.diffuse = Vector3::from_vec(numbers(3)?),
+            Some("Ks") => entry.specular = Vector3::from_vec(numbers(3)?),
+            Some("Ns") => entry.shininess = numbers(1)?[0],
+            Some("d") => entry.dissolve = numbers(1)?[0],
+            Some("Tr") => entry.dissolve = 1.0 - numbers(1)?[0],
+            Some("Ni") => entry.index_refraction = numbers(1)?[0],
+            Some("map_Kd") => entry.diffuse_map = rest.last().map(|&s| s.to_owned()),
+            _ => {}
+        }
+    }
+    entries.into_iter()
+        .map(|(name, entry)| Ok((name, entry.into_material(dir)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_errors, with_file};
+
+    fn load(path: &str) -> Result<HashMap<String, Box<dyn Material + Send + Sync>>> {
+        load_mtl(Path::new(path))
+    }
+
+    #[test]
+    fn parses_mtl() {
+        let mtl = "\
+# before any material
+Kd 1 1 1
+newmtl paint
+Kd 0.8 0.1 0.1
+newmtl chrome
+Kd 0.1 0.1 0.1
+Ks 0.9 0.9 0.9
+Ns 200
+newmtl glass
+d 0.2
+Ni 1.45
+";
+        let materials = with_file("kinds.mtl", mtl, load).unwrap();
+        let mut names = materials.keys().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["chrome", "glass", "paint"]);
+        assert!(!materials["paint"].specular() && materials["chrome"].specular() && materials["glass"].specular());
+    }
+
+    #[test]
+    fn reports_bad_mtl() {
+        assert_errors([
+            ("short.mtl", "newmtl paint\nKd 0.8 0.1\n", "Kd with 2 values"),
+            ("number.mtl", "newmtl paint\nNs shiny\n", "bad number shiny"),
+        ], load);
+    }
+}
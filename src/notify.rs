@@ -0,0 +1,43 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+// POSTs `body` as JSON. Only plain http:// URLs are supported; use `notify_command` with e.g. curl for
+// anything else.
+pub fn notify_webhook(url: &str, body: &Value) -> io::Result<()> {
+    let rest = url.strip_prefix("http://")
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "only http:// webhooks are supported"))?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let address = if host.contains(':') { host.to_owned() } else { format!("{}:80", host) };
+    let mut stream = TcpStream::connect(address)?;
+    let body = body.to_string();
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        path, host, body.len(), body,
+    )?;
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    match status.split_ascii_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!("webhook failed: {}", status.trim()))),
+    }
+}
+
+// Runs `command` through the shell with `body` as JSON on its standard input.
+pub fn notify_command(command: &str, body: &Value) -> io::Result<()> {
+    let mut child = Command::new("sh").arg("-c").arg(command).stdin(Stdio::piped()).spawn()?;
+    child.stdin.take().unwrap().write_all(body.to_string().as_bytes())?;
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("notify command failed: {}", status)))
+    }
+}
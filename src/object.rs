@@ -1,26 +1,68 @@
+use std::collections::HashSet;
 use std::ops::Range;
 
 use lazycell::LazyCell;
-use nalgebra::Vector3;
+use nalgebra::{Affine3, Point3, Vector2, Vector3};
 
+use crate::aabb::Aabb;
 use crate::geometry::Geometry;
-use crate::material::Material;
+use crate::light_tree::Emitter;
+use crate::material::{Material, Medium, orthonormal_basis};
 use crate::ray::Ray;
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct Cache {
     point: LazyCell<Vector3<f64>>,
+    local: LazyCell<Vector3<f64>>,
     normal_front: LazyCell<(Vector3<f64>, bool)>,
+    uv: LazyCell<Vector2<f64>>,
+    uv_derivatives: LazyCell<Option<(Vector2<f64>, Vector2<f64>)>>,
 }
 
+#[derive(Clone)]
 pub struct Intersection<'g> {
     t: f64,
     ray: Ray<f64>,
     object: &'g dyn Object,
+    index: usize,
+    transform: Option<(Affine3<f64>, Affine3<f64>)>,
+    material: Option<&'g dyn Material>,
     cache: Cache,
 }
 
-impl Intersection<'_> {
+impl<'g> Intersection<'g> {
+    pub(crate) fn new(t: f64, ray: &Ray<f64>, object: &'g dyn Object, index: usize) -> Self {
+        Self {
+            t,
+            ray: ray.clone(),
+            object,
+            index,
+            transform: None,
+            material: None,
+            cache: Default::default(),
+        }
+    }
+
+    // Places an intersection found in an instance's local space into the parent space, given the
+    // instance transform and its inverse. The ray is replaced by the parent ray, which shares its t.
+    pub(crate) fn transformed(self, ray: &Ray<f64>, to_parent: &Affine3<f64>, to_local: &Affine3<f64>) -> Self {
+        let transform = match self.transform {
+            Some((to_world, to_object)) => (to_parent * to_world, to_object * to_local),
+            None => (*to_parent, *to_local),
+        };
+        Self {
+            ray: ray.clone(),
+            transform: Some(transform),
+            cache: Default::default(),
+            ..self
+        }
+    }
+
+    // Shades the hit with `material` instead of the object's own, for instances that override it.
+    pub(crate) fn with_material(self, material: &'g dyn Material) -> Self {
+        Self { material: Some(material), ..self }
+    }
+
     pub fn t(&self) -> f64 {
         self.t
     }
@@ -29,13 +71,31 @@ impl Intersection<'_> {
         &self.ray
     }
 
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
     pub fn point(&self) -> &Vector3<f64> {
         self.cache.point.borrow_with(|| self.ray.at(self.t))
     }
 
+    pub(crate) fn local_point(&self) -> &Vector3<f64> {
+        match &self.transform {
+            Some((_, to_object)) => self.cache.local.borrow_with(|| {
+                to_object.transform_point(&Point3::from(*self.point())).coords
+            }),
+            None => self.point(),
+        }
+    }
+
     fn normal_front(&self) -> &(Vector3<f64>, bool) {
         self.cache.normal_front.borrow_with(|| {
-            let n = self.object.normal(self.point());
+            let n = self.object.normal(self.local_point(), self.index);
+            let n = match &self.transform {
+                // the inverse transpose of the linear part; the translation doesn't move normals
+                Some((_, to_object)) => (to_object.matrix().fixed_view::<3, 3>(0, 0).transpose() * n).normalize(),
+                None => n,
+            };
             let front = self.ray.direction().dot(&n) < 0.0;
             (if front { n } else { -n }, front)
         })
@@ -49,32 +109,277 @@ impl Intersection<'_> {
         self.normal_front().1
     }
 
+    pub fn uv(&self) -> &Vector2<f64> {
+        self.cache.uv.borrow_with(|| self.object.uv(self.local_point(), self.index))
+    }
+
+    fn uv_at(&self, point: &Vector3<f64>) -> Vector2<f64> {
+        let local = match &self.transform {
+            Some((_, to_object)) => to_object.transform_point(&Point3::from(*point)).coords,
+            None => *point,
+        };
+        self.object.uv(&local, self.index)
+    }
+
+    // How far uv moves for a one-pixel step in x and y, by carrying the ray differentials to the tangent
+    // plane at the hit point. Differences are wrapped since texture coordinates repeat.
+    pub fn uv_derivatives(&self) -> Option<&(Vector2<f64>, Vector2<f64>)> {
+        self.cache.uv_derivatives.borrow_with(|| {
+            let d = self.ray.differentials.as_ref()?;
+            let (n, direction) = (self.normal(), self.ray.direction());
+            let cos = direction.dot(n);
+            if cos.abs() < 1e-8 {
+                return None;
+            }
+            let derivative = |origin: &Vector3<f64>, dir: &Vector3<f64>| {
+                let offset = origin + dir * self.t;
+                let p = self.point() + offset - direction * (offset.dot(n) / cos);
+                (self.uv_at(&p) - self.uv()).map(|x| x - x.round())
+            };
+            Some((derivative(&d.origin_x, &d.direction_x), derivative(&d.origin_y, &d.direction_y)))
+        }).as_ref()
+    }
+
+    pub fn color(&self) -> Option<Vector3<f64>> {
+        self.object.color(self.local_point(), self.index)
+    }
+
+    pub fn tangent(&self) -> Option<Vector3<f64>> {
+        let t = self.object.tangent(self.local_point(), self.index)?;
+        Some(match &self.transform {
+            Some((to_world, _)) => to_world.transform_vector(&t).normalize(),
+            None => t,
+        })
+    }
+
+    // An orthonormal tangent and bitangent around the shading normal, following the object's tangent
+    // where it has one so that anisotropic materials line up with the surface.
+    pub fn tangent_frame(&self) -> (Vector3<f64>, Vector3<f64>) {
+        let n = self.normal();
+        let t = self.tangent()
+            .and_then(|t| (t - n * t.dot(n)).try_normalize(1e-8))
+            .unwrap_or_else(|| orthonormal_basis(n).0);
+        (t, n.cross(&t))
+    }
+
     pub fn scatter(&self) -> (Ray<f64>, Vector3<f64>) {
-        self.object.scatter(self)
+        match self.material {
+            Some(material) => material.scatter(self),
+            None => self.object.scatter(self),
+        }
+    }
+
+    // As `Material::scatter_lobe`.
+    pub fn scatter_lobe(&self) -> (Ray<f64>, Vector3<f64>, bool) {
+        match self.material {
+            Some(material) => material.scatter_lobe(self),
+            None => self.object.scatter_lobe(self),
+        }
+    }
+
+    pub fn eval(&self, wi: &Vector3<f64>) -> Vector3<f64> {
+        match self.material {
+            Some(material) => material.eval(self, wi),
+            None => self.object.eval(self, wi),
+        }
+    }
+
+    pub fn specular(&self) -> bool {
+        self.material.map_or_else(|| self.object.specular(self.index), |m| m.specular())
+    }
+
+    pub fn medium(&self) -> Option<Medium> {
+        self.material.map_or_else(|| self.object.medium(self.index), |m| m.medium())
+    }
+
+    pub fn shadow_catcher(&self) -> bool {
+        self.material.map_or_else(|| self.object.shadow_catcher(self.index), |m| m.shadow_catcher())
+    }
+
+    pub fn emitted(&self) -> Vector3<f64> {
+        match self.material {
+            Some(material) if self.front() => material.emission(),
+            Some(_) => Vector3::zeros(),
+            None => self.object.emitted(self),
+        }
     }
 }
 
 pub trait Object {
-    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection>;
-    fn normal(&self, point: &Vector3<f64>) -> Vector3<f64>;
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection<'_>>;
+    fn normal(&self, point: &Vector3<f64>, index: usize) -> Vector3<f64>;
+    fn uv(&self, point: &Vector3<f64>, index: usize) -> Vector2<f64>;
     fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>);
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64>;
+    fn specular(&self, index: usize) -> bool;
+    fn bounds(&self) -> Option<Aabb>;
+    fn specular_bounds(&self) -> Option<Aabb>;
+
+    fn color(&self, _point: &Vector3<f64>, _index: usize) -> Option<Vector3<f64>> {
+        None
+    }
+
+    fn tangent(&self, _point: &Vector3<f64>, _index: usize) -> Option<Vector3<f64>> {
+        None
+    }
+
+    fn medium(&self, _index: usize) -> Option<Medium> {
+        None
+    }
+
+    fn shadow_catcher(&self, _index: usize) -> bool {
+        false
+    }
+
+    // As `Material::scatter_lobe`, for the material at the hit; objects shading with materials pass it on.
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        let (ray, attenuation) = self.scatter(int);
+        (ray, attenuation, !self.specular(int.index()))
+    }
+
+    // The share of light the object lets through along the ray over `range`, for media that shadow rays pass
+    // through; None for objects that stop them wherever they hit.
+    fn transmittance(&self, _ray: &Ray<f64>, _range: Range<f64>) -> Option<f64> {
+        None
+    }
+
+    fn emitted(&self, _int: &Intersection) -> Vector3<f64> {
+        Vector3::zeros()
+    }
+
+    // The object's lights, shaded with `material` in place of its own if there is one, for sampling them
+    // directly; None if it gives off light that can't be, which is then only found by paths reaching it.
+    fn emitters(&self, _material: Option<&dyn Material>) -> Option<Vec<Emitter>> {
+        None
+    }
+
+    // The bounds of the nodes of the object's BVH at most `depth` levels deep, with their levels, for
+    // seeing how it was built; none for objects without one.
+    fn bvh_bounds(&self, _depth: usize) -> Vec<(usize, Aabb)> {
+        Vec::new()
+    }
+
+    // What the object is made of, such as a mesh's triangles, counting every instance; analytic shapes
+    // are one.
+    fn primitives(&self) -> usize {
+        1
+    }
+
+    // The bytes of geometry the object keeps on the heap, next to nothing for analytic shapes. Geometry
+    // shared with other objects, such as an instanced object or a mesh loaded once for several references,
+    // is only counted by whoever first puts its address into `shared`.
+    fn memory(&self, _shared: &mut HashSet<usize>) -> usize {
+        0
+    }
+}
+
+// Continues the search past masked-out hits, for objects whose geometry returns only the closest hit.
+pub(crate) fn unmasked<'a>(
+    mut range: Range<f64>, mut hit: impl FnMut(Range<f64>) -> Option<Intersection<'a>>,
+    masked: impl Fn(&Intersection) -> bool,
+) -> Option<Intersection<'a>> {
+    loop {
+        let int = hit(range.clone())?;
+        if !masked(&int) {
+            return Some(int);
+        }
+        range.start = int.t() + 1e-9 * int.t().abs().max(1.0);
+    }
 }
 
 impl<G: Geometry, M: Material> Object for (G, M) {
-    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection> {
-        self.0.intersect(ray, range).map(|t| Intersection {
-            t,
-            ray: ray.clone(),
-            object: self,
-            cache: Default::default(),
-        })
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection<'_>> {
+        let hit = |range| self.0.intersect(ray, range).map(|t| Intersection::new(t, ray, self, 0));
+        unmasked(range, hit, |int| self.1.masked(int))
     }
 
-    fn normal(&self, point: &Vector3<f64>) -> Vector3<f64> {
+    fn normal(&self, point: &Vector3<f64>, _index: usize) -> Vector3<f64> {
         self.0.normal(point)
     }
 
+    fn uv(&self, point: &Vector3<f64>, _index: usize) -> Vector2<f64> {
+        self.0.uv(point)
+    }
+
     fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
         self.1.scatter(int)
     }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        self.1.scatter_lobe(int)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        self.1.eval(int, wi)
+    }
+
+    fn specular(&self, _index: usize) -> bool {
+        self.1.specular()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.0.bounds())
+    }
+
+    fn specular_bounds(&self) -> Option<Aabb> {
+        Some(self.0.bounds()).filter(|_| self.1.specular())
+    }
+
+    fn tangent(&self, point: &Vector3<f64>, _index: usize) -> Option<Vector3<f64>> {
+        self.0.tangent(point)
+    }
+
+    fn medium(&self, _index: usize) -> Option<Medium> {
+        self.1.medium()
+    }
+
+    fn shadow_catcher(&self, _index: usize) -> bool {
+        self.1.shadow_catcher()
+    }
+
+    fn emitted(&self, int: &Intersection) -> Vector3<f64> {
+        if int.front() { self.1.emission() } else { Vector3::zeros() }
+    }
+
+    fn emitters(&self, material: Option<&dyn Material>) -> Option<Vec<Emitter>> {
+        let radiance = material.map_or_else(|| self.1.emission(), |m| m.emission());
+        match self.0.shape() {
+            _ if radiance == Vector3::zeros() => Some(Vec::new()),
+            Some(shape) => Some(vec![Emitter { shape, radiance }]),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+    use std::sync::Arc;
+
+    use nalgebra::{Translation3, UnitQuaternion};
+
+    use super::*;
+    use crate::geometry::Sphere;
+    use crate::instance::Instance;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn instances_keep_normals_and_sides() {
+        let sphere: Arc<dyn Object + Send + Sync> =
+            Arc::new((Sphere::new(Vector3::zeros(), 1.0), Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        for x in [5.0, -5.0] {
+            let rotation = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), FRAC_PI_2);
+            let transform = (Translation3::new(x, 0.0, 0.0) * rotation).to_homogeneous();
+            let transform = Affine3::from_matrix_unchecked(transform);
+            let instance = Instance::new(sphere.clone(), transform);
+            let outside = instance.intersect(&Ray::new(Vector3::new(x, 0.0, 10.0), -Vector3::z()), 0.0..f64::INFINITY)
+                .unwrap();
+            assert!(outside.front(), "outside at {}", x);
+            assert!((outside.normal() - Vector3::z()).norm() < 1e-9, "{} at {}", outside.normal(), x);
+            let inside = instance.intersect(&Ray::new(Vector3::new(x, 0.0, 0.0), Vector3::z()), 0.0..f64::INFINITY)
+                .unwrap();
+            assert!(!inside.front(), "inside at {}", x);
+            assert!((inside.normal() + Vector3::z()).norm() < 1e-9, "{} at {}", inside.normal(), x);
+        }
+    }
 }
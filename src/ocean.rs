@@ -0,0 +1,137 @@
+use std::f64::consts::PI;
+use std::ops::Range;
+
+use nalgebra::{Vector2, Vector3};
+
+use crate::aabb::Aabb;
+use crate::geometry::Geometry;
+use crate::ray::Ray;
+
+const GOLDEN_ANGLE: f64 = 2.399963229728653;
+const STEEPNESS: f64 = 0.04;
+const MARCH_STEPS_PER_WAVELENGTH: f64 = 8.0;
+const BISECTION_STEPS: usize = 40;
+
+#[derive(Clone, Copy)]
+pub struct Wave {
+    pub amplitude: f64,
+    pub wave_vector: Vector2<f64>,
+    pub phase: f64,
+}
+
+impl Wave {
+    pub fn new(amplitude: f64, wavelength: f64, direction: Vector2<f64>, phase: f64) -> Self {
+        let wave_vector = direction.normalize() * (2.0 * PI / wavelength);
+        Self { amplitude, wave_vector, phase }
+    }
+}
+
+pub struct Ocean {
+    center: Vector3<f64>,
+    half_size: Vector2<f64>,
+    waves: Vec<Wave>,
+}
+
+impl Ocean {
+    pub fn new(center: Vector3<f64>, size: Vector2<f64>, waves: Vec<Wave>) -> Self {
+        Self { center, half_size: size / 2.0, waves }
+    }
+
+    // A deterministic wave spectrum: wavelengths shrink geometrically from `wavelength` and directions
+    // fan out around the wind with the golden angle, amplitudes keeping a constant steepness.
+    pub fn with_wind(
+        center: Vector3<f64>, size: Vector2<f64>, wind: Vector2<f64>, wavelength: f64, count: usize,
+    ) -> Self {
+        let base = wind.y.atan2(wind.x);
+        let waves = (0..count).map(|i| {
+            let lambda = wavelength * 0.75f64.powi(i as i32);
+            let spread = ((i as f64 * GOLDEN_ANGLE).sin()) * PI / 3.0;
+            let angle = base + spread;
+            Wave::new(STEEPNESS * lambda, lambda, Vector2::new(angle.cos(), angle.sin()), i as f64 * GOLDEN_ANGLE)
+        }).collect();
+        Self::new(center, size, waves)
+    }
+
+    pub fn height(&self, x: f64, z: f64) -> f64 {
+        let p = Vector2::new(x, z);
+        self.center.y + self.waves.iter()
+            .map(|w| w.amplitude * (w.wave_vector.dot(&p) + w.phase).sin())
+            .sum::<f64>()
+    }
+
+    fn gradient(&self, x: f64, z: f64) -> Vector2<f64> {
+        let p = Vector2::new(x, z);
+        self.waves.iter()
+            .map(|w| w.wave_vector * (w.amplitude * (w.wave_vector.dot(&p) + w.phase).cos()))
+            .sum()
+    }
+
+    fn max_amplitude(&self) -> f64 {
+        self.waves.iter().map(|w| w.amplitude.abs()).sum()
+    }
+
+    fn min_wavelength(&self) -> f64 {
+        self.waves.iter()
+            .map(|w| 2.0 * PI / w.wave_vector.norm())
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+impl Geometry for Ocean {
+    // Ray marching through the slab containing all waves, refined by bisection.
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<f64> {
+        let bounds = self.bounds();
+        let inv = ray.direction().map(|x| 1.0 / x);
+        let t0 = (bounds.min - ray.origin).component_mul(&inv);
+        let t1 = (bounds.max - ray.origin).component_mul(&inv);
+        let start = t0.inf(&t1).max().max(range.start);
+        let end = t0.sup(&t1).min().min(range.end);
+        if start > end {
+            return None;
+        }
+
+        let f = |t: f64| {
+            let p = ray.at(t);
+            p.y - self.height(p.x, p.z)
+        };
+        let d = ray.direction();
+        let horizontal = (d.x * d.x + d.z * d.z).sqrt();
+        let step = (self.min_wavelength() / MARCH_STEPS_PER_WAVELENGTH / horizontal)
+            .min(self.max_amplitude().max(1e-6) / d.y.abs())
+            .max((end - start) * 1e-6);
+
+        let mut a = start;
+        let mut fa = f(a);
+        while a < end {
+            let b = (a + step).min(end);
+            let fb = f(b);
+            if fa.signum() != fb.signum() {
+                let (mut lo, mut hi, flo) = (a, b, fa);
+                for _ in 0..BISECTION_STEPS {
+                    let mid = (lo + hi) / 2.0;
+                    if f(mid).signum() == flo.signum() { lo = mid } else { hi = mid }
+                }
+                return Some(hi).filter(|t| range.contains(t));
+            }
+            a = b;
+            fa = fb;
+        }
+        None
+    }
+
+    fn normal(&self, point: &Vector3<f64>) -> Vector3<f64> {
+        let g = self.gradient(point.x, point.z);
+        Vector3::new(-g.x, 1.0, -g.y).normalize()
+    }
+
+    fn uv(&self, point: &Vector3<f64>) -> Vector2<f64> {
+        let p = Vector2::new(point.x - self.center.x, point.z - self.center.z);
+        (p + self.half_size).component_div(&(2.0 * self.half_size))
+    }
+
+    fn bounds(&self) -> Aabb {
+        let a = self.max_amplitude();
+        let h = Vector3::new(self.half_size.x, a, self.half_size.y);
+        Aabb::new(self.center - h, self.center + h)
+    }
+}
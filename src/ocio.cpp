@@ -0,0 +1,46 @@
+// A C shim over the parts of the OpenColorIO 2 C++ API the `ocio` feature uses, compiled by build.rs.
+#include <OpenColorIO/OpenColorIO.h>
+
+#include <cstdio>
+
+namespace OCIO = OCIO_NAMESPACE;
+
+struct RtOcio {
+    OCIO::ConstCPUProcessorRcPtr processor;
+};
+
+extern "C" {
+
+// Null arguments pick the config named by $OCIO, its default display, and that display's default view.
+// On failure returns null with the message in `error`.
+RtOcio *rt_ocio_new(const char *config, const char *display, const char *view, char *error, size_t error_len) {
+    try {
+        auto cfg = config ? OCIO::Config::CreateFromFile(config) : OCIO::GetCurrentConfig();
+        if (!display) {
+            display = cfg->getDefaultDisplay();
+        }
+        if (!view) {
+            view = cfg->getDefaultView(display);
+        }
+        auto transform = OCIO::DisplayViewTransform::Create();
+        transform->setSrc(OCIO::ROLE_SCENE_LINEAR);
+        transform->setDisplay(display);
+        transform->setView(view);
+        return new RtOcio{cfg->getProcessor(transform)->getDefaultCPUProcessor()};
+    } catch (const std::exception &e) {
+        std::snprintf(error, error_len, "%s", e.what());
+        return nullptr;
+    }
+}
+
+// Transforms `pixels` packed RGB floats in place. CPU processors are safe to share between threads.
+void rt_ocio_apply(const RtOcio *ocio, float *rgb, size_t pixels) {
+    OCIO::PackedImageDesc image(rgb, static_cast<long>(pixels), 1, 3);
+    ocio->processor->apply(image);
+}
+
+void rt_ocio_free(RtOcio *ocio) {
+    delete ocio;
+}
+
+}
@@ -0,0 +1,66 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+use nalgebra::Vector3;
+
+use crate::error::{Error, Result};
+
+// Display transforms from an OpenColorIO config, through the C shim in src/ocio.cpp. OpenColorIO 2 must
+// be installed.
+#[repr(C)]
+struct Processor {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn rt_ocio_new(
+        config: *const c_char, display: *const c_char, view: *const c_char, error: *mut c_char, error_len: usize,
+    ) -> *mut Processor;
+    fn rt_ocio_apply(processor: *const Processor, rgb: *mut f32, pixels: usize);
+    fn rt_ocio_free(processor: *mut Processor);
+}
+
+// Maps the scene-linear render to display values through a display and view of a config, such as an
+// ACES or Filmic view, in place of the square root used otherwise. Unset, the config is the one $OCIO
+// names, the display its default, and the view the display's default.
+pub struct DisplayTransform {
+    processor: *mut Processor,
+    pub config: Option<String>,
+    pub display: Option<String>,
+    pub view: Option<String>,
+}
+
+// the processor is immutable once created
+unsafe impl Send for DisplayTransform {}
+unsafe impl Sync for DisplayTransform {}
+
+impl DisplayTransform {
+    pub fn new(config: Option<String>, display: Option<String>, view: Option<String>) -> Result<Self> {
+        let c_string = |s: &Option<String>| s.as_deref().map(|s| CString::new(s).unwrap());
+        let [c_config, c_display, c_view] = [&config, &display, &view].map(c_string);
+        let ptr = |s: &Option<CString>| s.as_ref().map_or(std::ptr::null(), |s| s.as_ptr());
+        let mut error = [0 as c_char; 512];
+        let processor = unsafe {
+            rt_ocio_new(ptr(&c_config), ptr(&c_display), ptr(&c_view), error.as_mut_ptr(), error.len())
+        };
+        if processor.is_null() {
+            let message = unsafe { CStr::from_ptr(error.as_ptr()) };
+            return Err(Error::Ocio(message.to_string_lossy().into_owned()));
+        }
+        Ok(Self { processor, config, display, view })
+    }
+
+    pub fn apply(&self, image: (u32, u32, Vec<Vector3<f64>>)) -> (u32, u32, Vec<Vector3<f64>>) {
+        let (width, height, buffer) = image;
+        let mut rgb = buffer.iter().flat_map(|c| c.iter().map(|&x| x as f32)).collect::<Vec<_>>();
+        unsafe { rt_ocio_apply(self.processor, rgb.as_mut_ptr(), buffer.len()) };
+        let buffer = rgb.chunks(3).map(|c| Vector3::new(c[0], c[1], c[2]).cast()).collect();
+        (width, height, buffer)
+    }
+}
+
+impl Drop for DisplayTransform {
+    fn drop(&mut self) {
+        unsafe { rt_ocio_free(self.processor) }
+    }
+}
@@ -0,0 +1,216 @@
+use std::path::Path;
+
+use nalgebra::Vector3;
+
+use crate::sampler::Sampler;
+use crate::settings::{Integrator, RenderSettings};
+
+type Image = (u32, u32, Vec<Vector3<f64>>);
+
+// The printable ASCII characters of the public domain 8x8 IBM VGA font (from Marcel Sondaar's
+// font8_8), one byte per row from the top, with the leftmost pixel in the lowest bit.
+const FONT: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3c, 0x3c, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7f, 0x36, 0x7f, 0x36, 0x36, 0x00], // '#'
+    [0x0c, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x0c, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0c, 0x66, 0x63, 0x00], // '%'
+    [0x1c, 0x36, 0x1c, 0x6e, 0x3b, 0x33, 0x6e, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x18, 0x0c, 0x06, 0x06, 0x06, 0x0c, 0x18, 0x00], // '('
+    [0x06, 0x0c, 0x18, 0x18, 0x18, 0x0c, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3c, 0xff, 0x3c, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0c, 0x0c, 0x3f, 0x0c, 0x0c, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0c, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3e, 0x63, 0x73, 0x7b, 0x6f, 0x67, 0x3e, 0x00], // '0'
+    [0x0c, 0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x3f, 0x00], // '1'
+    [0x1e, 0x33, 0x30, 0x1c, 0x06, 0x33, 0x3f, 0x00], // '2'
+    [0x1e, 0x33, 0x30, 0x1c, 0x30, 0x33, 0x1e, 0x00], // '3'
+    [0x38, 0x3c, 0x36, 0x33, 0x7f, 0x30, 0x78, 0x00], // '4'
+    [0x3f, 0x03, 0x1f, 0x30, 0x30, 0x33, 0x1e, 0x00], // '5'
+    [0x1c, 0x06, 0x03, 0x1f, 0x33, 0x33, 0x1e, 0x00], // '6'
+    [0x3f, 0x33, 0x30, 0x18, 0x0c, 0x0c, 0x0c, 0x00], // '7'
+    [0x1e, 0x33, 0x33, 0x1e, 0x33, 0x33, 0x1e, 0x00], // '8'
+    [0x1e, 0x33, 0x33, 0x3e, 0x30, 0x18, 0x0e, 0x00], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x06], // ';'
+    [0x18, 0x0c, 0x06, 0x03, 0x06, 0x0c, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3f, 0x00, 0x00, 0x3f, 0x00, 0x00], // '='
+    [0x06, 0x0c, 0x18, 0x30, 0x18, 0x0c, 0x06, 0x00], // '>'
+    [0x1e, 0x33, 0x30, 0x18, 0x0c, 0x00, 0x0c, 0x00], // '?'
+    [0x3e, 0x63, 0x7b, 0x7b, 0x7b, 0x03, 0x1e, 0x00], // '@'
+    [0x0c, 0x1e, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x00], // 'A'
+    [0x3f, 0x66, 0x66, 0x3e, 0x66, 0x66, 0x3f, 0x00], // 'B'
+    [0x3c, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3c, 0x00], // 'C'
+    [0x1f, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1f, 0x00], // 'D'
+    [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x46, 0x7f, 0x00], // 'E'
+    [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x06, 0x0f, 0x00], // 'F'
+    [0x3c, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7c, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1e, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0f, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7f, 0x00], // 'L'
+    [0x63, 0x77, 0x7f, 0x7f, 0x6b, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6f, 0x7b, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1c, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1c, 0x00], // 'O'
+    [0x3f, 0x66, 0x66, 0x3e, 0x06, 0x06, 0x0f, 0x00], // 'P'
+    [0x1e, 0x33, 0x33, 0x33, 0x3b, 0x1e, 0x38, 0x00], // 'Q'
+    [0x3f, 0x66, 0x66, 0x3e, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1e, 0x33, 0x07, 0x0e, 0x38, 0x33, 0x1e, 0x00], // 'S'
+    [0x3f, 0x2d, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3f, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6b, 0x7f, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1c, 0x1c, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1e, 0x0c, 0x0c, 0x1e, 0x00], // 'Y'
+    [0x7f, 0x63, 0x31, 0x18, 0x4c, 0x66, 0x7f, 0x00], // 'Z'
+    [0x1e, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1e, 0x00], // '['
+    [0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x40, 0x00], // '\\'
+    [0x1e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1e, 0x00], // ']'
+    [0x08, 0x1c, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff], // '_'
+    [0x0c, 0x0c, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3e, 0x66, 0x66, 0x3b, 0x00], // 'b'
+    [0x00, 0x00, 0x1e, 0x33, 0x03, 0x33, 0x1e, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3e, 0x33, 0x33, 0x6e, 0x00], // 'd'
+    [0x00, 0x00, 0x1e, 0x33, 0x3f, 0x03, 0x1e, 0x00], // 'e'
+    [0x1c, 0x36, 0x06, 0x0f, 0x06, 0x06, 0x0f, 0x00], // 'f'
+    [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x1f], // 'g'
+    [0x07, 0x06, 0x36, 0x6e, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0c, 0x00, 0x0e, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1e, 0x36, 0x67, 0x00], // 'k'
+    [0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7f, 0x7f, 0x6b, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1f, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1e, 0x33, 0x33, 0x33, 0x1e, 0x00], // 'o'
+    [0x00, 0x00, 0x3b, 0x66, 0x66, 0x3e, 0x06, 0x0f], // 'p'
+    [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3b, 0x6e, 0x66, 0x06, 0x0f, 0x00], // 'r'
+    [0x00, 0x00, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x00], // 's'
+    [0x08, 0x0c, 0x3e, 0x0c, 0x0c, 0x2c, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6e, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6b, 0x7f, 0x7f, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1c, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3e, 0x30, 0x1f], // 'y'
+    [0x00, 0x00, 0x3f, 0x19, 0x0c, 0x26, 0x3f, 0x00], // 'z'
+    [0x38, 0x0c, 0x0c, 0x07, 0x0c, 0x0c, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0c, 0x0c, 0x38, 0x0c, 0x0c, 0x07, 0x00], // '}'
+    [0x6e, 0x3b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+pub(crate) const GLYPH_SIZE: u32 = 8;
+
+// The rows of a character's glyph; characters the font lacks come out as `?`.
+pub(crate) fn glyph(c: char) -> &'static [u8; 8] {
+    &FONT[(c as usize).checked_sub(32).filter(|&i| i < FONT.len()).unwrap_or('?' as usize - 32)]
+}
+
+// Draws directly onto display values, ignoring pixels off the image.
+pub(crate) struct Canvas<'a> {
+    width: u32,
+    height: u32,
+    buffer: &'a mut [Vector3<f64>],
+}
+
+impl<'a> Canvas<'a> {
+    pub(crate) fn new(image: &'a mut Image) -> Self {
+        Self { width: image.0, height: image.1, buffer: &mut image.2 }
+    }
+
+    // Mixes `color` into the pixel by `alpha`.
+    pub(crate) fn blend(&mut self, x: i64, y: i64, color: Vector3<f64>, alpha: f64) {
+        if (0..self.width as i64).contains(&x) && (0..self.height as i64).contains(&y) {
+            let pixel = &mut self.buffer[(x as u32 * self.height + y as u32) as usize];
+            *pixel = pixel.lerp(&color, alpha);
+        }
+    }
+
+    pub(crate) fn rectangle(&mut self, x0: i64, y0: i64, x1: i64, y1: i64, color: Vector3<f64>, alpha: f64) {
+        for x in x0..=x1 {
+            self.blend(x, y0, color, alpha);
+            self.blend(x, y1, color, alpha);
+        }
+        for y in y0 + 1..y1 {
+            self.blend(x0, y, color, alpha);
+            self.blend(x1, y, color, alpha);
+        }
+    }
+
+    fn fill(&mut self, x0: i64, y0: i64, x1: i64, y1: i64, color: Vector3<f64>, alpha: f64) {
+        for (x, y) in (x0..x1).flat_map(|x| (y0..y1).map(move |y| (x, y))) {
+            self.blend(x, y, color, alpha);
+        }
+    }
+
+    // Writes white text with its top left corner at (x, y) over a translucent dark band, with each font
+    // pixel `scale` pixels wide.
+    pub(crate) fn text(&mut self, x: i64, y: i64, text: &str, scale: u32) {
+        let size = (GLYPH_SIZE * scale) as i64;
+        let width = text.chars().count() as i64 * size;
+        self.fill(x - scale as i64, y - scale as i64, x + width + scale as i64, y + size, Vector3::zeros(), 0.5);
+        for (k, c) in text.chars().enumerate() {
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in (0..GLYPH_SIZE).filter(|c| bits >> c & 1 == 1) {
+                    let (px, py) = (x + k as i64 * size + (column * scale) as i64, y + (row as u32 * scale) as i64);
+                    self.fill(px, py, px + scale as i64, py + scale as i64, Vector3::repeat(1.0), 1.0);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn text_width(text: &str, scale: u32) -> i64 {
+        (text.chars().count() as u32 * GLYPH_SIZE * scale) as i64
+    }
+}
+
+// Stamps a render for review: the scene's name at the top left, the frame number if there is one at the
+// top right, a summary of the settings at the bottom left, and the action and title safe areas, at 90%
+// and 80% of the frame, as faint outlines.
+pub fn burn_in(mut image: Image, settings: &RenderSettings, frame: Option<u32>) -> Image {
+    let (width, height) = (image.0 as i64, image.1 as i64);
+    // the font stays legible from small previews to large frames
+    let scale = (image.1 / 360).max(1);
+    let margin = (GLYPH_SIZE * scale) as i64;
+    let mut canvas = Canvas::new(&mut image);
+    for fraction in [0.9, 0.8] {
+        let inset = |size: i64| (size as f64 * (1.0 - fraction) / 2.0) as i64;
+        let (dx, dy) = (inset(width), inset(height));
+        canvas.rectangle(dx, dy, width - 1 - dx, height - 1 - dy, Vector3::repeat(1.0), 0.3);
+    }
+    let scene = settings.scene.as_deref()
+        .map(|path| Path::new(path).file_stem().map_or(path.into(), |s| s.to_string_lossy()))
+        .unwrap_or_else(|| "built-in scene".into());
+    canvas.text(margin, margin, &scene, scale);
+    if let Some(frame) = frame {
+        let label = format!("frame {:04}", frame);
+        canvas.text(width - margin - Canvas::text_width(&label, scale), margin, &label, scale);
+    }
+    canvas.text(margin, height - 2 * margin, &summary(settings), scale);
+    image
+}
+
+fn summary(settings: &RenderSettings) -> String {
+    let sampler = match settings.sampler {
+        Sampler::Random => "random",
+        Sampler::Halton => "halton",
+    };
+    let mut summary = format!(
+        "{}x{} {}spp {} depth {}", settings.width, settings.height, settings.samples * settings.threads, sampler,
+        settings.max_depth,
+    );
+    // path tracing is the norm, so only other integrators are named
+    match settings.integrator {
+        Integrator::PathTracing => (),
+        Integrator::PathGuiding => summary += " guided",
+        Integrator::PhotonMapping { .. } => summary += " photons",
+    }
+    summary
+}
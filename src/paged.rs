@@ -0,0 +1,494 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem::size_of_val;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use memmap2::Mmap;
+use nalgebra::{Vector2, Vector3};
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::error::{Error, Result};
+use crate::geometry::intersect_triangle;
+use crate::material::{Material, Medium};
+use crate::object::{Intersection, Object};
+use crate::ply::read_ply;
+use crate::ray::Ray;
+
+// A packed mesh is a header, a table of chunks, the vertices as f64 triples, and the faces as u32
+// triples, all little-endian. Faces are sorted along a Morton curve and cut into chunks of nearby
+// faces, each with its bounds, so that a chunk is a compact piece of the surface with a small BVH.
+const MAGIC: &[u8; 8] = b"RTPAGED1";
+const HEADER_SIZE: usize = 32;
+const CHUNK_SIZE: usize = 64;
+const VERTEX_SIZE: usize = 24;
+const FACE_SIZE: usize = 12;
+// Packing sorts faces by a Morton code of 21 bits per axis, spreading them over buckets by six bits of it at
+// a time until a bucket holds few enough faces to sort in memory.
+const CODE_BITS: u32 = 63;
+const BUCKET_BITS: u32 = 6;
+const SORT_FACES: usize = 1 << 22;
+const RECORD_SIZE: usize = 8 + FACE_SIZE;
+// Rays that reach a chunk before its BVH is built for it. When the budget is far below the working set,
+// most chunks would be evicted again before their BVH pays for itself, so those rays test the faces
+// directly, which is much cheaper than a build.
+const ADMIT_AFTER: u32 = 16;
+
+struct Chunk {
+    start: usize,
+    count: usize,
+    bounds: Aabb,
+}
+
+// Chunks whose BVH has been built, with the tick they were last used at. Hits only take the read lock
+// and bump the tick atomically, so threads tracing resident chunks don't wait on each other.
+struct Resident {
+    chunks: HashMap<usize, (Arc<Bvh>, AtomicU64)>,
+    faces: usize,
+}
+
+// A mesh too large to keep in memory. The packed file is memory-mapped, so the OS pages its vertices and
+// faces in and out as they are touched, and only the top-level BVH over the chunks is always resident.
+// The BVH of a chunk is built the first time a ray reaches it and kept in an LRU cache of at most
+// `budget` faces, so a scene bigger than RAM renders, slowly, instead of running out of memory.
+pub struct PagedMesh {
+    map: Mmap,
+    vertices: usize,
+    faces: usize,
+    chunks: Vec<Chunk>,
+    bvh: Bvh,
+    budget: usize,
+    resident: RwLock<Resident>,
+    clock: AtomicU64,
+    misses: Vec<AtomicU32>,
+}
+
+impl PagedMesh {
+    // Packs the OBJ or PLY file at `input` into `path` with about `chunk_faces` faces per chunk. Only
+    // positions are kept; packed meshes are flat shaded with a single material. The mesh is never held in
+    // memory: its vertices and triangles are streamed out to scratch files beside `path`, then the faces
+    // are spread over buckets by their Morton codes and written out a sorted bucket at a time. `path` only
+    // appears once it is complete, so an interrupted pack is redone rather than read.
+    pub fn pack(input: &Path, path: &Path, chunk_faces: usize) -> Result<()> {
+        let invalid = |what: String| Error::Mesh(format!("{}: {}", input.display(), what));
+        let chunk_faces = chunk_faces.max(1);
+        let mut scratch = Scratch { path: path.to_owned(), files: Vec::new() };
+        let (vertex_path, mut vertex_out) = scratch.create()?;
+        let (face_path, mut face_out) = scratch.create()?;
+        let (mut vertices, mut faces, mut highest) = (0usize, 0usize, 0);
+        let mut extent = Aabb::new(Vector3::repeat(f64::INFINITY), Vector3::repeat(f64::NEG_INFINITY));
+        let mut vertex = |v: Vector3<f64>| {
+            for x in v.iter() {
+                vertex_out.write_all(&x.to_le_bytes())?;
+            }
+            extent = Aabb::new(extent.min.inf(&v), extent.max.sup(&v));
+            vertices += 1;
+            Ok(())
+        };
+        let face = |f: [usize; 3]| {
+            for &i in &f {
+                let i = u32::try_from(i).map_err(|_| invalid("too many vertices to pack".to_owned()))?;
+                face_out.write_all(&i.to_le_bytes())?;
+            }
+            highest = highest.max(*f.iter().max().unwrap());
+            faces += 1;
+            Ok(())
+        };
+        match input.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("ply") => {
+                // the file must not be modified while mapped
+                let map = unsafe { Mmap::map(&File::open(input)?)? };
+                read_ply(&input.display().to_string(), &map, |v, _| vertex(v), face)?;
+            }
+            _ => read_obj(input, vertex, face)?,
+        }
+        vertex_out.flush()?;
+        face_out.flush()?;
+        drop((vertex_out, face_out));
+        if faces > 0 && highest >= vertices {
+            return Err(invalid(format!("face on vertex {} of {}", highest, vertices)));
+        }
+        if vertices > u32::MAX as usize + 1 {
+            return Err(invalid("too many vertices to pack".to_owned()));
+        }
+
+        let count = faces.div_ceil(chunk_faces);
+        let (partial, file) = scratch.create()?;
+        let mut out = BufWriter::new(file);
+        out.write_all(MAGIC)?;
+        for n in [vertices, faces, count] {
+            out.write_all(&(n as u64).to_le_bytes())?;
+        }
+        // the chunk table is filled in once the faces are sorted
+        out.write_all(&vec![0; count * CHUNK_SIZE])?;
+        io::copy(&mut File::open(&vertex_path)?, &mut out)?;
+        let mut table = Vec::with_capacity(count * CHUNK_SIZE);
+        if faces > 0 {
+            let map = unsafe { Mmap::map(&File::open(&vertex_path)?)? };
+            let point = |i: u32| vector_at(&map, i as usize * VERTEX_SIZE);
+            let mut records = BufReader::new(File::open(&face_path)?);
+            let records = (0..faces).map(|_| {
+                let mut record = [0; RECORD_SIZE];
+                records.read_exact(&mut record[8..])?;
+                let center = face_at(&record[8..]).map(point).iter().sum::<Vector3<f64>>() / 3.0;
+                record[..8].copy_from_slice(&morton(&extent, &center).to_le_bytes());
+                Ok(record)
+            });
+            let buckets = split(&mut scratch, records, CODE_BITS)?;
+            let mut chunk = None::<Aabb>;
+            let mut written = 0;
+            let mut emit = |face: &[u8]| {
+                out.write_all(face)?;
+                let bounds = Aabb::from_points(&face_at(face).map(point));
+                chunk = Some(chunk.map_or(bounds, |c| c.union(&bounds)));
+                written += 1;
+                if written % chunk_faces == 0 || written == faces {
+                    let (start, bounds) = ((written - 1) / chunk_faces * chunk_faces, chunk.take().unwrap());
+                    table.extend((start as u64).to_le_bytes());
+                    table.extend(((written - start) as u64).to_le_bytes());
+                    table.extend(bounds.min.iter().chain(bounds.max.iter()).flat_map(|x| x.to_le_bytes()));
+                }
+                Ok(())
+            };
+            for (bucket, size) in buckets {
+                sort_bucket(&mut scratch, &bucket, size, CODE_BITS - BUCKET_BITS, &mut emit)?;
+            }
+        }
+        out.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+        out.write_all(&table)?;
+        out.flush()?;
+        drop(out);
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    // Fails on files that aren't packed meshes or don't hold together, checking every face once so that
+    // tracing never reads past the vertices.
+    pub fn open(path: &Path, budget: usize) -> Result<Self> {
+        let invalid = |what: &str| Error::Mesh(format!("{}: {}", path.display(), what));
+        let file = File::open(path)?;
+        // the file must not be modified while mapped
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_SIZE || &map[..8] != MAGIC {
+            return Err(invalid("not a packed mesh"));
+        }
+        let [vertices, faces, count] = [8, 16, 24].map(|at| u64_at(&map, at) as usize);
+        let size = [(count, CHUNK_SIZE), (vertices, VERTEX_SIZE), (faces, FACE_SIZE)].iter()
+            .try_fold(HEADER_SIZE, |size, &(n, each)| n.checked_mul(each).and_then(|n| n.checked_add(size)));
+        if size != Some(map.len()) {
+            return Err(invalid("truncated or corrupt"));
+        }
+        let chunks = (0..count).map(|i| {
+            let at = HEADER_SIZE + i * CHUNK_SIZE;
+            Chunk {
+                start: u64_at(&map, at) as usize,
+                count: u64_at(&map, at + 8) as usize,
+                bounds: Aabb::new(vector_at(&map, at + 16), vector_at(&map, at + 40)),
+            }
+        }).collect::<Vec<_>>();
+        if chunks.iter().any(|c| c.start.checked_add(c.count).is_none_or(|end| end > faces)) {
+            return Err(invalid("chunk past the last face"));
+        }
+        let first_face = HEADER_SIZE + count * CHUNK_SIZE + vertices * VERTEX_SIZE;
+        if map[first_face..].chunks_exact(4).any(|i| u32::from_le_bytes(i.try_into().unwrap()) as usize >= vertices) {
+            return Err(invalid("face on a missing vertex"));
+        }
+        let bvh = Bvh::build(&chunks.iter().map(|c| c.bounds).collect::<Vec<_>>());
+        let resident = RwLock::new(Resident { chunks: HashMap::new(), faces: 0 });
+        let misses = chunks.iter().map(|_| AtomicU32::new(0)).collect();
+        Ok(Self { map, vertices, faces, chunks, bvh, budget, resident, clock: AtomicU64::new(0), misses })
+    }
+
+    fn vertex(&self, i: u32) -> Vector3<f64> {
+        vector_at(&self.map, HEADER_SIZE + self.chunks.len() * CHUNK_SIZE + i as usize * VERTEX_SIZE)
+    }
+
+    fn triangle(&self, face: usize) -> [Vector3<f64>; 3] {
+        let at = HEADER_SIZE + self.chunks.len() * CHUNK_SIZE + self.vertices * VERTEX_SIZE + face * FACE_SIZE;
+        face_at(&self.map[at..]).map(|i| self.vertex(i))
+    }
+
+    // The chunk's BVH, built if it isn't resident and has been missed often enough, evicting the least
+    // recently used chunks to stay within the budget. Building happens outside the lock, so two threads
+    // may occasionally both build the same chunk.
+    fn chunk_bvh(&self, chunk: usize) -> Option<Arc<Bvh>> {
+        let clock = self.clock.fetch_add(1, Ordering::Relaxed);
+        if let Some((bvh, used)) = self.resident.read().unwrap().chunks.get(&chunk) {
+            used.store(clock, Ordering::Relaxed);
+            return Some(bvh.clone());
+        }
+        if self.misses[chunk].fetch_add(1, Ordering::Relaxed) < ADMIT_AFTER {
+            return None;
+        }
+        self.misses[chunk].store(0, Ordering::Relaxed);
+        let Chunk { start, count, .. } = self.chunks[chunk];
+        let bounds = (start..start + count).map(|f| Aabb::from_points(&self.triangle(f))).collect::<Vec<_>>();
+        let bvh = Arc::new(Bvh::build(&bounds));
+        let mut resident = self.resident.write().unwrap();
+        if resident.chunks.insert(chunk, (bvh.clone(), AtomicU64::new(clock))).is_none() {
+            resident.faces += count;
+        }
+        while resident.faces > self.budget && resident.chunks.len() > 1 {
+            let (&oldest, _) = resident.chunks.iter()
+                .filter(|(&c, _)| c != chunk)
+                .min_by_key(|(_, (_, used))| used.load(Ordering::Relaxed))
+                .unwrap();
+            resident.chunks.remove(&oldest);
+            resident.faces -= self.chunks[oldest].count;
+        }
+        Some(bvh)
+    }
+
+    pub fn intersect_with(
+        &self, ray: &Ray<f64>, range: Range<f64>, mut accept: impl FnMut(f64, usize) -> bool,
+    ) -> Option<(f64, usize)> {
+        self.bvh.intersect(ray, range, |chunk, mut range| {
+            let Chunk { start, count, .. } = self.chunks[chunk];
+            let mut hit = |f, range: &Range<f64>| {
+                let [a, b, c] = self.triangle(f);
+                intersect_triangle(ray, range, &a, &b, &c).filter(|&t| accept(t, f)).map(|t| (t, f))
+            };
+            match self.chunk_bvh(chunk) {
+                Some(bvh) => bvh.intersect(ray, range, |f, range| hit(start + f, &range)),
+                None => (start..start + count).fold(None, |closest, f| match hit(f, &range) {
+                    Some((t, f)) => {
+                        range.end = t;
+                        Some((t, f))
+                    }
+                    None => closest,
+                }),
+            }
+        })
+    }
+
+    pub fn normal(&self, face: usize) -> Vector3<f64> {
+        let [a, b, c] = self.triangle(face);
+        (b - a).cross(&(c - a)).normalize()
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.chunks.iter().map(|c| c.bounds).reduce(|a, b| a.union(&b))
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.faces
+    }
+}
+
+// Where packed meshes go unless the scene says otherwise: `$XDG_CACHE_HOME/raytracer`, `~/.cache/raytracer`
+// or, failing both, the temporary directory.
+pub(crate) fn cache_dir() -> PathBuf {
+    let home = || env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache"));
+    let base = env::var_os("XDG_CACHE_HOME").map(PathBuf::from).or_else(home).unwrap_or_else(env::temp_dir);
+    base.join("raytracer")
+}
+
+fn u64_at(map: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(map[at..at + 8].try_into().unwrap())
+}
+
+fn f64_at(map: &[u8], at: usize) -> f64 {
+    f64::from_le_bytes(map[at..at + 8].try_into().unwrap())
+}
+
+fn vector_at(map: &[u8], at: usize) -> Vector3<f64> {
+    Vector3::new(f64_at(map, at), f64_at(map, at + 8), f64_at(map, at + 16))
+}
+
+// The vertex indices of the face at the start of `bytes`.
+fn face_at(bytes: &[u8]) -> [u32; 3] {
+    [0, 4, 8].map(|at| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()))
+}
+
+// The vertices and triangles of an OBJ file, handed over as they are read like `read_ply`'s.
+fn read_obj(
+    path: &Path, mut vertex: impl FnMut(Vector3<f64>) -> Result<()>, mut face: impl FnMut([usize; 3]) -> Result<()>,
+) -> Result<()> {
+    let invalid = |what: String| Error::Mesh(format!("{}: {}", path.display(), what));
+    let number = |s: &str| s.parse::<f64>().map_err(|_| invalid(format!("bad number {}", s)));
+    let mut vertices = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let mut tokens = line.split_ascii_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let c = tokens.take(3).map(number).collect::<Result<Vec<_>>>()?;
+                if c.len() < 3 {
+                    return Err(invalid(format!("vertex with {} coordinates", c.len())));
+                }
+                vertex(Vector3::from_row_slice(&c))?;
+                vertices += 1;
+            }
+            Some("f") => {
+                // one-based, or negative counting back from the last vertex so far
+                let indices = tokens.map(|s| {
+                    let v = s.split('/').next().unwrap_or_default();
+                    let i = v.parse::<isize>().map_err(|_| invalid(format!("bad index {}", v)))?;
+                    let k = if i < 0 { vertices as isize + i } else { i - 1 };
+                    match (0..vertices as isize).contains(&k) {
+                        true => Ok(k as usize),
+                        false => Err(invalid(format!("no vertex {}", v))),
+                    }
+                }).collect::<Result<Vec<_>>>()?;
+                if indices.len() < 3 {
+                    return Err(invalid(format!("face with {} vertices", indices.len())));
+                }
+                for k in 1..indices.len() - 1 {
+                    face([indices[0], indices[k], indices[k + 1]])?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Scratch files beside a packed file, `<path>.0`, `<path>.1` and so on, removed however packing ends.
+struct Scratch {
+    path: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+impl Scratch {
+    fn create(&mut self) -> Result<(PathBuf, BufWriter<File>)> {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", self.files.len()));
+        let path = PathBuf::from(name);
+        let file = File::create(&path)?;
+        self.files.push(path.clone());
+        Ok((path, BufWriter::new(file)))
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        for path in &self.files {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Spreads records, each a Morton code followed by a face, over `1 << BUCKET_BITS` scratch files by the bits
+// of the code just below `shift`, giving each file in code order with its count.
+fn split(
+    scratch: &mut Scratch, records: impl Iterator<Item = Result<[u8; RECORD_SIZE]>>, shift: u32,
+) -> Result<Vec<(PathBuf, usize)>> {
+    let mut buckets = (0..1 << BUCKET_BITS).map(|_| Ok((scratch.create()?, 0))).collect::<Result<Vec<_>>>()?;
+    for record in records {
+        let record = record?;
+        let code = u64_at(&record, 0);
+        let ((_, out), count) = &mut buckets[(code >> (shift - BUCKET_BITS)) as usize & ((1 << BUCKET_BITS) - 1)];
+        out.write_all(&record)?;
+        *count += 1;
+    }
+    buckets.into_iter().map(|((path, mut out), count)| {
+        out.flush()?;
+        Ok((path, count))
+    }).collect()
+}
+
+// Hands the faces of a bucket to `emit` in code order, sorting it in memory if it is small enough and
+// splitting it again by the next bits of the code otherwise. Codes left equal keep the order they came in.
+fn sort_bucket(
+    scratch: &mut Scratch, bucket: &Path, count: usize, shift: u32, emit: &mut impl FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    if count <= SORT_FACES || shift < BUCKET_BITS {
+        let bytes = fs::read(bucket)?;
+        let mut records = bytes.chunks_exact(RECORD_SIZE).collect::<Vec<_>>();
+        records.sort_by_key(|r| u64_at(r, 0));
+        records.iter().try_for_each(|r| emit(&r[8..]))?;
+    } else {
+        let mut reader = BufReader::new(File::open(bucket)?);
+        let records = (0..count).map(|_| {
+            let mut record = [0; RECORD_SIZE];
+            reader.read_exact(&mut record)?;
+            Ok(record)
+        });
+        for (part, size) in split(scratch, records, shift)? {
+            sort_bucket(scratch, &part, size, shift - BUCKET_BITS, emit)?;
+        }
+    }
+    Ok(fs::remove_file(bucket)?)
+}
+
+// Interleaves the bits of the point's position within `extent`, 21 bits per axis.
+fn morton(extent: &Aabb, p: &Vector3<f64>) -> u64 {
+    let cell = (p - extent.min).component_div(&extent.diagonal().map(|d| d.max(1e-300)));
+    let spread = |x: f64| {
+        let x = (x.clamp(0.0, 1.0) * ((1 << 21) - 1) as f64) as u64;
+        (0..21).fold(0, |code, bit| code | ((x >> bit) & 1) << (3 * bit))
+    };
+    spread(cell.x) | spread(cell.y) << 1 | spread(cell.z) << 2
+}
+
+impl<M: Material> Object for (PagedMesh, M) {
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection<'_>> {
+        let accept = |t, face| !self.1.masked(&Intersection::new(t, ray, self, face));
+        self.0.intersect_with(ray, range, accept).map(|(t, face)| Intersection::new(t, ray, self, face))
+    }
+
+    fn normal(&self, _point: &Vector3<f64>, index: usize) -> Vector3<f64> {
+        self.0.normal(index)
+    }
+
+    fn uv(&self, _point: &Vector3<f64>, _index: usize) -> Vector2<f64> {
+        Vector2::zeros()
+    }
+
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        self.1.scatter(int)
+    }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        self.1.scatter_lobe(int)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        self.1.eval(int, wi)
+    }
+
+    fn specular(&self, _index: usize) -> bool {
+        self.1.specular()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.0.bounds()
+    }
+
+    fn specular_bounds(&self) -> Option<Aabb> {
+        self.0.bounds().filter(|_| self.1.specular())
+    }
+
+    // Just the tree over the chunks, as the chunks' own trees come and go.
+    fn bvh_bounds(&self, depth: usize) -> Vec<(usize, Aabb)> {
+        self.0.bvh.node_bounds(depth)
+    }
+
+    fn primitives(&self) -> usize {
+        self.0.faces
+    }
+
+    // What always stays resident: the mapped file is paged by the OS, and the chunks' trees are at most
+    // the budget's worth.
+    fn memory(&self, _shared: &mut HashSet<usize>) -> usize {
+        size_of_val(self.0.chunks.as_slice()) + size_of_val(self.0.misses.as_slice()) + self.0.bvh.memory()
+    }
+
+    fn medium(&self, _index: usize) -> Option<Medium> {
+        self.1.medium()
+    }
+
+    fn shadow_catcher(&self, _index: usize) -> bool {
+        self.1.shadow_catcher()
+    }
+
+    fn emitted(&self, int: &Intersection) -> Vector3<f64> {
+        if int.front() { self.1.emission() } else { Vector3::zeros() }
+    }
+}
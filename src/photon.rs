@@ -0,0 +1,92 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use nalgebra::Vector3;
+use rand_distr::{Distribution, UnitDisc};
+
+use crate::{closest_hit, RNG};
+use crate::background::Background;
+use crate::material::{orthonormal_basis, random_unit_vector};
+use crate::object::{Intersection, Object};
+use crate::ray::Ray;
+
+struct Photon {
+    position: Vector3<f64>,
+    direction: Vector3<f64>,
+    power: Vector3<f64>,
+}
+
+// Caustic photon map: only photons that reached a diffuse surface through at least one specular
+// bounce are stored. Everything else is left to the path tracer.
+pub struct PhotonMap {
+    radius: f64,
+    cells: HashMap<[i64; 3], Vec<Photon>>,
+}
+
+impl PhotonMap {
+    pub fn emit<R: Borrow<dyn Object + Sync>>(
+        objects: &[R], background: &Background, count: usize, radius: f64, max_depth: usize,
+    ) -> Self {
+        let mut map = Self { radius, cells: HashMap::new() };
+        let bounds = match objects.iter().filter_map(|o| o.borrow().specular_bounds()).reduce(|a, b| a.union(&b)) {
+            Some(bounds) => bounds,
+            None => return map,
+        };
+        let center = bounds.center();
+        let r = bounds.diagonal().norm() / 2.0;
+        let scale = 4.0 * PI * PI * r * r / count as f64;
+        for _ in 0..count {
+            let sky = random_unit_vector();
+            let (t, b) = orthonormal_basis(&sky);
+            let [x, y]: [f64; 2] = RNG.with(|r| UnitDisc.sample(&mut *r.borrow_mut()));
+            let origin = center + 2.0 * r * sky + r * (x * t + y * b);
+            let to_sky = Ray::new(origin, sky);
+            if closest_hit(objects, &to_sky).is_some() {
+                continue;
+            }
+            let mut power = background.radiance(&sky) * scale;
+            let mut ray = Ray::new(origin, -sky);
+            let mut bounced = false;
+            for _ in 0..max_depth {
+                match closest_hit(objects, &ray) {
+                    Some(i) if i.specular() => {
+                        let (r, attenuation) = i.scatter();
+                        ray = r;
+                        power = power.component_mul(&attenuation);
+                        bounced = true;
+                    }
+                    Some(i) => {
+                        if bounced {
+                            map.store(Photon { position: *i.point(), direction: *ray.direction(), power });
+                        }
+                        break;
+                    }
+                    None => break,
+                }
+            }
+        }
+        map
+    }
+
+    fn cell(&self, point: &Vector3<f64>) -> [i64; 3] {
+        let c = (point / self.radius).map(|x| x.floor() as i64);
+        [c.x, c.y, c.z]
+    }
+
+    fn store(&mut self, photon: Photon) {
+        let cell = self.cell(&photon.position);
+        self.cells.entry(cell).or_default().push(photon);
+    }
+
+    pub fn radiance(&self, int: &Intersection) -> Vector3<f64> {
+        let [x, y, z] = self.cell(int.point());
+        let r2 = self.radius * self.radius;
+        itertools::iproduct!(x - 1..=x + 1, y - 1..=y + 1, z - 1..=z + 1)
+            .filter_map(|(i, j, k)| self.cells.get(&[i, j, k]))
+            .flatten()
+            .filter(|p| (p.position - int.point()).norm_squared() < r2)
+            .map(|p| int.eval(&-p.direction).component_mul(&p.power))
+            .sum::<Vector3<f64>>() / (PI * r2)
+    }
+}
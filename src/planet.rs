@@ -0,0 +1,112 @@
+use nalgebra::Vector3;
+
+use crate::geometry::spherical_uv;
+use crate::mesh::Mesh;
+use crate::texture::ImageTexture;
+
+const SPLIT_RATIO: f64 = 1.5;
+
+pub struct Planet<'a> {
+    center: Vector3<f64>,
+    radius: f64,
+    amplitude: f64,
+    heightmap: &'a ImageTexture,
+}
+
+impl<'a> Planet<'a> {
+    // Heights are data, so load the heightmap with `ImageTexture::load_in(path, Some(ColorSpace::Linear))`.
+    pub fn new(center: Vector3<f64>, radius: f64, amplitude: f64, heightmap: &'a ImageTexture) -> Self {
+        Self { center, radius, amplitude, heightmap }
+    }
+
+    fn surface(&self, direction: &Vector3<f64>, depth: f64) -> Vector3<f64> {
+        let height = self.heightmap.sample(&spherical_uv(direction)).mean();
+        self.center + direction * (self.radius + self.amplitude * height - depth)
+    }
+
+    // Chunked LOD: every cube face is a quadtree whose leaves are refined until their size relative
+    // to the distance from `viewpoint` drops below SPLIT_RATIO. Leaves carry skirts to hide cracks
+    // between neighbouring chunks of different levels.
+    pub fn tessellate(&self, viewpoint: &Vector3<f64>, max_level: u32, resolution: usize) -> Mesh {
+        let mut builder = Builder {
+            planet: self,
+            viewpoint: *viewpoint,
+            max_level,
+            resolution,
+            vertices: Vec::new(),
+            faces: Vec::new(),
+        };
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                let normal = Vector3::ith(axis, sign);
+                let u = Vector3::ith((axis + 1) % 3, sign);
+                let v = Vector3::ith((axis + 2) % 3, 1.0);
+                builder.chunk(&[normal, u, v], (0.0, 0.0), 1.0, 0);
+            }
+        }
+        let materials = vec![0; builder.faces.len()];
+        Mesh::new(builder.vertices, builder.faces, materials)
+    }
+}
+
+struct Builder<'p, 'a> {
+    planet: &'p Planet<'a>,
+    viewpoint: Vector3<f64>,
+    max_level: u32,
+    resolution: usize,
+    vertices: Vec<Vector3<f64>>,
+    faces: Vec<[usize; 3]>,
+}
+
+impl Builder<'_, '_> {
+    fn chunk(&mut self, frame: &[Vector3<f64>; 3], corner: (f64, f64), size: f64, level: u32) {
+        let direction = |s: f64, t: f64| {
+            let [n, u, v] = frame;
+            (n + u * (2.0 * s - 1.0) + v * (2.0 * t - 1.0)).normalize()
+        };
+        let planet = self.planet;
+        let middle = planet.surface(&direction(corner.0 + size / 2.0, corner.1 + size / 2.0), 0.0);
+        let extent = planet.radius * size * 2.0;
+        if level < self.max_level && extent > SPLIT_RATIO * (self.viewpoint - middle).norm() {
+            let half = size / 2.0;
+            for (ds, dt) in [(0.0, 0.0), (half, 0.0), (0.0, half), (half, half)] {
+                self.chunk(frame, (corner.0 + ds, corner.1 + dt), half, level + 1);
+            }
+            return;
+        }
+
+        let n = self.resolution;
+        let step = size / n as f64;
+        let skirt = extent / n as f64 + planet.amplitude * 0.1;
+        let base = self.vertices.len();
+        let index = |i: usize, j: usize| base + i * (n + 1) + j;
+        for i in 0..=n {
+            for j in 0..=n {
+                let d = direction(corner.0 + i as f64 * step, corner.1 + j as f64 * step);
+                self.vertices.push(planet.surface(&d, 0.0));
+            }
+        }
+        for i in 0..n {
+            for j in 0..n {
+                self.faces.push([index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+                self.faces.push([index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+            }
+        }
+
+        let border = (0..n).map(|k| (k, 0)).chain((0..n).map(|k| (n, k)))
+            .chain((1..=n).rev().map(|k| (k, n))).chain((1..=n).rev().map(|k| (0, k)))
+            .collect::<Vec<_>>();
+        let skirt_base = self.vertices.len();
+        for &(i, j) in &border {
+            let d = direction(corner.0 + i as f64 * step, corner.1 + j as f64 * step);
+            self.vertices.push(planet.surface(&d, skirt));
+        }
+        for k in 0..border.len() {
+            let l = (k + 1) % border.len();
+            let (top0, top1) = (index(border[k].0, border[k].1), index(border[l].0, border[l].1));
+            let (bottom0, bottom1) = (skirt_base + k, skirt_base + l);
+            self.faces.push([top0, bottom0, bottom1]);
+            self.faces.push([top0, bottom1, top1]);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::fs::File;
+use std::io::Read;
+use std::str::SplitAsciiWhitespace;
+
+use nalgebra::Vector3;
+
+use crate::error::{Error, Result};
+use crate::mesh::Mesh;
+
+enum Property {
+    Scalar(String, String),
+    List(String, String, String),
+}
+
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary(&'a [u8], bool),
+}
+
+impl Body<'_> {
+    // What's wrong if the value can't be read.
+    fn read(&mut self, ty: &str) -> std::result::Result<f64, String> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or("unexpected end of file")?;
+                token.parse().map_err(|_| format!("bad number {}", token))
+            }
+            Body::Binary(bytes, little_endian) => {
+                let size = match ty {
+                    "char" | "uchar" | "int8" | "uint8" => 1,
+                    "short" | "ushort" | "int16" | "uint16" => 2,
+                    "int" | "uint" | "float" | "int32" | "uint32" | "float32" => 4,
+                    "double" | "float64" => 8,
+                    _ => return Err(format!("unknown property type {}", ty)),
+                };
+                if bytes.len() < size {
+                    return Err("unexpected end of file".to_owned());
+                }
+                let mut b = [0u8; 8];
+                b[..size].copy_from_slice(&bytes[..size]);
+                if !*little_endian {
+                    b[..size].reverse();
+                }
+                *bytes = &bytes[size..];
+                Ok(match ty {
+                    "char" | "int8" => b[0] as i8 as f64,
+                    "uchar" | "uint8" => b[0] as f64,
+                    "short" | "int16" => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    "ushort" | "uint16" => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    "int" | "int32" => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    "uint" | "uint32" => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    "float" | "float32" => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    _ => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+}
+
+pub fn load_ply(path: &str) -> Result<Mesh> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let mut vertices = Vec::new();
+    let mut colors = Vec::new();
+    let mut faces = Vec::new();
+    read_ply(path, &bytes, |position, color| {
+        vertices.push(position);
+        colors.extend(color);
+        Ok(())
+    }, |face| {
+        faces.push(face);
+        Ok(())
+    })?;
+
+    if let Some(&i) = faces.iter().flatten().find(|&&i| i >= vertices.len()) {
+        return Err(Error::Mesh(format!("{}: face on vertex {} of {}", path, i, vertices.len())));
+    }
+    let materials = vec![0; faces.len()];
+    let mesh = Mesh::new(vertices, faces, materials);
+    Ok(if !colors.is_empty() { mesh.with_colors(colors) } else { mesh })
+}
+
+// Hands each vertex of the PLY file in `bytes`, with its color if it has one, and each triangle of its faces
+// to `vertex` and `face` as they are read, leaving what to keep of them to the caller. Face indices aren't
+// checked against the vertex count.
+pub(crate) fn read_ply(
+    path: &str, bytes: &[u8], mut vertex: impl FnMut(Vector3<f64>, Option<Vector3<f64>>) -> Result<()>,
+    mut face: impl FnMut([usize; 3]) -> Result<()>,
+) -> Result<()> {
+    let invalid = |what: String| Error::Mesh(format!("{}: {}", path, what));
+    let end = bytes.windows(11).position(|w| w == b"end_header\n").ok_or_else(|| invalid("no PLY header".to_owned()))?
+        + 11;
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|e| invalid(e.to_string()))?;
+
+    let mut format = "";
+    let mut elements: Vec<(String, usize, Vec<Property>)> = Vec::new();
+    for line in header.lines() {
+        let tokens = line.split_ascii_whitespace().collect::<Vec<_>>();
+        match tokens[..] {
+            ["format", f, ..] => format = f,
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| invalid(format!("bad element count {}", count)))?;
+                elements.push((name.to_owned(), count, Vec::new()));
+            }
+            ["property", ..] if elements.is_empty() => return Err(invalid("property before any element".to_owned())),
+            ["property", "list", count, item, name] => elements.last_mut().unwrap().2
+                .push(Property::List(name.to_owned(), count.to_owned(), item.to_owned())),
+            ["property", ty, name] => elements.last_mut().unwrap().2
+                .push(Property::Scalar(name.to_owned(), ty.to_owned())),
+            _ => {}
+        }
+    }
+    let mut body = match format {
+        "ascii" => {
+            let text = std::str::from_utf8(&bytes[end..]).map_err(|e| invalid(e.to_string()))?;
+            Body::Ascii(text.split_ascii_whitespace())
+        }
+        "binary_little_endian" => Body::Binary(&bytes[end..], true),
+        "binary_big_endian" => Body::Binary(&bytes[end..], false),
+        _ => return Err(invalid(format!("unknown format {}", format))),
+    };
+
+    for (name, count, properties) in &elements {
+        for _ in 0..*count {
+            let mut position = Vector3::zeros();
+            let mut color = None::<Vector3<f64>>;
+            for property in properties {
+                match property {
+                    Property::Scalar(p, ty) => {
+                        let value = body.read(ty).map_err(invalid)?;
+                        let bytes = ty.contains("char") || ty.contains("int8");
+                        let value_color = if bytes { value / 255.0 } else { value };
+                        match p.as_str() {
+                            "x" => position.x = value,
+                            "y" => position.y = value,
+                            "z" => position.z = value,
+                            "red" | "diffuse_red" => color.get_or_insert_with(Vector3::zeros).x = value_color,
+                            "green" | "diffuse_green" => color.get_or_insert_with(Vector3::zeros).y = value_color,
+                            "blue" | "diffuse_blue" => color.get_or_insert_with(Vector3::zeros).z = value_color,
+                            _ => {}
+                        }
+                    }
+                    Property::List(p, count_type, item_type) => {
+                        let n = body.read(count_type).map_err(invalid)? as usize;
+                        let items = (0..n).map(|_| Ok(body.read(item_type).map_err(invalid)? as usize))
+                            .collect::<Result<Vec<_>>>()?;
+                        if name == "face" && (p == "vertex_indices" || p == "vertex_index") {
+                            for k in 1..n.saturating_sub(1) {
+                                face([items[0], items[k], items[k + 1]])?;
+                            }
+                        }
+                    }
+                }
+            }
+            if name == "vertex" {
+                vertex(position, color)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_errors, with_file};
+
+    const HEADER: &str = "\
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    #[test]
+    fn parses_ascii_ply() {
+        let body = "0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 0 0 0\n4 0 1 2 3\n";
+        let mesh = with_file("ascii.ply", format!("ply\nformat ascii 1.0\n{}{}", HEADER, body), load_ply).unwrap();
+        assert_eq!(mesh.faces(), [[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.vertices()[2], Vector3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.color(&Vector3::zeros(), 0), Some(Vector3::new(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn parses_binary_ply() {
+        let vertices = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        for (format, little_endian) in [("binary_little_endian", true), ("binary_big_endian", false)] {
+            let mut bytes = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+            for v in vertices {
+                for x in v {
+                    bytes.extend(if little_endian { x.to_le_bytes() } else { x.to_be_bytes() });
+                }
+                bytes.extend([128, 128, 128]);
+            }
+            bytes.push(3);
+            for i in [3i32, 2, 1] {
+                bytes.extend(if little_endian { i.to_le_bytes() } else { i.to_be_bytes() });
+            }
+            let mesh = with_file(format, bytes, load_ply).unwrap();
+            assert_eq!(mesh.faces(), [[3, 2, 1]], "{}", format);
+            assert_eq!(mesh.vertices()[1], Vector3::new(1.0, 0.0, 0.0), "{}", format);
+        }
+    }
+
+    #[test]
+    fn reports_bad_ply() {
+        let ascii = |body: &str| format!("ply\nformat ascii 1.0\n{}{}", HEADER, body);
+        assert_errors([
+            ("header.ply", "ply\nformat ascii 1.0\n".to_owned(), "no PLY header"),
+            ("format.ply", format!("ply\nformat utf8 1.0\n{}", HEADER), "unknown format utf8"),
+            ("short.ply", ascii("0 0 0 255 0 0\n"), "unexpected end of file"),
+            ("number.ply", ascii("0 zero 0"), "bad number zero"),
+            ("index.ply", ascii(&format!("{}3 0 1 7\n", "0 0 0 0 0 0\n".repeat(4))), "face on vertex 7 of 4"),
+        ], load_ply);
+    }
+}
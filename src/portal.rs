@@ -0,0 +1,66 @@
+use nalgebra::Vector3;
+use rand::Rng;
+
+use crate::ray::Ray;
+use crate::RNG;
+
+// An opening, such as a window, that the sky lights an interior through: the parallelogram spanned by
+// `u` and `v` from `corner`. Portals aren't objects and rays pass straight through them; they only tell
+// the path tracer where to aim when sampling the sky directly, instead of waiting for diffuse bounces to
+// find their way out by chance.
+#[derive(Clone)]
+pub struct Portal {
+    pub corner: Vector3<f64>,
+    pub u: Vector3<f64>,
+    pub v: Vector3<f64>,
+}
+
+impl Portal {
+    pub fn new(corner: Vector3<f64>, u: Vector3<f64>, v: Vector3<f64>) -> Self {
+        Self { corner, u, v }
+    }
+
+    // The distance along the ray to where it passes through the portal, in units of its direction.
+    fn crossing(&self, ray: &Ray<f64>) -> Option<f64> {
+        let n = self.u.cross(&self.v);
+        let t = (self.corner - ray.origin).dot(&n) / ray.direction().dot(&n);
+        if t.is_nan() || t <= 0.0 {
+            return None;
+        }
+        let p = ray.at(t) - self.corner;
+        let (uu, uv, vv) = (self.u.norm_squared(), self.u.dot(&self.v), self.v.norm_squared());
+        let (pu, pv) = (p.dot(&self.u), p.dot(&self.v));
+        let det = uu * vv - uv * uv;
+        let a = (pu * vv - pv * uv) / det;
+        let b = (pv * uu - pu * uv) / det;
+        ((0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b)).then_some(t)
+    }
+
+    // The solid angle density at `ray.origin` of directions picked through a uniform point on the portal.
+    fn pdf(&self, ray: &Ray<f64>) -> f64 {
+        let direction = ray.direction().normalize();
+        match self.crossing(&Ray::new(ray.origin, direction)) {
+            // the squared distance over the area foreshortened towards the origin
+            Some(t) => t * t / direction.dot(&self.u.cross(&self.v)).abs(),
+            None => 0.0,
+        }
+    }
+}
+
+pub(crate) fn crosses_any(portals: &[Portal], ray: &Ray<f64>) -> bool {
+    portals.iter().any(|p| p.crossing(ray).is_some())
+}
+
+// A direction from `origin` through a random point of a random portal, with its density over the solid
+// angle, which counts every portal it goes through since overlapping ones could have picked it too.
+pub(crate) fn sample(portals: &[Portal], origin: &Vector3<f64>) -> (Vector3<f64>, f64) {
+    let (k, a, b) = RNG.with(|r| {
+        let mut r = r.borrow_mut();
+        (r.gen_range(0..portals.len()), r.gen::<f64>(), r.gen::<f64>())
+    });
+    let portal = &portals[k];
+    let direction = (portal.corner + a * portal.u + b * portal.v - origin).normalize();
+    let ray = Ray::new(*origin, direction);
+    let pdf = portals.iter().map(|p| p.pdf(&ray)).sum::<f64>() / portals.len() as f64;
+    (direction, pdf)
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use nalgebra::Vector3;
+use pyo3::exceptions::{PyOSError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::control::Control;
+use crate::error::Error;
+use crate::sampler::Sampler;
+use crate::scene::View;
+
+// Python bindings, built as the `raytracer` extension module with the `python` feature (for instance by
+// `maturin develop --features python`):
+//
+//     import numpy, raytracer
+//     scene = raytracer.Scene.load("scenes/colonnade.json")
+//     image = numpy.asarray(raytracer.render(scene, raytracer.RenderSettings(samples=16)))
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => PyOSError::new_err(e.to_string()),
+            e => PyRuntimeError::new_err(e.to_string()),
+        }
+    }
+}
+
+type Triple = (f64, f64, f64);
+
+fn vector((x, y, z): Triple) -> Vector3<f64> {
+    Vector3::new(x, y, z)
+}
+
+fn triple(v: &Vector3<f64>) -> Triple {
+    (v.x, v.y, v.z)
+}
+
+#[pyclass(name = "Camera", module = "raytracer", get_all, set_all, from_py_object)]
+#[derive(Clone)]
+struct Camera {
+    from: Triple,
+    at: Triple,
+    up: Triple,
+    fov: f64,
+    aperture: f64,
+    focus_distance: f64,
+}
+
+#[pymethods]
+impl Camera {
+    #[new]
+    #[pyo3(signature = (from = (0.0, 0.0, 1.0), at = (0.0, 0.0, 0.0), up = (0.0, 1.0, 0.0), fov = 40.0,
+        aperture = 0.0, focus_distance = 1.0))]
+    fn new(from: Triple, at: Triple, up: Triple, fov: f64, aperture: f64, focus_distance: f64) -> Self {
+        Self { from, at, up, fov, aperture, focus_distance }
+    }
+}
+
+#[pyclass(name = "Scene", module = "raytracer", unsendable)]
+struct Scene(crate::scene::Scene);
+
+#[pymethods]
+impl Scene {
+    #[staticmethod]
+    #[pyo3(signature = (path, overrides = None))]
+    fn load(path: &str, overrides: Option<HashMap<String, String>>) -> PyResult<Self> {
+        let overrides = overrides.unwrap_or_default().into_iter().collect::<Vec<_>>();
+        Ok(Self(crate::scene::Scene::load(Path::new(path), &overrides)?))
+    }
+
+    // Asset paths in the description are relative to the working directory.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let description = serde_json::from_str(json).map_err(Error::from)?;
+        Ok(Self(crate::scene::Scene::from_json(&description, Path::new(""))?))
+    }
+
+    #[getter]
+    fn camera(&self) -> Camera {
+        let view = &self.0.view;
+        Camera {
+            from: triple(&view.from),
+            at: triple(&view.at),
+            up: triple(&view.up),
+            fov: view.fov,
+            aperture: view.aperture,
+            focus_distance: view.focus_distance,
+        }
+    }
+
+    #[setter]
+    fn set_camera(&mut self, camera: Camera) {
+        self.0.view = View {
+            from: vector(camera.from),
+            at: vector(camera.at),
+            up: vector(camera.up),
+            fov: camera.fov,
+            aperture: camera.aperture,
+            focus_distance: camera.focus_distance,
+            focus: None,
+            lens: self.0.view.lens,
+            clipping: self.0.view.clipping,
+        };
+        self.0.source = None;
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.objects.len()
+    }
+
+    // The corners of the box around the scene, or None if nothing in it is bounded.
+    #[getter]
+    fn bounds(&self) -> Option<(Triple, Triple)> {
+        self.0.bounds().map(|b| (triple(&b.min), triple(&b.max)))
+    }
+
+    // Points the camera at the whole scene from where it looks now.
+    #[pyo3(signature = (aspect_ratio = 1.0))]
+    fn frame(&mut self, aspect_ratio: f64) {
+        if let Some(bounds) = self.0.bounds() {
+            self.0.view = self.0.view.framed(&bounds, aspect_ratio);
+            self.0.source = None;
+        }
+    }
+}
+
+#[pyclass(name = "RenderSettings", module = "raytracer", get_all, set_all, skip_from_py_object)]
+#[derive(Clone)]
+struct RenderSettings {
+    width: u32,
+    height: u32,
+    samples: u32,
+    threads: u32,
+    max_depth: usize,
+    sampler: String,
+    lens_splits: u32,
+}
+
+#[pymethods]
+impl RenderSettings {
+    #[new]
+    #[pyo3(signature = (
+        width = None, height = None, samples = None, threads = None, max_depth = None, sampler = None,
+        lens_splits = None,
+    ))]
+    fn new(
+        width: Option<u32>, height: Option<u32>, samples: Option<u32>, threads: Option<u32>,
+        max_depth: Option<usize>, sampler: Option<String>, lens_splits: Option<u32>,
+    ) -> Self {
+        let defaults = crate::settings::RenderSettings::default();
+        Self {
+            width: width.unwrap_or(defaults.width),
+            height: height.unwrap_or(defaults.height),
+            samples: samples.unwrap_or(defaults.samples),
+            threads: threads.unwrap_or(defaults.threads),
+            max_depth: max_depth.unwrap_or(defaults.max_depth),
+            sampler: sampler.unwrap_or_else(|| "halton".to_owned()),
+            lens_splits: lens_splits.unwrap_or(defaults.lens_splits),
+        }
+    }
+}
+
+impl RenderSettings {
+    fn settings(&self) -> PyResult<crate::settings::RenderSettings> {
+        let sampler = match self.sampler.as_str() {
+            "halton" => Sampler::Halton,
+            "random" => Sampler::Random,
+            s => return Err(PyValueError::new_err(format!("unknown sampler {}", s))),
+        };
+        Ok(crate::settings::RenderSettings {
+            width: self.width,
+            height: self.height,
+            samples: self.samples,
+            threads: self.threads,
+            max_depth: self.max_depth,
+            sampler,
+            lens_splits: self.lens_splits,
+            ..Default::default()
+        })
+    }
+}
+
+// A rendered image, height by width by RGB display values in float64. It exposes the numpy array
+// interface, so `numpy.asarray` wraps it without copying.
+#[pyclass(module = "raytracer", frozen)]
+struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<f64>,
+}
+
+#[pymethods]
+impl Image {
+    #[getter]
+    fn shape(&self) -> (u32, u32, u32) {
+        (self.height, self.width, 3)
+    }
+
+    #[getter]
+    fn __array_interface__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let interface = PyDict::new(py);
+        interface.set_item("shape", self.shape())?;
+        interface.set_item("typestr", "<f8")?;
+        interface.set_item("data", (self.data.as_ptr() as usize, true))?;
+        interface.set_item("version", 3)?;
+        Ok(interface)
+    }
+}
+
+#[pyfunction]
+fn render(mut scene: PyRefMut<'_, Scene>, settings: &RenderSettings) -> PyResult<Image> {
+    let settings = settings.settings()?;
+    scene.0.autofocus(settings.width as f64 / settings.height as f64)?;
+    Ok(Image::from(crate::render_scene(&scene.0, &settings, &Control::new(&settings))?))
+}
+
+// The renders through each named camera, by name.
+#[pyfunction]
+fn render_all_cameras(mut scene: PyRefMut<'_, Scene>, settings: &RenderSettings) -> PyResult<HashMap<String, Image>> {
+    let settings = settings.settings()?;
+    let images = crate::render_all_cameras(&mut scene.0, &settings, || Control::new(&settings))?;
+    Ok(images.into_iter().map(|(name, image)| (name, Image::from(image))).collect())
+}
+
+impl From<(u32, u32, Vec<Vector3<f64>>)> for Image {
+    fn from((width, height, buffer): (u32, u32, Vec<Vector3<f64>>)) -> Self {
+        // the renderer stores pixels column by column
+        let data = (0..height).flat_map(|j| (0..width).map(move |i| (i, j)))
+            .flat_map(|(i, j)| {
+                let c = buffer[(i * height + j) as usize];
+                [c.x, c.y, c.z]
+            })
+            .collect();
+        Self { width, height, data }
+    }
+}
+
+#[pymodule]
+fn raytracer(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Camera>()?;
+    module.add_class::<Scene>()?;
+    module.add_class::<RenderSettings>()?;
+    module.add_class::<Image>()?;
+    module.add_function(wrap_pyfunction!(render, module)?)?;
+    module.add_function(wrap_pyfunction!(render_all_cameras, module)?)?;
+    Ok(())
+}
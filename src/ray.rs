@@ -1,14 +1,28 @@
 use nalgebra::{ClosedAdd, ClosedMul, Scalar, Vector3};
 
+// Changes in origin and direction for a one-pixel step in x and y on the image plane.
+#[derive(Clone)]
+pub struct Differentials<T> {
+    pub origin_x: Vector3<T>,
+    pub direction_x: Vector3<T>,
+    pub origin_y: Vector3<T>,
+    pub direction_y: Vector3<T>,
+}
+
 #[derive(Clone)]
 pub struct Ray<T> {
     pub origin: Vector3<T>,
     direction: Vector3<T>,
+    pub differentials: Option<Differentials<T>>,
 }
 
 impl<T> Ray<T> {
     pub const fn new(origin: Vector3<T>, direction: Vector3<T>) -> Self {
-        Self { origin, direction }
+        Self { origin, direction, differentials: None }
+    }
+
+    pub fn with_differentials(self, differentials: Differentials<T>) -> Self {
+        Self { differentials: Some(differentials), ..self }
     }
 }
 
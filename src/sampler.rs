@@ -0,0 +1,62 @@
+use std::f64::consts::PI;
+
+use crate::splitmix64;
+
+// How pixel positions are jittered across passes. `Halton` walks the (2, 3) Halton sequence by pass
+// index, with every pixel's points shifted by a random offset (Cranley-Patterson rotation) so that
+// neighbouring pixels don't share the same pattern.
+#[derive(Clone, Copy)]
+pub enum Sampler {
+    Random,
+    Halton,
+}
+
+impl Sampler {
+    pub(crate) fn pixel_offset(&self, pixel: usize, pass: u32, mut random: impl FnMut() -> f64) -> (f64, f64) {
+        match self {
+            Sampler::Random => (random(), random()),
+            Sampler::Halton => {
+                let hash = splitmix64(pixel as u64);
+                let shift = ((hash >> 32) as f64 / 4294967296.0, (hash & 0xffffffff) as f64 / 4294967296.0);
+                ((radical_inverse(pass, 2) + shift.0).fract(), (radical_inverse(pass, 3) + shift.1).fract())
+            }
+        }
+    }
+}
+
+// How points of the lens are chosen across passes for depth of field. `Spiral` gives the passes the points
+// of a golden-angle (Vogel) spiral in turn, which covers the disc evenly whatever the number of passes, turned
+// by an angle of every pixel's own so neighbours don't share a pattern; bokeh then comes out smooth at a few
+// samples rather than speckled. Pixels whose samples are split over the lens keep their random strata.
+#[derive(Clone, Copy)]
+pub enum LensSampler {
+    Random,
+    Spiral,
+}
+
+impl LensSampler {
+    // The point of the unit disc for the pass out of `passes`, or None to draw one at random.
+    pub(crate) fn lens_point(&self, pixel: usize, pass: u32, passes: u32) -> Option<[f64; 2]> {
+        match self {
+            LensSampler::Random => None,
+            LensSampler::Spiral => {
+                let golden_angle = PI * (3.0 - 5f64.sqrt());
+                let rotation = splitmix64(!(pixel as u64)) as f64 / u64::MAX as f64 * 2.0 * PI;
+                let passes = passes.max(1);
+                let index = (pass % passes) as f64;
+                let (radius, angle) = (((index + 0.5) / passes as f64).sqrt(), index * golden_angle + rotation);
+                Some([radius * angle.cos(), radius * angle.sin()])
+            }
+        }
+    }
+}
+
+pub(crate) fn radical_inverse(mut index: u32, base: u32) -> f64 {
+    let (mut result, mut scale) = (0.0, 1.0);
+    while index > 0 {
+        scale /= base as f64;
+        result += (index % base) as f64 * scale;
+        index /= base;
+    }
+    result
+}
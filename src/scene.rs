@@ -0,0 +1,1133 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::iter;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use itertools::Itertools;
+use nalgebra::{Affine3, Isometry3, Matrix4, Quaternion, Translation3, Unit, UnitQuaternion, Vector3};
+use rand::{Rng, SeedableRng};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::SmallRng;
+use serde_json::{Map, Value};
+
+use crate::aabb::Aabb;
+use crate::assets::{Assets, AssetStats};
+use crate::background::Background;
+use crate::camera::{Camera, Lens};
+use crate::geometry::Sphere;
+use crate::{closest_hit, counter_rng};
+use crate::counters;
+use crate::error::{Error, Result};
+use crate::fingerprint::{canonical, description_hash};
+use crate::instance::{Instance, Tlas};
+use crate::library::{MaterialHandle, MaterialLibrary, SharedMaterial};
+use crate::material::{
+    Backface, Convention, Dielectric, Emissive, Ggx, Lambertian, Layered, Material, Metal, Mix, ShadowCatcher, Sided,
+    ThinFilm,
+};
+use crate::mesh::{Mesh, Winding};
+use crate::object::Object;
+use crate::paged::{self, PagedMesh};
+use crate::portal::Portal;
+use crate::section::{Section, Sectioned};
+use crate::settings::RenderSettings;
+use crate::sidecar::fnv1a;
+use crate::ray::Ray;
+use crate::ply::load_ply;
+use crate::sdf::{self, Sdf};
+use crate::subdivision::Creases;
+use crate::text::text_mesh;
+use crate::texture::{ColorSpace, Filter, Texture};
+use crate::volume::{Fog, Grid, Volume};
+
+// What the camera focuses on in place of its `focus_distance`, found once the shape of the frame is known.
+#[derive(Clone, Debug)]
+pub enum Focus {
+    // The named object, where the line of sight to the center of its bounds meets its surface.
+    Object(String),
+    // Whatever is seen at this point of the frame, in fractions of its width and height from the top left.
+    Point(f64, f64),
+}
+
+// What the interactive viewer cuts back to stay responsive on heavy scenes, fewer bounces, a low clamp
+// on fireflies and no fog, each no more than the render settings allow; final renders ignore it.
+#[derive(Clone, Copy)]
+pub struct PreviewSettings {
+    pub max_depth: usize,
+    pub clamp: Option<f64>,
+    pub fog: bool,
+}
+
+impl Default for PreviewSettings {
+    fn default() -> Self {
+        Self { max_depth: 4, clamp: Some(10.0), fog: false }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct View {
+    pub from: Vector3<f64>,
+    pub at: Vector3<f64>,
+    pub up: Vector3<f64>,
+    pub fov: f64,
+    pub aperture: f64,
+    pub focus_distance: f64,
+    pub focus: Option<Focus>,
+    pub lens: Lens,
+    // The near and far clipping distances along the line of sight, between which alone the camera sees.
+    // Everything still casts shadows and shows in reflections.
+    pub clipping: (f64, f64),
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            from: Vector3::new(0.0, 0.0, 1.0),
+            at: Vector3::zeros(),
+            up: Vector3::y(),
+            fov: 40.0,
+            aperture: 0.0,
+            focus_distance: 1.0,
+            focus: None,
+            lens: Lens::default(),
+            clipping: (0.0, f64::INFINITY),
+        }
+    }
+}
+
+impl View {
+    pub fn camera(&self, aspect_ratio: f64) -> Camera {
+        Camera::look_at(
+            self.from, &self.at, &self.up, self.fov.to_radians(), aspect_ratio, self.aperture, self.focus_distance,
+        ).lens(self.lens)
+    }
+
+    // The view turned to the center of `bounds` from the same direction and moved just far enough back
+    // that they fit in the frame, focused on their center.
+    pub fn framed(&self, bounds: &Aabb, aspect_ratio: f64) -> Self {
+        let center = bounds.center();
+        let radius = (bounds.max - bounds.min).norm() / 2.0;
+        let half = (self.fov.to_radians() / 2.0).tan();
+        let distance = radius / half.min(half * aspect_ratio).atan().sin();
+        let direction = (self.at - self.from).normalize();
+        Self { from: center - direction * distance, at: center, focus_distance: distance, ..self.clone() }
+    }
+
+    // The view turned `degrees` about the up axis through the point it looks at, counterclockwise seen
+    // from above, keeping its distance and focus.
+    pub fn orbited(&self, degrees: f64) -> Self {
+        let turn = UnitQuaternion::from_axis_angle(&Unit::new_normalize(self.up), degrees.to_radians());
+        Self { from: self.at + turn * (self.from - self.at), ..self.clone() }
+    }
+
+    // The stretch of a camera ray between the clipping planes, which are square to the line of sight.
+    pub(crate) fn clipped(&self, ray: &Ray<f64>) -> Range<f64> {
+        let (near, far) = self.clipping;
+        let forward = (self.at - self.from).normalize();
+        let (offset, along) = ((ray.origin - self.from).dot(&forward), ray.direction().dot(&forward));
+        if (near, far) == (0.0, f64::INFINITY) || along <= 0.0 {
+            return 0.0..f64::INFINITY;
+        }
+        ((near - offset) / along).max(0.0)..(far - offset) / along
+    }
+}
+
+// The cameras are open to editing, as the fingerprint hashes them as they are. Everything else goes through
+// methods, which forget where the scene came from when they change what it renders.
+pub struct Scene {
+    pub view: View,
+    // More views by name, from `"cameras": {"name": {...}}` with the keys of `"camera"`, in name order, for
+    // rendering a subject from several sides with `render_all_cameras`.
+    pub cameras: Vec<(String, View)>,
+    pub(crate) objects: Vec<Box<dyn Object + Sync>>,
+    pub(crate) materials: MaterialLibrary,
+    pub(crate) fog: Option<Fog>,
+    pub(crate) portals: Vec<Portal>,
+    // The object marked `"neutral": true`, for white balancing.
+    pub(crate) neutral: Option<usize>,
+    // Object names for the ID matte, from `"name"` or else the type and index; may be shorter than
+    // `objects` for scenes built in code.
+    pub(crate) names: Vec<String>,
+    // How many times over paths reaching each object are split, from `"sampling": n`, for objects such
+    // as glass that need more samples than the rest of the image; missing entries are 1.
+    pub(crate) sampling: Vec<u32>,
+    // The objects with `"emission"`, for marking lights in previews.
+    pub(crate) emissive: Vec<usize>,
+    pub(crate) background: Background,
+    pub(crate) preview: PreviewSettings,
+    // What the scene file held; None for scenes built in code.
+    pub summary: Option<Summary>,
+    // A hash of the description the scene was loaded from, overrides applied, and the files it names, for
+    // `fingerprint`; None for scenes built in code, and cleared by the methods that edit the scene after
+    // loading. Code in the crate setting the fields directly must clear it too.
+    pub(crate) source: Option<u64>,
+}
+
+// A tally of a loaded scene file, printed at load so an import can be checked at a glance. Objects are
+// counted by type with every copy of an array or random block, materials by type among the named ones,
+// and lights are the portals, emissive volumes and the emitters the light tree samples, such as the
+// triangles of an emissive mesh.
+#[derive(Clone, Default)]
+pub struct Summary {
+    pub objects: BTreeMap<String, usize>,
+    pub materials: BTreeMap<String, usize>,
+    pub lights: BTreeMap<String, usize>,
+    // Triangles and other primitives, counting every copy, as `Scene::primitives`.
+    pub primitives: usize,
+    // The decoded size of the image textures, mip levels included.
+    pub texture_bytes: u64,
+    pub geometry_bytes: u64,
+    // The imported meshes, by path, whose faces aren't all wound consistently and facing out.
+    pub miswound: BTreeMap<String, Winding>,
+    // What was loaded and what was shared, counting the scenes loaded before through the same `Assets`.
+    pub assets: AssetStats,
+    pub bounds: Option<Aabb>,
+}
+
+// An object added to a scene in code. It stands for the object's index in `objects` without being one, so
+// it can't be mixed up with a count or a handle of another kind.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ObjectHandle(usize);
+
+impl ObjectHandle {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+// A light added to a scene in code: an emissive object, or a portal the sky lights the scene through.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct LightHandle(LightKind);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum LightKind {
+    Emitter(ObjectHandle),
+    Portal(usize),
+}
+
+impl LightHandle {
+    // The object emitting the light, None for a portal.
+    pub fn object(self) -> Option<ObjectHandle> {
+        match self.0 {
+            LightKind::Emitter(object) => Some(object),
+            LightKind::Portal(_) => None,
+        }
+    }
+}
+
+type SharedObject = Box<dyn Object + Send + Sync>;
+
+impl Scene {
+    pub fn load(path: &Path, overrides: &[(String, String)]) -> Result<Self> {
+        let mut description = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        for (key, value) in overrides {
+            set(&mut description, key, value)?;
+        }
+        Self::from_json(&description, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    // Relative asset paths are resolved against `dir`.
+    pub fn from_json(description: &Value, dir: &Path) -> Result<Self> {
+        Self::from_json_with_assets(description, dir, &Assets::new())
+    }
+
+    // Shares images and meshes with the scenes loaded before through `assets`, as when reloading a scene
+    // that changed.
+    pub fn from_json_with_assets(description: &Value, dir: &Path, assets: &Assets) -> Result<Self> {
+        counters::take_texture_bytes();
+        counters::take_miswound();
+        let mut summary = Summary::default();
+        let view = parse_view(&description["camera"])?;
+        let cameras = description["cameras"].as_object().map_or_else(|| Ok(Vec::new()), |cameras| {
+            cameras.iter().map(|(name, camera)| Ok((name.clone(), parse_view(camera)?))).collect::<Result<_>>()
+        })?;
+        let empty = Map::new();
+        let materials = description["materials"].as_object().unwrap_or(&empty);
+        // Every object draws from its own stream, as does every copy in a random block, so edits to one
+        // object don't reshuffle the rest and copies could be generated in any order.
+        let seed = description["seed"].as_u64().unwrap_or_default();
+        let mut rng = SmallRng::seed_from_u64(seed);
+        // Named materials without random distributions are built once and shared by handle.
+        let mut library = MaterialLibrary::new();
+        for material in materials.values() {
+            *summary.materials.entry(material["type"].as_str().unwrap_or("random").to_owned()).or_default() += 1;
+        }
+        for (name, material) in materials.iter().filter(|(_, m)| !is_random(m)) {
+            let material = parse_material(material, materials, &library, dir, assets, &mut rng)?;
+            library.insert(name, material);
+        }
+        let library = library;
+        let objects = description["objects"].as_array().map(Vec::as_slice).unwrap_or_default().iter().enumerate()
+            .map(|(i, o)| -> Result<Box<dyn Object + Sync>> {
+                let mut rng = counter_rng(seed, i as u64);
+                let (object, copies): (Box<dyn Object + Sync>, _) = match (o.get("array"), o.get("random")) {
+                    (_, Some(random)) => {
+                        let tlas = randomize(o, random, materials, &library, dir, assets, rng.gen())?;
+                        let copies = tlas.instances().len();
+                        (Box::new(tlas), copies)
+                    }
+                    (Some(array), None) => {
+                        let object = parse_object(o, &o["material"], materials, &library, dir, assets, &mut rng)?;
+                        let overrides = o["instance_materials"].as_array().map(Vec::as_slice).unwrap_or_default()
+                            .iter()
+                            .map(|m| Ok(Arc::from(parse_material(m, materials, &library, dir, assets, &mut rng)?)))
+                            .collect::<Result<Vec<_>>>()?;
+                        let tlas = expand_array(object, array, &overrides)?;
+                        let copies = tlas.instances().len();
+                        (Box::new(tlas), copies)
+                    }
+                    (None, None) => (parse_object(o, &o["material"], materials, &library, dir, assets, &mut rng)?, 1),
+                };
+                *summary.objects.entry(string(&o["type"])?.to_owned()).or_default() += copies;
+                if o.get("emission").is_some() {
+                    *summary.lights.entry("emissive volume".to_owned()).or_default() += copies;
+                }
+                Ok(object)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // `"sections": [{"point": [x, y, z], "normal": [x, y, z], "cap": material}]`, each cutting away the side
+        // its normal points to, and capping the objects it cuts through with the optional cap
+        let sections = description["sections"].as_array().map(Vec::as_slice).unwrap_or_default().iter()
+            .map(|s| Ok(Section {
+                point: vector(&s["point"])?,
+                normal: vector(&s["normal"])?,
+                cap: match &s["cap"] {
+                    Value::Null => None,
+                    cap => Some(parse_material(cap, materials, &library, dir, assets, &mut rng)?),
+                },
+            }))
+            .collect::<Result<Arc<[_]>>>()?;
+        let objects = match sections.is_empty() {
+            true => objects,
+            false => objects.into_iter()
+                .map(|o| -> Box<dyn Object + Sync> { Box::new(Sectioned::new(o, sections.clone())) })
+                .collect(),
+        };
+        let fog = parse_fog(&description["fog"])?;
+        // `"background": {"map": path, "rotation": degrees, "intensity": k}`, all optional
+        let background = &description["background"];
+        let background = Background {
+            map: background["map"].as_str().map(|path| assets.image(dir.join(path).to_str().unwrap(), None))
+                .transpose()?,
+            rotation: number_or(&background["rotation"], 0.0),
+            intensity: number_or(&background["intensity"], 1.0),
+        };
+        // `"preview": {"max_depth": 4, "clamp": 10, "fog": false}`, all optional, with `"clamp": false` for none
+        let preview = &description["preview"];
+        let default = PreviewSettings::default();
+        let preview = PreviewSettings {
+            max_depth: preview["max_depth"].as_u64().map_or(default.max_depth, |n| n as usize),
+            clamp: match &preview["clamp"] {
+                Value::Bool(false) => None,
+                clamp => clamp.as_f64().or(default.clamp),
+            },
+            fog: preview["fog"].as_bool().unwrap_or(default.fog),
+        };
+        // `"portals": [{"corner": [x, y, z], "u": [x, y, z], "v": [x, y, z]}]`, the openings such as windows
+        // that the sky lights the scene through, each spanned by u and v from its corner
+        let portals = description["portals"].as_array().map(Vec::as_slice).unwrap_or_default().iter()
+            .map(|p| Ok(Portal::new(vector(&p["corner"])?, vector(&p["u"])?, vector(&p["v"])?)))
+            .collect::<Result<Vec<_>>>()?;
+        let neutral = description["objects"].as_array().and_then(|o| o.iter().position(|o| o["neutral"] == true));
+        let names = description["objects"].as_array().map(Vec::as_slice).unwrap_or_default().iter().enumerate()
+            .map(|(i, o)| Ok(match o["name"].as_str() {
+                Some(name) => name.to_owned(),
+                None => format!("{}.{}", string(&o["type"])?, i),
+            }))
+            .collect::<Result<Vec<_>>>()?;
+        let emissive = description["objects"].as_array().map(Vec::as_slice).unwrap_or_default().iter()
+            .positions(|o| o.get("emission").is_some())
+            .collect();
+        // checked here rather than at render time so a misspelt name fails the load like any other mistake
+        for view in iter::once(&view).chain(cameras.iter().map(|(_, view)| view)) {
+            if let Some(Focus::Object(name)) = &view.focus {
+                focus_target(&names, name)?;
+            }
+        }
+        let sampling = description["objects"].as_array().map(Vec::as_slice).unwrap_or_default().iter()
+            .map(|o| o["sampling"].as_u64().unwrap_or(1) as u32)
+            .collect();
+        if !portals.is_empty() {
+            summary.lights.insert("portal".to_owned(), portals.len());
+        }
+        let emitters = objects.iter().filter_map(|o| o.emitters(None)).map(|e| e.len()).sum::<usize>();
+        if emitters > 0 {
+            summary.lights.insert("emitter".to_owned(), emitters);
+        }
+        summary.texture_bytes = counters::take_texture_bytes();
+        summary.miswound = counters::take_miswound();
+        summary.assets = assets.stats();
+        let mut scene = Self {
+            view, cameras, objects, materials: library, fog, portals, neutral, names, sampling, emissive, background,
+            preview, summary: None, source: Some(description_hash(description, dir)),
+        };
+        summary.bounds = scene.bounds();
+        summary.primitives = scene.primitives();
+        summary.geometry_bytes = scene.geometry_bytes();
+        scene.summary = Some(summary);
+        Ok(scene)
+    }
+
+    // How far along the line of sight `target` is, as `focus_distance` measures it, or None if the ray
+    // to it hits nothing. Focusing on an object the scene has no name for is an error.
+    pub fn focus_distance(&self, target: &Focus, aspect_ratio: f64) -> Result<Option<f64>> {
+        let view = &self.view;
+        let point = match target {
+            Focus::Object(name) => {
+                let object = &self.objects[focus_target(&self.names, name)?];
+                let Some(center) = object.bounds().map(|b| b.center()) else { return Ok(None) };
+                let ray = Ray::new(view.from, center - view.from);
+                object.intersect(&ray, 1e-6..f64::INFINITY).map_or(center, |i| *i.point())
+            }
+            Focus::Point(x, y) => {
+                let ray = view.camera(aspect_ratio).ray_through(*x, 1.0 - y, 0.0, 0.0, [0.0, 0.0]);
+                match closest_hit(&self.objects, &ray) {
+                    Some(i) => *i.point(),
+                    None => return Ok(None),
+                }
+            }
+        };
+        Ok(Some((point - view.from).dot(&(view.at - view.from).normalize())))
+    }
+
+    // Focuses on the view's `focus`, if it has one and there is something there.
+    pub fn autofocus(&mut self, aspect_ratio: f64) -> Result<()> {
+        let distance = match &self.view.focus {
+            Some(focus) => self.focus_distance(focus, aspect_ratio)?,
+            None => None,
+        };
+        if let Some(distance) = distance {
+            self.view.focus_distance = distance;
+        }
+        Ok(())
+    }
+
+    // The box around every bounded object, for framing the camera on the scene with `View::framed`.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.objects.iter().filter_map(|o| o.bounds()).reduce(|a, b| a.union(&b))
+    }
+
+    pub fn primitives(&self) -> usize {
+        self.objects.iter().map(|o| o.primitives()).sum()
+    }
+
+    // The memory the objects' geometry and BVHs take, with shared geometry counted once. Textures are
+    // counted apart, in the summary of a loaded scene.
+    pub fn geometry_bytes(&self) -> u64 {
+        let mut shared = HashSet::new();
+        self.objects.iter().map(|o| o.memory(&mut shared) as u64).sum()
+    }
+
+    // A hash of everything that decides the image, the same on every platform, for telling whether a cached
+    // render, a snapshot or an output file came from this scene and `settings`: the version, the settings
+    // in canonical form along with the LUT and backplate they name, the cameras as they are now, and the
+    // scene's source. The scene file is hashed by content, so moving it doesn't matter. Scenes built in code,
+    // or with objects, lights or materials edited since loading, have no source, as those can't be hashed,
+    // and no fingerprint either.
+    pub fn fingerprint(&self, settings: &RenderSettings) -> Option<String> {
+        let source = self.source.filter(|_| !self.materials.overridden())?;
+        let mut text = env!("CARGO_PKG_VERSION").to_owned();
+        let mut settings_json = settings.to_json();
+        settings_json["scene"] = Value::Null;
+        canonical(&settings_json, &mut text);
+        // `{:?}` writes floats as the shortest decimal that reads back the same, on every platform
+        write!(text, "{:?}", (&self.view, &self.cameras)).unwrap();
+        let mut bytes = text.into_bytes();
+        for path in settings.lut.iter().map(|lut| &lut.path).chain(&settings.backplate) {
+            bytes.extend(fs::read(path).unwrap_or_default());
+        }
+        bytes.extend(source.to_le_bytes());
+        Some(format!("{:016x}", fnv1a(&bytes)))
+    }
+
+    // Adds an object named as `"name"` names one in a scene file, for focusing and the ID matte.
+    pub fn add_object(&mut self, name: &str, object: impl Object + Sync + 'static) -> ObjectHandle {
+        let handle = ObjectHandle(self.objects.len());
+        // scenes built in code may have left objects unnamed
+        let unnamed = self.names.len()..handle.0;
+        self.names.extend(unnamed.map(|i| format!("object.{}", i)));
+        self.names.push(name.to_owned());
+        self.objects.push(Box::new(object));
+        self.source = None;
+        handle
+    }
+
+    // Adds an object that emits light, marked as a light in previews.
+    pub fn add_light(&mut self, name: &str, object: impl Object + Sync + 'static) -> LightHandle {
+        let handle = self.add_object(name, object);
+        self.emissive.push(handle.0);
+        LightHandle(LightKind::Emitter(handle))
+    }
+
+    pub fn add_portal(&mut self, portal: Portal) -> LightHandle {
+        self.portals.push(portal);
+        self.source = None;
+        LightHandle(LightKind::Portal(self.portals.len() - 1))
+    }
+
+    // Adds a material to the library, or replaces the one of the same name for every object using it.
+    pub fn add_material(&mut self, name: &str, material: impl Material + Send + Sync + 'static) -> MaterialHandle {
+        self.source = None;
+        self.materials.insert(name, Box::new(material))
+    }
+
+    pub fn object(&self, handle: ObjectHandle) -> &(dyn Object + Sync) {
+        &*self.objects[handle.0]
+    }
+
+    pub fn objects(&self) -> &[Box<dyn Object + Sync>] {
+        &self.objects
+    }
+
+    // The named materials. Overriding one through `MaterialLibrary::set` leaves the scene without a
+    // fingerprint.
+    pub fn materials(&self) -> &MaterialLibrary {
+        &self.materials
+    }
+
+    pub fn fog(&self) -> Option<&Fog> {
+        self.fog.as_ref()
+    }
+
+    pub fn set_fog(&mut self, fog: Option<Fog>) {
+        self.fog = fog;
+        self.source = None;
+    }
+
+    pub fn portals(&self) -> &[Portal] {
+        &self.portals
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+        self.source = None;
+    }
+
+    pub fn preview(&self) -> PreviewSettings {
+        self.preview
+    }
+
+    // Only the interactive viewer goes by the preview settings, so they don't change the fingerprint.
+    pub fn set_preview(&mut self, preview: PreviewSettings) {
+        self.preview = preview;
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn neutral(&self) -> Option<ObjectHandle> {
+        self.neutral.map(ObjectHandle)
+    }
+
+    // How many times over paths reaching each object are split, by index; missing entries are 1.
+    pub fn sampling(&self) -> &[u32] {
+        &self.sampling
+    }
+
+    pub fn emissive(&self) -> impl Iterator<Item = ObjectHandle> + '_ {
+        self.emissive.iter().copied().map(ObjectHandle)
+    }
+
+    pub fn portal(&self, handle: LightHandle) -> Option<&Portal> {
+        match handle.0 {
+            LightKind::Portal(i) => Some(&self.portals[i]),
+            LightKind::Emitter(_) => None,
+        }
+    }
+
+    // Splits paths reaching the object `sampling` times over, as `"sampling"` does in a scene file.
+    pub fn set_sampling(&mut self, handle: ObjectHandle, sampling: u32) {
+        if self.sampling.len() <= handle.0 {
+            self.sampling.resize(handle.0 + 1, 1);
+        }
+        self.sampling[handle.0] = sampling;
+        self.source = None;
+    }
+
+    // Makes the object the one white balancing takes for neutral, as `"neutral": true` does.
+    pub fn set_neutral(&mut self, handle: ObjectHandle) {
+        self.neutral = Some(handle.0);
+        self.source = None;
+    }
+}
+
+// The index of the object named `name`, for focusing on it.
+fn focus_target(names: &[String], name: &str) -> Result<usize> {
+    names.iter().position(|n| n == name)
+        .ok_or_else(|| Error::Description(format!("no object named {} to focus on", name)))
+}
+
+// Sets the entry at a dotted path such as `camera.fov` or `objects.2.radius`, creating missing object
+// keys. The value is parsed as JSON, falling back to a plain string (`materials.floor.type=metal`). Paths
+// through an index past the end of an array or into a number or string are errors.
+pub fn set(description: &mut Value, key: &str, value: &str) -> Result<()> {
+    let invalid = |what: String| Error::Description(format!("cannot set {}: {}", key, what));
+    let entry = key.split('.').try_fold(description, |v, k| match v {
+        Value::Array(a) => {
+            let len = a.len();
+            k.parse::<usize>().ok().and_then(move |i| a.get_mut(i))
+                .ok_or_else(|| invalid(format!("no index {} in an array of {}", k, len)))
+        }
+        Value::Object(_) | Value::Null => Ok(&mut v[k]),
+        v => Err(invalid(format!("no key {} in {}", k, v))),
+    })?;
+    *entry = serde_json::from_str(value).unwrap_or_else(|_| value.into());
+    Ok(())
+}
+
+// `{"density": 0.02, "color": [0.8, 0.8, 0.9], "anisotropy": 0.3, "height": 5}`, where color is the
+// scattering albedo and the fog fills everything below height.
+fn parse_fog(value: &Value) -> Result<Option<Fog>> {
+    if !value.is_object() {
+        return Ok(None);
+    }
+    Ok(Some(Fog {
+        density: number(&value["density"])?,
+        albedo: vector_or(&value["color"], Vector3::new(1.0, 1.0, 1.0))?,
+        anisotropy: number_or(&value["anisotropy"], 0.0),
+        height: number_or(&value["height"], f64::INFINITY),
+    }))
+}
+
+fn invalid(what: &str, value: &Value) -> Error {
+    Error::Description(format!("expected {}, got {}", what, value))
+}
+
+fn vector(value: &Value) -> Result<Vector3<f64>> {
+    match value.as_array().map(Vec::as_slice) {
+        Some([x, y, z]) => Ok(Vector3::new(number(x)?, number(y)?, number(z)?)),
+        _ => Err(invalid("three numbers", value)),
+    }
+}
+
+fn vector_or(value: &Value, default: Vector3<f64>) -> Result<Vector3<f64>> {
+    if value.is_null() { Ok(default) } else { vector(value) }
+}
+
+fn number(value: &Value) -> Result<f64> {
+    value.as_f64().ok_or_else(|| invalid("a number", value))
+}
+
+fn number_or(value: &Value, default: f64) -> f64 {
+    value.as_f64().unwrap_or(default)
+}
+
+fn string(value: &Value) -> Result<&str> {
+    value.as_str().ok_or_else(|| invalid("a string", value))
+}
+
+// Two numbers, as the bounds of a range or a pair of parameters.
+fn pair(value: &Value) -> Result<(f64, f64)> {
+    match value.as_array().map(Vec::as_slice) {
+        Some([a, b]) => Ok((number(a)?, number(b)?)),
+        _ => Err(invalid("two numbers", value)),
+    }
+}
+
+// Replaces random distributions in a description by draws from them: `{"uniform": [lo, hi]}` draws a
+// number, or a vector if the bounds are vectors, and `{"product": [a, b]}` multiplies two draws.
+fn sample(value: &Value, rng: &mut SmallRng) -> Result<Value> {
+    // `gen_range` panics on an empty range
+    let mut draw = |lo: &Value, hi: &Value| match (number(lo)?, number(hi)?) {
+        (lo, hi) if lo <= hi => Ok(rng.gen_range(lo..=hi)),
+        _ => Err(invalid("bounds in order", value)),
+    };
+    Ok(match value {
+        Value::Object(map) if map.contains_key("uniform") => {
+            let bounds = &map["uniform"];
+            match (&bounds[0], &bounds[1]) {
+                (Value::Array(lo), Value::Array(hi)) => {
+                    lo.iter().zip(hi).map(|(lo, hi)| draw(lo, hi)).collect::<Result<_>>()?
+                }
+                (lo, hi) => draw(lo, hi)?.into(),
+            }
+        }
+        Value::Object(map) if map.contains_key("product") => {
+            let factors = &map["product"];
+            match (sample(&factors[0], rng)?, sample(&factors[1], rng)?) {
+                (Value::Array(a), Value::Array(b)) => {
+                    a.iter().zip(&b).map(|(a, b)| Ok(number(a)? * number(b)?)).collect::<Result<_>>()?
+                }
+                (a, b) => (number(&a)? * number(&b)?).into(),
+            }
+        }
+        Value::Object(map) => {
+            Value::Object(map.iter().map(|(k, v)| Ok((k.clone(), sample(v, rng)?))).collect::<Result<_>>()?)
+        }
+        Value::Array(values) => values.iter().map(|v| sample(v, rng)).collect::<Result<_>>()?,
+        value => value.clone(),
+    })
+}
+
+fn parse_view(camera: &Value) -> Result<View> {
+    let default = View::default();
+    Ok(View {
+        from: vector_or(&camera["from"], default.from)?,
+        at: vector_or(&camera["at"], default.at)?,
+        up: vector_or(&camera["up"], default.up)?,
+        fov: number_or(&camera["fov"], default.fov),
+        aperture: number_or(&camera["aperture"], default.aperture),
+        focus_distance: number_or(&camera["focus_distance"], default.focus_distance),
+        // `"focus": "name"` of an object or `"focus": [x, y]`, a point of the frame
+        focus: match &camera["focus"] {
+            Value::Null => None,
+            Value::String(name) => Some(Focus::Object(name.clone())),
+            p => {
+                let (x, y) = pair(p)?;
+                Some(Focus::Point(x, y))
+            }
+        },
+        // `"distortion": [k1, k2]`, or just k1
+        lens: Lens {
+            distortion: match &camera["distortion"] {
+                Value::Array(k) => {
+                    let k1 = k.first().ok_or_else(|| invalid("one or two numbers", &camera["distortion"]))?;
+                    [number(k1)?, k.get(1).and_then(Value::as_f64).unwrap_or_default()]
+                }
+                k => [number_or(k, 0.0), 0.0],
+            },
+            dispersion: number_or(&camera["dispersion"], 0.0),
+        },
+        clipping: (number_or(&camera["near"], default.clipping.0), number_or(&camera["far"], default.clipping.1)),
+    })
+}
+
+fn is_random(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => map.contains_key("uniform") || map.contains_key("product") || map.values().any(is_random),
+        Value::Array(values) => values.iter().any(is_random),
+        _ => false,
+    }
+}
+
+// A material is either the name of an entry in the scene's `materials` table or an inline description.
+// Names found in the library resolve to a handle; random entries are drawn afresh for every reference.
+fn parse_material(
+    value: &Value, materials: &Map<String, Value>, library: &MaterialLibrary, dir: &Path, assets: &Assets,
+    rng: &mut SmallRng,
+) -> Result<SharedMaterial> {
+    if let Some(handle) = value.as_str().and_then(|name| library.get(name)) {
+        return Ok(Box::new(handle));
+    }
+    let value = sample(match value {
+        Value::String(name) => materials.get(name).ok_or_else(|| invalid("a material", value))?,
+        value => value,
+    }, rng)?;
+    let unknown = |what: &str, name: &str| Err(Error::Description(format!("unknown {} {}", what, name)));
+    let backface = match value["backface"].as_str() {
+        None | Some("shade") => Backface::Shade,
+        Some("cull") => Backface::Cull,
+        Some("black") => Backface::Black,
+        Some(b) => return unknown("backface mode", b),
+    };
+    let material: SharedMaterial = match string(&value["type"])? {
+        "lambertian" => match &value["albedo"] {
+            Value::String(_) => {
+                Box::new(Lambertian::new(parse_texture(&value["albedo"], &value, dir, assets, false)?))
+            }
+            albedo => Box::new(Lambertian::new(vector(albedo)?)),
+        },
+        "metal" => Box::new(Metal::new(vector(&value["albedo"])?, number_or(&value["fuzz"], 0.0))),
+        "dielectric" => {
+            let priority = value["priority"].as_u64().unwrap_or_default() as u32;
+            Box::new(Dielectric::new(number_or(&value["ior"], 1.5)).with_priority(priority))
+        }
+        // `roughness` is perceptual and squared into GGX alpha, as in Blender and glTF: a number stretched by
+        // `anisotropy` the way the `convention`, "blender" or "gltf", does, or an [along tangent, along
+        // bitangent] pair. `alpha` takes the alphas as they are instead, for renderers that don't square.
+        "ggx" => {
+            let albedo = vector(&value["albedo"])?;
+            let convention = match value["convention"].as_str() {
+                None | Some("blender") => Convention::Blender,
+                Some("gltf") => Convention::Gltf,
+                Some(c) => return unknown("roughness convention", c),
+            };
+            match (&value["alpha"], &value["roughness"]) {
+                (a @ Value::Array(_), _) => {
+                    let (x, y) = pair(a)?;
+                    Box::new(Ggx::anisotropic(albedo, x, y))
+                }
+                (a @ Value::Number(_), _) => Box::new(Ggx::new(albedo, number(a)?)),
+                (_, r @ Value::Array(_)) => {
+                    let (x, y) = pair(r)?;
+                    Box::new(Ggx::anisotropic(albedo, x * x, y * y))
+                }
+                (_, r) => {
+                    let anisotropy = number_or(&value["anisotropy"], 0.0);
+                    Box::new(Ggx::perceptual(albedo, number_or(r, 0.5), anisotropy, convention))
+                }
+            }
+        }
+        "mix" => {
+            let mut material = |v| parse_material(v, materials, library, dir, assets, rng);
+            let (a, b) = (material(&value["a"])?, material(&value["b"])?);
+            Box::new(Mix::new(a, b, parse_texture(&value["factor"], &value, dir, assets, true)?))
+        }
+        "layered" => {
+            let base = parse_material(&value["base"], materials, library, dir, assets, rng)?;
+            let color = vector_or(&value["color"], Vector3::new(1.0, 1.0, 1.0))?;
+            Box::new(Layered::new(base, number_or(&value["ior"], 1.5)).with_color(color))
+        }
+        "thin_film" => {
+            let base = parse_material(&value["base"], materials, library, dir, assets, rng)?;
+            let film = ThinFilm::new(base, number_or(&value["thickness"], 400.0), number_or(&value["ior"], 1.33));
+            Box::new(film.with_substrate(number_or(&value["substrate"], 1.0)))
+        }
+        // `albedo` is that of the real surface it stands for, which tints the light objects bounce onto it
+        "shadow_catcher" => Box::new(ShadowCatcher::new(vector_or(&value["albedo"], Vector3::new(0.8, 0.8, 0.8))?)),
+        // `color` scaled by `strength`, which may take it past 1
+        "emissive" => {
+            let color = vector_or(&value["color"], Vector3::new(1.0, 1.0, 1.0))?;
+            Box::new(Emissive::new(color * number_or(&value["strength"], 1.0)))
+        }
+        t => return unknown("material type", t),
+    };
+    Ok(match backface {
+        Backface::Shade => material,
+        backface => Box::new(Sided::new(material, backface)),
+    })
+}
+
+// A texture is an image path (sampled with the description's `filter`), a color, or a gray level. Images
+// of colors are decoded from sRGB unless they're in a float format, and images of `data`, such as blend
+// factors, are read as they are, unless the description's `color_space` is "srgb" or "linear".
+fn parse_texture(
+    value: &Value, description: &Value, dir: &Path, assets: &Assets, data: bool,
+) -> Result<Box<dyn Texture + Send + Sync>> {
+    Ok(match value {
+        Value::String(path) => {
+            let space = match description["color_space"].as_str() {
+                None if data => Some(ColorSpace::Linear),
+                None => None,
+                Some("srgb") => Some(ColorSpace::Srgb),
+                Some("linear") => Some(ColorSpace::Linear),
+                Some(s) => return Err(Error::Description(format!("unknown color space {}", s))),
+            };
+            let filter = match description["filter"].as_str() {
+                Some("nearest") => Filter::Nearest,
+                Some("bilinear") => Filter::Bilinear,
+                _ => Filter::Trilinear,
+            };
+            Box::new(assets.image(dir.join(path).to_str().unwrap(), space)?.with_filter(filter))
+        }
+        Value::Number(_) => Box::new(Vector3::repeat(number(value)?)),
+        value => Box::new(vector(value)?),
+    })
+}
+
+fn parse_object(
+    value: &Value, material: &Value, materials: &Map<String, Value>, library: &MaterialLibrary, dir: &Path,
+    assets: &Assets, rng: &mut SmallRng,
+) -> Result<SharedObject> {
+    let path = dir.join(string(&value["path"]).unwrap_or_default()).to_str().unwrap().to_owned();
+    let mut material = || parse_material(material, materials, library, dir, assets, rng);
+    let kind = string(&value["type"])?;
+    if ["obj", "ply", "paged"].contains(&kind) {
+        string(&value["path"])?;
+    }
+    Ok(match kind {
+        "sphere" => Box::new((Sphere::new(vector(&value["center"])?, number(&value["radius"])?), material()?)),
+        "obj" if value["material"].is_null() => Box::new(assets.mesh_with_materials(&mesh_key(value, &path), || {
+            let (levels, creases) = subdivision(value)?;
+            let (mesh, materials) = Mesh::load_obj_subdivided_with_materials(&path, levels, &creases)?;
+            Ok((check_winding(mesh, value, &path), materials))
+        })?),
+        "obj" => {
+            let (mesh, names) = assets.mesh(&mesh_key(value, &path), || {
+                let (levels, creases) = subdivision(value)?;
+                let (mesh, names) = Mesh::load_obj_subdivided(&path, levels, &creases)?;
+                Ok((check_winding(mesh, value, &path), names))
+            })?;
+            let materials = names.iter().map(|_| material()).collect::<Result<Vec<_>>>()?;
+            Box::new((mesh, if materials.is_empty() { vec![material()?] } else { materials }))
+        }
+        "ply" => {
+            let (mesh, _) = assets.mesh(&mesh_key(value, &path), || {
+                Ok((check_winding(load_ply(&path)?, value, &path), Vec::new()))
+            })?;
+            Box::new((mesh, vec![material()?]))
+        }
+        "paged" => Box::new((open_paged(value, &path, dir)?, material()?)),
+        "text" => {
+            // `size` is the height of a character, which is as wide, and the letters are one font pixel deep
+            // unless `depth` says otherwise
+            let size = number_or(&value["size"], 1.0);
+            let right = vector_or(&value["right"], Vector3::x())?.normalize();
+            let up = vector_or(&value["up"], Vector3::y())?;
+            let up = (up - right * up.dot(&right)).normalize();
+            let depth = number_or(&value["depth"], size / 8.0);
+            let position = vector(&value["position"])?;
+            let mesh = text_mesh(string(&value["text"])?, position, right * size, up * size, depth);
+            Box::new((mesh, vec![material()?]))
+        }
+        "volume" => {
+            let grid = |key: &str| Grid::load_vol(dir.join(string(&value[key])?).to_str().unwrap());
+            let albedo = vector_or(&value["albedo"], Vector3::new(1.0, 1.0, 1.0))?;
+            let volume = Volume::new(grid("density")?, number_or(&value["scale"], 1.0), albedo)
+                .with_anisotropy(number_or(&value["anisotropy"], 0.0));
+            let color = vector_or(&value["color"], Vector3::new(1.0, 1.0, 1.0))?;
+            match value.get("emission") {
+                Some(_) => Box::new(volume.with_emission(grid("emission")?, color)),
+                None => Box::new(volume),
+            }
+        }
+        "mandelbulb" | "julia" => {
+            let (center, scale) = (vector(&value["center"])?, number_or(&value["scale"], 1.0));
+            let iterations = value["iterations"].as_u64().unwrap_or(12) as usize;
+            let bounds = Aabb::new(center - Vector3::repeat(1.5 * scale), center + Vector3::repeat(1.5 * scale));
+            match kind {
+                "mandelbulb" => {
+                    let power = number_or(&value["power"], 8.0);
+                    Box::new((Sdf::new(sdf::mandelbulb(center, scale, power, iterations), bounds), material()?))
+                }
+                _ => {
+                    let c = match value["c"].as_array().map(Vec::as_slice) {
+                        Some([w, x, y, z]) => Quaternion::new(number(w)?, number(x)?, number(y)?, number(z)?),
+                        _ => return Err(invalid("four numbers", &value["c"])),
+                    };
+                    Box::new((Sdf::new(sdf::julia(center, scale, c, iterations), bounds), material()?))
+                }
+            }
+        }
+        t => return Err(Error::Description(format!("unknown object type {}", t))),
+    })
+}
+
+// Meshes are shared between references to the same file loaded the same way.
+fn mesh_key(value: &Value, path: &str) -> String {
+    format!("{} {} {} {}", path, value["subdivide"], value["creases"], value["fix_winding"])
+}
+
+// Fixes the winding of an imported mesh with `"fix_winding": true`, and otherwise notes it for the summary
+// if it's off.
+fn check_winding(mesh: Mesh, value: &Value, path: &str) -> Mesh {
+    if value["fix_winding"] == true {
+        return mesh.fix_winding();
+    }
+    let winding = mesh.winding();
+    if winding.flipped > 0 {
+        counters::miswound(path, winding);
+    }
+    mesh
+}
+
+// An OBJ or PLY file is packed on first use with `chunk` faces per chunk, and again whenever it changes,
+// into `cache` relative to the scene or else the user's cache directory. `budget` is the most faces whose
+// BVHs are kept in memory at once.
+fn open_paged(value: &Value, path: &str, dir: &Path) -> Result<PagedMesh> {
+    let budget = value["budget"].as_u64().unwrap_or(1 << 22) as usize;
+    if path.ends_with(".paged") {
+        return PagedMesh::open(Path::new(path), budget);
+    }
+    let chunk = value["chunk"].as_u64().unwrap_or(1024) as usize;
+    let cache = match value.get("cache") {
+        Some(cache) => dir.join(string(cache)?),
+        None => paged::cache_dir(),
+    };
+    fs::create_dir_all(&cache)?;
+    // named for the file, and told apart from others of the same name by its full path and the chunk size
+    let input = fs::canonicalize(path)?;
+    let key = fnv1a(format!("{}:{}", input.display(), chunk).as_bytes());
+    let name = input.file_name().unwrap_or_default().to_string_lossy();
+    let packed = cache.join(format!("{}-{:016x}.paged", name, key));
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    if modified(&packed) < modified(&input) {
+        PagedMesh::pack(&input, &packed, chunk)?;
+    }
+    PagedMesh::open(&packed, budget)
+}
+
+// `subdivide` is the number of Catmull-Clark levels and `creases` a list of [from, to, sharpness] edges
+// between zero-based vertex indices.
+fn subdivision(value: &Value) -> Result<(usize, Creases)> {
+    let index = |v: &Value| v.as_u64().map(|i| i as usize).ok_or_else(|| invalid("a vertex index", v));
+    let creases = value["creases"].as_array().map(Vec::as_slice).unwrap_or_default().iter()
+        .map(|c| match c.as_array().map(Vec::as_slice) {
+            Some([from, to, sharpness]) => Ok(((index(from)?, index(to)?), number(sharpness)?)),
+            _ => Err(invalid("[from, to, sharpness]", c)),
+        })
+        .collect::<Result<_>>()?;
+    Ok((value["subdivide"].as_u64().unwrap_or_default() as usize, creases))
+}
+
+// Each entry of an array modifier repeats everything before it `count` times, applying its step
+// transform (a rotation in degrees about the origin, then an offset) once more for each copy.
+fn array_transforms(array: &Value) -> Result<Vec<Affine3<f64>>> {
+    let entries = array.as_array().ok_or_else(|| invalid("a list of array entries", array))?;
+    entries.iter().try_fold(vec![Affine3::identity()], |transforms, a| {
+        let rotation = vector_or(&a["rotation"], Vector3::zeros())?.map(f64::to_radians);
+        let step = Isometry3::from_parts(
+            Translation3::from(vector_or(&a["offset"], Vector3::zeros())?),
+            UnitQuaternion::from_euler_angles(rotation.x, rotation.y, rotation.z),
+        );
+        let step = Affine3::from_matrix_unchecked(step.to_homogeneous());
+        let count = a["count"].as_u64().ok_or_else(|| invalid("a count", &a["count"]))?;
+        let mut copies = Vec::new();
+        let mut power = Affine3::identity();
+        for _ in 0..count {
+            copies.extend(transforms.iter().map(|t| power * t));
+            power = step * power;
+        }
+        Ok(copies)
+    })
+}
+
+// Copies of the array take their materials from `instance_materials` in turn, if there are any.
+fn expand_array(
+    object: SharedObject, array: &Value, overrides: &[Arc<dyn Material + Send + Sync>],
+) -> Result<Tlas> {
+    let object: Arc<dyn Object + Send + Sync> = Arc::from(object);
+    let instances = array_transforms(array)?.into_iter().enumerate().map(|(i, t)| {
+        let instance = Instance::new(object.clone(), t);
+        match overrides {
+            [] => instance,
+            overrides => instance.with_material(overrides[i % overrides.len()].clone()),
+        }
+    });
+    Ok(Tlas::new(instances.collect()))
+}
+
+// A random block places an instance of the object at every array transform (or just one without an
+// array), each drawing its own offset within `jitter`, a uniform `scale` about its center, and a material
+// picked by weight from `materials`. Copies centered inside one of the `avoid` spheres are dropped.
+fn randomize(
+    description: &Value, random: &Value, materials: &Map<String, Value>, library: &MaterialLibrary, dir: &Path,
+    assets: &Assets, seed: u64,
+) -> Result<Tlas> {
+    let transforms = match description.get("array") {
+        Some(array) => array_transforms(array)?,
+        None => vec![Affine3::identity()],
+    };
+    let jitter = vector_or(&random["jitter"], Vector3::zeros())?;
+    let scale = if random["scale"].is_null() { (1.0, 1.0) } else { pair(&random["scale"])? };
+    let choices = match random["materials"].as_array() {
+        Some(choices) => choices.iter().map(|c| (number_or(&c["weight"], 1.0), &c["material"])).collect(),
+        None => vec![(1.0, &description["material"])],
+    };
+    let weights = WeightedIndex::new(choices.iter().map(|c| c.0))
+        .map_err(|e| Error::Description(format!("material weights: {}", e)))?;
+    let avoid = random["avoid"].as_array().map(Vec::as_slice).unwrap_or_default().iter()
+        .map(|a| Ok((vector(&a["center"])?, number(&a["radius"])?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    // the geometry is shared, with the picked materials set on the instances
+    let material = if description["material"].is_null() { choices[0].1 } else { &description["material"] };
+    let rng = &mut counter_rng(seed, u64::MAX);
+    let object = parse_object(description, material, materials, library, dir, assets, rng)?;
+    let object: Arc<dyn Object + Send + Sync> = Arc::from(object);
+    let center = object.bounds().map_or_else(Vector3::zeros, |b| b.center());
+    let mut instances = Vec::new();
+    for (i, t) in transforms.iter().enumerate() {
+        let rng = &mut counter_rng(seed, i as u64);
+        let offset = jitter.map(|j| rng.gen::<f64>() * j);
+        let s = scale.0 + (scale.1 - scale.0) * rng.gen::<f64>();
+        let material = choices[weights.sample(rng)].1;
+        let material = random.get("materials")
+            .map(|_| parse_material(material, materials, library, dir, assets, rng))
+            .transpose()?;
+        let local = Matrix4::new_translation(&(offset + center))
+            * Matrix4::new_scaling(s)
+            * Matrix4::new_translation(&-center);
+        let transform = t * Affine3::from_matrix_unchecked(local);
+        let position = transform.transform_point(&center.into()).coords;
+        if avoid.iter().any(|(c, r)| (position - c).norm() < *r) {
+            continue;
+        }
+        let instance = Instance::new(object.clone(), transform);
+        instances.push(match material {
+            Some(material) => instance.with_material(Arc::from(material)),
+            None => instance,
+        });
+    }
+    Ok(Tlas::new(instances))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    // A white ball named `ball` in front of the camera, focused on `focus` if it isn't null.
+    fn description(focus: Value) -> Value {
+        json!({
+            "camera": { "from": [0, 0, 5], "at": [0, 0, 0], "fov": 40, "focus": focus },
+            "materials": { "white": { "type": "lambertian", "albedo": [0.8, 0.8, 0.8] } },
+            "objects": [{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "white", "name": "ball" }],
+        })
+    }
+
+    fn scene() -> Scene {
+        Scene::from_json(&description(Value::Null), Path::new("")).unwrap()
+    }
+
+    #[test]
+    fn set_creates_keys_and_indexes_arrays() {
+        let mut description = json!({ "objects": [{ "radius": 1 }] });
+        set(&mut description, "objects.0.radius", "2.5").unwrap();
+        set(&mut description, "camera.fov", "30").unwrap();
+        set(&mut description, "materials.floor.type", "metal").unwrap();
+        assert_eq!(description["objects"][0]["radius"], json!(2.5));
+        assert_eq!(description["camera"]["fov"], json!(30));
+        assert_eq!(description["materials"]["floor"]["type"], json!("metal"));
+    }
+
+    #[test]
+    fn set_reports_bad_paths() {
+        let mut description = json!({ "objects": [{ "radius": 1 }] });
+        let error = set(&mut description, "objects.3.radius", "2").unwrap_err().to_string();
+        assert!(error.contains("objects.3.radius") && error.contains("no index 3 in an array of 1"), "{}", error);
+        let error = set(&mut description, "objects.0.radius.x", "2").unwrap_err().to_string();
+        assert!(error.contains("no key x in 1"), "{}", error);
+        assert!(set(&mut description, "objects.first", "2").is_err());
+    }
+
+    #[test]
+    fn focusing_on_an_unknown_object_is_an_error() {
+        let scene = scene();
+        assert!(scene.focus_distance(&Focus::Object("ball".to_owned()), 1.0).unwrap().is_some());
+        let error = scene.focus_distance(&Focus::Object("cube".to_owned()), 1.0).unwrap_err().to_string();
+        assert!(error.contains("no object named cube"), "{}", error);
+        assert!(Scene::from_json(&description(json!("cube")), Path::new("")).is_err());
+        assert!(Scene::from_json(&description(json!("ball")), Path::new("")).is_ok());
+    }
+
+    #[test]
+    fn autofocus_measures_along_the_line_of_sight() {
+        let mut scene = Scene::from_json(&description(json!("ball")), Path::new("")).unwrap();
+        scene.autofocus(1.0).unwrap();
+        assert!((scene.view.focus_distance - 4.0).abs() < 1e-9, "{}", scene.view.focus_distance);
+        let mut scene = Scene::from_json(&description(json!([0.5, 0.5])), Path::new("")).unwrap();
+        scene.autofocus(1.0).unwrap();
+        assert!((scene.view.focus_distance - 4.0).abs() < 1e-9, "{}", scene.view.focus_distance);
+    }
+
+    #[test]
+    fn fingerprint_follows_edits() {
+        let settings = RenderSettings::default();
+        let fingerprint = scene().fingerprint(&settings).unwrap();
+        assert_eq!(scene().fingerprint(&settings), Some(fingerprint.clone()));
+        assert_ne!(scene().fingerprint(&RenderSettings { samples: 3, ..settings.clone() }).unwrap(), fingerprint);
+
+        let mut moved = scene();
+        moved.view.from.x += 1.0;
+        assert_ne!(moved.fingerprint(&settings).unwrap(), fingerprint);
+
+        let mut fogged = scene();
+        fogged.set_fog(None);
+        assert_eq!(fogged.fingerprint(&settings), None);
+
+        let overridden = scene();
+        assert!(overridden.materials().set("white", Box::new(Lambertian::new(Vector3::new(0.1, 0.1, 0.1)))));
+        assert_eq!(overridden.fingerprint(&settings), None);
+    }
+}
@@ -0,0 +1,185 @@
+use std::ops::Range;
+
+use nalgebra::{Quaternion, Vector2, Vector3};
+
+use crate::aabb::Aabb;
+use crate::geometry::{spherical_uv, Geometry};
+use crate::ray::Ray;
+
+const MAX_STEPS: usize = 512;
+const EPSILON: f64 = 1e-4;
+
+pub struct Sdf<F> {
+    distance: F,
+    bounds: Aabb,
+}
+
+impl<F: Fn(Vector3<f64>) -> f64> Sdf<F> {
+    pub fn new(distance: F, bounds: Aabb) -> Self {
+        Self { distance, bounds }
+    }
+
+    fn gradient(&self, p: &Vector3<f64>) -> Vector3<f64> {
+        let f = &self.distance;
+        let h = EPSILON;
+        Vector3::new(
+            f(p + Vector3::x() * h) - f(p - Vector3::x() * h),
+            f(p + Vector3::y() * h) - f(p - Vector3::y() * h),
+            f(p + Vector3::z() * h) - f(p - Vector3::z() * h),
+        )
+    }
+}
+
+impl<F: Fn(Vector3<f64>) -> f64> Geometry for Sdf<F> {
+    // Sphere tracing: the distance bound is followed from the side of the surface the ray starts on,
+    // so refracted rays leaving the surface are not caught by the surface they started from.
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<f64> {
+        let inv = ray.direction().map(|x| 1.0 / x);
+        let t0 = (self.bounds.min - ray.origin).component_mul(&inv);
+        let t1 = (self.bounds.max - ray.origin).component_mul(&inv);
+        let start = t0.inf(&t1).max().max(range.start);
+        let end = t0.sup(&t1).min().min(range.end);
+        if start > end {
+            return None;
+        }
+
+        let f = &self.distance;
+        let length = ray.direction().norm();
+        let origin = ray.at(start);
+        let d0 = f(origin);
+        let side = if d0.abs() < 4.0 * EPSILON {
+            if ray.direction().dot(&self.gradient(&origin)) < 0.0 { -1.0 } else { 1.0 }
+        } else {
+            d0.signum()
+        };
+        let min_t = start + 4.0 * EPSILON / length;
+        let mut t = start;
+        for _ in 0..MAX_STEPS {
+            if t > end {
+                return None;
+            }
+            let d = side * f(ray.at(t)) / length;
+            if d < EPSILON / length && t > min_t {
+                return Some(t);
+            }
+            t += d.max(EPSILON / length);
+        }
+        None
+    }
+
+    fn normal(&self, point: &Vector3<f64>) -> Vector3<f64> {
+        self.gradient(point).normalize()
+    }
+
+    fn uv(&self, point: &Vector3<f64>) -> Vector2<f64> {
+        spherical_uv(&(point - self.bounds.center()).normalize())
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+pub fn sphere(center: Vector3<f64>, radius: f64) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| (p - center).norm() - radius
+}
+
+pub fn cuboid(center: Vector3<f64>, half_size: Vector3<f64>) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| {
+        let q = (p - center).abs() - half_size;
+        q.sup(&Vector3::zeros()).norm() + q.max().min(0.0)
+    }
+}
+
+pub fn torus(center: Vector3<f64>, major: f64, minor: f64) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| {
+        let p = p - center;
+        Vector2::new(Vector2::new(p.x, p.z).norm() - major, p.y).norm() - minor
+    }
+}
+
+pub fn capsule(a: Vector3<f64>, b: Vector3<f64>, radius: f64) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| {
+        let (pa, ba) = (p - a, b - a);
+        let h = (pa.dot(&ba) / ba.norm_squared()).clamp(0.0, 1.0);
+        (pa - ba * h).norm() - radius
+    }
+}
+
+pub fn plane(normal: Vector3<f64>, offset: f64) -> impl Fn(Vector3<f64>) -> f64 {
+    let normal = normal.normalize();
+    move |p| p.dot(&normal) - offset
+}
+
+pub fn union(a: impl Fn(Vector3<f64>) -> f64, b: impl Fn(Vector3<f64>) -> f64) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| a(p).min(b(p))
+}
+
+pub fn intersection(a: impl Fn(Vector3<f64>) -> f64, b: impl Fn(Vector3<f64>) -> f64) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| a(p).max(b(p))
+}
+
+pub fn difference(a: impl Fn(Vector3<f64>) -> f64, b: impl Fn(Vector3<f64>) -> f64) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| a(p).max(-b(p))
+}
+
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+pub fn smooth_union(
+    a: impl Fn(Vector3<f64>) -> f64, b: impl Fn(Vector3<f64>) -> f64, k: f64,
+) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| smooth_min(a(p), b(p), k)
+}
+
+pub fn smooth_intersection(
+    a: impl Fn(Vector3<f64>) -> f64, b: impl Fn(Vector3<f64>) -> f64, k: f64,
+) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| -smooth_min(-a(p), -b(p), k)
+}
+
+pub fn smooth_difference(
+    a: impl Fn(Vector3<f64>) -> f64, b: impl Fn(Vector3<f64>) -> f64, k: f64,
+) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| -smooth_min(-a(p), b(p), k)
+}
+
+// Distance estimate for the power-`power` Mandelbulb fitted into radius `scale`, from the running
+// derivative of the escape-time iteration in spherical coordinates. The poles are on the y axis.
+pub fn mandelbulb(center: Vector3<f64>, scale: f64, power: f64, iterations: usize) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| {
+        let p = (p - center) / scale;
+        let c = Vector3::new(p.x, p.z, p.y);
+        let (mut z, mut dr, mut r) = (c, 1.0, c.norm());
+        for _ in 0..iterations {
+            if r > 2.0 || r == 0.0 {
+                break;
+            }
+            let (theta, phi) = ((z.z / r).acos() * power, z.y.atan2(z.x) * power);
+            dr = power * r.powf(power - 1.0) * dr + 1.0;
+            z = Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * r.powf(power) + c;
+            r = z.norm();
+        }
+        0.5 * r.max(1e-12).ln() * r / dr * scale
+    }
+}
+
+// Distance estimate for the slice of the quaternion Julia set of z^2 + c with no k component, at radius
+// `scale`. Points map to quaternions with x as the real part.
+pub fn julia(center: Vector3<f64>, scale: f64, c: Quaternion<f64>, iterations: usize) -> impl Fn(Vector3<f64>) -> f64 {
+    move |p| {
+        let p = (p - center) / scale;
+        let (mut z, mut dz) = (Quaternion::new(p.x, p.y, p.z, 0.0), 1.0);
+        for _ in 0..iterations {
+            if z.norm_squared() > 16.0 {
+                break;
+            }
+            dz *= 2.0 * z.norm();
+            z = z * z + c;
+        }
+        let r = z.norm();
+        0.5 * r.max(1e-12).ln() * r / dz * scale
+    }
+}
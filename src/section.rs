@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::Arc;
+
+use nalgebra::{Vector2, Vector3};
+
+use crate::aabb::Aabb;
+use crate::library::SharedMaterial;
+use crate::material::orthonormal_basis;
+use crate::object::{Intersection, Object};
+use crate::ray::Ray;
+
+// A plane cutting away everything on the side `normal` points to, for cutaway renders of buildings and
+// machines. Where it cuts through a closed object, the opening is capped with `cap` if there is one, and
+// left open to show the inside otherwise.
+pub struct Section {
+    pub point: Vector3<f64>,
+    pub normal: Vector3<f64>,
+    pub cap: Option<SharedMaterial>,
+}
+
+// An object with the scene's sections cutting into it. The kept part of every ray is a single stretch, as
+// each section keeps a half-space, so sectioning only narrows the range the object is searched in.
+pub struct Sectioned {
+    object: Box<dyn Object + Sync>,
+    sections: Arc<[Section]>,
+}
+
+impl Sectioned {
+    pub fn new(object: Box<dyn Object + Sync>, sections: Arc<[Section]>) -> Self {
+        Self { object, sections }
+    }
+
+    // The kept part of `range` along `ray`, and the section it enters the kept part through, if its start
+    // was moved up to one.
+    fn clip(&self, ray: &Ray<f64>, mut range: Range<f64>) -> Option<(Range<f64>, Option<usize>)> {
+        let mut entry = None;
+        for (k, section) in self.sections.iter().enumerate() {
+            let along = ray.direction().dot(&section.normal);
+            let ahead = (section.point - ray.origin).dot(&section.normal);
+            let t = ahead / along;
+            if along > 0.0 {
+                range.end = range.end.min(t);
+            } else if along < 0.0 && t > range.start {
+                range.start = t;
+                entry = Some(k);
+            } else if along == 0.0 && ahead < 0.0 {
+                return None;
+            }
+        }
+        (range.start < range.end).then_some((range, entry))
+    }
+
+    fn cap(&self, index: usize) -> &SharedMaterial {
+        self.sections[index].cap.as_ref().unwrap()
+    }
+}
+
+// Hits on the object itself refer to it, so only the caps, whose index is their section's, are shaded here.
+impl Object for Sectioned {
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection<'_>> {
+        let (range, entry) = self.clip(ray, range)?;
+        let int = self.object.intersect(ray, range.clone())?;
+        // seeing the inside of the object through the opening means the section cut through it
+        match entry.filter(|&k| self.sections[k].cap.is_some() && !int.front()) {
+            Some(k) => Some(Intersection::new(range.start, ray, self, k)),
+            None => Some(int),
+        }
+    }
+
+    fn normal(&self, _point: &Vector3<f64>, index: usize) -> Vector3<f64> {
+        self.sections[index].normal.normalize()
+    }
+
+    // planar, in the units of the scene
+    fn uv(&self, point: &Vector3<f64>, index: usize) -> Vector2<f64> {
+        let (t, b) = orthonormal_basis(&self.normal(point, index));
+        Vector2::new(point.dot(&t), point.dot(&b))
+    }
+
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        self.cap(int.index()).scatter(int)
+    }
+
+    fn scatter_lobe(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>, bool) {
+        self.cap(int.index()).scatter_lobe(int)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        self.cap(int.index()).eval(int, wi)
+    }
+
+    fn specular(&self, index: usize) -> bool {
+        self.cap(index).specular()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.object.bounds()
+    }
+
+    fn specular_bounds(&self) -> Option<Aabb> {
+        let capped = self.sections.iter().any(|s| s.cap.as_ref().is_some_and(|c| c.specular()));
+        if capped { self.object.bounds() } else { self.object.specular_bounds() }
+    }
+
+    fn bvh_bounds(&self, depth: usize) -> Vec<(usize, Aabb)> {
+        self.object.bvh_bounds(depth)
+    }
+
+    fn primitives(&self) -> usize {
+        self.object.primitives()
+    }
+
+    fn memory(&self, shared: &mut HashSet<usize>) -> usize {
+        self.object.memory(shared)
+    }
+}
@@ -0,0 +1,251 @@
+use std::sync::Arc;
+
+use serde_json::json;
+
+use crate::camera::{Stereo, StereoLayout};
+use crate::filter::PixelFilter;
+use crate::lut::Lut;
+#[cfg(feature = "ocio")]
+use crate::ocio::DisplayTransform;
+use crate::sampler::{LensSampler, Sampler};
+use crate::white_balance::WhiteBalance;
+
+const NUM_SAMPLES: u32 = 128;
+// wasm32 has no threads
+const NUM_THREADS: u32 = if cfg!(target_arch = "wasm32") { 1 } else { 8 };
+const MAX_DEPTH: usize = 20;
+const IMAGE_WIDTH: u32 = 300;
+const IMAGE_HEIGHT: u32 = 200;
+
+#[derive(Clone, Copy)]
+pub enum Integrator {
+    PathTracing,
+    // Path tracing that learns where light comes from as it goes and aims bounces that way.
+    PathGuiding,
+    PhotonMapping { photons: usize, radius: f64 },
+}
+
+// Presets trading time for noise and bias, each a starting point for the individual settings: samples,
+// bounces, lens splits, clamping, denoising and the pixel filter. Draft and medium clamp and denoise to hide
+// what their few samples leave; final keeps the energy and detail those cost.
+#[derive(Clone, Copy)]
+pub enum Quality {
+    // Quick and noisy, for framing and lookdev.
+    Draft,
+    Medium,
+    // Enough samples and bounces for glass and caustics to resolve.
+    Final,
+}
+
+// A part of the frame to render on its own, from the top left, at the size it has in the full frame.
+#[derive(Clone, Copy)]
+pub enum Crop {
+    Pixels { x: u32, y: u32, width: u32, height: u32 },
+    // In fractions of the frame's width and height.
+    Normalized { x: f64, y: f64, width: f64, height: f64 },
+}
+
+impl Crop {
+    // The window in pixels of a `width` by `height` frame, clipped to the frame and at least a pixel.
+    pub fn pixels(self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let (x, y, w, h) = match self {
+            Crop::Pixels { x, y, width, height } => (x, y, width, height),
+            Crop::Normalized { x, y, width: w, height: h } => {
+                let (fw, fh) = (width as f64, height as f64);
+                ((x * fw).round() as u32, (y * fh).round() as u32, (w * fw).round() as u32, (h * fh).round() as u32)
+            }
+        };
+        let (x, y) = (x.min(width - 1), y.min(height - 1));
+        (x, y, w.clamp(1, width - x), h.clamp(1, height - y))
+    }
+}
+
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub threads: u32,
+    pub max_depth: usize,
+    pub integrator: Integrator,
+    pub sampler: Sampler,
+    pub lens_sampler: LensSampler,
+    pub filter: PixelFilter,
+    // Lens samples per pixel sample on average, adaptively split; only used with a defocused camera.
+    pub lens_splits: u32,
+    pub white_balance: Option<WhiteBalance>,
+    // The most any one light contribution may add to a channel of a sample, to tame fireflies at the cost
+    // of some energy, and whether to record where the contributions cut came from.
+    pub clamp: Option<f64>,
+    pub fireflies: bool,
+    // Smooth the noise out of the finished image, trading fine texture for it.
+    pub denoise: bool,
+    // Whether to record the light by the number of bounces it took and how long the paths were, per pixel.
+    pub light_paths: bool,
+    // Leave out the background the camera sees, leaving it transparent in the alpha channel, and the image
+    // to composite the render over in its place, such as the photo shadow catchers stand in for.
+    pub transparent: bool,
+    pub backplate: Option<String>,
+    // An OpenColorIO view to display the render through instead of the built-in gamma.
+    #[cfg(feature = "ocio")]
+    pub display_transform: Option<Arc<DisplayTransform>>,
+    // A look applied to the display values last.
+    pub lut: Option<Arc<Lut>>,
+    // Render only this part of the frame, as an image of its size.
+    pub crop: Option<Crop>,
+    // Both eyes in one frame, each taking half of it.
+    pub stereo: Option<Stereo>,
+    pub scene: Option<String>,
+    pub overrides: Vec<(String, String)>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: IMAGE_WIDTH,
+            height: IMAGE_HEIGHT,
+            samples: NUM_SAMPLES,
+            threads: NUM_THREADS,
+            max_depth: MAX_DEPTH,
+            integrator: Integrator::PathTracing,
+            sampler: Sampler::Halton,
+            lens_sampler: LensSampler::Random,
+            filter: PixelFilter::Box,
+            lens_splits: 1,
+            white_balance: None,
+            clamp: None,
+            fireflies: false,
+            denoise: false,
+            light_paths: false,
+            transparent: false,
+            backplate: None,
+            #[cfg(feature = "ocio")]
+            display_transform: None,
+            lut: None,
+            crop: None,
+            stereo: None,
+            scene: None,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn with_quality(self, quality: Quality) -> Self {
+        let (samples, max_depth, lens_splits, clamp, denoise, filter) = match quality {
+            Quality::Draft => (4, 4, 1, Some(10.0), true, PixelFilter::Box),
+            Quality::Medium => (32, 8, 2, Some(50.0), true, PixelFilter::Tent),
+            Quality::Final => (256, MAX_DEPTH, 4, None, false, PixelFilter::BlackmanHarris),
+        };
+        Self { samples, max_depth, lens_splits, clamp, denoise, filter, ..self }
+    }
+
+    // The size of the rendered image, the crop window's if there is one.
+    pub fn image_size(&self) -> (u32, u32) {
+        match self.crop_window() {
+            Some((_, _, width, height)) => (width, height),
+            None => (self.width, self.height),
+        }
+    }
+
+    pub fn crop_window(&self) -> Option<(u32, u32, u32, u32)> {
+        self.crop.map(|crop| crop.pixels(self.width, self.height))
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let integrator = match self.integrator {
+            Integrator::PathTracing => json!({ "type": "path_tracing" }),
+            Integrator::PathGuiding => json!({ "type": "path_guiding" }),
+            Integrator::PhotonMapping { photons, radius } => {
+                json!({ "type": "photon_mapping", "photons": photons, "radius": radius })
+            }
+        };
+        json!({
+            "width": self.width,
+            "height": self.height,
+            "samples": self.samples,
+            "threads": self.threads,
+            "max_depth": self.max_depth,
+            "integrator": integrator,
+            "sampler": match self.sampler {
+                Sampler::Random => "random",
+                Sampler::Halton => "halton",
+            },
+            "lens_sampler": match self.lens_sampler {
+                LensSampler::Random => "random",
+                LensSampler::Spiral => "spiral",
+            },
+            "filter": match self.filter {
+                PixelFilter::Box => "box",
+                PixelFilter::Tent => "tent",
+                PixelFilter::Gaussian => "gaussian",
+                PixelFilter::BlackmanHarris => "blackman_harris",
+            },
+            "lens_splits": self.lens_splits,
+            "white_balance": match self.white_balance {
+                Some(WhiteBalance::GreyWorld) => Some("grey"),
+                Some(WhiteBalance::Neutral) => Some("neutral"),
+                None => None,
+            },
+            "clamp": self.clamp,
+            "denoise": self.denoise,
+            "transparent": self.transparent,
+            "backplate": self.backplate,
+            "display_transform": self.display_transform_json(),
+            "lut": self.lut.as_ref().map(|lut| &lut.path),
+            "crop": self.crop_window().map(|(x, y, width, height)| [x, y, width, height]),
+            "stereo": self.stereo.map(|stereo| json!({
+                "layout": match stereo.layout {
+                    StereoLayout::SideBySide => "side_by_side",
+                    StereoLayout::OverUnder => "over_under",
+                },
+                "interocular": stereo.interocular,
+                "ods": stereo.ods,
+            })),
+            "scene": self.scene,
+            "overrides": self.overrides.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>(),
+        })
+    }
+
+    #[cfg(feature = "ocio")]
+    fn display_transform_json(&self) -> serde_json::Value {
+        self.display_transform.as_ref().map_or(serde_json::Value::Null, |transform| json!({
+            "config": transform.config,
+            "display": transform.display,
+            "view": transform.view,
+        }))
+    }
+
+    #[cfg(not(feature = "ocio"))]
+    fn display_transform_json(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_trade_noise_for_time() {
+        let draft = RenderSettings::default().with_quality(Quality::Draft);
+        let medium = RenderSettings::default().with_quality(Quality::Medium);
+        let last = RenderSettings::default().with_quality(Quality::Final);
+        assert!(draft.samples < medium.samples && medium.samples < last.samples);
+        assert!(draft.max_depth < medium.max_depth && medium.max_depth < last.max_depth);
+        assert!(draft.clamp < medium.clamp && last.clamp.is_none());
+        assert!(draft.denoise && medium.denoise && !last.denoise);
+        assert!(matches!(
+            (draft.filter, medium.filter, last.filter),
+            (PixelFilter::Box, PixelFilter::Tent, PixelFilter::BlackmanHarris),
+        ));
+    }
+
+    #[test]
+    fn presets_keep_other_settings() {
+        let settings = RenderSettings { width: 64, threads: 3, fireflies: true, ..Default::default() };
+        let draft = settings.with_quality(Quality::Draft);
+        assert_eq!((draft.width, draft.threads, draft.fireflies), (64, 3, true));
+        assert_eq!(draft.to_json()["denoise"], true);
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use nalgebra::Vector3;
+use serde_json::{json, Map, Value};
+
+use crate::control::Control;
+use crate::error::Result;
+use crate::material::Lambertian;
+use crate::mesh::Mesh;
+use crate::overlay::{Canvas, GLYPH_SIZE};
+use crate::portal::Portal;
+use crate::render_scene;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+type Image = (u32, u32, Vec<Vector3<f64>>);
+
+// The half width and the height of the rooms.
+const ROOM: (f64, f64) = (4.0, 5.0);
+
+#[derive(Clone, Copy)]
+enum Lighting {
+    // On a ground plane under the open sky.
+    Sky,
+    // In a closed room lit through a window in the wall to the left.
+    Window,
+    // In a closed room lit through an opening in the middle of the ceiling.
+    Skylight,
+}
+
+impl Lighting {
+    fn name(self) -> &'static str {
+        match self {
+            Lighting::Sky => "sky",
+            Lighting::Window => "window",
+            Lighting::Skylight => "skylight",
+        }
+    }
+}
+
+// Renders a shader ball in one material under each lighting setup, side by side and labeled, for look
+// development. `path` is a scene file, with `name` one of its materials, or a file holding just a material
+// definition; texture paths are relative to it as in scenes. Every setup is rendered at the size the
+// settings give, with a fresh control from `control`.
+pub fn material_preview(
+    path: &Path, name: Option<&str>, settings: &RenderSettings, control: impl Fn() -> Control,
+) -> Result<Image> {
+    let file: Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let (materials, material) = match name {
+        Some(name) => {
+            let materials = file["materials"].as_object().cloned().unwrap_or_default();
+            if !materials.contains_key(name) {
+                panic!("{} has no material named {}", path.display(), name);
+            }
+            (materials, Value::from(name))
+        }
+        None => (Map::new(), file),
+    };
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let images = [Lighting::Sky, Lighting::Window, Lighting::Skylight].iter().map(|&lighting| {
+        let scene = scene(lighting, &materials, &material, dir)?;
+        let mut image = render_scene(&scene, settings, &control())?;
+        let scale = (image.1 / 360).max(1);
+        let margin = (GLYPH_SIZE * scale) as i64;
+        Canvas::new(&mut image).text(margin, margin, lighting.name(), scale);
+        Ok(image)
+    }).collect::<Result<Vec<_>>>()?;
+    // images are stored column by column, so putting them side by side just joins their buffers
+    Ok(images.into_iter().reduce(|(width, height, mut buffer), image| {
+        buffer.extend(image.2);
+        (width + image.0, height, buffer)
+    }).unwrap())
+}
+
+fn scene(lighting: Lighting, materials: &Map<String, Value>, material: &Value, dir: &Path) -> Result<Scene> {
+    let mut objects = vec![json!({ "type": "sphere", "center": [0, 1, 0], "radius": 1, "material": material })];
+    if let Lighting::Sky = lighting {
+        objects.push(json!({
+            "type": "sphere", "center": [0, -1000, 0], "radius": 1000,
+            "material": { "type": "lambertian", "albedo": [0.6, 0.6, 0.6] },
+        }));
+    }
+    let mut scene = Scene::from_json(&json!({
+        "camera": { "from": [0, 1.8, 3.5], "at": [0, 0.9, 0], "up": [0, 1, 0], "fov": 45 },
+        "materials": materials,
+        "objects": objects,
+    }), dir)?;
+    let (r, h) = ROOM;
+    let (x, y, z) = (Vector3::x(), Vector3::y(), Vector3::z());
+    // each wall as a corner and the sides spanning it, facing in: the floor, the ceiling and then the
+    // walls to the left, right, back and front
+    let walls = [
+        (Vector3::new(-r, 0.0, -r), z * 2.0 * r, x * 2.0 * r),
+        (Vector3::new(-r, h, -r), x * 2.0 * r, z * 2.0 * r),
+        (Vector3::new(-r, 0.0, -r), y * h, z * 2.0 * r),
+        (Vector3::new(r, 0.0, -r), z * 2.0 * r, y * h),
+        (Vector3::new(-r, 0.0, -r), x * 2.0 * r, y * h),
+        (Vector3::new(-r, 0.0, r), y * h, x * 2.0 * r),
+    ];
+    // which wall has the opening, and where along its sides
+    let (opening, [u0, u1, v0, v1]) = match lighting {
+        Lighting::Sky => return Ok(scene),
+        Lighting::Window => (2, [0.2, 0.9, 0.15, 0.85]),
+        Lighting::Skylight => (1, [0.25, 0.75, 0.25, 0.75]),
+    };
+    let (mut vertices, mut faces) = (Vec::new(), Vec::new());
+    let mut quad = |corner: Vector3<f64>, u: Vector3<f64>, v: Vector3<f64>| {
+        let base = vertices.len();
+        vertices.extend([corner, corner + u, corner + u + v, corner + v]);
+        faces.extend([[base, base + 1, base + 2], [base, base + 2, base + 3]]);
+    };
+    for (k, &(corner, u, v)) in walls.iter().enumerate() {
+        if k != opening {
+            quad(corner, u, v);
+            continue;
+        }
+        // the wall around the opening, as strips before and after it along v and beside it along u
+        quad(corner, u, v * v0);
+        quad(corner + v * v1, u, v * (1.0 - v1));
+        quad(corner + v * v0, u * u0, v * (v1 - v0));
+        quad(corner + v * v0 + u * u1, u * (1.0 - u1), v * (v1 - v0));
+        scene.add_portal(Portal::new(corner + u * u0 + v * v0, u * (u1 - u0), v * (v1 - v0)));
+    }
+    let (materials, walls) = (vec![0; faces.len()], vec![Lambertian::new(Vector3::repeat(0.6))]);
+    scene.add_object("walls", (Mesh::new(vertices, faces, materials), walls));
+    Ok(scene)
+}
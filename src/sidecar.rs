@@ -0,0 +1,48 @@
+use std::fs;
+use std::path::Path;
+
+use image::{Rgb, RgbImage};
+use itertools::iproduct;
+use nalgebra::Vector3;
+use serde_json::{json, Value};
+
+use crate::error::Result;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+const THUMBNAIL_SIZE: u32 = 256;
+
+// Writes `<output>.thumb.png`, box-filtered down to at most THUMBNAIL_SIZE on the longer side, and
+// `<output>.json` with the settings, the final stats and the fingerprint of the scene and settings, if it has
+// one.
+pub fn write_sidecar(
+    output: &str, image: &(u32, u32, Vec<Vector3<f64>>), scene: &Scene, settings: &RenderSettings, stats: &Value,
+) -> Result<()> {
+    let output = Path::new(output);
+    thumbnail(image).save(output.with_extension("thumb.png"))?;
+    let sidecar = json!({
+        "output": output,
+        "settings": settings.to_json(),
+        "stats": stats,
+        "fingerprint": scene.fingerprint(settings),
+    });
+    Ok(fs::write(output.with_extension("json"), serde_json::to_string_pretty(&sidecar)?)?)
+}
+
+fn thumbnail(image: &(u32, u32, Vec<Vector3<f64>>)) -> RgbImage {
+    let (width, height, buffer) = image;
+    let factor = width.max(height).div_ceil(THUMBNAIL_SIZE);
+    let (w, h) = ((width / factor).max(1), (height / factor).max(1));
+    RgbImage::from_fn(w, h, |x, y| {
+        let block = iproduct!(x * factor..((x + 1) * factor).min(*width), y * factor..((y + 1) * factor).min(*height))
+            .map(|(i, j)| buffer[(i * height + j) as usize])
+            .collect::<Vec<_>>();
+        let c = (block.iter().sum::<Vector3<f64>>() / block.len() as f64).map(|x| (x.clamp(0.0, 1.0) * 255.0) as u8);
+        Rgb([c.x, c.y, c.z])
+    })
+}
+
+// 64-bit FNV-1a, stable across builds unlike the std hasher.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use nalgebra::{Vector2, Vector3};
+
+pub(crate) struct Polygon {
+    pub vertices: Vec<usize>,
+    pub uvs: Option<Vec<usize>>,
+    pub material: usize,
+}
+
+// Sharpness of the edges between pairs of vertices. Sharpness decreases by one each level, so an edge of
+// sharpness 2 stays sharp for two levels and then smooths out; boundary edges are always sharp.
+pub type Creases = HashMap<(usize, usize), f64>;
+
+fn key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+pub(crate) struct Cage {
+    pub vertices: Vec<Vector3<f64>>,
+    pub uvs: Vec<Vector2<f64>>,
+    pub polygons: Vec<Polygon>,
+    pub creases: Creases,
+}
+
+struct Edge {
+    faces: Vec<usize>,
+    sharpness: f64,
+}
+
+// One level of Catmull-Clark subdivision, turning every n-gon into n quads. The new vertices are the
+// moved original vertices, then one per edge, then one per face. Texture coordinates are interpolated
+// linearly within each face, sharing the new ones across faces that share the parent uvs.
+pub(crate) fn catmull_clark(cage: &Cage) -> Cage {
+    let Cage { vertices, uvs, polygons, creases } = cage;
+    let mut edges = HashMap::<(usize, usize), usize>::new();
+    let mut edge_list = Vec::<((usize, usize), Edge)>::new();
+    for (f, polygon) in polygons.iter().enumerate() {
+        for (a, b) in sides(&polygon.vertices) {
+            let e = *edges.entry(key(a, b)).or_insert_with(|| {
+                let sharpness = creases.get(&key(a, b)).copied().unwrap_or(0.0);
+                edge_list.push((key(a, b), Edge { faces: Vec::new(), sharpness }));
+                edge_list.len() - 1
+            });
+            edge_list[e].1.faces.push(f);
+        }
+    }
+    let is_sharp = |e: &Edge| e.faces.len() < 2 || e.sharpness > 0.0;
+    let sharpness = |e: &Edge| if e.faces.len() < 2 { f64::INFINITY } else { e.sharpness };
+
+    let face_points = polygons.iter()
+        .map(|p| p.vertices.iter().map(|&v| vertices[v]).sum::<Vector3<f64>>() / p.vertices.len() as f64)
+        .collect::<Vec<_>>();
+    let edge_points = edge_list.iter().map(|((a, b), edge)| {
+        let mid = (vertices[*a] + vertices[*b]) / 2.0;
+        if edge.faces.len() < 2 {
+            return mid;
+        }
+        let faces = edge.faces.iter().map(|&f| face_points[f]).sum::<Vector3<f64>>() / edge.faces.len() as f64;
+        let smooth = (mid + faces) / 2.0;
+        smooth.lerp(&mid, edge.sharpness.min(1.0))
+    }).collect::<Vec<_>>();
+
+    let mut incident = vec![Vec::new(); vertices.len()];
+    for (e, ((a, b), _)) in edge_list.iter().enumerate() {
+        incident[*a].push(e);
+        incident[*b].push(e);
+    }
+    let mut faces_around = vec![Vec::new(); vertices.len()];
+    for (f, polygon) in polygons.iter().enumerate() {
+        polygon.vertices.iter().for_each(|&v| faces_around[v].push(f));
+    }
+    let vertex_points = vertices.iter().enumerate().map(|(v, &p)| {
+        let around = &incident[v];
+        if around.is_empty() {
+            return p;
+        }
+        let n = around.len() as f64;
+        let other = |e: usize| {
+            let (a, b) = edge_list[e].0;
+            vertices[if a == v { b } else { a }]
+        };
+        let faces = &faces_around[v];
+        let q = faces.iter().map(|&f| face_points[f]).sum::<Vector3<f64>>() / faces.len() as f64;
+        let r = around.iter().map(|&e| (p + other(e)) / 2.0).sum::<Vector3<f64>>() / n;
+        let smooth = (q + 2.0 * r + (n - 3.0) * p) / n;
+        let sharp = around.iter().filter(|&&e| is_sharp(&edge_list[e].1)).collect::<Vec<_>>();
+        let s = sharp.iter().map(|&&e| sharpness(&edge_list[e].1)).sum::<f64>() / sharp.len().max(1) as f64;
+        match sharp.len() {
+            0 | 1 => smooth,
+            2 => smooth.lerp(&((other(*sharp[0]) + other(*sharp[1]) + 6.0 * p) / 8.0), s.min(1.0)),
+            _ => smooth.lerp(&p, s.min(1.0)),
+        }
+    }).collect::<Vec<_>>();
+
+    let (nv, ne) = (vertices.len(), edge_list.len());
+    let mut new_uvs = uvs.to_vec();
+    let mut uv_edges = HashMap::new();
+    let mut new_creases = Creases::new();
+    for (e, ((a, b), edge)) in edge_list.iter().enumerate() {
+        if edge.sharpness > 1.0 {
+            new_creases.insert(key(*a, nv + e), edge.sharpness - 1.0);
+            new_creases.insert(key(*b, nv + e), edge.sharpness - 1.0);
+        }
+    }
+    let mut new_polygons = Vec::new();
+    for (f, polygon) in polygons.iter().enumerate() {
+        let n = polygon.vertices.len();
+        let corner_uvs = polygon.uvs.as_ref().map(|corners| {
+            let center = corners.iter().map(|&i| uvs[i]).sum::<Vector2<f64>>() / n as f64;
+            new_uvs.push(center);
+            let center = new_uvs.len() - 1;
+            let mids = sides(corners).map(|(a, b)| *uv_edges.entry(key(a, b)).or_insert_with(|| {
+                new_uvs.push((uvs[a] + uvs[b]) / 2.0);
+                new_uvs.len() - 1
+            })).collect::<Vec<_>>();
+            (center, mids)
+        });
+        let edge_index = |i: usize| {
+            let (a, b) = (polygon.vertices[i % n], polygon.vertices[(i + 1) % n]);
+            nv + edges[&key(a, b)]
+        };
+        for i in 0..n {
+            new_polygons.push(Polygon {
+                vertices: vec![polygon.vertices[i], edge_index(i), nv + ne + f, edge_index(i + n - 1)],
+                uvs: polygon.uvs.as_ref().zip(corner_uvs.as_ref())
+                    .map(|(corners, (center, mids))| vec![corners[i], mids[i], *center, mids[(i + n - 1) % n]]),
+                material: polygon.material,
+            });
+        }
+    }
+
+    let vertices = vertex_points.into_iter().chain(edge_points).chain(face_points).collect();
+    Cage { vertices, uvs: new_uvs, polygons: new_polygons, creases: new_creases }
+}
+
+fn sides(indices: &[usize]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    indices.iter().zip(indices.iter().cycle().skip(1)).map(|(&a, &b)| (a, b))
+}
@@ -0,0 +1,69 @@
+use std::fmt::Write;
+
+use itertools::iproduct;
+use nalgebra::Vector3;
+
+use crate::frame_camera;
+use crate::matte::{hash, Matte};
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+// An SVG the size of the render outlining every object the camera sees and boxing what its bounds project
+// to, each in its ID matte color and labeled with its name, for annotating figures and labeling datasets.
+// Outlines follow the pixels of the ID matte, so they line up with the render exactly; boxes take in the
+// whole object, hidden parts included, and are left out for objects reaching behind the camera.
+pub fn svg_overlay(scene: &Scene, settings: &RenderSettings) -> String {
+    let (width, height) = settings.image_size();
+    let matte = Matte::new(scene, settings);
+    let camera = frame_camera(&scene.view, settings);
+    let pixel = |p: &Vector3<f64>| camera.project(p).map(|(u, v)| (u * width as f64, (1.0 - v) * height as f64));
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" \
+         fill=\"none\" font-family=\"sans-serif\" font-size=\"12\">\n",
+        width, height,
+    );
+    for (k, object) in scene.objects.iter().enumerate() {
+        let name = matte.name(k);
+        let color = format!("#{:06x}", hash(name));
+        // the pixel edges between the object and anything else
+        let mut outline = String::new();
+        for (i, j) in iproduct!(0..width as i64, 0..height as i64).filter(|&(i, j)| matte.id(i, j) == Some(k)) {
+            for (di, dj, edge) in [(-1, 0, "v1"), (1, 0, "v1"), (0, -1, "h1"), (0, 1, "h1")] {
+                if matte.id(i + di, j + dj) != Some(k) {
+                    write!(outline, "M{} {}{}", i + di.max(0), j + dj.max(0), edge).unwrap();
+                }
+            }
+        }
+        let corners = object.bounds().map(|b| iproduct!([b.min.x, b.max.x], [b.min.y, b.max.y], [b.min.z, b.max.z])
+            .map(|(x, y, z)| pixel(&Vector3::new(x, y, z)))
+            .collect::<Option<Vec<_>>>());
+        let corners = corners.flatten().filter(|c| c.iter().all(|(x, y)| x.is_finite() && y.is_finite()));
+        let rect = corners.map(|c| c.iter().fold(
+            (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            |(x0, y0, x1, y1), &(x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        )).filter(|&(x0, y0, x1, y1)| x1 > 0.0 && y1 > 0.0 && x0 < width as f64 && y0 < height as f64);
+        if outline.is_empty() && rect.is_none() {
+            continue;
+        }
+        writeln!(svg, "<g id=\"{}\" stroke=\"{}\">", escape(name), color).unwrap();
+        if !outline.is_empty() {
+            writeln!(svg, "<path d=\"{}\"/>", outline).unwrap();
+        }
+        if let Some((x0, y0, x1, y1)) = rect {
+            writeln!(
+                svg, "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" stroke-dasharray=\"4 2\"/>",
+                x0, y0, x1 - x0, y1 - y0,
+            ).unwrap();
+            let (x, y) = (x0.max(0.0) + 2.0, y0.max(0.0) + 12.0);
+            let label = format!("<text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" stroke=\"none\">", x, y, color);
+            writeln!(svg, "{}{}</text>", label, escape(name)).unwrap();
+        }
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,31 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::Result;
+
+// A path in the temp directory of this test run alone, so tests running at once don't share files.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("raytracer-{}-{}", std::process::id(), name))
+}
+
+// Writes `contents` to a file of its own named like `name` for `load` to read, and removes it after.
+pub(crate) fn with_file<T>(name: &str, contents: impl AsRef<[u8]>, load: impl FnOnce(&str) -> T) -> T {
+    let path = temp_path(name);
+    fs::write(&path, contents).unwrap();
+    let loaded = load(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+    loaded
+}
+
+// Checks that `load` rejects each file with an error that says what was wrong with it.
+pub(crate) fn assert_errors<'a, T, C: AsRef<[u8]>>(
+    cases: impl IntoIterator<Item = (&'a str, C, &'a str)>, load: impl Fn(&str) -> Result<T>,
+) {
+    for (name, contents, what) in cases {
+        match with_file(name, contents, &load) {
+            Ok(_) => panic!("{} loaded", name),
+            Err(error) => assert!(error.to_string().contains(what), "{}: {}", name, error),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use nalgebra::Vector3;
+
+use crate::mesh::Mesh;
+use crate::overlay::{glyph, GLYPH_SIZE};
+
+// The faces of a unit box whose corner `i` is at (i & 1, i >> 1 & 1, i >> 2 & 1), wound outwards.
+const BOX_FACES: [[usize; 3]; 12] = [
+    [0, 4, 6], [0, 6, 2], [1, 3, 7], [1, 7, 5], [0, 1, 5], [0, 5, 4],
+    [2, 6, 7], [2, 7, 3], [0, 2, 3], [0, 3, 1], [4, 5, 7], [4, 7, 6],
+];
+
+// Extrudes text in the bundled bitmap font into a mesh for labels and captions, one box per run of font
+// pixels in a row. `origin` is the bottom left corner of the first character, `right` the advance of one
+// character and `up` its height; the faces of the letters look along `right` × `up` and they reach `depth`
+// back from there. Each line of the text starts `up` below the one before.
+pub fn text_mesh(text: &str, origin: Vector3<f64>, right: Vector3<f64>, up: Vector3<f64>, depth: f64) -> Mesh {
+    let back = right.cross(&up).normalize() * -depth;
+    let (mut vertices, mut faces) = (Vec::new(), Vec::new());
+    for (line, text) in text.lines().enumerate() {
+        for (k, c) in text.chars().enumerate() {
+            for (row, &bits) in glyph(c).iter().enumerate() {
+                let mut column = 0;
+                while column < GLYPH_SIZE {
+                    if bits >> column & 1 == 0 {
+                        column += 1;
+                        continue;
+                    }
+                    let start = column;
+                    while column < GLYPH_SIZE && bits >> column & 1 == 1 {
+                        column += 1;
+                    }
+                    let pixel = 1.0 / GLYPH_SIZE as f64;
+                    let x = [k as f64 + start as f64 * pixel, k as f64 + column as f64 * pixel];
+                    let y = [1.0 - (row + 1) as f64 * pixel, 1.0 - row as f64 * pixel].map(|y| y - line as f64);
+                    let base = vertices.len();
+                    vertices.extend((0..8).map(|i| {
+                        let front = origin + right * x[i & 1] + up * y[i >> 1 & 1];
+                        if i >> 2 & 1 == 1 { front } else { front + back }
+                    }));
+                    faces.extend(BOX_FACES.iter().map(|f| f.map(|i| base + i)));
+                }
+            }
+        }
+    }
+    let materials = vec![0; faces.len()];
+    Mesh::new(vertices, faces, materials)
+}
@@ -0,0 +1,209 @@
+use std::mem::size_of;
+use std::sync::Arc;
+
+use image::DynamicImage;
+use itertools::iproduct;
+use nalgebra::{Vector2, Vector3};
+
+use crate::counters;
+use crate::error::{Error, Result};
+use crate::object::Intersection;
+
+pub(crate) const ALPHA_CUTOFF: f64 = 0.5;
+
+pub trait Texture {
+    fn value(&self, int: &Intersection) -> Vector3<f64>;
+
+    fn opacity(&self, _int: &Intersection) -> f64 {
+        1.0
+    }
+}
+
+impl Texture for Vector3<f64> {
+    fn value(&self, _int: &Intersection) -> Vector3<f64> {
+        *self
+    }
+}
+
+impl<T: Texture + ?Sized> Texture for Box<T> {
+    fn value(&self, int: &Intersection) -> Vector3<f64> {
+        (**self).value(int)
+    }
+
+    fn opacity(&self, int: &Intersection) -> f64 {
+        (**self).opacity(int)
+    }
+}
+
+impl<T: Texture + ?Sized> Texture for &T {
+    fn value(&self, int: &Intersection) -> Vector3<f64> {
+        (**self).value(int)
+    }
+
+    fn opacity(&self, int: &Intersection) -> f64 {
+        (**self).opacity(int)
+    }
+}
+
+pub struct VertexColor;
+
+impl Texture for VertexColor {
+    fn value(&self, int: &Intersection) -> Vector3<f64> {
+        int.color().unwrap_or_else(|| Vector3::new(1.0, 1.0, 1.0))
+    }
+}
+
+// How an image file stores its values. Colors are mostly stored sRGB-encoded and have to be decoded
+// before shading, or they come out washed out; data such as blend factors is stored as is, as are the
+// colors of float formats like HDR and EXR.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    // What the colors of `image` are most likely in, from its format.
+    fn of(image: &DynamicImage) -> Self {
+        match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => ColorSpace::Linear,
+            _ => ColorSpace::Srgb,
+        }
+    }
+
+    fn decode(self, value: f32) -> f64 {
+        let value = value as f64;
+        match self {
+            ColorSpace::Linear => value,
+            ColorSpace::Srgb if value <= 0.04045 => value / 12.92,
+            ColorSpace::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Trilinear,
+}
+
+struct Level {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vector3<f64>>,
+}
+
+impl Level {
+    fn texel(&self, i: i64, j: i64) -> Vector3<f64> {
+        let i = i.rem_euclid(self.width as i64) as usize;
+        let j = j.rem_euclid(self.height as i64) as usize;
+        self.pixels[j * self.width as usize + i]
+    }
+
+    fn downsample(&self) -> Self {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let pixels = iproduct!(0..height as i64, 0..width as i64)
+            .map(|(j, i)| {
+                let (x, y) = (2 * i, 2 * j);
+                (self.texel(x, y) + self.texel(x + 1, y) + self.texel(x, y + 1) + self.texel(x + 1, y + 1)) / 4.0
+            })
+            .collect();
+        Self { width, height, pixels }
+    }
+
+    fn bilinear(&self, uv: &Vector2<f64>) -> Vector3<f64> {
+        let x = uv.x.rem_euclid(1.0) * self.width as f64 - 0.5;
+        let y = (1.0 - uv.y).rem_euclid(1.0) * self.height as f64 - 0.5;
+        let (i, j) = (x.floor(), y.floor());
+        let (s, t) = (x - i, y - j);
+        let (i, j) = (i as i64, j as i64);
+        let top = self.texel(i, j) * (1.0 - s) + self.texel(i + 1, j) * s;
+        let bottom = self.texel(i, j + 1) * (1.0 - s) + self.texel(i + 1, j + 1) * s;
+        top * (1.0 - t) + bottom * t
+    }
+}
+
+// Image textures keep a box-filtered mip pyramid built at load time. With ray differentials the level
+// is chosen from the pixel footprint in texels; without them the full-resolution image is used. Clones
+// share the pixels.
+#[derive(Clone)]
+pub struct ImageTexture {
+    levels: Arc<[Level]>,
+    alpha: Arc<[f64]>,
+    filter: Filter,
+}
+
+impl ImageTexture {
+    // Loads colors, in the color space the file's format suggests.
+    pub fn load(path: &str) -> Result<Self> {
+        Self::load_in(path, None)
+    }
+
+    // Alpha is always linear.
+    pub fn load_in(path: &str, space: Option<ColorSpace>) -> Result<Self> {
+        let image = image::open(path).map_err(|e| Error::ImageFile(format!("{}: {}", path, e)))?;
+        let space = space.unwrap_or_else(|| ColorSpace::of(&image));
+        let image = image.to_rgba32f();
+        let (width, height) = image.dimensions();
+        let pixels = image.pixels()
+            .map(|p| Vector3::new(space.decode(p[0]), space.decode(p[1]), space.decode(p[2])))
+            .collect();
+        let alpha = image.pixels().map(|p| p[3] as f64).collect::<Vec<_>>();
+        let mut levels = vec![Level { width, height, pixels }];
+        while let Some(level) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+            levels.push(level.downsample());
+        }
+        let texels = levels.iter().map(|l| l.pixels.len()).sum::<usize>();
+        counters::load_texture((texels * size_of::<Vector3<f64>>() + alpha.len() * size_of::<f64>()) as u64);
+        Ok(Self { levels: levels.into(), alpha: alpha.into(), filter: Filter::Trilinear })
+    }
+
+    pub fn with_filter(self, filter: Filter) -> Self {
+        Self { filter, ..self }
+    }
+
+    fn index(&self, uv: &Vector2<f64>) -> usize {
+        let Level { width, height, .. } = self.levels[0];
+        let i = (uv.x.rem_euclid(1.0) * width as f64) as u32;
+        let j = ((1.0 - uv.y).rem_euclid(1.0) * height as f64) as u32;
+        (j.min(height - 1) * width + i.min(width - 1)) as usize
+    }
+
+    pub fn sample(&self, uv: &Vector2<f64>) -> Vector3<f64> {
+        self.levels[0].pixels[self.index(uv)]
+    }
+
+    // `lod` is the mip level, fractional levels blending the two nearest ones for trilinear filtering.
+    pub fn lookup(&self, uv: &Vector2<f64>, lod: f64) -> Vector3<f64> {
+        let lod = lod.clamp(0.0, (self.levels.len() - 1) as f64);
+        match self.filter {
+            Filter::Nearest => self.sample(uv),
+            Filter::Bilinear => self.levels[lod.round() as usize].bilinear(uv),
+            Filter::Trilinear => {
+                let (lo, t) = (lod.floor() as usize, lod.fract());
+                let hi = (lo + 1).min(self.levels.len() - 1);
+                self.levels[lo].bilinear(uv) * (1.0 - t) + self.levels[hi].bilinear(uv) * t
+            }
+        }
+    }
+
+    pub fn alpha(&self, uv: &Vector2<f64>) -> f64 {
+        self.alpha[self.index(uv)]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, int: &Intersection) -> Vector3<f64> {
+        let lod = int.uv_derivatives().map_or(0.0, |(dx, dy)| {
+            let size = Vector2::new(self.levels[0].width as f64, self.levels[0].height as f64);
+            let texels = dx.component_mul(&size).norm().max(dy.component_mul(&size).norm());
+            texels.max(1.0).log2()
+        });
+        self.lookup(int.uv(), lod)
+    }
+
+    fn opacity(&self, int: &Intersection) -> f64 {
+        self.alpha(int.uv())
+    }
+}
@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use image::{Delay, Frame, RgbaImage};
+use image::codecs::gif::{self, Repeat};
+use nalgebra::Vector3;
+
+use crate::error::Result;
+
+type Image = (u32, u32, Vec<Vector3<f64>>);
+
+// Encodes frames into a video by piping them as raw RGB to ffmpeg, which must be on the PATH. The codec
+// follows the extension: VP9 for .webm, H.264 for anything else, such as .mp4 or .mkv.
+pub struct VideoEncoder {
+    child: Child,
+    width: u32,
+    height: u32,
+}
+
+impl VideoEncoder {
+    pub fn new(path: &str, width: u32, height: u32, fps: f64) -> io::Result<Self> {
+        let codec = if path.ends_with(".webm") {
+            ["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "31"]
+        } else {
+            ["-c:v", "libx264", "-preset", "slow", "-crf", "18"]
+        };
+        let size = format!("{}x{}", width, height);
+        let child = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgb24", "-s", &size])
+            .args(["-r", &fps.to_string(), "-i", "-"])
+            .args(codec)
+            // players expect 4:2:0 chroma, which needs even dimensions
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p", path])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("could not run ffmpeg: {}", e)))?;
+        Ok(Self { child, width, height })
+    }
+
+    pub fn push(&mut self, image: &Image) -> io::Result<()> {
+        let (width, height, buffer) = image;
+        if (*width, *height) != (self.width, self.height) {
+            return Err(io::Error::other(format!(
+                "frame is {}x{} but the video is {}x{}", width, height, self.width, self.height,
+            )));
+        }
+        // the renderer stores pixels column by column, and ffmpeg wants rows
+        let bytes = (0..*height).flat_map(|j| (0..*width).map(move |i| buffer[(i * height + j) as usize]))
+            .flat_map(|c| [c.x, c.y, c.z].map(|x| (x.clamp(0.0, 1.0) * 255.0) as u8))
+            .collect::<Vec<_>>();
+        self.child.stdin.as_mut().unwrap().write_all(&bytes)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        drop(self.child.stdin.take());
+        let status = self.child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("ffmpeg failed: {}", status)))
+        }
+    }
+}
+
+// Encodes frames into a looping animated GIF, which plays anywhere without ffmpeg, for short clips such as
+// turntables. GIF has 256 colors to a frame and counts delays in hundredths of a second, so it suits
+// previews more than finals. The file is complete once the encoder is dropped.
+pub struct GifEncoder {
+    encoder: gif::GifEncoder<BufWriter<File>>,
+    delay: Delay,
+}
+
+impl GifEncoder {
+    pub fn new(path: &str, fps: f64) -> Result<Self> {
+        // a middling speed of the color quantizer, as the slowest takes seconds a frame
+        let mut encoder = gif::GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        Ok(Self { encoder, delay: Delay::from_saturating_duration(Duration::from_secs_f64(1.0 / fps)) })
+    }
+
+    pub fn push(&mut self, image: &Image) -> Result<()> {
+        let (width, height, buffer) = image;
+        let frame = RgbaImage::from_fn(*width, *height, |i, j| {
+            let c = buffer[(i * height + j) as usize].map(|x| (x.clamp(0.0, 1.0) * 255.0) as u8);
+            image::Rgba([c.x, c.y, c.z, 255])
+        });
+        Ok(self.encoder.encode_frame(Frame::from_parts(frame, 0, 0, self.delay))?)
+    }
+}
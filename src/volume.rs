@@ -0,0 +1,335 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::Read;
+use std::mem::size_of_val;
+use std::ops::Range;
+
+use nalgebra::{Vector2, Vector3};
+use rand::Rng;
+
+use crate::aabb::Aabb;
+use crate::error::{Error, Result};
+use crate::material::orthonormal_basis;
+use crate::object::{Intersection, Object};
+use crate::ray::Ray;
+use crate::RNG;
+
+// A voxel grid of one or three channels over a box, sampled with trilinear interpolation. Voxel centers
+// sit at the cell centers of a regular division of the box.
+pub struct Grid {
+    resolution: [usize; 3],
+    channels: usize,
+    bounds: Aabb,
+    data: Vec<f32>,
+    max: f64,
+}
+
+impl Grid {
+    pub fn new(resolution: [usize; 3], channels: usize, bounds: Aabb, data: Vec<f32>) -> Self {
+        assert!(channels == 1 || channels == 3, "grids must have one or three channels");
+        assert_eq!(data.len(), resolution.iter().product::<usize>() * channels, "grid size mismatch");
+        let max = data.iter().fold(0.0f32, |m, &x| m.max(x)) as f64;
+        Self { resolution, channels, bounds, data, max }
+    }
+
+    // Mitsuba's gridvol format, which VDB grids can be converted to: a "VOL" header with version 3,
+    // float32 encoding, the resolution, the channel count and the bounding box, then the voxels with x
+    // varying fastest.
+    pub fn load_vol(path: &str) -> Result<Self> {
+        let invalid = |what: &str| Error::Volume(format!("{}: {}", path, what));
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        if bytes.len() < 48 || !bytes.starts_with(b"VOL") || bytes[3] != 3 {
+            return Err(invalid("not a version 3 gridvol file"));
+        }
+        let int = |i: usize| i32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let float = |i: usize| f32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if int(4) != 1 {
+            return Err(invalid("only float32 grids are supported"));
+        }
+        let size = |i: usize| usize::try_from(int(i)).ok().filter(|&n| n > 0);
+        let resolution = match [size(8), size(12), size(16)] {
+            [Some(x), Some(y), Some(z)] => [x, y, z],
+            _ => return Err(invalid("the resolution must be positive")),
+        };
+        let channels = match int(20) {
+            1 => 1,
+            3 => 3,
+            _ => return Err(invalid("grids must have one or three channels")),
+        };
+        let values = resolution.iter().try_fold(channels, |n: usize, &k| n.checked_mul(k))
+            .ok_or_else(|| invalid("the resolution is too large"))?;
+        if (bytes.len() - 48) / 4 < values {
+            return Err(invalid("fewer voxels than the resolution calls for"));
+        }
+        let corner = |i: usize| Vector3::new(float(i) as f64, float(i + 4) as f64, float(i + 8) as f64);
+        let bounds = Aabb::new(corner(24), corner(36));
+        let data = bytes[48..].chunks_exact(4).take(values).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        Ok(Self::new(resolution, channels, bounds, data))
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> Vector3<f64> {
+        let [nx, ny, _] = self.resolution;
+        let i = ((z * ny + y) * nx + x) * self.channels;
+        match self.channels {
+            1 => Vector3::repeat(self.data[i] as f64),
+            _ => Vector3::new(self.data[i] as f64, self.data[i + 1] as f64, self.data[i + 2] as f64),
+        }
+    }
+
+    pub fn lookup(&self, point: &Vector3<f64>) -> Vector3<f64> {
+        let local = (point - self.bounds.min).component_div(&self.bounds.diagonal());
+        let cell = |k: usize| {
+            let x = (local[k] * self.resolution[k] as f64 - 0.5).clamp(0.0, (self.resolution[k] - 1) as f64);
+            let i = (x as usize).min(self.resolution[k].saturating_sub(2));
+            (i, (i + 1).min(self.resolution[k] - 1), x - i as f64)
+        };
+        let ((x0, x1, fx), (y0, y1, fy), (z0, z1, fz)) = (cell(0), cell(1), cell(2));
+        let lerp = |a: Vector3<f64>, b: Vector3<f64>, t: f64| a * (1.0 - t) + b * t;
+        let plane = |z| lerp(
+            lerp(self.voxel(x0, y0, z), self.voxel(x1, y0, z), fx),
+            lerp(self.voxel(x0, y1, z), self.voxel(x1, y1, z), fx),
+            fy,
+        );
+        lerp(plane(z0), plane(z1), fz)
+    }
+
+    pub fn density(&self, point: &Vector3<f64>) -> f64 {
+        self.lookup(point).x
+    }
+}
+
+// A heterogeneous participating medium. Free flights are sampled by delta tracking against the
+// densest voxel, so a hit is a real collision inside the volume and a ray that gets through returns
+// no hit at all. Collisions scatter by the Henyey-Greenstein phase function with probability
+// `albedo`; the rest of the time they absorb, picking up the emission grid if there is one.
+pub struct Volume {
+    density: Grid,
+    scale: f64,
+    albedo: Vector3<f64>,
+    anisotropy: f64,
+    emission: Option<(Grid, Vector3<f64>)>,
+}
+
+impl Volume {
+    pub fn new(density: Grid, scale: f64, albedo: Vector3<f64>) -> Self {
+        Self { density, scale, albedo, anisotropy: 0.0, emission: None }
+    }
+
+    pub fn with_anisotropy(self, anisotropy: f64) -> Self {
+        Self { anisotropy, ..self }
+    }
+
+    // Emitted radiance is the emission grid tinted by `color`.
+    pub fn with_emission(self, emission: Grid, color: Vector3<f64>) -> Self {
+        Self { emission: Some((emission, color)), ..self }
+    }
+
+    fn majorant(&self) -> f64 {
+        self.density.max * self.scale
+    }
+}
+
+// A homogeneous medium filling the scene below `height`. Rays scatter in it after exponentially
+// distributed distances, so distant objects fade into the light scattered by the fog itself. Light from
+// the sky needs a top to the fog to get in; shadow rays to lights and portals are dimmed by what they cross.
+pub struct Fog {
+    pub density: f64,
+    pub albedo: Vector3<f64>,
+    pub anisotropy: f64,
+    pub height: f64,
+}
+
+impl Fog {
+    // Where along the ray, in units of its parameter, it is in the fog.
+    fn inside(&self, ray: &Ray<f64>) -> Option<Range<f64>> {
+        let (y, dy) = (ray.origin.y, ray.direction().y);
+        let crossing = (self.height - y) / dy;
+        match (y < self.height, dy > 0.0) {
+            (true, true) => Some(0.0..crossing),
+            (true, false) => Some(0.0..f64::INFINITY),
+            (false, false) if dy < 0.0 => Some(crossing..f64::INFINITY),
+            (false, _) => None,
+        }
+    }
+
+    // Samples how far along the ray, in units of its parameter, it gets before scattering, or infinity
+    // if it leaves the fog first.
+    pub(crate) fn distance(&self, ray: &Ray<f64>) -> f64 {
+        let Some(inside) = self.inside(ray) else {
+            return f64::INFINITY;
+        };
+        let u = RNG.with(|r| r.borrow_mut().gen::<f64>());
+        let t = inside.start - (1.0 - u).ln() / (self.density * ray.direction().norm());
+        if t < inside.end { t } else { f64::INFINITY }
+    }
+
+    // The share of light that gets through the fog along the ray over `range` without scattering, the chance
+    // that `distance` is past it.
+    pub(crate) fn transmittance(&self, ray: &Ray<f64>, range: Range<f64>) -> f64 {
+        let length = self.inside(ray).map_or(0.0, |inside| inside.end.min(range.end) - inside.start.max(range.start));
+        if length <= 0.0 || self.density <= 0.0 {
+            return 1.0;
+        }
+        (-self.density * ray.direction().norm() * length).exp()
+    }
+
+    pub(crate) fn scatter(&self, ray: &Ray<f64>, t: f64) -> (Ray<f64>, Vector3<f64>) {
+        let direction = sample_henyey_greenstein(ray.direction(), self.anisotropy);
+        (Ray::new(ray.at(t), direction), self.albedo)
+    }
+}
+
+pub(crate) fn henyey_greenstein(cos: f64, g: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cos;
+    (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+}
+
+// Samples a direction scattered off `direction` with the Henyey-Greenstein phase function.
+pub(crate) fn sample_henyey_greenstein(direction: &Vector3<f64>, g: f64) -> Vector3<f64> {
+    let (u, v): (f64, f64) = RNG.with(|r| {
+        let mut r = r.borrow_mut();
+        (r.gen(), r.gen())
+    });
+    let cos = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    let d = direction.normalize();
+    let (t, b) = orthonormal_basis(&d);
+    t * (sin * phi.cos()) + b * (sin * phi.sin()) + d * cos
+}
+
+impl Object for Volume {
+    fn intersect(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<Intersection<'_>> {
+        let majorant = self.majorant();
+        let Range { start, end } = self.density.bounds.clip(ray, range).filter(|_| majorant > 0.0)?;
+        let speed = ray.direction().norm();
+        let mut t = start;
+        loop {
+            let (u, v): (f64, f64) = RNG.with(|r| {
+                let mut r = r.borrow_mut();
+                (r.gen(), r.gen())
+            });
+            t -= (1.0 - u).ln() / (majorant * speed);
+            if t >= end {
+                return None;
+            }
+            if v * majorant < self.density.density(&ray.at(t)) * self.scale {
+                return Some(Intersection::new(t, ray, self, 0));
+            }
+        }
+    }
+
+    // Media have no surface, so the normal is arbitrary; nothing here scatters off it.
+    fn normal(&self, _point: &Vector3<f64>, _index: usize) -> Vector3<f64> {
+        Vector3::y()
+    }
+
+    fn uv(&self, _point: &Vector3<f64>, _index: usize) -> Vector2<f64> {
+        Vector2::zeros()
+    }
+
+    fn scatter(&self, int: &Intersection) -> (Ray<f64>, Vector3<f64>) {
+        let direction = sample_henyey_greenstein(int.ray().direction(), self.anisotropy);
+        (Ray::new(*int.point(), direction), self.albedo)
+    }
+
+    fn eval(&self, int: &Intersection, wi: &Vector3<f64>) -> Vector3<f64> {
+        let cos = wi.normalize().dot(&int.ray().direction().normalize());
+        self.albedo * henyey_greenstein(cos, self.anisotropy)
+    }
+
+    fn specular(&self, _index: usize) -> bool {
+        false
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.density.bounds)
+    }
+
+    fn specular_bounds(&self) -> Option<Aabb> {
+        None
+    }
+
+    // By ratio tracking, which weighs the collisions rather than stopping at the first.
+    fn transmittance(&self, ray: &Ray<f64>, range: Range<f64>) -> Option<f64> {
+        let majorant = self.majorant();
+        let mut transmittance = 1.0;
+        let (mut t, end) = match self.density.bounds.clip(ray, range) {
+            Some(range) if majorant > 0.0 => (range.start, range.end),
+            _ => return Some(transmittance),
+        };
+        let speed = ray.direction().norm();
+        loop {
+            t -= (1.0 - RNG.with(|r| r.borrow_mut().gen::<f64>())).ln() / (majorant * speed);
+            if t >= end {
+                return Some(transmittance);
+            }
+            transmittance *= 1.0 - self.density.density(&ray.at(t)) * self.scale / majorant;
+        }
+    }
+
+    fn memory(&self, _shared: &mut HashSet<usize>) -> usize {
+        let emission = self.emission.as_ref().map_or(0, |(grid, _)| size_of_val(grid.data.as_slice()));
+        size_of_val(self.density.data.as_slice()) + emission
+    }
+
+    fn emitted(&self, int: &Intersection) -> Vector3<f64> {
+        match &self.emission {
+            Some((grid, color)) => {
+                let absorbed = Vector3::new(1.0, 1.0, 1.0) - self.albedo;
+                grid.lookup(int.local_point()).component_mul(color).component_mul(&absorbed)
+            }
+            None => Vector3::zeros(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_errors, with_file};
+
+    // A gridvol file with the given header values, over the unit cube, followed by `voxels` floats.
+    fn vol(resolution: [i32; 3], channels: i32, voxels: usize) -> Vec<u8> {
+        let mut bytes = b"VOL\x03".to_vec();
+        for i in [1, resolution[0], resolution[1], resolution[2], channels] {
+            bytes.extend(i.to_le_bytes());
+        }
+        for x in [0.0f32, 0.0, 0.0, 1.0, 1.0, 1.0].iter().copied().chain((0..voxels).map(|v| v as f32)) {
+            bytes.extend(x.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn loads_vol() {
+        let grid = with_file("grid.vol", vol([2, 1, 1], 1, 2), Grid::load_vol).unwrap();
+        assert_eq!(grid.resolution, [2, 1, 1]);
+        assert_eq!(grid.lookup(&Vector3::new(0.75, 0.5, 0.5)), Vector3::repeat(1.0));
+    }
+
+    #[test]
+    fn reports_bad_vol() {
+        assert_errors([
+            ("header.vol", b"VOL\x03".to_vec(), "not a version 3 gridvol file"),
+            ("channels.vol", vol([1, 1, 1], 2, 2), "one or three channels"),
+            ("zero.vol", vol([2, 0, 2], 1, 0), "the resolution must be positive"),
+            ("negative.vol", vol([2, -2, 2], 1, 0), "the resolution must be positive"),
+            ("huge.vol", vol([i32::MAX; 3], 3, 0), "the resolution is too large"),
+            ("truncated.vol", vol([2, 2, 2], 1, 5), "fewer voxels than the resolution calls for"),
+        ], Grid::load_vol);
+    }
+}
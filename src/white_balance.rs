@@ -0,0 +1,56 @@
+use std::ops::Range;
+
+use nalgebra::Vector3;
+
+use crate::camera::Camera;
+use crate::object::Object;
+use crate::ray::Ray;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WhiteBalance {
+    // Assume the image averages out to grey.
+    GreyWorld,
+    // Make the scene's neutral object grey, falling back to grey world if it has none or it's out of view.
+    Neutral,
+}
+
+// Scales the channels so the reference pixels, the ones that see the neutral object or else all of them,
+// average to a grey of the same luminance. The image holds radiance, before the display transform.
+pub(crate) fn white_balance(
+    image: (u32, u32, Vec<Vector3<f64>>), mode: WhiteBalance, camera: &Camera, objects: &[Box<dyn Object + Sync>],
+    neutral: Option<usize>,
+) -> (u32, u32, Vec<Vector3<f64>>) {
+    let (width, height, linear) = image;
+    let mask = match (mode, neutral) {
+        (WhiteBalance::Neutral, Some(neutral)) => neutral_pixels(width, height, camera, objects, neutral),
+        _ => Vec::new(),
+    };
+    let reference = match mask.iter().filter(|&&m| m).count() {
+        0 => linear.iter().sum::<Vector3<f64>>() / linear.len() as f64,
+        n => linear.iter().zip(&mask).filter(|(_, &m)| m).map(|(c, _)| c).sum::<Vector3<f64>>() / n as f64,
+    };
+    let luminance = reference.dot(&Vector3::new(0.2126, 0.7152, 0.0722));
+    let gains = reference.map(|x| if x > 0.0 { luminance / x } else { 1.0 });
+    let buffer = linear.iter().map(|c| c.component_mul(&gains)).collect();
+    (width, height, buffer)
+}
+
+// Whether the ray through the center of each pixel first hits the neutral object.
+fn neutral_pixels(
+    width: u32, height: u32, camera: &Camera, objects: &[Box<dyn Object + Sync>], neutral: usize,
+) -> Vec<bool> {
+    (0..width).flat_map(|i| (0..height).map(move |j| (i, j))).map(|(i, j)| {
+        let u = (i as f64 + 0.5) / width as f64;
+        let v = 1.0 - (j as f64 + 0.5) / height as f64;
+        let ray = camera.ray_through(u, v, 1.0 / width as f64, 1.0 / height as f64, [0.0, 0.0]);
+        first_object(objects, &ray, 0.0..f64::INFINITY) == Some(neutral)
+    }).collect()
+}
+
+pub(crate) fn first_object(objects: &[Box<dyn Object + Sync>], ray: &Ray<f64>, range: Range<f64>) -> Option<usize> {
+    objects.iter().enumerate()
+        .filter_map(|(k, o)| o.intersect(ray, range.clone()).map(|i| (k, i.t())))
+        .filter(|(_, t)| !t.is_nan())
+        .min_by(|(_, x), (_, y)| x.total_cmp(y))
+        .map(|(k, _)| k)
+}
@@ -0,0 +1,25 @@
+use std::env;
+use std::process::Command;
+
+fn raytracer(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_raytracer")).args(args).output().unwrap()
+}
+
+// A frame rendered to `.png` is a PNG that --encode reads back. Encoding itself needs ffmpeg, so without it
+// the test only gets as far as ffmpeg failing to start, which happens after the frame was decoded.
+#[test]
+fn render_then_encode() {
+    let dir = env::temp_dir();
+    let frame = dir.join(format!("cli-frame-{}.png", std::process::id()));
+    let video = dir.join(format!("cli-video-{}.mp4", std::process::id()));
+    let (frame, video) = (frame.to_str().unwrap(), video.to_str().unwrap());
+    let scene = format!("{}/scenes/colonnade.json", env!("CARGO_MANIFEST_DIR"));
+    let render = raytracer(&["--quality", "draft", "--samples", "1", "--crop", "0,0,16,16", "-q", "-o", frame, &scene]);
+    assert!(render.status.success(), "{}", String::from_utf8_lossy(&render.stderr));
+    assert_eq!(image::open(frame).unwrap().to_rgb8().dimensions(), (16, 16));
+    let encode = raytracer(&["--encode", video, frame]);
+    std::fs::remove_file(frame).unwrap();
+    let _ = std::fs::remove_file(video);
+    let stderr = String::from_utf8_lossy(&encode.stderr);
+    assert!(encode.status.success() || stderr.contains("could not run ffmpeg"), "{}", stderr);
+}
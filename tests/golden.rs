@@ -0,0 +1,96 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use nalgebra::Vector3;
+use raytracer::texture::ImageTexture;
+use raytracer::{Background, Control, Crop, RenderSettings, Sampler, SceneBuilder};
+
+// Renders small versions of the canonical scenes and compares them against the references in
+// tests/golden. Renders are deterministic given the passes, so only the order passes are summed in
+// differs between runs; the tolerance is for that, not for noise. After an intended change to the
+// output, run with UPDATE_GOLDEN=1 to rewrite the references, and look at them before committing.
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 36;
+// Root mean square difference over all channels, in 8-bit levels.
+const TOLERANCE: f64 = 1.0;
+
+fn golden(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name))
+}
+
+fn check(name: &str, settings: RenderSettings) {
+    let settings = RenderSettings { width: WIDTH, height: HEIGHT, samples: 1, threads: 2, ..settings };
+    let output = env::temp_dir().join(format!("golden-{}-{}.png", name, std::process::id()));
+    raytracer::save_image(output.to_str().unwrap(), raytracer::render(&settings).unwrap()).unwrap();
+    let reference = golden(name);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::rename(&output, &reference).unwrap();
+        return;
+    }
+    let actual = image::open(&output).unwrap().to_rgb8();
+    std::fs::remove_file(&output).unwrap();
+    let expected = image::open(&reference)
+        .unwrap_or_else(|e| panic!("no reference for {} ({}); run with UPDATE_GOLDEN=1 to create it", name, e))
+        .to_rgb8();
+    assert_eq!(actual.dimensions(), expected.dimensions(), "{} changed size", name);
+    let squared = actual.as_raw().iter().zip(expected.as_raw())
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum::<f64>();
+    let rmse = (squared / actual.as_raw().len() as f64).sqrt();
+    assert!(rmse <= TOLERANCE, "{} differs from its reference by {:.2} levels RMS", name, rmse);
+}
+
+fn scene(name: &str) -> Option<String> {
+    Some(format!("{}/scenes/{}.json", env!("CARGO_MANIFEST_DIR"), name))
+}
+
+#[test]
+fn builtin_scene() {
+    check("builtin", RenderSettings::default());
+}
+
+#[test]
+fn random_spheres_random_sampler() {
+    let settings = RenderSettings { scene: scene("random_spheres"), sampler: Sampler::Random, ..Default::default() };
+    check("random_spheres", settings);
+}
+
+#[test]
+fn colonnade() {
+    check("colonnade", RenderSettings { scene: scene("colonnade"), ..Default::default() });
+}
+
+#[test]
+fn colonnade_lens_splitting() {
+    check("colonnade_lens_splits", RenderSettings { scene: scene("colonnade"), lens_splits: 4, ..Default::default() });
+}
+
+#[test]
+fn colonnade_crop() {
+    let crop = Some(Crop::Pixels { x: 16, y: 8, width: 32, height: 20 });
+    check("colonnade_crop", RenderSettings { scene: scene("colonnade"), crop, ..Default::default() });
+}
+
+// A white furnace: a grey ground under a uniform white sky, seen from above, reflects exactly its albedo
+// at every sample, as a convex surface only sees the sky. Surfaces that shadow themselves come out darker.
+#[test]
+fn white_furnace() {
+    let sky = env::temp_dir().join(format!("furnace-{}.ppm", std::process::id()));
+    std::fs::write(&sky, "P3\n1 1\n255\n255 255 255\n").unwrap();
+    let background = Background { map: Some(ImageTexture::load(sky.to_str().unwrap()).unwrap()), ..Default::default() };
+    std::fs::remove_file(&sky).unwrap();
+    let scene = SceneBuilder::new()
+        .look_at(Vector3::new(0.0, 2.0, 0.0), Vector3::new(0.0, 0.0, 1.0))
+        .fov(40.0)
+        .background(background)
+        .sphere(Vector3::new(0.0, -1000.0, 0.0), 1000.0)
+        .lambertian(Vector3::repeat(0.5))
+        .build();
+    let settings = RenderSettings { width: 16, height: 16, samples: 4, threads: 2, ..Default::default() };
+    let (_, _, image) = raytracer::render_scene(&scene, &settings, &Control::new(&settings)).unwrap();
+    // through the built-in gamma of 2
+    let expected = 0.5f64.sqrt();
+    for color in image {
+        assert!((color - Vector3::repeat(expected)).amax() < 1e-6, "the ground came out {:?}", color);
+    }
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use wasm_bindgen::prelude::*;
+
+use raytracer::{Control, RenderSettings, Scene};
+
+// Renders progressively into a canvas: each call to `pass` adds a sample per pixel. Scenes are given as
+// the JSON of a scene file; the browser can't read asset files, so they should stick to analytic shapes.
+#[wasm_bindgen]
+pub struct Renderer {
+    scene: Scene,
+    settings: RenderSettings,
+    control: Control,
+    passes: u32,
+}
+
+#[wasm_bindgen]
+impl Renderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(scene: &str, width: u32, height: u32) -> Result<Renderer, JsValue> {
+        let description = serde_json::from_str(scene).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let scene = Scene::from_json(&description, Path::new(""));
+        let settings = RenderSettings { width, height, samples: 0, threads: 1, ..Default::default() };
+        let control = Control::new(&settings);
+        Ok(Self { scene, settings, control, passes: 0 })
+    }
+
+    // The image so far as RGBA rows, ready for an ImageData.
+    pub fn pass(&mut self) -> Vec<u8> {
+        self.passes += 1;
+        self.control.set_samples(self.passes);
+        let (width, height, buffer) = raytracer::render_scene(&self.scene, &self.settings, &self.control);
+        (0..height).flat_map(|j| (0..width).map(move |i| (i, j))).flat_map(|(i, j)| {
+            let c = buffer[(i * height + j) as usize].map(|x| (x.clamp(0.0, 1.0) * 255.0) as u8);
+            [c.x, c.y, c.z, 255]
+        }).collect()
+    }
+
+    pub fn passes(&self) -> u32 {
+        self.passes
+    }
+}